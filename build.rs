@@ -0,0 +1,54 @@
+use std::path::Path;
+use std::process::Command;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=proto");
+    println!(
+        "cargo:rustc-env=TASKIE_GIT_SHA={}",
+        git_sha().unwrap_or_else(|| "unknown".to_string())
+    );
+    // The gRPC service is only generated along with the `grpc` frontend, with
+    // a bundled protoc so that it does not have to be installed
+    #[cfg(feature = "grpc")]
+    {
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+        tonic_build::compile_protos("proto/taskie.proto")?;
+    }
+    Ok(())
+}
+
+/// The commit the server is built from, as reported by `/v1/version`. It can
+/// be given with `TASKIE_GIT_SHA` when building out of the repository, i.e.
+/// from a source archive.
+fn git_sha() -> Option<String> {
+    println!("cargo:rerun-if-env-changed=TASKIE_GIT_SHA");
+    if let Ok(sha) = std::env::var("TASKIE_GIT_SHA") {
+        return Some(sha);
+    }
+    // Built again once another commit is checked out, or made on the current
+    // branch. A missing file would have it built every time instead.
+    let head = Path::new(".git/HEAD");
+    if !head.exists() {
+        return None;
+    }
+    println!("cargo:rerun-if-changed={}", head.display());
+    if let Some(reference) = std::fs::read_to_string(head)
+        .ok()
+        .and_then(|head| Some(head.strip_prefix("ref: ")?.trim().to_string()))
+    {
+        let reference = Path::new(".git").join(reference);
+        if reference.exists() {
+            println!("cargo:rerun-if-changed={}", reference.display());
+        }
+    }
+    let output = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8(output.stdout).ok())
+        .flatten()
+        .map(|sha| sha.trim().to_string())
+}
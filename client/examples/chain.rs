@@ -0,0 +1,57 @@
+//! Pushes a chain of tasks, each depending on the previous one, and reports
+//! how long the pushes took. Useful to measure the cost of inserting tasks
+//! into a deep dependency graph.
+//!
+//! ```sh
+//! cargo run --release --example chain -- http://localhost:3000 10000
+//! ```
+
+use std::time::Instant;
+
+use taskie_client::Client;
+use taskie_structures::{DependencyMode, InsertTask, Task, TaskId};
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let host = args
+        .next()
+        .unwrap_or_else(|| "http://localhost:3000".to_string());
+    let length: usize = args.next().map_or(Ok(10_000), |s| s.parse())?;
+    let client = Client::new(host.parse()?);
+
+    let start = Instant::now();
+    let mut previous: Option<TaskId> = None;
+    for i in 0..length {
+        let task = InsertTask {
+            name: format!("chain-{}", i),
+            payload: None,
+            depends_on: previous.take().into_iter().collect(),
+            depends_on_batch: vec![],
+            duration: time::Duration::seconds(60),
+            priority: 0,
+            max_retries: 0,
+            cost: 1,
+            labels: Default::default(),
+            tenant: None,
+            run_at: None,
+            expires_at: None,
+            schedule: None,
+            idempotency_key: None,
+            dedupe: false,
+            callback_url: None,
+            dependency_mode: DependencyMode::All,
+            traceparent: None,
+        };
+        let pushed: Task = client.push(&task).await?;
+        previous = Some(pushed.id);
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "Pushed a chain of {} tasks in {:?} ({:.0} tasks/s)",
+        length,
+        elapsed,
+        length as f64 / elapsed.as_secs_f64()
+    );
+    Ok(())
+}
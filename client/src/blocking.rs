@@ -0,0 +1,149 @@
+//! A synchronous client, for the programs which do not run an async runtime.
+//! It mirrors the basic calls of the async [`Client`](crate::Client).
+
+use std::time::Duration;
+
+use reqwest::{
+    header::{CONTENT_ENCODING, CONTENT_TYPE},
+    StatusCode,
+};
+
+use crate::{
+    pop_url, push_body, telemetry, ClientBuilder, ClientError, CompleteTask, Error, Execution,
+    InsertTask, Task, TaskId, Uuid,
+};
+
+/// Like `ClientError::unsuccessful`, for the blocking responses.
+fn unsuccessful(response: reqwest::blocking::Response) -> ClientError {
+    let status = response.status();
+    match response.json::<Error>() {
+        Ok(err) => ClientError::api(status, err),
+        Err(_) => ClientError::Unsuccessful(status),
+    }
+}
+
+/// Like the async one, its clones share its pool of connections.
+#[derive(Clone)]
+pub struct Client {
+    pub(crate) host: url::Url,
+    pub(crate) client: reqwest::blocking::Client,
+    pub(crate) max_pop_attempts: Option<u32>,
+    pub(crate) compress_push: Option<usize>,
+}
+
+impl Client {
+    pub fn new(host: url::Url) -> Result<Self, ClientError> {
+        ClientBuilder::new(host).build_blocking()
+    }
+
+    /// Pushes a single task.
+    pub fn push<N>(&self, task: &InsertTask<N>) -> Result<Task<N>, ClientError>
+    where
+        N: serde::Serialize + for<'a> serde::Deserialize<'a>,
+    {
+        self.push_many(std::slice::from_ref(task))?
+            .pop()
+            .ok_or(ClientError::MissingTask)
+    }
+
+    /// Pushes a batch of tasks atomically, returning them in the same order.
+    pub fn push_many<N>(&self, tasks: &[InsertTask<N>]) -> Result<Vec<Task<N>>, ClientError>
+    where
+        N: serde::Serialize + for<'a> serde::Deserialize<'a>,
+    {
+        let push_url = self.host.join("/v1/push")?;
+        let (body, gzipped) = push_body(&tasks, self.compress_push)?;
+        let mut request = self
+            .client
+            .put(push_url)
+            .headers(telemetry::headers())
+            .header(CONTENT_TYPE, "application/json")
+            .body(body);
+        if gzipped {
+            request = request.header(CONTENT_ENCODING, "gzip");
+        }
+        let response = request.send()?;
+        if response.status().is_success() {
+            Ok(response.json()?)
+        } else {
+            Err(unsuccessful(response))
+        }
+    }
+
+    /// Waits for a task to be ready and pops it. When a `timeout` is given
+    /// the server gives up after it, and `None` is returned.
+    pub fn pop<N>(
+        &self,
+        timeout: Option<Duration>,
+    ) -> Result<Option<Execution<Task<N>>>, ClientError>
+    where
+        N: for<'a> serde::Deserialize<'a>,
+    {
+        self.pop_matching(timeout, "")
+    }
+
+    /// Like `pop`, but only for the tasks whose labels match `selector`.
+    pub fn pop_matching<N>(
+        &self,
+        timeout: Option<Duration>,
+        selector: &str,
+    ) -> Result<Option<Execution<Task<N>>>, ClientError>
+    where
+        N: for<'a> serde::Deserialize<'a>,
+    {
+        let pop_url = pop_url(&self.host, timeout, selector, None, None, None)?;
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let response = self
+                .client
+                .get(pop_url.clone())
+                .headers(telemetry::headers())
+                .send();
+            match response {
+                Err(e) => {
+                    let exhausted = self.max_pop_attempts.is_some_and(|max| attempts >= max);
+                    if !e.is_timeout() || exhausted {
+                        return Err(e.into());
+                    }
+                }
+                Ok(response) if response.status() == StatusCode::NO_CONTENT => return Ok(None),
+                Ok(response) if !response.status().is_success() => {
+                    return Err(unsuccessful(response))
+                }
+                Ok(response) => return Ok(Some(response.json()?)),
+            }
+        }
+    }
+
+    pub fn complete(&self, task_id: &TaskId, lease_token: Uuid) -> Result<(), ClientError> {
+        self.complete_with_result(task_id, lease_token, None)
+    }
+
+    /// Completes a task, handing its `result` over to the tasks depending on
+    /// it.
+    pub fn complete_with_result(
+        &self,
+        task_id: &TaskId,
+        lease_token: Uuid,
+        result: Option<serde_json::Value>,
+    ) -> Result<(), ClientError> {
+        let complete_url = self.host.join("/v1/complete")?;
+        let response = self
+            .client
+            .post(complete_url)
+            .headers(telemetry::headers())
+            .json(&CompleteTask {
+                id: Some(task_id),
+                idempotency_key: None,
+                lease_token: Some(lease_token),
+                result,
+            })
+            .send()?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(unsuccessful(response))
+        }
+    }
+}
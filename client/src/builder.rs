@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+
+use crate::{Client, ClientError};
+
+/// Configures the HTTP client used to talk to the taskie server.
+pub struct ClientBuilder {
+    host: url::Url,
+    request_timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    max_retries: u32,
+    backoff: Duration,
+    max_pop_attempts: Option<u32>,
+    compress_push: Option<usize>,
+    token: Option<String>,
+}
+
+impl ClientBuilder {
+    pub fn new(host: url::Url) -> Self {
+        ClientBuilder {
+            host,
+            request_timeout: None,
+            connect_timeout: None,
+            max_retries: 0,
+            backoff: Duration::from_millis(100),
+            max_pop_attempts: None,
+            compress_push: None,
+            token: None,
+        }
+    }
+
+    /// The token sent as `Authorization: Bearer <token>` on every request.
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// How long a request may take, including a `pop` waiting for a task.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// How many times idempotent calls are retried when the server cannot be
+    /// reached or does not answer in time. Pushes are retried as many times
+    /// when the queue is full, after the wait the server asks for.
+    pub fn max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = retries;
+        self
+    }
+
+    /// The wait before the first retry, doubled on each following one.
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// How many requests `pop` makes before giving up when they all time out.
+    /// By default it waits for a task forever.
+    pub fn max_pop_attempts(mut self, attempts: u32) -> Self {
+        self.max_pop_attempts = Some(attempts);
+        self
+    }
+
+    /// Gzips the bodies of the pushes larger than `threshold` bytes, i.e. for
+    /// large batches over slow links. They are sent uncompressed by default.
+    pub fn compress_push(mut self, threshold: usize) -> Self {
+        self.compress_push = Some(threshold);
+        self
+    }
+
+    /// The headers sent on every request.
+    fn default_headers(&self) -> Result<HeaderMap, ClientError> {
+        let mut headers = HeaderMap::new();
+        if let Some(token) = &self.token {
+            let mut value = HeaderValue::from_str(&format!("Bearer {}", token))
+                .map_err(|_| ClientError::InvalidToken)?;
+            value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, value);
+        }
+        Ok(headers)
+    }
+
+    pub fn build(self) -> Result<Client, ClientError> {
+        let mut builder = reqwest::Client::builder()
+            .default_headers(self.default_headers()?)
+            .gzip(true);
+        if let Some(timeout) = self.request_timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        Ok(Client {
+            host: self.host,
+            client: builder.build()?,
+            max_retries: self.max_retries,
+            backoff: self.backoff,
+            max_pop_attempts: self.max_pop_attempts,
+            compress_push: self.compress_push,
+        })
+    }
+
+    /// Builds a synchronous client instead. The retries of the idempotent
+    /// calls do not apply to it, as it only pushes, pops and completes tasks.
+    #[cfg(feature = "blocking")]
+    pub fn build_blocking(self) -> Result<crate::blocking::Client, ClientError> {
+        // Unlike the async one, the blocking client times out after 30
+        // seconds by default, which would cut a `pop` waiting for a task short
+        let mut builder = reqwest::blocking::Client::builder()
+            .default_headers(self.default_headers()?)
+            .gzip(true)
+            .timeout(self.request_timeout);
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        Ok(crate::blocking::Client {
+            host: self.host,
+            client: builder.build()?,
+            max_pop_attempts: self.max_pop_attempts,
+            compress_push: self.compress_push,
+        })
+    }
+}
@@ -1,11 +1,38 @@
-use reqwest::StatusCode;
+use std::time::Duration;
+
+use std::io::Write;
+
+use flate2::{write::GzEncoder, Compression};
+use reqwest::{
+    header::{CONTENT_ENCODING, CONTENT_TYPE, RETRY_AFTER},
+    StatusCode,
+};
 use thiserror::Error;
 
+pub use builder::ClientBuilder;
 pub use taskie_structures::*;
+pub use typed::{TypedClient, TypedExecution};
+pub use worker::Worker;
+
+pub mod telemetry;
 
+#[cfg(feature = "blocking")]
+pub mod blocking;
+mod builder;
+mod typed;
+mod worker;
+
+/// A client of the taskie server. Cloning it is cheap, and the clones share
+/// its pool of connections, so it can be handed to many tasks without an
+/// `Arc`.
+#[derive(Clone)]
 pub struct Client {
     host: url::Url,
     client: reqwest::Client,
+    max_retries: u32,
+    backoff: Duration,
+    max_pop_attempts: Option<u32>,
+    compress_push: Option<usize>,
 }
 
 #[derive(Error, Debug)]
@@ -16,62 +43,862 @@ pub enum ClientError {
     Request(#[from] reqwest::Error),
     #[error("Request failed with status code: {}", .0)]
     Unsuccessful(StatusCode),
+    /// The request failed with an error the server described
+    #[error("Request failed with status code {status} ({code}): {message}")]
+    Api {
+        status: StatusCode,
+        code: ErrorCode,
+        message: String,
+    },
+    #[error("The API token cannot be sent in a header")]
+    InvalidToken,
+    #[error("The server did not return the pushed task")]
+    MissingTask,
+    #[error("Could not convert the payload of the task: {}", .0)]
+    Payload(#[source] serde_json::Error),
 }
+impl ClientError {
+    /// The error of an unsuccessful `response`, as described by the server
+    /// unless its body is not an error, i.e. when sent by a proxy.
+    async fn unsuccessful(response: reqwest::Response) -> Self {
+        let status = response.status();
+        match response.json::<Error>().await {
+            Ok(err) => ClientError::api(status, err),
+            Err(_) => ClientError::Unsuccessful(status),
+        }
+    }
+
+    pub(crate) fn api(status: StatusCode, err: Error) -> Self {
+        ClientError::Api {
+            status,
+            code: err.code(),
+            message: err.message,
+        }
+    }
+
+    /// The status code the request failed with, if it got a response.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            ClientError::Unsuccessful(status) | ClientError::Api { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    /// What went wrong, when described by the server.
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            ClientError::Api { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+/// How often `await_result` checks whether the task has finished
+static RESULT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The JSON body of a push of `tasks`, and whether it is gzipped, as it is
+/// when larger than `threshold` bytes.
+fn push_body<T: serde::Serialize>(
+    tasks: &T,
+    threshold: Option<usize>,
+) -> Result<(Vec<u8>, bool), ClientError> {
+    let body = serde_json::to_vec(tasks).map_err(ClientError::Payload)?;
+    match threshold {
+        Some(threshold) if body.len() > threshold => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder
+                .write_all(&body)
+                .expect("writing to a Vec cannot fail");
+            Ok((
+                encoder.finish().expect("writing to a Vec cannot fail"),
+                true,
+            ))
+        }
+        _ => Ok((body, false)),
+    }
+}
+
+/// The URL of `GET /v1/pop` with the given parameters.
+fn pop_url(
+    host: &url::Url,
+    timeout: Option<Duration>,
+    selector: &str,
+    name: Option<&str>,
+    count: Option<usize>,
+    budget: Option<u32>,
+) -> Result<url::Url, url::ParseError> {
+    let mut pop_url = host.join("/v1/pop")?;
+    if let Some(timeout) = timeout {
+        pop_url
+            .query_pairs_mut()
+            .append_pair("timeout", &timeout.as_secs().to_string());
+    }
+    if !selector.is_empty() {
+        pop_url.query_pairs_mut().append_pair("label", selector);
+    }
+    if let Some(name) = name {
+        pop_url.query_pairs_mut().append_pair("name", name);
+    }
+    if let Some(count) = count {
+        pop_url
+            .query_pairs_mut()
+            .append_pair("count", &count.to_string());
+    }
+    if let Some(budget) = budget {
+        pop_url
+            .query_pairs_mut()
+            .append_pair("budget", &budget.to_string());
+    }
+    Ok(pop_url)
+}
+
 impl Client {
     pub fn new(host: url::Url) -> Self {
+        Client::with_client(host, reqwest::Client::new())
+    }
+
+    /// A client sending its requests through `client`, i.e. one configured
+    /// with a proxy or custom TLS settings. Responses are only decompressed
+    /// if `client` has gzip enabled, as it has by default.
+    pub fn with_client(host: url::Url, client: reqwest::Client) -> Self {
         Client {
             host,
-            client: reqwest::Client::new(),
+            client,
+            max_retries: 0,
+            backoff: Duration::ZERO,
+            max_pop_attempts: None,
+            compress_push: None,
+        }
+    }
+
+    pub fn builder(host: url::Url) -> ClientBuilder {
+        ClientBuilder::new(host)
+    }
+
+    /// Sends a request whose effect does not change when repeated, retrying it
+    /// with an exponential backoff when the server cannot be reached.
+    async fn send_idempotent(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, ClientError> {
+        self.send_retrying(request, true).await
+    }
+
+    /// Sends a request, retrying it up to `max_retries` times when the server
+    /// refuses it with `429 Too Many Requests`, after the `Retry-After` it
+    /// sends along or else the backoff. Since nothing was done, any request
+    /// can be retried then, while only the `idempotent` ones are retried when
+    /// the server cannot be reached, as they might have gone through.
+    async fn send_retrying(
+        &self,
+        request: reqwest::RequestBuilder,
+        idempotent: bool,
+    ) -> Result<reqwest::Response, ClientError> {
+        let mut retry = 0;
+        loop {
+            let response = request
+                .try_clone()
+                .expect("requests without a streaming body can be cloned")
+                .send()
+                .await;
+            let backoff = self.backoff.saturating_mul(1 << retry.min(16));
+            match response {
+                Err(e)
+                    if idempotent
+                        && retry < self.max_retries
+                        && (e.is_connect() || e.is_timeout()) =>
+                {
+                    tokio::time::sleep(backoff).await;
+                    retry += 1;
+                }
+                Ok(response)
+                    if response.status() == StatusCode::TOO_MANY_REQUESTS
+                        && retry < self.max_retries =>
+                {
+                    let retry_after = response
+                        .headers()
+                        .get(RETRY_AFTER)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| value.parse().ok())
+                        .map_or(backoff, Duration::from_secs);
+                    tokio::time::sleep(retry_after).await;
+                    retry += 1;
+                }
+                response => return Ok(response?),
+            }
         }
     }
 
-    pub async fn push<N, K>(&self, task: &[InsertTask<N>]) -> Result<Vec<Task<N, K>>, ClientError>
+    /// Pushes a single task.
+    pub async fn push<N>(&self, task: &InsertTask<N>) -> Result<Task<N>, ClientError>
+    where
+        N: serde::Serialize + for<'a> serde::Deserialize<'a>,
+    {
+        self.push_many(std::slice::from_ref(task))
+            .await?
+            .pop()
+            .ok_or(ClientError::MissingTask)
+    }
+
+    /// Pushes a batch of tasks, returning them in the same order. The batch
+    /// is atomic: if any of the tasks is rejected, i.e. because one of its
+    /// dependencies is missing, none of them is pushed. When all the tasks
+    /// have an idempotency key the push is retried like the other idempotent
+    /// requests.
+    pub async fn push_many<N>(&self, tasks: &[InsertTask<N>]) -> Result<Vec<Task<N>>, ClientError>
     where
         N: serde::Serialize + for<'a> serde::Deserialize<'a>,
-        K: for<'a> serde::Deserialize<'a>,
     {
         let push_url = self.host.join("/v1/push")?;
-        Ok(self
+        let (body, gzipped) = push_body(&tasks, self.compress_push)?;
+        let mut request = self
             .client
-            .put(push_url.clone())
-            .json(task)
-            .send()
+            .put(push_url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body);
+        if gzipped {
+            request = request.header(CONTENT_ENCODING, "gzip");
+        }
+        let request = telemetry::inject(request);
+        let idempotent = tasks.iter().all(|task| task.idempotency_key.is_some());
+        let response = self.send_retrying(request, idempotent).await?;
+        if response.status().is_success() {
+            Ok(response.json().await?)
+        } else {
+            Err(ClientError::unsuccessful(response).await)
+        }
+    }
+
+    /// Checks a batch of tasks as `push_many` would, without pushing any of
+    /// them, and returns the keys they would be given.
+    pub async fn dry_run<N>(&self, tasks: &[InsertTask<N>]) -> Result<Vec<TaskId>, ClientError>
+    where
+        N: serde::Serialize,
+    {
+        let push_url = self.host.join("/v1/push?dry_run=true")?;
+        let request = self.client.put(push_url).json(&tasks);
+        let response = telemetry::inject(request).send().await?;
+        if response.status().is_success() {
+            Ok(response.json().await?)
+        } else {
+            Err(ClientError::unsuccessful(response).await)
+        }
+    }
+
+    /// Waits for a task to be ready and pops it. When a `timeout` is given
+    /// the server gives up after it, and `None` is returned.
+    pub async fn pop<N>(
+        &self,
+        timeout: Option<Duration>,
+    ) -> Result<Option<Execution<Task<N>>>, ClientError>
+    where
+        N: for<'a> serde::Deserialize<'a>,
+    {
+        self.pop_matching(timeout, "").await
+    }
+
+    /// Pops a task if one is ready, returning `None` right away otherwise,
+    /// for the callers which poll rather than wait.
+    pub async fn try_pop<N>(&self) -> Result<Option<Execution<Task<N>>>, ClientError>
+    where
+        N: for<'a> serde::Deserialize<'a>,
+    {
+        let mut pop_url = pop_url(&self.host, None, "", None, None, None)?;
+        pop_url.query_pairs_mut().append_pair("block", "false");
+        let response = telemetry::inject(self.client.get(pop_url)).send().await?;
+        match response.status() {
+            StatusCode::NO_CONTENT => Ok(None),
+            status if status.is_success() => Ok(Some(response.json().await?)),
+            _ => Err(ClientError::unsuccessful(response).await),
+        }
+    }
+
+    /// Like `pop`, but only for the tasks whose labels match `selector`: a
+    /// comma separated list of either `name`, for the tasks having the label,
+    /// or `name=value`, for the tasks where the label has that value.
+    pub async fn pop_matching<N>(
+        &self,
+        timeout: Option<Duration>,
+        selector: &str,
+    ) -> Result<Option<Execution<Task<N>>>, ClientError>
+    where
+        N: for<'a> serde::Deserialize<'a>,
+    {
+        self.send_pop(timeout, selector, None, None, None).await
+    }
+
+    /// Like `pop`, but only for the tasks named `name`, for the workers which
+    /// can only handle some of the tasks.
+    pub async fn pop_named<N>(
+        &self,
+        timeout: Option<Duration>,
+        name: &str,
+    ) -> Result<Option<Execution<Task<N>>>, ClientError>
+    where
+        N: for<'a> serde::Deserialize<'a>,
+    {
+        self.send_pop(timeout, "", Some(name), None, None).await
+    }
+
+    /// Like `pop`, but pops up to `count` tasks at once: it waits for one to
+    /// be ready, and then pops as many others as are ready right away. An
+    /// empty list is returned when the `timeout` expires.
+    pub async fn pop_many<N>(
+        &self,
+        timeout: Option<Duration>,
+        count: usize,
+    ) -> Result<Vec<Execution<Task<N>>>, ClientError>
+    where
+        N: for<'a> serde::Deserialize<'a>,
+    {
+        Ok(self
+            .send_pop(timeout, "", None, Some(count), None)
             .await?
-            .json()
-            .await?)
+            .unwrap_or_default())
     }
 
-    pub async fn pop<N, K>(&self) -> Result<Execution<Task<N, K>>, ClientError>
+    /// Like `pop_many`, but pops as many tasks as fit in `budget` by their
+    /// cost, along with their total cost. The first task is popped whatever
+    /// its cost. `None` is returned when the `timeout` expires.
+    pub async fn pop_budget<N>(
+        &self,
+        timeout: Option<Duration>,
+        budget: u32,
+    ) -> Result<Option<Lease<Execution<Task<N>>>>, ClientError>
     where
         N: for<'a> serde::Deserialize<'a>,
-        K: for<'a> serde::Deserialize<'a>,
     {
-        let pop_url = self.host.join("/v1/pop")?;
+        self.send_pop(timeout, "", None, None, Some(budget)).await
+    }
+
+    /// Sends `GET /v1/pop`, retrying it on timeout up to `max_pop_attempts`,
+    /// and returns `None` when the server gives up waiting for a task.
+    async fn send_pop<T>(
+        &self,
+        timeout: Option<Duration>,
+        selector: &str,
+        name: Option<&str>,
+        count: Option<usize>,
+        budget: Option<u32>,
+    ) -> Result<Option<T>, ClientError>
+    where
+        T: for<'a> serde::Deserialize<'a>,
+    {
+        let pop_url = pop_url(&self.host, timeout, selector, name, count, budget)?;
+        let mut attempts = 0;
         loop {
-            let response = self.client.get(pop_url.clone()).send().await;
+            attempts += 1;
+            let response = telemetry::inject(self.client.get(pop_url.clone()))
+                .send()
+                .await;
             match response {
                 Err(e) => {
-                    if !e.is_timeout() {
+                    let exhausted = self.max_pop_attempts.is_some_and(|max| attempts >= max);
+                    if !e.is_timeout() || exhausted {
                         return Err(e.into());
                     }
                 }
-                Ok(response) => return Ok(response.json().await?),
+                Ok(response) if response.status() == StatusCode::NO_CONTENT => return Ok(None),
+                Ok(response) if !response.status().is_success() => {
+                    return Err(ClientError::unsuccessful(response).await)
+                }
+                Ok(response) => return Ok(Some(response.json().await?)),
             }
         }
     }
 
-    pub async fn complete<K: serde::Serialize>(&self, task_id: K) -> Result<(), ClientError> {
+    /// Completes a task, with the `lease_token` of the execution it was
+    /// popped with. Completing a task popped by another worker, i.e. after it
+    /// timed out, fails with `ErrorCode::LeaseMismatch`.
+    pub async fn complete(&self, task_id: &TaskId, lease_token: Uuid) -> Result<(), ClientError> {
+        self.complete_with_result(task_id, lease_token, None).await
+    }
+
+    /// Completes a task, handing its `result` over to the tasks depending on
+    /// it, which can look it up with `dependency_results`.
+    pub async fn complete_with_result(
+        &self,
+        task_id: &TaskId,
+        lease_token: Uuid,
+        result: Option<serde_json::Value>,
+    ) -> Result<(), ClientError> {
         let complete_url = self.host.join("/v1/complete")?;
+        let response =
+            telemetry::inject(self.client.post(complete_url.clone()).json(&CompleteTask {
+                id: Some(task_id),
+                idempotency_key: None,
+                lease_token: Some(lease_token),
+                result,
+            }))
+            .send()
+            .await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ClientError::unsuccessful(response).await)
+        }
+    }
+
+    /// Completes the task pushed with the idempotency key `key`, for the
+    /// workers which do not keep the id of the task they are processing.
+    pub async fn complete_by_idempotency_key(
+        &self,
+        key: &str,
+        lease_token: Uuid,
+        result: Option<serde_json::Value>,
+    ) -> Result<(), ClientError> {
+        let complete_url = self.host.join("/v1/complete")?;
+        let response = telemetry::inject(self.client.post(complete_url).json(&CompleteTask::<
+            TaskId,
+        > {
+            id: None,
+            idempotency_key: Some(key.to_string()),
+            lease_token: Some(lease_token),
+            result,
+        }))
+        .send()
+        .await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ClientError::unsuccessful(response).await)
+        }
+    }
+
+    /// Completes a task and pushes `tasks` as a whole, so that the tasks
+    /// following it are not lost if the worker crashes in between. They can
+    /// depend on the completed task, which is ended with the `lease_token` of
+    /// its execution like in `complete`.
+    pub async fn complete_and_push<N>(
+        &self,
+        task_id: &TaskId,
+        lease_token: Uuid,
+        result: Option<serde_json::Value>,
+        tasks: &[InsertTask<N>],
+    ) -> Result<Vec<Task<N>>, ClientError>
+    where
+        N: serde::Serialize + for<'a> serde::Deserialize<'a> + Clone,
+    {
+        let url = self.host.join("/v1/complete-and-push")?;
+        let response = telemetry::inject(self.client.post(url).json(&CompleteAndPush {
+            id: task_id.clone(),
+            lease_token: Some(lease_token),
+            result,
+            tasks: tasks.to_vec(),
+        }))
+        .send()
+        .await?;
+        if response.status().is_success() {
+            Ok(response.json().await?)
+        } else {
+            Err(ClientError::unsuccessful(response).await)
+        }
+    }
+
+    /// Completes each of the given tasks, along with the lease token of its
+    /// execution, independently, and reports which ones could not be
+    /// completed.
+    pub async fn complete_many(
+        &self,
+        tasks: Vec<(TaskId, Uuid)>,
+    ) -> Result<Vec<Completion>, ClientError> {
+        let complete_url = self.host.join("/v1/complete-batch")?;
+        let (ids, lease_tokens) = tasks.into_iter().unzip();
+        let response = telemetry::inject(
+            self.client
+                .post(complete_url)
+                .json(&CompleteBatch { ids, lease_tokens }),
+        )
+        .send()
+        .await?;
+        if response.status().is_success() {
+            Ok(response.json().await?)
+        } else {
+            Err(ClientError::unsuccessful(response).await)
+        }
+    }
+
+    /// Fails a task, with the `lease_token` of the execution it was popped
+    /// with, like `complete`.
+    pub async fn fail(
+        &self,
+        task_id: &TaskId,
+        lease_token: Uuid,
+        reason: Option<String>,
+    ) -> Result<(), ClientError> {
+        let fail_url = self.host.join("/v1/fail")?;
+        let response = telemetry::inject(self.client.post(fail_url.clone()).json(&FailTask {
+            id: task_id,
+            lease_token: Some(lease_token),
+            reason,
+        }))
+        .send()
+        .await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ClientError::unsuccessful(response).await)
+        }
+    }
+
+    /// Puts a task being processed back on the queue right away, as if it
+    /// timed out, to reclaim it from a worker known to be dead.
+    pub async fn requeue(&self, task_id: &TaskId) -> Result<(), ClientError> {
+        let requeue_url = self.host.join(&format!("/v1/task/{}/requeue", task_id))?;
+        let response = telemetry::inject(self.client.post(requeue_url))
+            .send()
+            .await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ClientError::unsuccessful(response).await)
+        }
+    }
+
+    /// Moves the deadline of a task to `extend` from now, with the
+    /// `lease_token` of the execution it was popped with, like `complete`.
+    pub async fn heartbeat(
+        &self,
+        task_id: &TaskId,
+        lease_token: Uuid,
+        extend: time::Duration,
+    ) -> Result<time::OffsetDateTime, ClientError> {
+        let heartbeat_url = self.host.join("/v1/heartbeat")?;
         let response = self
+            .send_idempotent(self.client.post(heartbeat_url.clone()).json(&Heartbeat {
+                id: task_id,
+                lease_token: Some(lease_token),
+                extend,
+            }))
+            .await?;
+        if response.status().is_success() {
+            let Deadline { deadline } = response.json().await?;
+            Ok(deadline)
+        } else {
+            Err(ClientError::unsuccessful(response).await)
+        }
+    }
+
+    /// Reports how far a task got, in percent, and moves its deadline to leave
+    /// it the share of its duration matching the progress left when `extend`
+    /// is set. Returns the new deadline, if it was moved. The `lease_token`
+    /// of the execution is required like in `complete`.
+    pub async fn progress(
+        &self,
+        task_id: &TaskId,
+        lease_token: Uuid,
+        percent: u8,
+        extend: bool,
+    ) -> Result<Option<time::OffsetDateTime>, ClientError> {
+        let progress_url = self.host.join("/v1/progress")?;
+        let response = self
+            .send_idempotent(self.client.post(progress_url).json(&ReportProgress {
+                id: task_id,
+                lease_token: Some(lease_token),
+                percent,
+                extend,
+            }))
+            .await?;
+        if response.status().is_success() {
+            let Progress { deadline, .. } = response.json().await?;
+            Ok(deadline)
+        } else {
+            Err(ClientError::unsuccessful(response).await)
+        }
+    }
+
+    /// Counts the tasks in each state.
+    pub async fn stats(&self) -> Result<Stats, ClientError> {
+        let stats_url = self.host.join("/v1/stats")?;
+        let response = self.send_idempotent(self.client.get(stats_url)).await?;
+        if response.status().is_success() {
+            Ok(response.json().await?)
+        } else {
+            Err(ClientError::unsuccessful(response).await)
+        }
+    }
+
+    /// The build of the server, and the backend of its store.
+    pub async fn server_version(&self) -> Result<Version, ClientError> {
+        let version_url = self.host.join("/v1/version")?;
+        let response = self.send_idempotent(self.client.get(version_url)).await?;
+        if response.status().is_success() {
+            Ok(response.json().await?)
+        } else {
+            Err(ClientError::unsuccessful(response).await)
+        }
+    }
+
+    /// Sets the JSON Schema the payloads of the tasks named `name` have to
+    /// match for them to be pushed.
+    pub async fn register_schema(
+        &self,
+        name: &str,
+        schema: &serde_json::Value,
+    ) -> Result<(), ClientError> {
+        let schema_url = self.host.join("/v1/schemas/")?.join(name)?;
+        let response = self
+            .send_idempotent(self.client.post(schema_url).json(schema))
+            .await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ClientError::unsuccessful(response).await)
+        }
+    }
+
+    /// Removes every task from the server, whatever its state.
+    pub async fn purge(&self) -> Result<(), ClientError> {
+        let purge_url = self.host.join("/v1/admin/purge")?;
+        let response = self.send_idempotent(self.client.post(purge_url)).await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ClientError::unsuccessful(response).await)
+        }
+    }
+
+    /// Looks up a task, along with its current status.
+    pub async fn get<N>(&self, task_id: &TaskId) -> Result<Task<N>, ClientError>
+    where
+        N: for<'a> serde::Deserialize<'a>,
+    {
+        let get_url = self.host.join(&format!("/v1/task/{}", task_id))?;
+        let response = self.send_idempotent(self.client.get(get_url)).await?;
+        if response.status().is_success() {
+            Ok(response.json().await?)
+        } else {
+            Err(ClientError::unsuccessful(response).await)
+        }
+    }
+
+    /// Looks up the results the dependencies of a task were completed with.
+    pub async fn dependency_results(
+        &self,
+        task_id: &TaskId,
+    ) -> Result<Vec<DependencyResult>, ClientError> {
+        let results_url = self
+            .host
+            .join(&format!("/v1/task/{}/deps-results", task_id))?;
+        let response = self.send_idempotent(self.client.get(results_url)).await?;
+        if response.status().is_success() {
+            Ok(response.json().await?)
+        } else {
+            Err(ClientError::unsuccessful(response).await)
+        }
+    }
+
+    /// Waits for a task to be completed or to fail, and returns its outcome.
+    /// The server is polled until then, or until `timeout` elapses and `None`
+    /// is returned. The server only retains the outcome of the tasks for a
+    /// while, after which this fails with a 404.
+    pub async fn await_result(
+        &self,
+        task_id: &TaskId,
+        timeout: Duration,
+    ) -> Result<Option<TaskResult>, ClientError> {
+        let result_url = self.host.join(&format!("/v1/task/{}/result", task_id))?;
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let response = self
+                .send_idempotent(self.client.get(result_url.clone()))
+                .await?;
+            match response.status() {
+                status if status.is_success() => return Ok(Some(response.json().await?)),
+                // The task has not finished yet
+                StatusCode::CONFLICT => {}
+                _ => return Err(ClientError::unsuccessful(response).await),
+            }
+            if tokio::time::Instant::now() + RESULT_POLL_INTERVAL > deadline {
+                return Ok(None);
+            }
+            tokio::time::sleep(RESULT_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Waits for a specific task to be ready, completed or failed, without
+    /// popping it, and returns the status it reached. When a `timeout` is
+    /// given the server gives up after it, and `None` is returned.
+    pub async fn await_task<N>(
+        &self,
+        task_id: &TaskId,
+        timeout: Option<Duration>,
+    ) -> Result<Option<AwaitedTask<N>>, ClientError>
+    where
+        N: for<'a> serde::Deserialize<'a>,
+    {
+        let mut await_url = self.host.join(&format!("/v1/task/{}/await", task_id))?;
+        if let Some(timeout) = timeout {
+            await_url
+                .query_pairs_mut()
+                .append_pair("timeout", &timeout.as_secs().to_string());
+        }
+        let response = self.send_idempotent(self.client.get(await_url)).await?;
+        match response.status() {
+            StatusCode::NO_CONTENT => Ok(None),
+            status if status.is_success() => Ok(Some(response.json().await?)),
+            _ => Err(ClientError::unsuccessful(response).await),
+        }
+    }
+
+    /// Waits for every one of the given tasks to be completed or failed, and
+    /// returns the status each reached, in the same order. When a `timeout`
+    /// is given the server gives up after it, and `None` is returned.
+    pub async fn wait<N>(
+        &self,
+        task_ids: Vec<TaskId>,
+        timeout: Option<Duration>,
+    ) -> Result<Option<Vec<AwaitedTask<N>>>, ClientError>
+    where
+        N: for<'a> serde::Deserialize<'a>,
+    {
+        let mut wait_url = self.host.join("/v1/wait")?;
+        if let Some(timeout) = timeout {
+            wait_url
+                .query_pairs_mut()
+                .append_pair("timeout", &timeout.as_secs().to_string());
+        }
+        let request = self
             .client
-            .post(complete_url.clone())
-            .json(&CompleteTask { id: task_id })
-            .send()
+            .post(wait_url)
+            .json(&WaitTasks { ids: task_ids });
+        let response = self.send_idempotent(request).await?;
+        match response.status() {
+            StatusCode::NO_CONTENT => Ok(None),
+            status if status.is_success() => Ok(Some(response.json().await?)),
+            _ => Err(ClientError::unsuccessful(response).await),
+        }
+    }
+
+    /// Lists the keys of the tasks still waiting for a task to be completed.
+    pub async fn dependents(&self, task_id: &TaskId) -> Result<Vec<TaskId>, ClientError> {
+        let dependents_url = self
+            .host
+            .join(&format!("/v1/task/{}/dependents", task_id))?;
+        let response = self
+            .send_idempotent(self.client.get(dependents_url))
+            .await?;
+        if response.status().is_success() {
+            Ok(response.json().await?)
+        } else {
+            Err(ClientError::unsuccessful(response).await)
+        }
+    }
+
+    /// Lists up to `limit` tasks, skipping the first `offset`, in the order of
+    /// their keys, along with how many there are in all. The server caps the
+    /// `limit`, so that pages may be shorter than asked for.
+    pub async fn list<N>(
+        &self,
+        offset: usize,
+        limit: usize,
+        status: Option<Status>,
+    ) -> Result<TaskPage<Task<N>>, ClientError>
+    where
+        N: for<'a> serde::Deserialize<'a>,
+    {
+        let mut list_url = self.host.join("/v1/tasks")?;
+        list_url
+            .query_pairs_mut()
+            .append_pair("offset", &offset.to_string())
+            .append_pair("limit", &limit.to_string());
+        if let Some(status) = status {
+            list_url
+                .query_pairs_mut()
+                .append_pair("status", status.as_str());
+        }
+        let response = self.send_idempotent(self.client.get(list_url)).await?;
+        if response.status().is_success() {
+            Ok(response.json().await?)
+        } else {
+            Err(ClientError::unsuccessful(response).await)
+        }
+    }
+
+    /// Looks up the task which would be popped next, if any is ready, without
+    /// popping it.
+    pub async fn peek<N>(&self) -> Result<Option<Task<N>>, ClientError>
+    where
+        N: for<'a> serde::Deserialize<'a>,
+    {
+        let peek_url = self.host.join("/v1/peek")?;
+        let response = self.send_idempotent(self.client.get(peek_url)).await?;
+        if response.status() == StatusCode::NO_CONTENT {
+            Ok(None)
+        } else if response.status().is_success() {
+            Ok(Some(response.json().await?))
+        } else {
+            Err(ClientError::unsuccessful(response).await)
+        }
+    }
+
+    /// Exports the tasks and their pending dependencies as a Graphviz DOT
+    /// document, to be rendered with i.e. `dot -Tsvg`.
+    pub async fn graph(&self) -> Result<String, ClientError> {
+        let graph_url = self.host.join("/v1/graph?format=dot")?;
+        let response = self.send_idempotent(self.client.get(graph_url)).await?;
+        if response.status().is_success() {
+            Ok(response.text().await?)
+        } else {
+            Err(ClientError::unsuccessful(response).await)
+        }
+    }
+
+    pub async fn cancel(&self, task_id: &TaskId) -> Result<(), ClientError> {
+        let cancel_url = self.host.join(&format!("/v1/task/{}", task_id))?;
+        let response = self.send_idempotent(self.client.delete(cancel_url)).await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ClientError::unsuccessful(response).await)
+        }
+    }
+
+    /// Changes a task which has not been popped yet, and returns it.
+    pub async fn update<N>(
+        &self,
+        task_id: &TaskId,
+        patch: &TaskPatch,
+    ) -> Result<Task<N>, ClientError>
+    where
+        N: for<'a> serde::Deserialize<'a>,
+    {
+        let update_url = self.host.join(&format!("/v1/task/{}", task_id))?;
+        let response = self
+            .send_idempotent(self.client.patch(update_url).json(patch))
+            .await?;
+        if response.status().is_success() {
+            Ok(response.json().await?)
+        } else {
+            Err(ClientError::unsuccessful(response).await)
+        }
+    }
+
+    /// Lists the recurring tasks, along with their current instance.
+    pub async fn recurring<N>(&self) -> Result<Vec<Recurring<Task<N>>>, ClientError>
+    where
+        N: for<'a> serde::Deserialize<'a>,
+    {
+        let recurring_url = self.host.join("/v1/recurring")?;
+        let response = self.send_idempotent(self.client.get(recurring_url)).await?;
+        if response.status().is_success() {
+            Ok(response.json().await?)
+        } else {
+            Err(ClientError::unsuccessful(response).await)
+        }
+    }
+
+    /// Stops a recurring task from being pushed again, cancelling its current
+    /// instance unless it is being processed.
+    pub async fn delete_recurring(&self, id: &TaskId) -> Result<(), ClientError> {
+        let recurring_url = self.host.join(&format!("/v1/recurring/{}", id))?;
+        let response = self
+            .send_idempotent(self.client.delete(recurring_url))
             .await?;
         if response.status().is_success() {
             Ok(())
         } else {
-            Err(ClientError::Unsuccessful(response.status()))
+            Err(ClientError::unsuccessful(response).await)
         }
     }
 }
@@ -0,0 +1,47 @@
+//! W3C trace context propagation: the requests carry the trace context of the
+//! current span, and the popped tasks carry the one they were pushed in.
+
+use std::fmt::Display;
+
+use opentelemetry::{propagation::TextMapPropagator, trace::TraceContextExt};
+use opentelemetry_http::{HeaderExtractor, HeaderInjector};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use reqwest::header::{HeaderMap, HeaderValue};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::Task;
+
+static TRACEPARENT: &str = "traceparent";
+
+/// The `traceparent` header of the current span, when it is traced with
+/// OpenTelemetry.
+pub(crate) fn headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let context = Span::current().context();
+    if context.span().span_context().is_valid() {
+        TraceContextPropagator::new().inject_context(&context, &mut HeaderInjector(&mut headers));
+    }
+    headers
+}
+
+/// Adds the `traceparent` header of the current span to the request.
+pub(crate) fn inject(request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    request.headers(headers())
+}
+
+/// A span for handling `task`, continuing the trace it was pushed in. The
+/// requests sent from within it, i.e. to complete the task, carry it along.
+pub fn span<N, K: Display>(task: &Task<N, K>) -> Span {
+    let span = tracing::info_span!("task", id = %task.id);
+    if let Some(header) = task
+        .traceparent
+        .as_deref()
+        .and_then(|traceparent| HeaderValue::from_str(traceparent).ok())
+    {
+        let mut headers = HeaderMap::new();
+        headers.insert(TRACEPARENT, header);
+        span.set_parent(TraceContextPropagator::new().extract(&HeaderExtractor(&headers)));
+    }
+    span
+}
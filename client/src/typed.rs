@@ -0,0 +1,102 @@
+use std::{marker::PhantomData, time::Duration};
+
+use serde::{de::DeserializeOwned, Serialize};
+use time::OffsetDateTime;
+
+use crate::{
+    Client, ClientError, DependencyMode, InsertTask, Task, Uuid, DEFAULT_COST, DEFAULT_DURATION,
+    DEFAULT_MAX_RETRIES,
+};
+
+/// A client for a single kind of task, all pushed with the same name and a
+/// payload of type `P`, which is converted to and from JSON on the way.
+///
+/// Every popped task is expected to have a payload of type `P`, so the queue
+/// (or the labels popped from) should only hold tasks of this kind.
+pub struct TypedClient<P> {
+    client: Client,
+    template: InsertTask<String>,
+    payload: PhantomData<fn(P) -> P>,
+}
+
+/// A popped task, along with its payload.
+#[derive(Clone, Debug)]
+pub struct TypedExecution<P> {
+    pub payload: P,
+    pub task: Task,
+    pub deadline: OffsetDateTime,
+    pub remaining: time::Duration,
+    pub lease_token: Uuid,
+}
+
+impl<P> TypedClient<P>
+where
+    P: Serialize + DeserializeOwned,
+{
+    /// Pushes the tasks named `name`, with the default duration and retries.
+    pub fn new(client: Client, name: impl Into<String>) -> Self {
+        TypedClient::from_template(
+            client,
+            InsertTask {
+                name: name.into(),
+                payload: None,
+                depends_on: vec![],
+                depends_on_batch: vec![],
+                duration: DEFAULT_DURATION,
+                priority: 0,
+                max_retries: DEFAULT_MAX_RETRIES,
+                cost: DEFAULT_COST,
+                labels: Default::default(),
+                tenant: None,
+                run_at: None,
+                expires_at: None,
+                schedule: None,
+                idempotency_key: None,
+                dedupe: false,
+                callback_url: None,
+                dependency_mode: DependencyMode::All,
+                traceparent: None,
+            },
+        )
+    }
+
+    /// Pushes the tasks as copies of `template`, with their own payload.
+    pub fn from_template(client: Client, template: InsertTask<String>) -> Self {
+        TypedClient {
+            client,
+            template,
+            payload: PhantomData,
+        }
+    }
+
+    /// The underlying client, i.e. to complete the popped tasks.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub async fn push(&self, payload: P) -> Result<Task, ClientError> {
+        let task = InsertTask {
+            payload: Some(serde_json::to_value(payload).map_err(ClientError::Payload)?),
+            ..self.template.clone()
+        };
+        self.client.push(&task).await
+    }
+
+    /// Waits for a task to be ready and pops it, like `Client::pop`.
+    pub async fn pop(
+        &self,
+        timeout: Option<Duration>,
+    ) -> Result<Option<TypedExecution<P>>, ClientError> {
+        let Some(execution) = self.client.pop::<String>(timeout).await? else {
+            return Ok(None);
+        };
+        let payload = execution.task.payload.clone().unwrap_or_default();
+        Ok(Some(TypedExecution {
+            payload: serde_json::from_value(payload).map_err(ClientError::Payload)?,
+            task: execution.task,
+            deadline: execution.deadline,
+            remaining: execution.remaining,
+            lease_token: execution.lease_token,
+        }))
+    }
+}
@@ -0,0 +1,106 @@
+use std::{fmt::Display, future::Future, sync::Arc};
+
+use tokio::{signal::ctrl_c, sync::Semaphore};
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+
+use crate::{telemetry, Client, ClientError, Task};
+
+/// Runs a handler on every task popped from the queue, completing the tasks
+/// it succeeds on and failing the others. Each task is handled in a span
+/// continuing the trace it was pushed in.
+pub struct Worker {
+    client: Client,
+    concurrency: usize,
+    selector: String,
+    shutdown: CancellationToken,
+}
+
+impl Worker {
+    pub fn new(client: Client) -> Self {
+        Worker {
+            client,
+            concurrency: 1,
+            selector: String::new(),
+            shutdown: CancellationToken::new(),
+        }
+    }
+
+    /// Sets how many tasks are handled at the same time.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Only handles the tasks whose labels match `selector`, see
+    /// `Client::pop_matching`.
+    pub fn labels(mut self, selector: impl Into<String>) -> Self {
+        self.selector = selector.into();
+        self
+    }
+
+    /// A token which stops `run` when cancelled. It is also cancelled on
+    /// Ctrl-C.
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    /// Pops tasks and hands them to `handler` until shutdown, then waits for
+    /// the tasks being handled to finish.
+    pub async fn run<N, F, Fut, E>(&self, handler: F) -> Result<(), ClientError>
+    where
+        N: for<'a> serde::Deserialize<'a> + Send + 'static,
+        F: Fn(Task<N>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Display,
+    {
+        let shutdown = self.shutdown.clone();
+        let signal = tokio::spawn(async move {
+            if ctrl_c().await.is_ok() {
+                shutdown.cancel();
+            }
+        });
+
+        let handler = Arc::new(handler);
+        let semaphore = Arc::new(Semaphore::new(self.concurrency));
+        let result = loop {
+            let permit = tokio::select! {
+                _ = self.shutdown.cancelled() => break Ok(()),
+                permit = semaphore.clone().acquire_owned() => permit.expect("the semaphore is never closed"),
+            };
+            let execution = tokio::select! {
+                _ = self.shutdown.cancelled() => break Ok(()),
+                execution = self.client.pop_matching::<N>(None, &self.selector) => match execution {
+                    Ok(Some(execution)) => execution,
+                    Ok(None) => continue,
+                    Err(err) => break Err(err),
+                },
+            };
+
+            let client = self.client.clone();
+            let handler = handler.clone();
+            let span = telemetry::span(&execution.task);
+            tokio::spawn(
+                async move {
+                    let _permit = permit;
+                    let id = execution.task.id.clone();
+                    let lease_token = execution.lease_token;
+                    let outcome = handler(execution.task).await.map_err(|err| err.to_string());
+                    let result = match outcome {
+                        Ok(()) => client.complete(&id, lease_token).await,
+                        Err(reason) => client.fail(&id, lease_token, Some(reason)).await,
+                    };
+                    if let Err(err) = result {
+                        tracing::warn!(%id, %err, "Could not report the outcome of the task");
+                    }
+                }
+                .instrument(span),
+            );
+        };
+        signal.abort();
+
+        // Wait for the tasks being handled
+        let _ = semaphore.acquire_many(self.concurrency as u32).await;
+        result
+    }
+}
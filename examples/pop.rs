@@ -0,0 +1,103 @@
+//! Pushes tasks onto a `MemoryStore` and has many concurrent workers pop and
+//! complete them, reporting the throughput. Useful to measure the contention
+//! of the store's locks, without the cost of handling the requests.
+//!
+//! ```sh
+//! cargo run --release --example pop -- 1000 100000
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use taskie::{
+    store::{InsertTask, Selector, Store},
+    stores::mem::MemoryStore,
+};
+use taskie_structures::DependencyMode;
+
+fn insert_task(i: usize) -> InsertTask {
+    InsertTask(taskie_structures::InsertTask {
+        name: format!("pop-{}", i),
+        payload: None,
+        depends_on: vec![],
+        depends_on_batch: vec![],
+        duration: time::Duration::seconds(60),
+        priority: 0,
+        max_retries: 0,
+        cost: 1,
+        labels: BTreeMap::new(),
+        tenant: None,
+        run_at: None,
+        expires_at: None,
+        schedule: None,
+        idempotency_key: None,
+        dedupe: false,
+        callback_url: None,
+        dependency_mode: DependencyMode::All,
+        traceparent: None,
+    })
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let workers: usize = args.next().map_or(Ok(1000), |s| s.parse())?;
+    let count: usize = args.next().map_or(Ok(100_000), |s| s.parse())?;
+
+    let store = Arc::new(MemoryStore::new());
+    let monitor = tokio::spawn({
+        let store = store.clone();
+        async move { store.monitor().await }
+    });
+    for chunk in (0..count).collect::<Vec<_>>().chunks(1000) {
+        store
+            .push(chunk.iter().copied().map(insert_task).collect())
+            .await?;
+    }
+
+    // Each worker claims a pop before waiting on the queue, so that none of
+    // them is left waiting once all the tasks are taken
+    let remaining = Arc::new(AtomicUsize::new(count));
+    let start = Instant::now();
+    let handles: Vec<_> = (0..workers)
+        .map(|_| {
+            let store = store.clone();
+            let remaining = remaining.clone();
+            tokio::spawn(async move {
+                while remaining
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+                    .is_ok()
+                {
+                    let execution = store.pop(&Selector::default()).await?.0;
+                    store
+                        .complete(execution.task.0.id, execution.lease_token, None)
+                        .await?;
+                }
+                Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle
+            .await?
+            .map_err(|err| err as Box<dyn std::error::Error>)?;
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "Popped and completed {} tasks with {} workers in {:?} ({:.0} tasks/s)",
+        count,
+        workers,
+        elapsed,
+        count as f64 / elapsed.as_secs_f64()
+    );
+
+    store.shutdown().await;
+    monitor.await??;
+    Ok(())
+}
@@ -0,0 +1,30 @@
+CREATE SEQUENCE task_keys START WITH 1;
+
+CREATE TABLE tasks (
+    id BIGINT PRIMARY KEY,
+    name TEXT NOT NULL,
+    payload JSONB,
+    depends_on JSONB NOT NULL,
+    duration BIGINT NOT NULL
+);
+
+-- Tasks whose dependencies have all been completed, in FIFO order
+CREATE TABLE queue (
+    position BIGSERIAL PRIMARY KEY,
+    task BIGINT NOT NULL UNIQUE
+);
+
+-- Tasks currently being executed
+CREATE TABLE processing (
+    task BIGINT PRIMARY KEY,
+    deadline TIMESTAMPTZ NOT NULL
+);
+CREATE INDEX processing_deadline ON processing (deadline);
+
+-- Pending dependencies: `task` cannot run until `dependency` is completed
+CREATE TABLE edges (
+    task BIGINT NOT NULL,
+    dependency BIGINT NOT NULL,
+    PRIMARY KEY (task, dependency)
+);
+CREATE INDEX edges_dependency ON edges (dependency);
@@ -0,0 +1,4 @@
+ALTER TABLE tasks ADD COLUMN max_retries BIGINT NOT NULL DEFAULT 3;
+ALTER TABLE tasks ADD COLUMN attempt BIGINT NOT NULL DEFAULT 0;
+-- Whether the task timed out more than `max_retries` times
+ALTER TABLE tasks ADD COLUMN failed BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,2 @@
+-- Why the last execution of a dead-lettered (`failed`) task failed
+ALTER TABLE tasks ADD COLUMN failure_reason TEXT;
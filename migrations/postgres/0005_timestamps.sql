@@ -0,0 +1,2 @@
+ALTER TABLE tasks ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now();
+ALTER TABLE tasks ADD COLUMN started_at TIMESTAMPTZ;
@@ -0,0 +1,2 @@
+ALTER TABLE tasks ADD COLUMN labels JSONB NOT NULL DEFAULT '{}';
+CREATE INDEX tasks_labels ON tasks USING GIN (labels);
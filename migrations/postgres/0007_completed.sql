@@ -0,0 +1,3 @@
+-- How many tasks have been completed, as they are removed once completed. A
+-- sequence does not serialize the transactions completing tasks.
+CREATE SEQUENCE completed_tasks START WITH 1;
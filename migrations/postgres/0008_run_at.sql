@@ -0,0 +1,2 @@
+-- Queued tasks are not popped before then
+ALTER TABLE tasks ADD COLUMN run_at TIMESTAMPTZ;
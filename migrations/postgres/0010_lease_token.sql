@@ -0,0 +1,3 @@
+-- The token handed out with the current execution, which has to be sent back
+-- to end or extend it
+ALTER TABLE processing ADD COLUMN lease_token TEXT;
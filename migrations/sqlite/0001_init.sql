@@ -0,0 +1,34 @@
+CREATE TABLE counters (
+    name TEXT PRIMARY KEY NOT NULL,
+    value INTEGER NOT NULL
+);
+INSERT INTO counters (name, value) VALUES ('next_key', 1);
+
+CREATE TABLE tasks (
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    payload TEXT,
+    depends_on TEXT NOT NULL,
+    duration INTEGER NOT NULL
+);
+
+-- Tasks whose dependencies have all been completed, in FIFO order
+CREATE TABLE queue (
+    position INTEGER PRIMARY KEY AUTOINCREMENT,
+    task INTEGER NOT NULL UNIQUE REFERENCES tasks (id)
+);
+
+-- Tasks currently being executed, with their deadline in unix milliseconds
+CREATE TABLE processing (
+    task INTEGER PRIMARY KEY NOT NULL REFERENCES tasks (id),
+    deadline INTEGER NOT NULL
+);
+CREATE INDEX processing_deadline ON processing (deadline);
+
+-- Pending dependencies: `task` cannot run until `dependency` is completed
+CREATE TABLE edges (
+    task INTEGER NOT NULL REFERENCES tasks (id),
+    dependency INTEGER NOT NULL REFERENCES tasks (id),
+    PRIMARY KEY (task, dependency)
+);
+CREATE INDEX edges_dependency ON edges (dependency);
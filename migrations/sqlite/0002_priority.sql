@@ -0,0 +1,5 @@
+ALTER TABLE tasks ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
+
+-- The priority is copied on the queue, so that it can be ordered by an index
+ALTER TABLE queue ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
+CREATE INDEX queue_priority ON queue (priority DESC, position);
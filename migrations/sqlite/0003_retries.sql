@@ -0,0 +1,4 @@
+ALTER TABLE tasks ADD COLUMN max_retries INTEGER NOT NULL DEFAULT 3;
+ALTER TABLE tasks ADD COLUMN attempt INTEGER NOT NULL DEFAULT 0;
+-- Whether the task timed out more than `max_retries` times
+ALTER TABLE tasks ADD COLUMN failed INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+-- In unix milliseconds, like the deadlines; tasks pushed before this
+-- migration are considered created at the epoch
+ALTER TABLE tasks ADD COLUMN created_at INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE tasks ADD COLUMN started_at INTEGER;
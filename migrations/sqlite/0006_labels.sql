@@ -0,0 +1,2 @@
+-- A JSON object from label name to value
+ALTER TABLE tasks ADD COLUMN labels TEXT NOT NULL DEFAULT '{}';
@@ -0,0 +1,2 @@
+-- How many tasks have been completed, as they are removed once completed
+INSERT INTO counters (name, value) VALUES ('completed', 0);
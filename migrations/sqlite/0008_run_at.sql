@@ -0,0 +1,2 @@
+-- In unix milliseconds: queued tasks are not popped before then
+ALTER TABLE tasks ADD COLUMN run_at INTEGER;
@@ -0,0 +1,2 @@
+-- The W3C trace context the task was pushed in
+ALTER TABLE tasks ADD COLUMN traceparent TEXT;
@@ -1,22 +1,46 @@
 use axum::{
     async_trait,
+    body::Bytes,
     body::HttpBody,
-    extract::{rejection::JsonRejection, FromRequest, Json as AxumJson},
-    http::{Request, StatusCode},
+    extract::{
+        rejection::{BytesRejection, JsonRejection, QueryRejection},
+        FromRequest, Json as AxumJson,
+    },
+    http::{
+        header::{ACCEPT, CONTENT_TYPE, RETRY_AFTER},
+        HeaderMap, HeaderValue, Request, StatusCode,
+    },
     response::{IntoResponse, Response},
     BoxError,
 };
 use serde::{de::DeserializeOwned, Serialize};
 use thiserror::Error;
 
-use crate::store::{CompleteError, ConcealError, KeyDecodeError, PopError, PushError};
-use taskie_structures::Error as SerializedError;
+use crate::namespaces::NamespaceError;
+use crate::schemas::SchemaError;
+use crate::store::{
+    AwaitError, CancelError, CompleteAndPushError, CompleteError, ConcealError, DeadLetterError,
+    DryRunError, FailError, GetError, GraphError, HeartbeatError, KeyDecodeError, ListError,
+    PeekError, PopError, ProgressError, PurgeError, PushError, RecurringError, RequeueError,
+    ResultsError, SelectorError, StatsError, UpdateError,
+};
+use taskie_structures::{Error as SerializedError, ErrorCode};
+use time::Duration;
 
 #[derive(Error, Debug)]
 pub enum ApiError {
     #[error("Could not parse JSON input {}", .0.body_text())]
     Parse(#[from] JsonRejection),
 
+    #[error("Could not read the request body: {}", .0.body_text())]
+    Body(#[from] BytesRejection),
+
+    #[error("Could not parse CBOR input: {0}")]
+    Cbor(String),
+
+    #[error("Could not parse the query string: {}", .0.body_text())]
+    Query(#[from] QueryRejection),
+
     #[error("Could not parse Task key: {}", .0)]
     KeyDecode(#[from] KeyDecodeError),
 
@@ -26,30 +50,200 @@ pub enum ApiError {
     #[error("Error while pushing a new task: {}", .0)]
     Push(#[from] PushError),
 
+    #[error("The tasks cannot be pushed: {}", .0)]
+    DryRun(#[from] DryRunError),
+
+    #[error("Could not parse the label selector: {}", .0)]
+    Selector(#[from] SelectorError),
+
     #[error("Error while popping from the queue: {}", .0)]
     Pop(#[from] PopError),
 
+    #[error("Error while peeking at the queue: {}", .0)]
+    Peek(#[from] PeekError),
+
     #[error("Error while setting a task as completed: {}", .0)]
     Complete(#[from] CompleteError),
+
+    #[error("Error while completing a task and pushing others: {}", .0)]
+    CompleteAndPush(#[from] CompleteAndPushError),
+
+    #[error("Error while setting a task as failed: {}", .0)]
+    Fail(#[from] FailError),
+
+    #[error("Error while requeueing a task being processed: {}", .0)]
+    Requeue(#[from] RequeueError),
+
+    #[error("Error while extending the deadline of a task: {}", .0)]
+    Heartbeat(#[from] HeartbeatError),
+
+    #[error("Error while accessing the dead-lettered tasks: {}", .0)]
+    DeadLetter(#[from] DeadLetterError),
+
+    #[error("Error while cancelling a task: {}", .0)]
+    Cancel(#[from] CancelError),
+
+    #[error("Error while updating a task: {}", .0)]
+    Update(#[from] UpdateError),
+
+    #[error("Error while looking up a task: {}", .0)]
+    Get(#[from] GetError),
+
+    #[error("Error while listing the tasks: {}", .0)]
+    List(#[from] ListError),
+
+    #[error("Error while counting the tasks: {}", .0)]
+    Stats(#[from] StatsError),
+
+    #[error("Error while purging the tasks: {}", .0)]
+    Purge(#[from] PurgeError),
+
+    #[error("Error while looking up the results of a task: {}", .0)]
+    Results(#[from] ResultsError),
+
+    #[error("Error while awaiting a task: {}", .0)]
+    Await(#[from] AwaitError),
+
+    #[error("Error while reporting the progress of a task: {}", .0)]
+    Progress(#[from] ProgressError),
+
+    #[error("Error while looking up a namespace: {}", .0)]
+    Namespace(#[from] NamespaceError),
+
+    #[error("Error while exporting the dependency graph: {}", .0)]
+    Graph(#[from] GraphError),
+
+    #[error("Error while accessing the recurring tasks: {}", .0)]
+    Recurring(#[from] RecurringError),
+
+    #[error("Error while validating the payload of a task: {}", .0)]
+    Schema(#[from] SchemaError),
+
+    #[error("The payload of a task is {size} bytes, more than the limit of {limit}")]
+    PayloadTooLarge { size: usize, limit: usize },
+
+    #[error("The server is shutting down")]
+    ShuttingDown,
+
+    #[error("Missing or invalid API token")]
+    Unauthorized,
+
+    #[error("Too many requests, retry in {retry_after}")]
+    RateLimited { retry_after: Duration },
 }
 
-impl IntoResponse for ApiError {
-    fn into_response(self) -> Response {
-        let (status, message) = match self {
+impl ApiError {
+    /// The status code and the message reported for the error.
+    pub fn parts(self) -> (StatusCode, String) {
+        match self {
             ApiError::Parse(err) => (err.status(), err.to_string()),
+            ApiError::Body(err) => (err.status(), err.to_string()),
+            ApiError::Cbor(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            ApiError::Query(err) => (err.status(), err.to_string()),
             ApiError::KeyDecode(err) => (err.status(), err.to_string()),
             ApiError::KeyEncode(err) => (err.status(), err.to_string()),
             ApiError::Push(err) => (err.status(), err.to_string()),
+            ApiError::DryRun(err) => (err.status(), err.to_string()),
+            ApiError::Selector(err) => (err.status(), err.to_string()),
             ApiError::Pop(err) => (err.status(), err.to_string()),
-            ApiError::Complete(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
-        };
+            ApiError::Peek(err) => (err.status(), err.to_string()),
+            ApiError::Complete(err) => (err.status(), err.to_string()),
+            ApiError::CompleteAndPush(err) => (err.status(), err.to_string()),
+            ApiError::Fail(err) => (err.status(), err.to_string()),
+            ApiError::Requeue(err) => (err.status(), err.to_string()),
+            ApiError::Heartbeat(err) => (err.status(), err.to_string()),
+            ApiError::DeadLetter(err) => (err.status(), err.to_string()),
+            ApiError::Cancel(err) => (err.status(), err.to_string()),
+            ApiError::Update(err) => (err.status(), err.to_string()),
+            ApiError::Get(err) => (err.status(), err.to_string()),
+            ApiError::List(err) => (err.status(), err.to_string()),
+            ApiError::Stats(err) => (err.status(), err.to_string()),
+            ApiError::Purge(err) => (err.status(), err.to_string()),
+            ApiError::Results(err) => (err.status(), err.to_string()),
+            ApiError::Await(err) => (err.status(), err.to_string()),
+            ApiError::Progress(err) => (err.status(), err.to_string()),
+            ApiError::Namespace(err) => (err.status(), err.to_string()),
+            ApiError::Graph(err) => (err.status(), err.to_string()),
+            ApiError::Recurring(err) => (err.status(), err.to_string()),
+            ApiError::Schema(err) => (err.status(), err.to_string()),
+            ApiError::PayloadTooLarge { .. } => (StatusCode::PAYLOAD_TOO_LARGE, self.to_string()),
+            ApiError::ShuttingDown => (StatusCode::SERVICE_UNAVAILABLE, self.to_string()),
+            ApiError::Unauthorized => (StatusCode::UNAUTHORIZED, self.to_string()),
+            ApiError::RateLimited { .. } => (StatusCode::TOO_MANY_REQUESTS, self.to_string()),
+        }
+    }
+}
 
-        let err = AxumJson(SerializedError {
+impl ApiError {
+    /// The error as reported in the body of the response, along with its
+    /// status. Its code is the specific one of the errors of a push or of a
+    /// task key, and the one matching the status for the others.
+    pub fn serialize(self) -> (StatusCode, SerializedError) {
+        let code = match &self {
+            ApiError::KeyDecode(_) => Some(ErrorCode::InvalidKey),
+            ApiError::Push(err)
+            | ApiError::CompleteAndPush(CompleteAndPushError::Push(err))
+            | ApiError::Update(UpdateError::Invalid(err)) => Some(err.code()),
+            ApiError::DryRun(DryRunError::Invalid(errors)) => {
+                errors.first().map(|(_, err)| err.code())
+            }
+            ApiError::Complete(CompleteError::LeaseMismatch(_))
+            | ApiError::Fail(FailError::LeaseMismatch(_))
+            | ApiError::CompleteAndPush(CompleteAndPushError::Complete(
+                CompleteError::LeaseMismatch(_),
+            ))
+            | ApiError::Heartbeat(HeartbeatError::LeaseMismatch(_))
+            | ApiError::Progress(ProgressError::LeaseMismatch(_)) => Some(ErrorCode::LeaseMismatch),
+            _ => None,
+        };
+        let (status, message) = self.parts();
+        let code = code.unwrap_or(match status {
+            StatusCode::UNAUTHORIZED => ErrorCode::Unauthorized,
+            StatusCode::NOT_FOUND => ErrorCode::NotFound,
+            StatusCode::CONFLICT => ErrorCode::Conflict,
+            StatusCode::PAYLOAD_TOO_LARGE => ErrorCode::PayloadTooLarge,
+            StatusCode::TOO_MANY_REQUESTS => ErrorCode::RateLimited,
+            StatusCode::NOT_IMPLEMENTED => ErrorCode::Unsupported,
+            StatusCode::SERVICE_UNAVAILABLE => ErrorCode::Unavailable,
+            status if status.is_client_error() => ErrorCode::InvalidRequest,
+            _ => ErrorCode::Internal,
+        });
+        let err = SerializedError {
             status: status.as_u16(),
+            code: code.to_string(),
             message,
-        });
+        };
+        (status, err)
+    }
 
-        (status, err).into_response()
+    /// How many seconds the client should wait before retrying, for the
+    /// errors which are only temporary.
+    fn retry_after(&self) -> Option<i64> {
+        let retry_after = match self {
+            ApiError::Push(PushError::QueueFull { retry_after, .. })
+            | ApiError::CompleteAndPush(CompleteAndPushError::Push(PushError::QueueFull {
+                retry_after,
+                ..
+            }))
+            | ApiError::RateLimited { retry_after } => retry_after,
+            _ => return None,
+        };
+        Some(retry_after.as_seconds_f64().ceil() as i64)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let retry_after = self.retry_after();
+        let (status, err) = self.serialize();
+
+        let mut response = (status, AxumJson(err)).into_response();
+        if let Some(seconds) = retry_after {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        response
     }
 }
 
@@ -81,3 +275,107 @@ where
         AxumJson(data).into_response()
     }
 }
+
+/// The media type of the bodies encoded as CBOR.
+pub const CBOR: &str = "application/cbor";
+
+/// How a body is encoded. JSON is the default, while clients can opt into
+/// CBOR with the `Content-Type` of what they send and the `Accept` header for
+/// what they receive.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    Json,
+    Cbor,
+}
+
+impl Encoding {
+    fn parse(media_type: &str) -> Option<Self> {
+        let essence = media_type.split(';').next().unwrap_or_default().trim();
+        if essence.eq_ignore_ascii_case("application/json") {
+            Some(Encoding::Json)
+        } else if essence.eq_ignore_ascii_case(CBOR) {
+            Some(Encoding::Cbor)
+        } else {
+            None
+        }
+    }
+
+    /// The encoding of the request body, as told by its `Content-Type`.
+    pub fn of_content(headers: &HeaderMap) -> Self {
+        headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Encoding::parse)
+            .unwrap_or_default()
+    }
+
+    /// The first supported encoding listed in the `Accept` header, JSON when
+    /// there is none. Quality values are not taken into account.
+    pub fn accepted(headers: &HeaderMap) -> Self {
+        headers
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .find_map(Encoding::parse)
+            .unwrap_or_default()
+    }
+}
+
+/// A request body, decoded according to its `Content-Type`. Anything but
+/// CBOR is handed over to the JSON extractor, which keeps rejecting the
+/// bodies that are not JSON either.
+pub struct Body<T>(pub T);
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for Body<T>
+where
+    T: DeserializeOwned,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        match Encoding::of_content(req.headers()) {
+            Encoding::Json => {
+                let Json(t) = Json::from_request(req, state).await?;
+                Ok(Body(t))
+            }
+            Encoding::Cbor => {
+                let bytes = Bytes::from_request(req, state).await?;
+                let t = ciborium::from_reader(bytes.as_ref())
+                    .map_err(|err| ApiError::Cbor(err.to_string()))?;
+                Ok(Body(t))
+            }
+        }
+    }
+}
+
+/// A response body, encoded as negotiated with the client.
+pub struct Negotiated<T>(pub Encoding, pub T);
+
+impl<T> IntoResponse for Negotiated<T>
+where
+    T: Serialize,
+{
+    fn into_response(self) -> Response {
+        match self {
+            Negotiated(Encoding::Json, data) => AxumJson(data).into_response(),
+            Negotiated(Encoding::Cbor, data) => {
+                let mut body = Vec::new();
+                match ciborium::into_writer(&data, &mut body) {
+                    Ok(()) => {
+                        ([(CONTENT_TYPE, HeaderValue::from_static(CBOR))], body).into_response()
+                    }
+                    Err(err) => {
+                        (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response()
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{header::AUTHORIZATION, Request},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::api::ApiError;
+
+/// The token clients have to send as `Authorization: Bearer <token>`, set by
+/// the `API_TOKEN` environment variable. When unset, no authentication is
+/// required.
+pub type ApiToken = Option<Arc<str>>;
+
+/// Compares the two strings in a time which only depends on their length, so
+/// that the token cannot be guessed by timing the responses.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// Whether the value of the `Authorization` header, if any, carries the token.
+pub fn authorized(token: &ApiToken, authorization: Option<&str>) -> bool {
+    let Some(token) = token else {
+        return true;
+    };
+    authorization
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|provided| constant_time_eq(provided, token))
+}
+
+pub async fn authenticate<B>(
+    State(token): State<ApiToken>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Result<Response, ApiError> {
+    let authorization = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    if !authorized(&token, authorization) {
+        return Err(ApiError::Unauthorized);
+    }
+    Ok(next.run(request).await)
+}
@@ -0,0 +1,65 @@
+//! Delivers the tasks which reached a terminal state, either completed or
+//! dead-lettered, to the `callback_url` they were pushed with.
+
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+
+use crate::store::{Conceal, Keys, Task};
+
+/// How long a delivery attempt can take
+static TIMEOUT: Duration = Duration::from_secs(10);
+/// How many times a failed delivery is attempted again
+static MAX_RETRIES: u32 = 5;
+/// How long to wait before the first retry, doubled at each of the next ones
+static BACKOFF: Duration = Duration::from_secs(1);
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .build()
+        .expect("the callback client can always be built")
+});
+
+/// POSTs the task to its callback URL, if it has one, in the background so
+/// that the store is never held up by a slow or unreachable receiver. Its
+/// keys are concealed with `keys`.
+pub fn notify(task: &Task, keys: &Keys) {
+    let Some(url) = task.0.callback_url.clone() else {
+        return;
+    };
+    let id = task.0.id;
+    let task = match task.clone().conceal(keys) {
+        Ok(task) => task,
+        Err(err) => {
+            tracing::error!(%id, %err, "Cannot conceal the task to deliver to its callback");
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        let mut retry = 0;
+        loop {
+            let delivered = CLIENT
+                .post(url.clone())
+                .json(&task)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            match delivered {
+                Ok(_) => {
+                    tracing::debug!(%id, %url, "Task delivered to its callback");
+                    return;
+                }
+                Err(err) if retry < MAX_RETRIES => {
+                    tracing::debug!(%id, %url, %err, retry, "Could not deliver the task to its callback, retrying");
+                    tokio::time::sleep(BACKOFF * 2u32.pow(retry)).await;
+                    retry += 1;
+                }
+                Err(err) => {
+                    tracing::warn!(%id, %url, %err, "Could not deliver the task to its callback, giving up");
+                    return;
+                }
+            }
+        }
+    });
+}
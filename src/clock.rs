@@ -0,0 +1,65 @@
+//! The time the memory store runs on. It follows the system clock, while tests
+//! can run it on the clock of tokio, which they can pause and advance at will
+//! instead of waiting for the timeouts to fire.
+
+use std::{future::Future, pin::Pin};
+
+use time::{Duration, OffsetDateTime};
+use tokio::time::Instant;
+
+pub trait Clock: Send + Sync {
+    /// The current time.
+    fn now(&self) -> OffsetDateTime;
+
+    /// Resolves once `duration` has elapsed, right away if it is negative.
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+}
+
+/// The system clock, the one the store runs on by default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc()
+    }
+
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(tokio::time::sleep(duration.try_into().unwrap_or_default()))
+    }
+}
+
+/// The clock of tokio, starting from the system time it is created at. When
+/// the time of the runtime is paused, i.e. by `tokio::time::pause`, it only
+/// moves as the runtime advances it, so that timeouts fire in order without
+/// any wait.
+#[derive(Clone, Copy, Debug)]
+pub struct TokioClock {
+    start: OffsetDateTime,
+    instant: Instant,
+}
+
+impl TokioClock {
+    pub fn new() -> Self {
+        TokioClock {
+            start: OffsetDateTime::now_utc(),
+            instant: Instant::now(),
+        }
+    }
+}
+
+impl Default for TokioClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for TokioClock {
+    fn now(&self) -> OffsetDateTime {
+        self.start + self.instant.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(tokio::time::sleep(duration.try_into().unwrap_or_default()))
+    }
+}
@@ -0,0 +1,55 @@
+//! Cross-origin requests, for the clients running in a browser. They are only
+//! allowed from the origins set by the `CORS_ALLOWED_ORIGINS` environment
+//! variable, and refused by the browsers when it is unset.
+
+use axum::http::{
+    header::{
+        HeaderName, InvalidHeaderValue, ACCEPT, AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE,
+        RETRY_AFTER,
+    },
+    HeaderValue, Method,
+};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use crate::telemetry::TRACEPARENT;
+
+/// How long browsers may cache the answer to a preflight request.
+static MAX_AGE: std::time::Duration = std::time::Duration::from_secs(3600);
+
+/// Builds the layer allowing requests from `origins`, either a comma-separated
+/// list of origins or `*` for any of them. It answers the preflight `OPTIONS`
+/// requests by itself, so it has to wrap the whole router to handle them
+/// before the authentication and the extractors.
+pub fn layer(origins: &str) -> Result<CorsLayer, InvalidHeaderValue> {
+    let origins = if origins.trim() == "*" {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            origins
+                .split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty())
+                .map(HeaderValue::from_str)
+                .collect::<Result<Vec<_>, _>>()?,
+        )
+    };
+    let traceparent = HeaderName::from_static(TRACEPARENT);
+    Ok(CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods([
+            Method::GET,
+            Method::PUT,
+            Method::POST,
+            Method::PATCH,
+            Method::DELETE,
+        ])
+        .allow_headers([
+            ACCEPT,
+            AUTHORIZATION,
+            CONTENT_ENCODING,
+            CONTENT_TYPE,
+            traceparent.clone(),
+        ])
+        .expose_headers([RETRY_AFTER, traceparent])
+        .max_age(MAX_AGE))
+}
@@ -0,0 +1,99 @@
+//! The lifecycle events of the tasks, published to a message broker for the
+//! consumers keeping track of them, i.e. to source their own state. Only the
+//! memory store publishes them, and nothing is published unless a sink is set
+//! on it.
+
+use axum::async_trait;
+use taskie_structures::TaskEvent;
+use time::OffsetDateTime;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+use crate::store::{Conceal, Keys, Task};
+
+/// Where the events are published to.
+#[async_trait]
+pub trait EventSink: Send + Sync + 'static {
+    /// Publishes a single event. Failures are for the sink to report, as the
+    /// store goes on regardless.
+    async fn emit(&self, event: TaskEvent);
+}
+
+/// Hands the events over to a sink in the background, so that the store is
+/// never held up by a slow broker, one at a time so that they are published
+/// in the order the changes happened in.
+#[derive(Clone)]
+pub struct Emitter(UnboundedSender<TaskEvent>);
+
+impl Emitter {
+    /// Spawns the task publishing the events to `sink`, which runs until the
+    /// emitter and its clones are dropped.
+    pub fn new(sink: impl EventSink) -> Self {
+        let (tx, mut rx) = unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(event) = rx.recv().await {
+                sink.emit(event).await;
+            }
+        });
+        Emitter(tx)
+    }
+
+    /// Publishes the current state of `task`, as of `at`, with its key
+    /// concealed with `keys`.
+    pub fn emit(&self, task: &Task, at: OffsetDateTime, keys: &Keys) {
+        let id = match task.0.id.conceal(keys) {
+            Ok(id) => id,
+            Err(err) => {
+                tracing::error!(id = %task.0.id, %err, "Cannot conceal the task to publish its event");
+                return;
+            }
+        };
+        let event = TaskEvent {
+            id,
+            name: task.0.name.clone(),
+            status: task.0.status,
+            at,
+        };
+        // The sink only stops once every sender is gone
+        let _ = self.0.send(event);
+    }
+}
+
+/// The subject the events are published to when none is set
+#[cfg(feature = "nats")]
+pub static DEFAULT_NATS_SUBJECT: &str = "taskie.events";
+
+/// Publishes the events as JSON to a NATS subject.
+#[cfg(feature = "nats")]
+pub struct NatsSink {
+    client: async_nats::Client,
+    subject: String,
+}
+
+#[cfg(feature = "nats")]
+impl NatsSink {
+    pub async fn connect(url: &str, subject: String) -> Result<Self, async_nats::ConnectError> {
+        let client = async_nats::connect(url).await?;
+        Ok(NatsSink { client, subject })
+    }
+}
+
+#[cfg(feature = "nats")]
+#[async_trait]
+impl EventSink for NatsSink {
+    async fn emit(&self, event: TaskEvent) {
+        let payload = match serde_json::to_vec(&event) {
+            Ok(payload) => payload,
+            Err(err) => {
+                tracing::error!(id = %event.id, %err, "Cannot serialize the task event");
+                return;
+            }
+        };
+        if let Err(err) = self
+            .client
+            .publish(self.subject.clone(), payload.into())
+            .await
+        {
+            tracing::warn!(id = %event.id, subject = %self.subject, %err, "Could not publish the task event");
+        }
+    }
+}
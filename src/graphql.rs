@@ -0,0 +1,240 @@
+//! The GraphQL frontend, served at `/graphql` next to the HTTP API. It only
+//! has queries, over the tasks, the stats and the dependency graph of the
+//! store, whose keys are concealed just like in the HTTP API.
+
+use std::collections::BTreeMap;
+
+use async_graphql::{
+    EmptyMutation, EmptySubscription, Enum, ErrorExtensions, Json, Object, Schema, SimpleObject,
+};
+use serde_json::Value;
+use time::OffsetDateTime;
+
+use crate::{
+    api::ApiError,
+    store::{Conceal, ConcealError, GetError, Keys, Reveal},
+    AppState, Context, Waiting, DEFAULT_LIST_LIMIT, MAX_LIST_LIMIT,
+};
+
+pub use async_graphql_axum::GraphQL;
+
+pub type QueueSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+/// Builds the schema, whose queries run on the store of `state`.
+pub fn schema(state: &AppState) -> QueueSchema {
+    Schema::build(Query, EmptyMutation, EmptySubscription)
+        .data(state.store.clone())
+        .data(state.keys.clone())
+        .data(state.waiting.clone())
+        .finish()
+}
+
+/// Reports `err` along with the HTTP status and the code the API would answer
+/// with.
+fn error(err: impl Into<ApiError>) -> async_graphql::Error {
+    let (_, err) = err.into().serialize();
+    async_graphql::Error::new(err.message).extend_with(|_, e| {
+        e.set("status", err.status);
+        e.set("code", err.code.clone());
+    })
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+#[graphql(remote = "taskie_structures::Status")]
+enum Status {
+    Pending,
+    Ready,
+    Processing,
+    Completed,
+    Failed,
+}
+
+#[derive(SimpleObject)]
+struct Task {
+    id: String,
+    name: String,
+    payload: Option<Json<Value>>,
+    depends_on: Vec<String>,
+    /// How many seconds the task has to be completed in once popped
+    duration: i64,
+    priority: i32,
+    max_retries: u32,
+    cost: u32,
+    labels: Json<BTreeMap<String, String>>,
+    tenant: Option<String>,
+    attempt: u32,
+    status: Status,
+    created_at: OffsetDateTime,
+    started_at: Option<OffsetDateTime>,
+    run_at: Option<OffsetDateTime>,
+    expires_at: Option<OffsetDateTime>,
+    progress: Option<u8>,
+    schedule: Option<String>,
+}
+
+impl From<taskie_structures::Task> for Task {
+    fn from(task: taskie_structures::Task) -> Self {
+        Task {
+            id: task.id.into(),
+            name: task.name,
+            payload: task.payload.map(Json),
+            depends_on: task.depends_on.into_iter().map(String::from).collect(),
+            duration: task.duration.whole_seconds(),
+            priority: task.priority,
+            max_retries: task.max_retries,
+            cost: task.cost,
+            labels: Json(task.labels),
+            tenant: task.tenant,
+            attempt: task.attempt,
+            status: task.status.into(),
+            created_at: task.created_at,
+            started_at: task.started_at,
+            run_at: task.run_at,
+            expires_at: task.expires_at,
+            progress: task.progress,
+            schedule: task.schedule,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+struct TaskPage {
+    tasks: Vec<Task>,
+    /// How many tasks there are in all, across the pages
+    total: u64,
+}
+
+#[derive(SimpleObject)]
+struct MonitorStats {
+    backlog: u64,
+    last_handled_at: Option<OffsetDateTime>,
+}
+
+#[derive(SimpleObject)]
+struct Stats {
+    pending: u64,
+    ready: u64,
+    processing: u64,
+    completed: u64,
+    dead_lettered: u64,
+    waiting: u64,
+    next_key: String,
+    monitor: Option<MonitorStats>,
+}
+
+#[derive(SimpleObject)]
+struct Node {
+    id: String,
+    name: String,
+}
+
+/// A pending dependency of a task
+#[derive(SimpleObject)]
+struct Edge {
+    dependent: String,
+    dependency: String,
+}
+
+#[derive(SimpleObject)]
+struct Graph {
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// Looks up a task, which is null once completed or if it never existed.
+    async fn task(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        id: String,
+    ) -> async_graphql::Result<Option<Task>> {
+        let store = ctx.data_unchecked::<Context>();
+        let keys = ctx.data_unchecked::<Keys>();
+        let id = taskie_structures::TaskId(id).reveal(keys).map_err(error)?;
+        match store.get(id).await {
+            Ok(task) => Ok(Some(task.conceal(keys).map_err(error)?.into())),
+            Err(GetError::InvalidTaskId(_)) => Ok(None),
+            Err(err) => Err(error(err)),
+        }
+    }
+
+    /// Lists the tasks like `GET /v1/tasks`, in the order they were pushed.
+    async fn tasks(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        #[graphql(default)] offset: usize,
+        limit: Option<usize>,
+        status: Option<Status>,
+    ) -> async_graphql::Result<TaskPage> {
+        let store = ctx.data_unchecked::<Context>();
+        let keys = ctx.data_unchecked::<Keys>();
+        let limit = limit.unwrap_or(DEFAULT_LIST_LIMIT).min(MAX_LIST_LIMIT);
+        let (tasks, total) = store
+            .list(offset, limit, status.map(Into::into))
+            .await
+            .map_err(error)?;
+        let tasks = tasks
+            .into_iter()
+            .map(|task| Ok(task.conceal(keys)?.into()))
+            .collect::<Result<_, ConcealError>>()
+            .map_err(error)?;
+        Ok(TaskPage {
+            tasks,
+            total: total as u64,
+        })
+    }
+
+    async fn stats(&self, ctx: &async_graphql::Context<'_>) -> async_graphql::Result<Stats> {
+        let store = ctx.data_unchecked::<Context>();
+        let keys = ctx.data_unchecked::<Keys>();
+        let mut stats = store.stats().await.map_err(error)?;
+        stats.0.waiting = ctx.data_unchecked::<Waiting>().count();
+        let stats = stats.conceal(keys).map_err(error)?;
+        Ok(Stats {
+            pending: stats.pending,
+            ready: stats.ready,
+            processing: stats.processing,
+            completed: stats.completed,
+            dead_lettered: stats.dead_lettered,
+            waiting: stats.waiting,
+            next_key: stats.next_key.into(),
+            monitor: stats.monitor.map(|monitor| MonitorStats {
+                backlog: monitor.backlog,
+                last_handled_at: monitor.last_handled_at,
+            }),
+        })
+    }
+
+    /// The tasks in the store, along with the dependencies they wait for.
+    async fn graph(&self, ctx: &async_graphql::Context<'_>) -> async_graphql::Result<Graph> {
+        let store = ctx.data_unchecked::<Context>();
+        let keys = ctx.data_unchecked::<Keys>();
+        let snapshot = store.graph().await.map_err(error)?;
+        let nodes = snapshot
+            .nodes
+            .into_iter()
+            .map(|(id, name)| {
+                Ok(Node {
+                    id: id.conceal(keys)?.into(),
+                    name,
+                })
+            })
+            .collect::<Result<_, ConcealError>>()
+            .map_err(error)?;
+        let edges = snapshot
+            .edges
+            .into_iter()
+            .map(|(dependent, dependency)| {
+                Ok(Edge {
+                    dependent: dependent.conceal(keys)?.into(),
+                    dependency: dependency.conceal(keys)?.into(),
+                })
+            })
+            .collect::<Result<_, ConcealError>>()
+            .map_err(error)?;
+        Ok(Graph { nodes, edges })
+    }
+}
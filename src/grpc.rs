@@ -0,0 +1,206 @@
+//! The gRPC frontend, defined in `proto/taskie.proto`. It exposes the push,
+//! pop and complete calls of the HTTP API on top of the same store, and
+//! converts its messages from and to the structures of the HTTP API.
+
+// tonic::Status is the error type of the generated service
+#![allow(clippy::result_large_err)]
+
+use std::future::Future;
+
+use axum::http::StatusCode;
+use taskie_structures::{
+    DependencyMode, TaskId, DEFAULT_COST, DEFAULT_DURATION, DEFAULT_MAX_RETRIES,
+};
+use time::Duration;
+use tonic::{metadata::MetadataMap, Code, Request, Response, Status};
+
+use crate::{
+    api::ApiError,
+    auth::{self, ApiToken},
+    complete_task, pop_tasks, push_tasks,
+    store::Selector,
+    telemetry::TRACEPARENT,
+    AppState, Batch,
+};
+
+pub mod proto {
+    tonic::include_proto!("taskie.v1");
+}
+
+pub use proto::taskie_server::TaskieServer;
+
+pub struct Service {
+    state: AppState,
+    api_token: ApiToken,
+}
+
+/// Builds the gRPC service with the given state. When `api_token` is set, it
+/// has to be sent as `authorization: Bearer <token>` metadata on every call.
+pub fn server(state: AppState, api_token: ApiToken) -> TaskieServer<Service> {
+    TaskieServer::new(Service { state, api_token })
+}
+
+impl From<ApiError> for Status {
+    fn from(err: ApiError) -> Self {
+        let (status, message) = err.parts();
+        let code = match status {
+            StatusCode::BAD_REQUEST => Code::InvalidArgument,
+            StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+            StatusCode::FORBIDDEN => Code::PermissionDenied,
+            StatusCode::NOT_FOUND => Code::NotFound,
+            StatusCode::CONFLICT => Code::FailedPrecondition,
+            StatusCode::PAYLOAD_TOO_LARGE
+            | StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INSUFFICIENT_STORAGE => Code::ResourceExhausted,
+            StatusCode::NOT_IMPLEMENTED => Code::Unimplemented,
+            StatusCode::SERVICE_UNAVAILABLE => Code::Unavailable,
+            _ => Code::Internal,
+        };
+        Status::new(code, message)
+    }
+}
+
+fn parse_json(json: Option<String>, field: &str) -> Result<Option<serde_json::Value>, Status> {
+    json.map(|json| serde_json::from_str(&json))
+        .transpose()
+        .map_err(|err| Status::invalid_argument(format!("Invalid JSON in {}: {}", field, err)))
+}
+
+fn insert_task(
+    task: proto::InsertTask,
+    traceparent: Option<&str>,
+) -> Result<taskie_structures::InsertTask, Status> {
+    Ok(taskie_structures::InsertTask {
+        name: task.name,
+        payload: parse_json(task.payload, "payload")?,
+        depends_on: task.depends_on.into_iter().map(TaskId).collect(),
+        depends_on_batch: task
+            .depends_on_batch
+            .into_iter()
+            .map(|position| position as usize)
+            .collect(),
+        duration: task.duration.map_or(DEFAULT_DURATION, Duration::seconds),
+        priority: task.priority,
+        max_retries: task.max_retries.unwrap_or(DEFAULT_MAX_RETRIES),
+        cost: DEFAULT_COST,
+        labels: task.labels.into_iter().collect(),
+        tenant: None,
+        run_at: None,
+        expires_at: None,
+        schedule: None,
+        idempotency_key: task.idempotency_key,
+        dedupe: false,
+        callback_url: None,
+        dependency_mode: DependencyMode::All,
+        traceparent: traceparent.map(str::to_owned),
+    })
+}
+
+fn task(task: taskie_structures::Task) -> proto::Task {
+    proto::Task {
+        id: task.id.into(),
+        name: task.name,
+        payload: task.payload.map(|payload| payload.to_string()),
+        depends_on: task.depends_on.into_iter().map(String::from).collect(),
+        duration: task.duration.whole_seconds(),
+        priority: task.priority,
+        max_retries: task.max_retries,
+        labels: task.labels.into_iter().collect(),
+        attempt: task.attempt,
+        status: task.status.to_string(),
+        traceparent: task.traceparent,
+    }
+}
+
+impl Service {
+    fn authenticate(&self, metadata: &MetadataMap) -> Result<(), Status> {
+        let authorization = metadata
+            .get("authorization")
+            .and_then(|value| value.to_str().ok());
+        if auth::authorized(&self.api_token, authorization) {
+            Ok(())
+        } else {
+            Err(ApiError::Unauthorized.into())
+        }
+    }
+
+    /// Runs a call with the keys of the server in scope, down to its error,
+    /// so that the keys of the tasks are concealed in its message.
+    async fn scoped<T>(
+        &self,
+        call: impl Future<Output = Result<T, ApiError>>,
+    ) -> Result<T, Status> {
+        let call = async { call.await.map_err(Status::from) };
+        self.state.keys.scope(call).await
+    }
+}
+
+#[tonic::async_trait]
+impl proto::taskie_server::Taskie for Service {
+    async fn push(
+        &self,
+        request: Request<proto::PushRequest>,
+    ) -> Result<Response<proto::PushResponse>, Status> {
+        self.authenticate(request.metadata())?;
+        let traceparent = request
+            .metadata()
+            .get(TRACEPARENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+        let tasks = request
+            .into_inner()
+            .tasks
+            .into_iter()
+            .map(|t| insert_task(t, traceparent.as_deref()))
+            .collect::<Result<Vec<_>, Status>>()?;
+        let tasks = self.scoped(push_tasks(&self.state, tasks)).await?;
+        Ok(Response::new(proto::PushResponse {
+            tasks: tasks.into_iter().map(task).collect(),
+        }))
+    }
+
+    async fn pop(
+        &self,
+        request: Request<proto::PopRequest>,
+    ) -> Result<Response<proto::PopResponse>, Status> {
+        self.authenticate(request.metadata())?;
+        let proto::PopRequest { timeout, label } = request.into_inner();
+        let selector: Selector = label.parse().map_err(ApiError::from)?;
+        let execution = self
+            .scoped(pop_tasks(&self.state, &selector, timeout, true, Batch::One))
+            .await?
+            .and_then(|mut executions| executions.pop())
+            .map(|execution| proto::Execution {
+                task: Some(task(execution.task)),
+                deadline: (execution.deadline.unix_timestamp_nanos() / 1_000_000) as i64,
+                remaining: execution.remaining.whole_seconds(),
+                lease_token: execution.lease_token.to_string(),
+            });
+        Ok(Response::new(proto::PopResponse { execution }))
+    }
+
+    async fn complete(
+        &self,
+        request: Request<proto::CompleteRequest>,
+    ) -> Result<Response<proto::CompleteResponse>, Status> {
+        self.authenticate(request.metadata())?;
+        let proto::CompleteRequest {
+            id,
+            result,
+            lease_token,
+        } = request.into_inner();
+        let result = parse_json(result, "result")?;
+        // A token which does not parse cannot match, just like a missing one
+        let lease_token = lease_token.parse().ok();
+        let completed = complete_task(
+            &self.state.store,
+            &self.state.keys,
+            &self.state.streamed,
+            TaskId(id),
+            lease_token,
+            result,
+        );
+        self.scoped(completed).await?;
+        Ok(Response::new(proto::CompleteResponse {}))
+    }
+}
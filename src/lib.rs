@@ -0,0 +1,1348 @@
+//! A task queue with dependencies between the tasks, served over HTTP. The
+//! API is built by [`router`] on top of any [`Store`] backend.
+
+pub mod api;
+pub mod auth;
+pub mod callback;
+pub mod clock;
+pub mod cors;
+pub mod events;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod metrics;
+pub mod namespaces;
+pub mod ratelimit;
+pub mod schemas;
+pub mod store;
+pub mod stores;
+pub mod telemetry;
+
+use futures::{future, stream, Stream, StreamExt};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use ::metrics::{counter, gauge, histogram, increment_counter};
+use axum::{
+    body::{HttpBody, StreamBody},
+    error_handling::HandleErrorLayer,
+    extract::{rejection::QueryRejection, DefaultBodyLimit, FromRef, Path, Query, State},
+    http::{header::CONTENT_TYPE, HeaderMap, HeaderValue, Request, StatusCode},
+    middleware,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{any, delete, get, post, put},
+    BoxError, Router,
+};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use time::OffsetDateTime;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_util::sync::CancellationToken;
+use tower::{ServiceBuilder, ServiceExt};
+use tower_http::{
+    compression::{
+        predicate::{NotForContentType, Predicate},
+        CompressionLayer, DefaultPredicate,
+    },
+    decompression::RequestDecompressionLayer,
+};
+
+use api::{ApiError, Body, Encoding, Json, Negotiated};
+use auth::ApiToken;
+use namespaces::{Namespace, Namespaces};
+use ratelimit::RateLimit;
+use schemas::Schemas;
+use store::{Conceal, KeyDecodeError, Keys, Reveal, Selector, Store};
+use taskie_structures::{
+    AwaitedTask, CompleteAndPush, CompleteBatch, CompleteTask, Completion, DeadLetter, Deadline,
+    DependencyResult, FailTask, Heartbeat, InsertTask, Lease, Progress, Recurring, ReportProgress,
+    Stats, Task, TaskPage, TaskPatch, TaskResult, Uuid, Version, WaitTasks,
+};
+
+use crate::store::{
+    CompleteError, ConcealError, FailError, HeartbeatError, PopError, ProgressError,
+};
+
+pub type Context = Arc<dyn Store>;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub store: Context,
+    /// How the keys of the tasks are concealed in the API
+    pub keys: Keys,
+    /// Cancelled when the server starts shutting down
+    pub shutdown: CancellationToken,
+    pub metrics: PrometheusHandle,
+    pub waiting: Waiting,
+    pub streamed: Streamed,
+    pub limits: Limits,
+    /// The schemas the payloads of the pushed tasks are validated against
+    pub schemas: Arc<Schemas>,
+    pub rate_limit: RateLimit,
+    pub namespaces: Namespaces,
+}
+
+impl AppState {
+    /// The state of a server using `store`, concealing the keys of its tasks
+    /// with `keys`, and with the defaults for the rest: the other fields can
+    /// be set once it is built.
+    pub fn new(store: Context, keys: Keys) -> Self {
+        AppState {
+            store,
+            keys,
+            shutdown: CancellationToken::new(),
+            // The recorder is not installed, as it is global
+            metrics: PrometheusBuilder::new().build_recorder().handle(),
+            waiting: Waiting::default(),
+            streamed: Streamed::default(),
+            limits: Limits::default(),
+            schemas: Default::default(),
+            rate_limit: RateLimit::default(),
+            namespaces: Namespaces::default(),
+        }
+    }
+}
+
+/// How many tasks are listed at once when no limit is asked for
+pub static DEFAULT_LIST_LIMIT: usize = 100;
+/// The most tasks listed at once, whatever the limit asked for
+pub static MAX_LIST_LIMIT: usize = 1000;
+pub static DEFAULT_MAX_PAYLOAD_BYTES: usize = 64 * 1024;
+pub static DEFAULT_MAX_BODY_BYTES: usize = 16 * 1024 * 1024;
+
+/// Bounds on the size of the requests, so that a client cannot bloat the
+/// memory of the server.
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    /// The largest payload of a pushed task, once encoded as JSON
+    pub max_payload_bytes: usize,
+    /// The largest request body, i.e. of a whole batch of pushed tasks
+    pub max_body_bytes: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_payload_bytes: DEFAULT_MAX_PAYLOAD_BYTES,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+        }
+    }
+}
+
+/// Counts the workers waiting for a task to be popped, either with
+/// `GET /v1/pop` or `GET /v1/stream`.
+#[derive(Clone, Default)]
+pub struct Waiting(Arc<AtomicU64>);
+
+impl Waiting {
+    /// Counts one more waiting worker, until the returned guard is dropped.
+    fn wait(&self) -> WaitingGuard {
+        let waiting = self.0.fetch_add(1, Ordering::Relaxed) + 1;
+        gauge!(metrics::WAITING_WORKERS, waiting as f64);
+        WaitingGuard(self.0.clone())
+    }
+
+    pub fn count(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+struct WaitingGuard(Arc<AtomicU64>);
+
+impl Drop for WaitingGuard {
+    fn drop(&mut self) {
+        let waiting = self.0.fetch_sub(1, Ordering::Relaxed) - 1;
+        gauge!(metrics::WAITING_WORKERS, waiting as f64);
+    }
+}
+
+/// The executions handed out by `GET /v1/stream` which are still running, by
+/// their lease token. Each of them takes up one of the slots of the
+/// connection it was streamed on, until its worker completes or fails it, or
+/// otherwise until its deadline. Lease tokens are unique, so the executions
+/// of every namespace are kept together.
+#[derive(Clone, Default)]
+pub struct Streamed(Arc<Mutex<HashMap<Uuid, StreamedExecution>>>);
+
+struct StreamedExecution {
+    deadline: OffsetDateTime,
+    /// Frees the slot of the connection once dropped
+    _slot: OwnedSemaphorePermit,
+}
+
+impl Streamed {
+    /// Takes up `slot` until the execution leased with `lease_token` ends, or
+    /// until `deadline`, as moved by `extend`.
+    fn hold(&self, lease_token: Uuid, deadline: OffsetDateTime, slot: OwnedSemaphorePermit) {
+        let execution = StreamedExecution {
+            deadline,
+            _slot: slot,
+        };
+        self.0.lock().unwrap().insert(lease_token, execution);
+        let streamed = self.clone();
+        tokio::spawn(async move {
+            // Heartbeats may have moved the deadline in the meantime
+            while let Some(deadline) = streamed.deadline(lease_token) {
+                let left = deadline - OffsetDateTime::now_utc();
+                match std::time::Duration::try_from(left) {
+                    Ok(left) if !left.is_zero() => tokio::time::sleep(left).await,
+                    _ => {
+                        streamed.release(lease_token);
+                        break;
+                    }
+                }
+            }
+        });
+    }
+
+    fn deadline(&self, lease_token: Uuid) -> Option<OffsetDateTime> {
+        let executions = self.0.lock().unwrap();
+        executions
+            .get(&lease_token)
+            .map(|execution| execution.deadline)
+    }
+
+    /// Moves the deadline of the execution leased with `lease_token`, if it
+    /// was streamed.
+    fn extend(&self, lease_token: Uuid, deadline: OffsetDateTime) {
+        if let Some(execution) = self.0.lock().unwrap().get_mut(&lease_token) {
+            execution.deadline = deadline;
+        }
+    }
+
+    /// Frees the slot of the execution leased with `lease_token`, if it was
+    /// streamed, and tells whether it was still holding it.
+    fn release(&self, lease_token: Uuid) -> bool {
+        self.0.lock().unwrap().remove(&lease_token).is_some()
+    }
+}
+
+impl FromRef<AppState> for Context {
+    fn from_ref(state: &AppState) -> Self {
+        state.store.clone()
+    }
+}
+
+impl FromRef<AppState> for Keys {
+    fn from_ref(state: &AppState) -> Self {
+        state.keys.clone()
+    }
+}
+
+impl FromRef<AppState> for CancellationToken {
+    fn from_ref(state: &AppState) -> Self {
+        state.shutdown.clone()
+    }
+}
+
+impl FromRef<AppState> for Limits {
+    fn from_ref(state: &AppState) -> Self {
+        state.limits
+    }
+}
+
+impl FromRef<AppState> for RateLimit {
+    fn from_ref(state: &AppState) -> Self {
+        state.rate_limit.clone()
+    }
+}
+
+impl FromRef<AppState> for Waiting {
+    fn from_ref(state: &AppState) -> Self {
+        state.waiting.clone()
+    }
+}
+
+impl FromRef<AppState> for Streamed {
+    fn from_ref(state: &AppState) -> Self {
+        state.streamed.clone()
+    }
+}
+
+impl FromRef<AppState> for PrometheusHandle {
+    fn from_ref(state: &AppState) -> Self {
+        state.metrics.clone()
+    }
+}
+
+/// Checks the tasks to be pushed against the limits, and decodes them.
+fn decode_tasks(
+    state: &AppState,
+    tasks: Vec<InsertTask>,
+) -> Result<Vec<store::InsertTask>, ApiError> {
+    if state.shutdown.is_cancelled() {
+        return Err(ApiError::ShuttingDown);
+    }
+    let limit = state.limits.max_payload_bytes;
+    for payload in tasks.iter().filter_map(|task| task.payload.as_ref()) {
+        let size = serde_json::to_vec(payload)
+            .expect("JSON values can always be encoded")
+            .len();
+        if size > limit {
+            return Err(ApiError::PayloadTooLarge { size, limit });
+        }
+    }
+    state.schemas.validate(&tasks)?;
+    Ok(tasks
+        .into_iter()
+        .map(|task| task.reveal(&state.keys))
+        .collect::<Result<Vec<_>, KeyDecodeError>>()?)
+}
+
+/// Records the tasks which have been pushed, and conceals them.
+fn pushed(keys: &Keys, tasks: Vec<store::Task>) -> Result<Vec<Task>, ApiError> {
+    counter!(metrics::TASKS_PUSHED, tasks.len() as u64);
+    tracing::info!(
+        tasks = ?tasks.iter().map(|t| (t.0.id, t.0.name.to_owned())).collect::<Vec<_>>(),
+        "Queued tasks"
+    );
+    let tasks = tasks
+        .into_iter()
+        .map(|task| task.conceal(keys))
+        .collect::<Result<Vec<_>, ConcealError>>()?;
+    Ok(tasks)
+}
+
+/// Pushes a batch of tasks, for either frontend.
+async fn push_tasks(state: &AppState, tasks: Vec<InsertTask>) -> Result<Vec<Task>, ApiError> {
+    let tasks = decode_tasks(state, tasks)?;
+    pushed(&state.keys, state.store.push(tasks).await?)
+}
+
+/// Sets the trace context of the tasks pushed without one to the one of the
+/// request.
+fn set_traceparent(headers: &HeaderMap, tasks: &mut [InsertTask]) {
+    if let Some(traceparent) = telemetry::traceparent(headers) {
+        for task in tasks.iter_mut().filter(|task| task.traceparent.is_none()) {
+            task.traceparent = Some(traceparent.clone());
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PushQuery {
+    /// Only check the tasks, and return the keys they would be given
+    #[serde(default)]
+    dry_run: bool,
+}
+
+async fn push(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    query: Result<Query<PushQuery>, QueryRejection>,
+    Body(mut tasks): Body<Vec<InsertTask>>,
+) -> Result<Response, ApiError> {
+    let Query(PushQuery { dry_run }) = query?;
+    let encoding = Encoding::accepted(&headers);
+    if dry_run {
+        let ids = state.store.dry_run(&decode_tasks(&state, tasks)?).await?;
+        let ids = ids
+            .into_iter()
+            .map(|id| id.conceal(&state.keys))
+            .collect::<Result<Vec<_>, ConcealError>>()?;
+        return Ok((StatusCode::OK, Negotiated(encoding, ids)).into_response());
+    }
+    set_traceparent(&headers, &mut tasks);
+    let tasks = push_tasks(&state, tasks).await?;
+    Ok((StatusCode::OK, Negotiated(encoding, tasks)).into_response())
+}
+
+#[derive(Deserialize)]
+struct PopQuery {
+    /// How many seconds to wait for a task to be ready, forever if unset
+    timeout: Option<u64>,
+    /// Whether to wait for a task to be ready at all, rather than answering
+    /// right away when none is. Only single tasks can be popped without
+    /// waiting.
+    block: Option<bool>,
+    /// Only pop the tasks matching this label selector, i.e. `gpu,region=eu`
+    label: Option<String>,
+    /// Only pop the tasks with this name
+    name: Option<String>,
+    /// Pop up to this many tasks, as a list, once at least one is ready
+    count: Option<usize>,
+    /// Pop as many tasks as fit in this budget by their cost, once at least
+    /// one is ready, whatever its cost
+    budget: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct ListQuery {
+    #[serde(default)]
+    offset: usize,
+    /// How many tasks to list, capped to `MAX_LIST_LIMIT`
+    limit: Option<usize>,
+    /// Only list the tasks in this state
+    status: Option<taskie_structures::Status>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+enum GraphFormat {
+    /// A Graphviz document
+    #[default]
+    Dot,
+}
+
+#[derive(Deserialize)]
+struct GraphQuery {
+    #[serde(default)]
+    format: GraphFormat,
+}
+
+/// How many streamed tasks a connection can be processing at once, unless
+/// asked for more
+pub static DEFAULT_MAX_IN_FLIGHT: usize = 1;
+
+#[derive(Deserialize)]
+struct StreamQuery {
+    /// Only stream the tasks matching this label selector
+    label: Option<String>,
+    /// How many of the streamed tasks can be processing at once: no more
+    /// are popped until some of them are completed or failed
+    max_in_flight: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct AwaitQuery {
+    /// How many seconds to wait for the task at most, forever if unset
+    timeout: Option<u64>,
+}
+
+/// Resolves once the `timeout`, in seconds, expires, or never if unset.
+async fn expired(timeout: Option<u64>) {
+    match timeout {
+        Some(timeout) => tokio::time::sleep(Duration::from_secs(timeout)).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// How many tasks a pop hands out, once one is ready.
+#[derive(Clone, Copy)]
+enum Batch {
+    One,
+    /// Up to this many tasks
+    Count(usize),
+    /// As many tasks as fit in this budget by their cost
+    Budget(u32),
+}
+
+/// Waits for a task matching `selector` to be ready and pops it, along with
+/// the other ready ones fitting in `batch`, for either frontend. `None` is
+/// returned once the `timeout`, in seconds, expires.
+async fn pop_tasks(
+    state: &AppState,
+    selector: &Selector,
+    timeout: Option<u64>,
+    block: bool,
+    batch: Batch,
+) -> Result<Option<Vec<taskie_structures::Execution>>, ApiError> {
+    let expired = expired(timeout);
+
+    // Waiting pops are interrupted on shutdown, so that the server can drain
+    let start = Instant::now();
+    let guard = state.waiting.wait();
+    let popped = async {
+        match batch {
+            Batch::One if !block => state
+                .store
+                .try_pop(selector)
+                .await
+                .map(|execution| execution.into_iter().collect()),
+            Batch::One => state
+                .store
+                .pop(selector)
+                .await
+                .map(|execution| vec![execution]),
+            Batch::Count(count) => state.store.pop_many(selector, count).await,
+            Batch::Budget(budget) => state.store.pop_budget(selector, budget).await,
+        }
+    };
+    let executions = tokio::select! {
+        executions = popped => executions?,
+        _ = expired => return Ok(None),
+        _ = state.shutdown.cancelled() => return Err(ApiError::ShuttingDown),
+    };
+    drop(guard);
+    if executions.is_empty() {
+        return Ok(None);
+    }
+    histogram!(metrics::POP_DURATION, start.elapsed());
+    counter!(metrics::TASKS_POPPED, executions.len() as u64);
+    for execution in executions.iter() {
+        if let Some(traceparent) = execution.0.task.0.traceparent.as_deref() {
+            telemetry::link(traceparent);
+        }
+        tracing::info!(id = ?execution.0.task.0.id, name = %execution.0.task.0.name, deadline = %execution.0.deadline, "Dequeued task");
+    }
+    let executions = executions
+        .into_iter()
+        .map(|execution| execution.conceal(&state.keys))
+        .collect::<Result<Vec<_>, ConcealError>>()?;
+    Ok(Some(executions))
+}
+
+async fn pop(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    query: Result<Query<PopQuery>, QueryRejection>,
+) -> Result<Response, ApiError> {
+    let Query(PopQuery {
+        timeout,
+        block,
+        label,
+        name,
+        count,
+        budget,
+    }) = query?;
+    let batch = match (count, budget) {
+        (None, None) => Batch::One,
+        (Some(count), None) => Batch::Count(count),
+        (None, Some(budget)) => Batch::Budget(budget),
+        (Some(_), Some(_)) => return Err(PopError::CountAndBudget.into()),
+    };
+    let block = block.unwrap_or(true);
+    if !block && !matches!(batch, Batch::One) {
+        return Err(PopError::NonBlockingBatch.into());
+    }
+    let selector: Selector = label.as_deref().unwrap_or_default().parse()?;
+    let selector = selector.named(name);
+    let Some(mut executions) = pop_tasks(&state, &selector, timeout, block, batch).await? else {
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    };
+    let encoding = Encoding::accepted(&headers);
+    match batch {
+        Batch::Count(_) => Ok((StatusCode::OK, Negotiated(encoding, executions)).into_response()),
+        Batch::Budget(_) => {
+            let cost = executions
+                .iter()
+                .map(|execution| u64::from(execution.task.cost))
+                .sum();
+            let lease = Lease { executions, cost };
+            Ok((StatusCode::OK, Negotiated(encoding, lease)).into_response())
+        }
+        Batch::One => {
+            // The worker continues the trace the task was pushed in
+            let execution = executions.remove(0);
+            let mut headers = HeaderMap::new();
+            if let Some(traceparent) = execution.task.traceparent.as_deref() {
+                if let Ok(traceparent) = HeaderValue::from_str(traceparent) {
+                    headers.insert(telemetry::TRACEPARENT, traceparent);
+                }
+            }
+            let execution = Negotiated(encoding, execution);
+            Ok((StatusCode::OK, headers, execution).into_response())
+        }
+    }
+}
+
+/// Streams an `execution` event for each task as soon as it is ready. Every
+/// streamed task is popped just like by `GET /v1/pop`, so it is processing
+/// and has to be completed before its deadline. Once `max_in_flight` of them
+/// are processing, no more are popped until one of them is completed or
+/// failed, or reaches its deadline. The ones still processing when the
+/// connection is closed, but for the server shutting down, are failed to be
+/// retried by another worker, as they may never have been received. The
+/// stream ends with an `error` event if a task cannot be popped.
+async fn stream(
+    State(context): State<Context>,
+    State(shutdown): State<CancellationToken>,
+    State(waiting): State<Waiting>,
+    State(streamed): State<Streamed>,
+    State(keys): State<Keys>,
+    query: Result<Query<StreamQuery>, QueryRejection>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, ApiError> {
+    let Query(StreamQuery {
+        label,
+        max_in_flight,
+    }) = query?;
+    let selector: Selector = label.as_deref().unwrap_or_default().parse()?;
+    let max_in_flight = max_in_flight.unwrap_or(DEFAULT_MAX_IN_FLIGHT);
+    if max_in_flight == 0 {
+        return Err(PopError::InvalidCount.into());
+    }
+    let connection = StreamConnection {
+        context,
+        shutdown: shutdown.clone(),
+        selector,
+        waiting,
+        streamed,
+        keys,
+        slots: Arc::new(Semaphore::new(max_in_flight)),
+        executions: Vec::new(),
+    };
+    let executions = stream::unfold(Some(connection), |connection| async move {
+        let mut connection = connection?;
+        let execution = match connection.next().await {
+            Ok(execution) => execution,
+            Err(err) => {
+                tracing::error!(%err, "Could not pop a task to be streamed");
+                let (_, err) = ApiError::from(err).serialize();
+                let event = Event::default().event("error").json_data(err);
+                return Some((event.map_err(axum::Error::new), None));
+            }
+        };
+        increment_counter!(metrics::TASKS_POPPED);
+        tracing::info!(id = ?execution.0.task.0.id, name = %execution.0.task.0.name, deadline = %execution.0.deadline, "Streamed task");
+        let event = execution
+            .conceal(&connection.keys)
+            .map_err(axum::Error::new)
+            .and_then(|execution| {
+                Event::default()
+                    .event("execution")
+                    .json_data(execution)
+                    .map_err(axum::Error::new)
+            });
+        Some((event, Some(connection)))
+    });
+    Ok(
+        Sse::new(executions.take_until(shutdown.cancelled_owned()))
+            .keep_alive(KeepAlive::default()),
+    )
+}
+
+/// The tasks streamed on a connection to `GET /v1/stream`.
+struct StreamConnection {
+    context: Context,
+    shutdown: CancellationToken,
+    selector: Selector,
+    waiting: Waiting,
+    streamed: Streamed,
+    keys: Keys,
+    /// One for each task which can be processing at once
+    slots: Arc<Semaphore>,
+    /// The tasks streamed, which may still be holding a slot
+    executions: Vec<(store::TaskKey, Uuid)>,
+}
+
+impl StreamConnection {
+    /// Waits for a slot to be free, and then for a task to be ready to pop.
+    async fn next(&mut self) -> Result<store::Execution, PopError> {
+        let slot = self
+            .slots
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("the slots are never closed");
+        let streamed = &self.streamed;
+        self.executions
+            .retain(|(_, lease_token)| streamed.deadline(*lease_token).is_some());
+        let guard = self.waiting.wait();
+        let execution = self.context.pop(&self.selector).await?;
+        drop(guard);
+        let lease_token = execution.0.lease_token;
+        self.streamed.hold(lease_token, execution.0.deadline, slot);
+        self.executions.push((execution.0.task.0.id, lease_token));
+        Ok(execution)
+    }
+}
+
+impl Drop for StreamConnection {
+    fn drop(&mut self) {
+        for (id, lease_token) in self.executions.drain(..) {
+            // The workers may still complete them while the server shuts down
+            if !self.streamed.release(lease_token) || self.shutdown.is_cancelled() {
+                continue;
+            }
+            let context = self.context.clone();
+            let keys = self.keys.clone();
+            let reason = "The stream it was handed out on was closed".to_string();
+            tokio::spawn(async move {
+                let failed = context.fail(id, lease_token, Some(reason));
+                match keys.scope(failed).await {
+                    Ok(()) => tracing::info!(?id, "Streamed task failed on disconnection"),
+                    Err(err) => tracing::debug!(?id, %err, "Could not fail streamed task"),
+                }
+            });
+        }
+    }
+}
+
+/// Completes a task being processed, for either frontend. The lease token
+/// of its execution is required.
+async fn complete_task(
+    context: &Context,
+    keys: &Keys,
+    streamed: &Streamed,
+    id: taskie_structures::TaskKey,
+    lease_token: Option<Uuid>,
+    result: Option<serde_json::Value>,
+) -> Result<(), ApiError> {
+    let id = id.reveal(keys)?;
+    let lease_token = lease_token.ok_or(CompleteError::LeaseMismatch(id))?;
+    match context.complete(id, lease_token, result).await {
+        Ok(()) => {
+            increment_counter!(metrics::TASKS_COMPLETED);
+            tracing::info!(?id, "Task completed");
+        }
+        // The worker retried a completion which went through
+        Err(CompleteError::AlreadyCompleted(_)) => {
+            tracing::debug!(?id, "Task already completed");
+        }
+        Err(err) => return Err(err.into()),
+    }
+    streamed.release(lease_token);
+    Ok(())
+}
+
+#[axum_macros::debug_handler(state = AppState)]
+async fn complete(
+    State(context): State<Context>,
+    State(keys): State<Keys>,
+    State(streamed): State<Streamed>,
+    Json(CompleteTask {
+        id,
+        idempotency_key,
+        lease_token,
+        result,
+    }): Json<CompleteTask>,
+) -> Result<StatusCode, ApiError> {
+    let id = match (id, idempotency_key) {
+        (Some(id), None) => id,
+        (id, Some(key)) => {
+            let pushed = context.idempotent_task(&key).await?;
+            match id.map(|id| id.reveal(&keys)).transpose()? {
+                Some(id) if id != pushed => {
+                    return Err(CompleteError::AmbiguousTask {
+                        id,
+                        idempotency_key: key,
+                    }
+                    .into())
+                }
+                _ => pushed.conceal(&keys)?,
+            }
+        }
+        (None, None) => return Err(CompleteError::MissingTask.into()),
+    };
+    complete_task(&context, &keys, &streamed, id, lease_token, result).await?;
+    Ok(StatusCode::OK)
+}
+
+/// Completes a task and pushes the tasks following it at once, so that they
+/// cannot be lost if the worker crashes in between.
+async fn complete_and_push(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(CompleteAndPush {
+        id,
+        lease_token,
+        result,
+        mut tasks,
+    }): Json<CompleteAndPush>,
+) -> Result<Json<Vec<Task>>, ApiError> {
+    set_traceparent(&headers, &mut tasks);
+    let id = id.reveal(&state.keys)?;
+    let lease_token = lease_token.ok_or(CompleteError::LeaseMismatch(id))?;
+    let tasks = decode_tasks(&state, tasks)?;
+    let tasks = state
+        .store
+        .complete_and_push(id, lease_token, result, tasks)
+        .await?;
+    state.streamed.release(lease_token);
+    increment_counter!(metrics::TASKS_COMPLETED);
+    tracing::info!(?id, "Task completed");
+    Ok(Json(pushed(&state.keys, tasks)?))
+}
+
+/// Completes each task independently, reporting whether it succeeded for
+/// every one of them. The tasks without a lease token are not completed.
+async fn complete_batch(
+    State(context): State<Context>,
+    State(keys): State<Keys>,
+    State(streamed): State<Streamed>,
+    Json(CompleteBatch { ids, lease_tokens }): Json<CompleteBatch>,
+) -> Result<Json<Vec<Completion>>, ApiError> {
+    let decoded: Vec<Result<(store::TaskKey, Uuid), ApiError>> = ids
+        .iter()
+        .enumerate()
+        .map(|(position, id)| {
+            let key = id.clone().reveal(&keys)?;
+            let lease_token = lease_tokens
+                .get(position)
+                .ok_or(CompleteError::LeaseMismatch(key))?;
+            Ok((key, *lease_token))
+        })
+        .collect();
+    let valid = decoded.iter().filter_map(|key| key.as_ref().ok().copied());
+    let mut outcomes = context.complete_many(valid.collect()).await?.into_iter();
+
+    let mut completions = Vec::with_capacity(ids.len());
+    for (id, key) in ids.into_iter().zip(decoded) {
+        let outcome: Result<(), ApiError> = match key {
+            Ok((_, lease_token)) => {
+                let (key, outcome) = outcomes.next().expect("an outcome for each task");
+                match outcome {
+                    Ok(()) => {
+                        increment_counter!(metrics::TASKS_COMPLETED);
+                        tracing::info!(id = ?key, "Task completed");
+                        streamed.release(lease_token);
+                        Ok(())
+                    }
+                    Err(CompleteError::AlreadyCompleted(_)) => {
+                        streamed.release(lease_token);
+                        Ok(())
+                    }
+                    Err(err) => Err(err.into()),
+                }
+            }
+            Err(err) => Err(err),
+        };
+        completions.push(Completion {
+            id,
+            error: outcome.err().map(|err| err.serialize().1),
+        });
+    }
+    Ok(Json(completions))
+}
+
+async fn fail(
+    State(context): State<Context>,
+    State(keys): State<Keys>,
+    State(streamed): State<Streamed>,
+    Json(FailTask {
+        id,
+        lease_token,
+        reason,
+    }): Json<FailTask>,
+) -> Result<StatusCode, ApiError> {
+    let id = id.reveal(&keys)?;
+    let lease_token = lease_token.ok_or(FailError::LeaseMismatch(id))?;
+    context.fail(id, lease_token, reason.clone()).await?;
+    streamed.release(lease_token);
+    increment_counter!(metrics::TASKS_FAILED);
+    tracing::info!(?id, ?reason, "Task failed");
+    Ok(StatusCode::OK)
+}
+
+/// Times out a task being processed right away, to reclaim it from a worker
+/// known to be dead without waiting for its deadline.
+async fn requeue(
+    State(context): State<Context>,
+    State(keys): State<Keys>,
+    Path(id): Path<taskie_structures::TaskKey>,
+) -> Result<StatusCode, ApiError> {
+    let id = id.reveal(&keys)?;
+    context.requeue(id).await?;
+    tracing::info!(?id, "Task requeued before its deadline");
+    Ok(StatusCode::OK)
+}
+
+async fn heartbeat(
+    State(context): State<Context>,
+    State(keys): State<Keys>,
+    State(streamed): State<Streamed>,
+    Json(Heartbeat {
+        id,
+        lease_token,
+        extend,
+    }): Json<Heartbeat>,
+) -> Result<(StatusCode, Json<Deadline>), ApiError> {
+    let id = id.reveal(&keys)?;
+    let lease_token = lease_token.ok_or(HeartbeatError::LeaseMismatch(id))?;
+    let deadline = context.heartbeat(id, lease_token, extend).await?;
+    streamed.extend(lease_token, deadline);
+    tracing::debug!(?id, %deadline, "Task deadline extended");
+    Ok((StatusCode::OK, Json(Deadline { deadline })))
+}
+
+/// Records how far a task got, and moves its deadline when asked to.
+async fn progress(
+    State(context): State<Context>,
+    State(keys): State<Keys>,
+    State(streamed): State<Streamed>,
+    Json(ReportProgress {
+        id,
+        lease_token,
+        percent,
+        extend,
+    }): Json<ReportProgress>,
+) -> Result<Json<Progress>, ApiError> {
+    let id = id.reveal(&keys)?;
+    let lease_token = lease_token.ok_or(ProgressError::LeaseMismatch(id))?;
+    let deadline = context.progress(id, lease_token, percent, extend).await?;
+    if let Some(deadline) = deadline {
+        streamed.extend(lease_token, deadline);
+    }
+    tracing::debug!(?id, percent, ?deadline, "Task progress reported");
+    Ok(Json(Progress { percent, deadline }))
+}
+
+/// Counts the tasks in each state.
+async fn stats(
+    State(context): State<Context>,
+    State(keys): State<Keys>,
+    State(waiting): State<Waiting>,
+) -> Result<Json<Stats>, ApiError> {
+    let mut stats = context.stats().await?;
+    stats.0.waiting = waiting.count();
+    Ok(Json(stats.conceal(&keys)?))
+}
+
+/// Tells which build of the server is deployed, and which backend its store
+/// runs on.
+async fn version(State(context): State<Context>) -> Json<Version> {
+    Json(Version {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        git_sha: env!("TASKIE_GIT_SHA").to_string(),
+        backend: context.backend_name().to_string(),
+    })
+}
+
+/// Removes every task, for testing or to recover from a broken state.
+async fn purge(State(context): State<Context>) -> Result<StatusCode, ApiError> {
+    context.purge().await?;
+    tracing::warn!("All the tasks have been purged");
+    Ok(StatusCode::OK)
+}
+
+async fn dead_letters(
+    State(context): State<Context>,
+    State(keys): State<Keys>,
+) -> Result<(StatusCode, Json<Vec<DeadLetter>>), ApiError> {
+    let dead_letters = context
+        .dead_letters()
+        .await?
+        .into_iter()
+        .map(|(task, reason)| {
+            Ok(DeadLetter {
+                task: task.conceal(&keys)?,
+                reason,
+            })
+        })
+        .collect::<Result<Vec<_>, ConcealError>>()?;
+    Ok((StatusCode::OK, Json(dead_letters)))
+}
+
+async fn requeue_dead_letter(
+    State(context): State<Context>,
+    State(keys): State<Keys>,
+    Path(id): Path<taskie_structures::TaskKey>,
+) -> Result<(StatusCode, Json<Task>), ApiError> {
+    let id = id.reveal(&keys)?;
+    let task = context.requeue_dead_letter(id).await?;
+    tracing::info!(?id, "Dead-lettered task requeued");
+    Ok((StatusCode::OK, Json(task.conceal(&keys)?)))
+}
+
+/// Sets the JSON Schema the payloads of the tasks named `name` are validated
+/// against from now on.
+async fn register_schema(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(schema): Json<serde_json::Value>,
+) -> Result<StatusCode, ApiError> {
+    state.schemas.register(&name, &schema)?;
+    tracing::info!(%name, "Payload schema registered");
+    Ok(StatusCode::OK)
+}
+
+async fn health() -> StatusCode {
+    StatusCode::OK
+}
+
+async fn ready(
+    State(context): State<Context>,
+    State(shutdown): State<CancellationToken>,
+) -> StatusCode {
+    if !shutdown.is_cancelled() && context.health().await {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+async fn render_metrics(State(handle): State<PrometheusHandle>) -> String {
+    handle.render()
+}
+
+/// Lists a page of the tasks in the store, for operators to go through.
+async fn list(
+    State(context): State<Context>,
+    State(keys): State<Keys>,
+    query: Result<Query<ListQuery>, QueryRejection>,
+) -> Result<Json<TaskPage>, ApiError> {
+    let Query(query) = query?;
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .min(MAX_LIST_LIMIT);
+    let (tasks, total) = context.list(query.offset, limit, query.status).await?;
+    let tasks = tasks
+        .into_iter()
+        .map(|task| task.conceal(&keys))
+        .collect::<Result<_, ConcealError>>()?;
+    Ok(Json(TaskPage { tasks, total }))
+}
+
+/// Returns the task which would be popped next, without popping it, for
+/// monitoring tools to look at.
+async fn peek(
+    State(context): State<Context>,
+    State(keys): State<Keys>,
+) -> Result<Response, ApiError> {
+    match context.peek().await? {
+        Some(task) => Ok(Json(task.conceal(&keys)?).into_response()),
+        None => Ok(StatusCode::NO_CONTENT.into_response()),
+    }
+}
+
+async fn get_task(
+    State(context): State<Context>,
+    State(keys): State<Keys>,
+    Path(id): Path<taskie_structures::TaskKey>,
+) -> Result<Json<Task>, ApiError> {
+    let id = id.reveal(&keys)?;
+    let task = context.get(id).await?;
+    Ok(Json(task.conceal(&keys)?))
+}
+
+/// Returns the tasks still waiting for a task to be completed, to find out
+/// what a slow task is holding up.
+async fn dependents(
+    State(context): State<Context>,
+    State(keys): State<Keys>,
+    Path(id): Path<taskie_structures::TaskKey>,
+) -> Result<Json<Vec<taskie_structures::TaskKey>>, ApiError> {
+    let id = id.reveal(&keys)?;
+    let dependents = context
+        .dependents(id)
+        .await?
+        .into_iter()
+        .map(|id| id.conceal(&keys))
+        .collect::<Result<_, ConcealError>>()?;
+    Ok(Json(dependents))
+}
+
+/// Quotes `id` as an identifier of a DOT document.
+fn dot_id(id: &str) -> String {
+    format!("\"{}\"", id.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Exports the tasks and their pending dependencies, as edges from each
+/// dependency to the tasks waiting for it, to render the graph. The document
+/// is streamed a line at a time rather than built in full.
+async fn graph(
+    State(context): State<Context>,
+    State(keys): State<Keys>,
+    query: Result<Query<GraphQuery>, QueryRejection>,
+) -> Result<Response, ApiError> {
+    let Query(GraphQuery {
+        format: GraphFormat::Dot,
+    }) = query?;
+    let snapshot = context.graph().await?;
+    let nodes = snapshot
+        .nodes
+        .into_iter()
+        .map(|(id, name)| Ok((id.conceal(&keys)?, name)))
+        .collect::<Result<Vec<_>, ConcealError>>()?;
+    let edges = snapshot
+        .edges
+        .into_iter()
+        .map(|(dependent, dependency)| Ok((dependent.conceal(&keys)?, dependency.conceal(&keys)?)))
+        .collect::<Result<Vec<_>, ConcealError>>()?;
+    let lines =
+        std::iter::once("digraph taskie {\n".to_string())
+            .chain(nodes.into_iter().map(|(id, name)| {
+                format!("  {} [label={}];\n", dot_id(id.as_str()), dot_id(&name))
+            }))
+            .chain(edges.into_iter().map(|(dependent, dependency)| {
+                format!(
+                    "  {} -> {};\n",
+                    dot_id(dependency.as_str()),
+                    dot_id(dependent.as_str())
+                )
+            }))
+            .chain(std::iter::once("}\n".to_string()))
+            .map(Ok::<_, Infallible>);
+    Ok((
+        [(CONTENT_TYPE, "text/vnd.graphviz")],
+        StreamBody::new(stream::iter(lines)),
+    )
+        .into_response())
+}
+
+/// Returns the results the dependencies of a task were completed with, so
+/// that it can consume their output.
+async fn dependency_results(
+    State(context): State<Context>,
+    State(keys): State<Keys>,
+    Path(id): Path<taskie_structures::TaskKey>,
+) -> Result<Json<Vec<DependencyResult>>, ApiError> {
+    let id = id.reveal(&keys)?;
+    let results = context
+        .dependency_results(id)
+        .await?
+        .into_iter()
+        .map(|(id, result)| {
+            Ok(DependencyResult {
+                id: id.conceal(&keys)?,
+                result,
+            })
+        })
+        .collect::<Result<_, ConcealError>>()?;
+    Ok(Json(results))
+}
+
+/// Returns the final status of a finished task, along with the result it was
+/// completed with, so that producers can wait for the outcome of a task.
+async fn task_result(
+    State(context): State<Context>,
+    State(keys): State<Keys>,
+    Path(id): Path<taskie_structures::TaskKey>,
+) -> Result<Json<TaskResult>, ApiError> {
+    let key = id.clone().reveal(&keys)?;
+    let (status, result) = context.task_result(key).await?;
+    Ok(Json(TaskResult { id, status, result }))
+}
+
+/// Waits for a specific task to be ready, completed or failed, and returns
+/// it along with the status it reached, without popping it. Nothing is
+/// returned once the `timeout` expires.
+async fn await_task(
+    State(context): State<Context>,
+    State(keys): State<Keys>,
+    State(shutdown): State<CancellationToken>,
+    Path(id): Path<taskie_structures::TaskKey>,
+    query: Result<Query<AwaitQuery>, QueryRejection>,
+) -> Result<Response, ApiError> {
+    let Query(AwaitQuery { timeout }) = query?;
+    let key = id.clone().reveal(&keys)?;
+    let (status, task) = tokio::select! {
+        awaited = context.await_task(key) => awaited?,
+        _ = expired(timeout) => return Ok(StatusCode::NO_CONTENT.into_response()),
+        _ = shutdown.cancelled() => return Err(ApiError::ShuttingDown),
+    };
+    let task = task.map(|task| task.conceal(&keys)).transpose()?;
+    Ok(Json(AwaitedTask { id, status, task }).into_response())
+}
+
+/// Waits for every one of the tasks to be completed or failed, and returns
+/// them in the order they were given along with the status they reached.
+/// Nothing is returned once the `timeout` expires, unless they all finished.
+async fn wait(
+    State(context): State<Context>,
+    State(keys): State<Keys>,
+    State(shutdown): State<CancellationToken>,
+    query: Result<Query<AwaitQuery>, QueryRejection>,
+    Json(WaitTasks { ids }): Json<WaitTasks>,
+) -> Result<Response, ApiError> {
+    let Query(AwaitQuery { timeout }) = query?;
+    let decoded = ids
+        .iter()
+        .map(|id| id.clone().reveal(&keys))
+        .collect::<Result<Vec<store::TaskKey>, KeyDecodeError>>()?;
+    let finished = future::try_join_all(decoded.into_iter().map(|key| context.await_finished(key)));
+    let finished = tokio::select! {
+        finished = finished => finished?,
+        _ = expired(timeout) => return Ok(StatusCode::NO_CONTENT.into_response()),
+        _ = shutdown.cancelled() => return Err(ApiError::ShuttingDown),
+    };
+    let awaited = ids
+        .into_iter()
+        .zip(finished)
+        .map(|(id, (status, task))| {
+            let task = task.map(|task| task.conceal(&keys)).transpose()?;
+            Ok(AwaitedTask { id, status, task })
+        })
+        .collect::<Result<Vec<_>, ConcealError>>()?;
+    Ok(Json(awaited).into_response())
+}
+
+async fn recurring(
+    State(context): State<Context>,
+    State(keys): State<Keys>,
+) -> Result<Json<Vec<Recurring>>, ApiError> {
+    let recurring = context
+        .recurring()
+        .await?
+        .into_iter()
+        .map(|(id, task)| {
+            Ok(Recurring {
+                id: id.conceal(&keys)?,
+                task: task.conceal(&keys)?,
+            })
+        })
+        .collect::<Result<_, ConcealError>>()?;
+    Ok(Json(recurring))
+}
+
+async fn delete_recurring(
+    State(context): State<Context>,
+    State(keys): State<Keys>,
+    Path(id): Path<taskie_structures::TaskKey>,
+) -> Result<StatusCode, ApiError> {
+    let id = id.reveal(&keys)?;
+    context.delete_recurring(id).await?;
+    tracing::info!(?id, "Recurring task deleted");
+    Ok(StatusCode::OK)
+}
+
+async fn cancel(
+    State(context): State<Context>,
+    State(keys): State<Keys>,
+    Path(id): Path<taskie_structures::TaskKey>,
+) -> Result<StatusCode, ApiError> {
+    let id = id.reveal(&keys)?;
+    context.cancel(id).await?;
+    tracing::info!(?id, "Task cancelled");
+    Ok(StatusCode::OK)
+}
+
+/// Changes a task which has not been popped yet, keeping its key.
+async fn update(
+    State(context): State<Context>,
+    State(keys): State<Keys>,
+    Path(id): Path<taskie_structures::TaskKey>,
+    Json(patch): Json<TaskPatch>,
+) -> Result<Json<Task>, ApiError> {
+    let id = id.reveal(&keys)?;
+    let task = context.update(id, patch.reveal(&keys)?).await?;
+    tracing::info!(?id, "Task updated");
+    Ok(Json(task.conceal(&keys)?))
+}
+
+/// Serves the request with the keys of the server in scope, so that the keys
+/// of the tasks are shown concealed in its errors and logs.
+async fn scope_keys<B>(
+    State(keys): State<Keys>,
+    request: Request<B>,
+    next: middleware::Next<B>,
+) -> Response {
+    keys.scope(next.run(request)).await
+}
+
+/// Serves the requests to a namespace with the API of the server, as if they
+/// were sent without its name, on its own store.
+async fn namespaced<B>(
+    State(state): State<AppState>,
+    Path((namespace, _)): Path<(String, String)>,
+    request: Request<B>,
+) -> Result<Response, ApiError>
+where
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    let Namespace { store, waiting } = state.namespaces.get(&namespace).await?;
+    let (parts, body) = request.into_parts();
+    let prefix = format!("/v1/{}", namespace);
+    let path = parts.uri.path().strip_prefix(&prefix).unwrap_or("/");
+    let uri = match parts.uri.query() {
+        Some(query) => format!("/v1{}?{}", path, query),
+        None => format!("/v1{}", path),
+    };
+    // Only the method, the headers and the body are passed along, as the
+    // parameters of this route would be mixed with the ones of the API
+    let mut request = Request::builder()
+        .method(parts.method)
+        .uri(uri)
+        .version(parts.version)
+        .body(body)
+        .expect("the parts come from a valid request");
+    *request.headers_mut() = parts.headers;
+
+    let state = AppState {
+        store,
+        waiting,
+        // A namespace has no namespaces of its own
+        namespaces: Namespaces::default(),
+        ..state
+    };
+    let response = routes().with_state(state).oneshot(request).await;
+    Ok(response.into_response())
+}
+
+/// The routes of the API of a store.
+fn routes<B>() -> Router<AppState, B>
+where
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    Router::new()
+        .route("/v1/push", put(push))
+        .route("/v1/pop", get(pop))
+        .route("/v1/peek", get(peek))
+        .route("/v1/stream", get(stream))
+        .route("/v1/complete", post(complete))
+        .route("/v1/complete-and-push", post(complete_and_push))
+        .route("/v1/complete-batch", post(complete_batch))
+        .route("/v1/fail", post(fail))
+        .route("/v1/heartbeat", post(heartbeat))
+        .route("/v1/progress", post(progress))
+        .route("/v1/dead-letters", get(dead_letters))
+        .route("/v1/stats", get(stats))
+        .route("/v1/version", get(version))
+        .route("/v1/dead-letters/:id/requeue", post(requeue_dead_letter))
+        .route("/v1/tasks", get(list))
+        .route("/v1/task/:id", get(get_task).delete(cancel).patch(update))
+        .route("/v1/task/:id/deps-results", get(dependency_results))
+        .route("/v1/task/:id/dependents", get(dependents))
+        .route("/v1/task/:id/result", get(task_result))
+        .route("/v1/task/:id/await", get(await_task))
+        .route("/v1/wait", post(wait))
+        .route("/v1/task/:id/requeue", post(requeue))
+        .route("/v1/graph", get(graph))
+        .route("/v1/schemas/:name", post(register_schema))
+        .route("/v1/recurring", get(recurring))
+        .route("/v1/recurring/:id", delete(delete_recurring))
+        .route("/v1/admin/purge", post(purge))
+}
+
+/// Builds the router serving the API with the given state. When `api_token`
+/// is set, it has to be sent with every request but the probes and the
+/// metrics.
+pub fn router(state: AppState, api_token: ApiToken) -> Router {
+    let body_limit = DefaultBodyLimit::max(state.limits.max_body_bytes);
+    let router = routes();
+    #[cfg(feature = "graphql")]
+    let router = router.route_service("/graphql", graphql::GraphQL::new(graphql::schema(&state)));
+    // The probes and the metrics are left out of the authentication and of
+    // the rate limit, which only counts the authenticated requests
+    router
+        .route("/v1/:namespace/*path", any(namespaced))
+        .route_layer(middleware::from_fn_with_state(
+            state.rate_limit.clone(),
+            ratelimit::limit,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            api_token,
+            auth::authenticate,
+        ))
+        .route("/metrics", get(render_metrics))
+        .route("/health", get(health))
+        .route("/ready", get(ready))
+        .layer(body_limit)
+        // Request bodies can be gzipped, and so can the responses for the
+        // clients accepting it, but for the event streams
+        .layer(
+            ServiceBuilder::new()
+                // Only the inner service could fail, and routes never do
+                .layer(HandleErrorLayer::new(|err: BoxError| async move {
+                    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+                }))
+                .layer(RequestDecompressionLayer::new()),
+        )
+        .layer(CompressionLayer::new().compress_when(
+            // Compressed events would be held back until enough of them
+            // followed
+            DefaultPredicate::new().and(NotForContentType::new("text/event-stream")),
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.keys.clone(),
+            scope_keys,
+        ))
+        .layer(middleware::from_fn(telemetry::propagate))
+        .with_state(state)
+}
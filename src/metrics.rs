@@ -0,0 +1,40 @@
+//! Prometheus metrics about the tasks going through the queue, rendered at
+//! `GET /metrics`.
+
+use metrics::{describe_counter, describe_gauge, describe_histogram, Unit};
+use metrics_exporter_prometheus::{BuildError, PrometheusBuilder, PrometheusHandle};
+
+pub static TASKS_PUSHED: &str = "taskie_tasks_pushed_total";
+pub static TASKS_POPPED: &str = "taskie_tasks_popped_total";
+pub static TASKS_COMPLETED: &str = "taskie_tasks_completed_total";
+pub static TASKS_FAILED: &str = "taskie_tasks_failed_total";
+pub static TASKS_TIMED_OUT: &str = "taskie_tasks_timed_out_total";
+pub static TASKS_EXPIRED: &str = "taskie_tasks_expired_total";
+pub static QUEUE_DEPTH: &str = "taskie_queue_depth";
+pub static PROCESSING: &str = "taskie_processing";
+pub static POP_DURATION: &str = "taskie_pop_duration_seconds";
+pub static WAITING_WORKERS: &str = "taskie_waiting_workers";
+
+/// Installs the global metrics recorder, returning the handle used to render
+/// the metrics in the Prometheus text format.
+pub fn install() -> Result<PrometheusHandle, BuildError> {
+    let handle = PrometheusBuilder::new().install_recorder()?;
+    describe_counter!(TASKS_PUSHED, "Tasks pushed on the queue");
+    describe_counter!(TASKS_POPPED, "Tasks popped by the workers");
+    describe_counter!(TASKS_COMPLETED, "Tasks completed by the workers");
+    describe_counter!(TASKS_FAILED, "Tasks failed by the workers");
+    describe_counter!(TASKS_TIMED_OUT, "Tasks which were not completed in time");
+    describe_counter!(
+        TASKS_EXPIRED,
+        "Tasks dropped as they were not popped in time"
+    );
+    describe_gauge!(QUEUE_DEPTH, "Tasks ready to be popped");
+    describe_gauge!(PROCESSING, "Tasks being processed by the workers");
+    describe_gauge!(WAITING_WORKERS, "Workers waiting for a task to be ready");
+    describe_histogram!(
+        POP_DURATION,
+        Unit::Seconds,
+        "How long the workers waited for a task to be popped"
+    );
+    Ok(handle)
+}
@@ -0,0 +1,116 @@
+//! Logically separate queues served by the same server. The API of a
+//! namespace is the one of the server with its name after `/v1`, e.g.
+//! `PUT /v1/:namespace/push`, and it has its own store: its tasks can only
+//! depend on the tasks of the same namespace. The stores are created the
+//! first time their namespace is used, up to a limit, and the names of the
+//! existing routes, such as `task`, cannot be used as namespaces.
+
+use std::{collections::HashMap, sync::Arc};
+
+use axum::http::StatusCode;
+use thiserror::Error;
+use tokio::sync::RwLock;
+
+use crate::{Context, Waiting};
+
+/// The longest name of a namespace
+pub static MAX_NAME_LENGTH: usize = 64;
+
+#[derive(Error, Debug)]
+pub enum NamespaceError {
+    #[error("Namespaces are not enabled")]
+    Disabled,
+    #[error("Invalid namespace {0:?}: only letters, digits, '-' and '_' are allowed, up to {MAX_NAME_LENGTH} of them")]
+    InvalidName(String),
+    #[error("There are already {0} namespaces, no more can be created")]
+    TooMany(usize),
+}
+
+impl NamespaceError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            NamespaceError::Disabled => StatusCode::NOT_FOUND,
+            NamespaceError::InvalidName(_) => StatusCode::BAD_REQUEST,
+            NamespaceError::TooMany(_) => StatusCode::INSUFFICIENT_STORAGE,
+        }
+    }
+}
+
+/// The state of a namespace, in place of the one of the server.
+#[derive(Clone)]
+pub struct Namespace {
+    pub store: Context,
+    pub waiting: Waiting,
+}
+
+struct Inner {
+    /// The most namespaces which can be created
+    limit: usize,
+    create: Box<dyn Fn() -> Context + Send + Sync>,
+    namespaces: RwLock<HashMap<String, Namespace>>,
+}
+
+/// The namespaces created so far, by name. None can be used by default.
+#[derive(Clone, Default)]
+pub struct Namespaces(Option<Arc<Inner>>);
+
+impl Namespaces {
+    /// Allows up to `limit` namespaces, each with a store built by `create`
+    /// whose monitor is spawned along with it.
+    pub fn new(limit: usize, create: impl Fn() -> Context + Send + Sync + 'static) -> Self {
+        Namespaces(Some(Arc::new(Inner {
+            limit,
+            create: Box::new(create),
+            namespaces: Default::default(),
+        })))
+    }
+
+    /// The namespace called `name`, which is created if it does not exist yet.
+    pub async fn get(&self, name: &str) -> Result<Namespace, NamespaceError> {
+        let inner = self.0.as_ref().ok_or(NamespaceError::Disabled)?;
+        if let Some(namespace) = inner.namespaces.read().await.get(name) {
+            return Ok(namespace.clone());
+        }
+        if name.is_empty()
+            || name.len() > MAX_NAME_LENGTH
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(NamespaceError::InvalidName(name.to_string()));
+        }
+
+        let mut namespaces = inner.namespaces.write().await;
+        if let Some(namespace) = namespaces.get(name) {
+            return Ok(namespace.clone());
+        }
+        if namespaces.len() >= inner.limit {
+            return Err(NamespaceError::TooMany(namespaces.len()));
+        }
+        let store = (inner.create)();
+        let monitor_store = store.clone();
+        let monitored = name.to_string();
+        tokio::spawn(async move {
+            if let Err(err) = monitor_store.monitor().await {
+                tracing::error!(namespace = %monitored, %err, "Task monitor failed");
+            }
+        });
+        tracing::info!(namespace = %name, "Namespace created");
+        let namespace = Namespace {
+            store,
+            waiting: Waiting::default(),
+        };
+        namespaces.insert(name.to_string(), namespace.clone());
+        Ok(namespace)
+    }
+
+    /// Stops the monitors of the stores of every namespace.
+    pub async fn shutdown(&self) {
+        let Some(inner) = &self.0 else {
+            return;
+        };
+        for namespace in inner.namespaces.read().await.values() {
+            namespace.store.shutdown().await;
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! Limits how many requests each client can send, so that a single
+//! misbehaving producer cannot flood the queue. Every client has a token
+//! bucket, refilled at `RATE_LIMIT` requests per second and holding up to
+//! `RATE_LIMIT_BURST` of them, and the requests finding it empty are refused
+//! with `429 Too Many Requests`. There is no limit unless `RATE_LIMIT` is set.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex, PoisonError},
+    time::Instant,
+};
+
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{header::AUTHORIZATION, Request},
+    middleware::Next,
+    response::Response,
+};
+use time::Duration;
+
+use crate::api::ApiError;
+
+/// How many buckets are kept before the full ones, i.e. of the clients which
+/// have been idle for a while, are swept away.
+static SWEEP_THRESHOLD: usize = 1024;
+
+/// Who the requests are accounted to: the token they are authenticated with
+/// or, when the API is unauthenticated, the address they come from.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+enum Client {
+    Token(Arc<str>),
+    Address(IpAddr),
+    /// The address is only known when the server is served with the
+    /// connection info, so the clients share a single bucket otherwise
+    Unknown,
+}
+
+impl Client {
+    fn of<B>(request: &Request<B>) -> Self {
+        let token = request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if let Some(token) = token {
+            return Client::Token(token.into());
+        }
+        match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+            Some(ConnectInfo(address)) => Client::Address(address.ip()),
+            None => Client::Unknown,
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+struct Limiter {
+    /// The requests per second the buckets are refilled with
+    rate: f64,
+    /// How many requests a bucket holds
+    burst: f64,
+    buckets: Mutex<HashMap<Client, Bucket>>,
+}
+
+impl Limiter {
+    /// Takes a token from the bucket of `client`, or returns how long until
+    /// there is one.
+    fn acquire(&self, client: Client, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        if buckets.len() >= SWEEP_THRESHOLD {
+            buckets.retain(|_, bucket| self.refill(bucket, now) < self.burst);
+        }
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        let tokens = self.refill(bucket, now);
+        bucket.updated = now;
+        if tokens >= 1.0 {
+            bucket.tokens = tokens - 1.0;
+            Ok(())
+        } else {
+            bucket.tokens = tokens;
+            Err(Duration::seconds_f64((1.0 - tokens) / self.rate))
+        }
+    }
+
+    /// The tokens in `bucket` as of `now`.
+    fn refill(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.rate).min(self.burst)
+    }
+}
+
+/// The rate limit shared by the requests to the API, unlimited by default.
+#[derive(Clone, Default)]
+pub struct RateLimit(Option<Arc<Limiter>>);
+
+impl RateLimit {
+    /// Allows `rate` requests per second to each client, and bursts of up to
+    /// `burst` of them. A rate which is not positive leaves it unlimited, and
+    /// bursts always allow at least one request.
+    pub fn new(rate: f64, burst: u32) -> Self {
+        if rate.is_nan() || rate <= 0.0 {
+            return RateLimit(None);
+        }
+        RateLimit(Some(Arc::new(Limiter {
+            rate,
+            burst: burst.max(1) as f64,
+            buckets: Default::default(),
+        })))
+    }
+}
+
+pub async fn limit<B>(
+    State(limit): State<RateLimit>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Result<Response, ApiError> {
+    if let Some(limiter) = &limit.0 {
+        limiter
+            .acquire(Client::of(&request), Instant::now())
+            .map_err(|retry_after| ApiError::RateLimited { retry_after })?;
+    }
+    Ok(next.run(request).await)
+}
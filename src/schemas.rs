@@ -0,0 +1,94 @@
+//! The JSON Schemas the payloads of the tasks are validated against when they
+//! are pushed, by the name of the task, so that malformed payloads are
+//! rejected before any worker sees them. The tasks whose name has no schema
+//! are pushed as they are, and a task without a payload is validated as
+//! `null`.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use axum::http::StatusCode;
+use jsonschema::JSONSchema;
+use serde_json::Value;
+use taskie_structures::InsertTask;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SchemaError {
+    #[error("Invalid JSON Schema for the tasks named {name}: {reason}")]
+    InvalidSchema { name: String, reason: String },
+    #[error("The payload of a task named {name} does not match its schema: {}", .errors.join("; "))]
+    InvalidPayload { name: String, errors: Vec<String> },
+}
+
+impl SchemaError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            SchemaError::InvalidSchema { .. } => StatusCode::BAD_REQUEST,
+            SchemaError::InvalidPayload { .. } => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+/// The compiled schemas, by task name.
+#[derive(Default)]
+pub struct Schemas(RwLock<HashMap<String, Arc<JSONSchema>>>);
+
+fn compile(name: &str, schema: &Value) -> Result<Arc<JSONSchema>, SchemaError> {
+    JSONSchema::compile(schema)
+        .map(Arc::new)
+        .map_err(|err| SchemaError::InvalidSchema {
+            name: name.to_string(),
+            reason: err.to_string(),
+        })
+}
+
+impl Schemas {
+    /// Compiles the schemas of `definitions`, by task name, i.e. as read from
+    /// a configuration file.
+    pub fn new(definitions: HashMap<String, Value>) -> Result<Self, SchemaError> {
+        let schemas = definitions
+            .iter()
+            .map(|(name, schema)| Ok((name.clone(), compile(name, schema)?)))
+            .collect::<Result<_, SchemaError>>()?;
+        Ok(Schemas(RwLock::new(schemas)))
+    }
+
+    /// Sets the schema of the tasks named `name`, replacing the one they had.
+    pub fn register(&self, name: &str, schema: &Value) -> Result<(), SchemaError> {
+        let schema = compile(name, schema)?;
+        self.0
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(name.to_string(), schema);
+        Ok(())
+    }
+
+    /// Checks the payload of each task against the schema of its name.
+    pub fn validate(&self, tasks: &[InsertTask]) -> Result<(), SchemaError> {
+        let schemas = self.0.read().unwrap_or_else(PoisonError::into_inner);
+        if schemas.is_empty() {
+            return Ok(());
+        }
+        for task in tasks.iter() {
+            let Some(schema) = schemas.get(&task.name) else {
+                continue;
+            };
+            let payload = task.payload.as_ref().unwrap_or(&Value::Null);
+            if let Err(errors) = schema.validate(payload) {
+                return Err(SchemaError::InvalidPayload {
+                    name: task.name.clone(),
+                    errors: errors
+                        .map(|err| match err.instance_path.to_string() {
+                            path if path.is_empty() => err.to_string(),
+                            path => format!("{} at {}", err, path),
+                        })
+                        .collect(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
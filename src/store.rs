@@ -1,18 +1,139 @@
-use std::fmt;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    future::Future,
+    str::FromStr,
+    sync::Arc,
+};
 
 use axum::{async_trait, http::StatusCode};
 use block_id::BlockId;
-use once_cell::sync::OnceCell;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use taskie_structures::{ErrorCode, Status, Uuid, DEFAULT_COST};
 use thiserror::Error;
+use time::{Duration, OffsetDateTime};
 
 use crate::stores::mem::CycleError;
 
-pub static KEY_GENERATOR: OnceCell<BlockId<char>> = OnceCell::new();
+tokio::task_local! {
+    /// The keys of the server the current request is for, to show the keys
+    /// of the tasks concealed in its errors and logs
+    static SCOPED_KEYS: Keys;
+}
+
+/// How many bytes of the HMAC of a key are appended to it, hex encoded
+static TAG_BYTES: usize = 8;
+
+/// Appends a truncated HMAC of the key to the concealed keys, and checks it
+/// back when they are decoded. Since the keys of the tasks are consecutive,
+/// their concealed form could otherwise be enumerated by anyone knowing the
+/// alphabet and the seed, or a few of them.
+#[derive(Clone)]
+pub struct KeySigner(Hmac<Sha256>);
+
+impl KeySigner {
+    pub fn new(secret: &[u8]) -> Self {
+        KeySigner(Hmac::new_from_slice(secret).expect("HMAC takes secrets of any length"))
+    }
+
+    fn mac(&self, key: u64) -> Hmac<Sha256> {
+        let mut mac = self.0.clone();
+        mac.update(&key.to_le_bytes());
+        mac
+    }
+
+    fn tag(&self, key: u64) -> String {
+        self.mac(key).finalize().into_bytes()[..TAG_BYTES]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Splits the concealed key into its unsigned part and its tag.
+    fn split(key: &str) -> Option<(&str, &str)> {
+        let at = key.len().checked_sub(2 * TAG_BYTES)?;
+        key.is_char_boundary(at).then(|| key.split_at(at))
+    }
+
+    /// Checks the tag in constant time, so that it cannot be guessed by
+    /// timing the responses.
+    fn verify(&self, key: u64, tag: &str) -> bool {
+        let tag: Option<Vec<u8>> = (0..tag.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(tag.get(i..i + 2)?, 16).ok())
+            .collect();
+        tag.is_some_and(|tag| self.mac(key).verify_truncated_left(&tag).is_ok())
+    }
+}
+
+/// Conceals the keys of the tasks into the ids handed out by the API, and
+/// decodes them back. Each server can have its own, so it is part of its
+/// state rather than global.
+#[derive(Clone)]
+pub struct Keys {
+    generator: Arc<BlockId<char>>,
+    /// Signs the concealed keys, when set, so that they cannot be guessed
+    signer: Option<KeySigner>,
+}
+
+impl Keys {
+    pub fn new(generator: BlockId<char>) -> Self {
+        Keys {
+            generator: Arc::new(generator),
+            signer: None,
+        }
+    }
+
+    /// Signs the concealed keys with `signer` as well.
+    pub fn signed(mut self, signer: KeySigner) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Runs `future` with these keys in scope, so that the keys of the tasks
+    /// are shown concealed in its errors and logs rather than as they are.
+    pub async fn scope<F: Future>(&self, future: F) -> F::Output {
+        SCOPED_KEYS.scope(self.clone(), future).await
+    }
+
+    fn encode(&self, TaskKey(key): TaskKey) -> Result<taskie_structures::TaskKey, ConcealError> {
+        let concealed = self
+            .generator
+            .encode_string(key)
+            .ok_or(ConcealError::InvalidKey)?;
+        Ok(taskie_structures::TaskId(match &self.signer {
+            Some(signer) => concealed + &signer.tag(key),
+            None => concealed,
+        }))
+    }
+
+    fn decode(
+        &self,
+        taskie_structures::TaskId(value): taskie_structures::TaskKey,
+    ) -> Result<TaskKey, KeyDecodeError> {
+        let Some(signer) = &self.signer else {
+            return self
+                .generator
+                .decode_string(&value)
+                .map(TaskKey)
+                .ok_or(KeyDecodeError::InvalidKey(value));
+        };
+        let Some((unsigned, tag)) = KeySigner::split(&value) else {
+            return Err(KeyDecodeError::InvalidSignature(value));
+        };
+        match self.generator.decode_string(unsigned) {
+            Some(key) if signer.verify(key, tag) => Ok(TaskKey(key)),
+            Some(_) => Err(KeyDecodeError::InvalidSignature(value)),
+            None => Err(KeyDecodeError::InvalidKey(value)),
+        }
+    }
+}
 
 #[derive(Error, Debug)]
 pub enum ConcealError {
-    #[error("Missing key decoder/generator")]
-    MissingGenerator,
     #[error("Encoding error")]
     InvalidKey,
 }
@@ -20,7 +141,6 @@ pub enum ConcealError {
 impl ConcealError {
     pub fn status(&self) -> StatusCode {
         match self {
-            ConcealError::MissingGenerator => StatusCode::INTERNAL_SERVER_ERROR,
             ConcealError::InvalidKey => StatusCode::BAD_REQUEST,
         }
     }
@@ -29,112 +149,263 @@ impl ConcealError {
 pub trait Conceal {
     type Concealed;
 
-    fn conceal(self) -> Result<Self::Concealed, ConcealError>;
+    fn conceal(self, keys: &Keys) -> Result<Self::Concealed, ConcealError>;
 }
 
 #[derive(Error, Debug)]
 pub enum KeyDecodeError {
-    #[error("Missing key decoder/generator")]
-    MissingGenerator,
     #[error("Invalid key: {}", .0)]
     InvalidKey(String),
+    #[error("Invalid key signature: {}", .0)]
+    InvalidSignature(String),
 }
 
 impl KeyDecodeError {
     pub fn status(&self) -> StatusCode {
         match self {
-            KeyDecodeError::MissingGenerator => StatusCode::INTERNAL_SERVER_ERROR,
-            KeyDecodeError::InvalidKey(_) => StatusCode::BAD_REQUEST,
+            KeyDecodeError::InvalidKey(_) | KeyDecodeError::InvalidSignature(_) => {
+                StatusCode::BAD_REQUEST
+            }
         }
     }
 }
 
-#[derive(Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+/// The inverse of `Conceal`, for what is received by the API.
+pub trait Reveal {
+    type Revealed;
+
+    fn reveal(self, keys: &Keys) -> Result<Self::Revealed, KeyDecodeError>;
+}
+
+#[derive(Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct TaskKey(pub u64);
 
-impl TryFrom<taskie_structures::TaskKey> for TaskKey {
-    type Error = KeyDecodeError;
+impl TaskKey {
+    /// The key concealed with the keys in scope, if any.
+    fn scoped(self) -> Option<taskie_structures::TaskKey> {
+        SCOPED_KEYS
+            .try_with(|keys| keys.encode(self).ok())
+            .ok()
+            .flatten()
+    }
+}
+
+impl Reveal for taskie_structures::TaskKey {
+    type Revealed = TaskKey;
 
-    fn try_from(value: taskie_structures::TaskKey) -> Result<Self, Self::Error> {
-        KEY_GENERATOR
-            .get()
-            .ok_or(KeyDecodeError::MissingGenerator)?
-            .decode_string(&value)
-            .map(TaskKey)
-            .ok_or(KeyDecodeError::InvalidKey(value))
+    fn reveal(self, keys: &Keys) -> Result<Self::Revealed, KeyDecodeError> {
+        keys.decode(self)
     }
 }
 
 impl Conceal for TaskKey {
-    type Concealed = String;
+    type Concealed = taskie_structures::TaskKey;
 
-    fn conceal(self) -> Result<Self::Concealed, ConcealError> {
-        KEY_GENERATOR
-            .get()
-            .ok_or(ConcealError::MissingGenerator)?
-            .encode_string(self.0)
-            .ok_or(ConcealError::InvalidKey)
+    fn conceal(self, keys: &Keys) -> Result<Self::Concealed, ConcealError> {
+        keys.encode(self)
     }
 }
 
 impl fmt::Display for TaskKey {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{}",
-            self.conceal().unwrap_or("broken concealer".to_string())
-        )
+        match self.scoped() {
+            Some(concealed) => write!(f, "{}", concealed),
+            None => write!(f, "#{}", self.0),
+        }
     }
 }
 
 impl fmt::Debug for TaskKey {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{}({})",
-            self.0,
-            self.conceal().unwrap_or("broken concealer".to_string())
-        )
+        match self.scoped() {
+            Some(concealed) => write!(f, "{}({})", self.0, concealed),
+            None => write!(f, "{}", self.0),
+        }
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct InsertTask(pub taskie_structures::InsertTask<taskie_structures::TaskName, TaskKey>);
 
-impl TryFrom<taskie_structures::InsertTask> for InsertTask {
-    type Error = KeyDecodeError;
+impl InsertTask {
+    /// Checks the parts of the task every store relies upon, before any of it
+    /// is stored.
+    pub fn validate(&self) -> Result<(), PushError> {
+        validate_duration(self.0.duration)?;
+        validate_dependencies(&self.0.depends_on)?;
+        let mut positions = HashSet::with_capacity(self.0.depends_on_batch.len());
+        match self
+            .0
+            .depends_on_batch
+            .iter()
+            .find(|&&position| !positions.insert(position))
+        {
+            Some(&position) => Err(PushError::InvalidBatchDependency {
+                position,
+                reason: "it is listed more than once",
+            }),
+            None => Ok(()),
+        }
+    }
 
-    fn try_from(value: taskie_structures::InsertTask) -> Result<Self, Self::Error> {
-        Ok(Self(taskie_structures::InsertTask {
-            name: value.name,
-            payload: value.payload,
-            duration: value.duration,
-            depends_on: value
+    /// Every dependency of the task at `position` in its batch, once the
+    /// tasks of the batch are given `keys`: the ones in `depends_on`, then
+    /// the ones on the other tasks of the batch. A task pushed again depends
+    /// on the one it repeats, which could already be a dependency.
+    pub fn dependencies(
+        &self,
+        position: usize,
+        keys: &[TaskKey],
+    ) -> Result<Vec<TaskKey>, PushError> {
+        let mut dependencies = self.0.depends_on.clone();
+        for &dependency in self.0.depends_on_batch.iter() {
+            let Some(&key) = keys.get(dependency) else {
+                return Err(PushError::InvalidBatchDependency {
+                    position: dependency,
+                    reason: "there is no such task in the batch",
+                });
+            };
+            if dependency == position {
+                return Err(PushError::SelfDependency(key));
+            }
+            if !dependencies.contains(&key) {
+                dependencies.push(key);
+            }
+        }
+        Ok(dependencies)
+    }
+}
+
+fn validate_duration(duration: Duration) -> Result<(), PushError> {
+    if !duration.is_positive() {
+        return Err(PushError::InvalidDuration {
+            duration,
+            reason: "it has to be positive",
+        });
+    }
+    // The deadline of the task has to be representable once it is popped
+    if OffsetDateTime::now_utc().checked_add(duration).is_none() {
+        return Err(PushError::InvalidDuration {
+            duration,
+            reason: "it is too large",
+        });
+    }
+    Ok(())
+}
+
+fn validate_dependencies(depends_on: &[TaskKey]) -> Result<(), PushError> {
+    let mut dependencies = HashSet::with_capacity(depends_on.len());
+    if let Some(&dependency) = depends_on
+        .iter()
+        .find(|&&dependency| !dependencies.insert(dependency))
+    {
+        return Err(PushError::DuplicateDependency { dependency });
+    }
+    Ok(())
+}
+
+impl Reveal for taskie_structures::InsertTask {
+    type Revealed = InsertTask;
+
+    fn reveal(self, keys: &Keys) -> Result<Self::Revealed, KeyDecodeError> {
+        Ok(InsertTask(taskie_structures::InsertTask {
+            name: self.name,
+            payload: self.payload,
+            duration: self.duration,
+            priority: self.priority,
+            max_retries: self.max_retries,
+            cost: self.cost,
+            labels: self.labels,
+            tenant: self.tenant,
+            run_at: self.run_at,
+            expires_at: self.expires_at,
+            schedule: self.schedule,
+            idempotency_key: self.idempotency_key,
+            dedupe: self.dedupe,
+            callback_url: self.callback_url,
+            dependency_mode: self.dependency_mode,
+            traceparent: self.traceparent,
+            depends_on_batch: self.depends_on_batch,
+            depends_on: self
                 .depends_on
                 .into_iter()
-                .map(|k| k.try_into())
+                .map(|k| k.reveal(keys))
                 .collect::<Result<Vec<TaskKey>, KeyDecodeError>>()?,
         }))
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct TaskPatch(pub taskie_structures::TaskPatch<TaskKey>);
+
+impl TaskPatch {
+    /// Checks the changed parts of the task like `InsertTask::validate`.
+    pub fn validate(&self) -> Result<(), PushError> {
+        if let Some(duration) = self.0.duration {
+            validate_duration(duration)?;
+        }
+        match &self.0.depends_on {
+            Some(depends_on) => validate_dependencies(depends_on),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Reveal for taskie_structures::TaskPatch {
+    type Revealed = TaskPatch;
+
+    fn reveal(self, keys: &Keys) -> Result<Self::Revealed, KeyDecodeError> {
+        Ok(TaskPatch(taskie_structures::TaskPatch {
+            payload: self.payload,
+            duration: self.duration,
+            priority: self.priority,
+            depends_on: self
+                .depends_on
+                .map(|depends_on| {
+                    depends_on
+                        .into_iter()
+                        .map(|k| k.reveal(keys))
+                        .collect::<Result<Vec<TaskKey>, KeyDecodeError>>()
+                })
+                .transpose()?,
+        }))
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Task(pub taskie_structures::Task<taskie_structures::TaskName, TaskKey>);
 
 impl Conceal for Task {
     type Concealed = taskie_structures::Task;
 
-    fn conceal(self) -> Result<Self::Concealed, ConcealError> {
+    fn conceal(self, keys: &Keys) -> Result<Self::Concealed, ConcealError> {
         let Task(task) = self;
         Ok(taskie_structures::Task {
-            id: task.id.conceal()?,
+            id: task.id.conceal(keys)?,
             depends_on: task
                 .depends_on
                 .into_iter()
-                .map(|k| k.conceal())
+                .map(|k| k.conceal(keys))
                 .collect::<Result<Vec<taskie_structures::TaskKey>, ConcealError>>()?,
             name: task.name,
             duration: task.duration,
+            priority: task.priority,
+            max_retries: task.max_retries,
+            cost: task.cost,
+            labels: task.labels,
+            tenant: task.tenant,
+            attempt: task.attempt,
+            status: task.status,
+            created_at: task.created_at,
+            started_at: task.started_at,
+            run_at: task.run_at,
+            expires_at: task.expires_at,
+            progress: task.progress,
+            schedule: task.schedule,
+            callback_url: task.callback_url,
+            dependency_mode: task.dependency_mode,
+            traceparent: task.traceparent,
             payload: task.payload,
         })
     }
@@ -146,15 +417,145 @@ pub struct Execution(pub taskie_structures::Execution<Task>);
 impl Conceal for Execution {
     type Concealed = taskie_structures::Execution;
 
-    fn conceal(self) -> Result<Self::Concealed, ConcealError> {
+    fn conceal(self, keys: &Keys) -> Result<Self::Concealed, ConcealError> {
         let Execution(execution) = self;
         Ok(taskie_structures::Execution {
-            task: execution.task.conceal()?,
+            task: execution.task.conceal(keys)?,
             deadline: execution.deadline,
+            remaining: execution.remaining,
+            lease_token: execution.lease_token,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Stats(pub taskie_structures::Stats<TaskKey>);
+
+impl Conceal for Stats {
+    type Concealed = taskie_structures::Stats;
+
+    fn conceal(self, keys: &Keys) -> Result<Self::Concealed, ConcealError> {
+        let Stats(stats) = self;
+        Ok(taskie_structures::Stats {
+            pending: stats.pending,
+            ready: stats.ready,
+            processing: stats.processing,
+            completed: stats.completed,
+            dead_lettered: stats.dead_lettered,
+            progress: stats.progress,
+            waiting: stats.waiting,
+            next_key: stats.next_key.conceal(keys)?,
+            monitor: stats.monitor,
+        })
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SelectorError {
+    #[error("Missing label name in selector entry: {}", .0)]
+    MissingLabel(String),
+}
+
+impl SelectorError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            SelectorError::MissingLabel(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+/// Restricts a pop to the tasks whose labels match, and to the ones with a
+/// given name if set. The labels are parsed from a comma separated list of
+/// entries, either `name`, which requires the task to have the label, or
+/// `name=value`, which requires the label to have that value. The empty
+/// selector matches any task.
+#[derive(Clone, Debug, Default)]
+pub struct Selector {
+    pub labels: BTreeMap<String, Option<String>>,
+    pub name: Option<String>,
+}
+
+impl Selector {
+    /// Restricts the selector to the tasks named `name`, if set.
+    pub fn named(self, name: Option<String>) -> Self {
+        Selector { name, ..self }
+    }
+
+    pub fn matches(&self, name: &str, labels: &BTreeMap<String, String>) -> bool {
+        self.name.as_deref().is_none_or(|selected| selected == name)
+            && self.labels.iter().all(|(label, value)| match value {
+                Some(value) => labels.get(label) == Some(value),
+                None => labels.contains_key(label),
+            })
+    }
+
+    /// Splits the selector in the labels which must have a given value, and
+    /// the names of the ones which only have to be set.
+    #[cfg(any(feature = "postgres", feature = "redis"))]
+    pub fn split(&self) -> (BTreeMap<&str, &str>, Vec<&str>) {
+        let mut values = BTreeMap::new();
+        let mut names = Vec::new();
+        for (name, value) in self.labels.iter() {
+            match value {
+                Some(value) => {
+                    values.insert(name.as_str(), value.as_str());
+                }
+                None => names.push(name.as_str()),
+            }
+        }
+        (values, names)
+    }
+}
+
+impl FromStr for Selector {
+    type Err = SelectorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut selector = BTreeMap::new();
+        for entry in s
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (name, value) = match entry.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().to_string())),
+                None => (entry, None),
+            };
+            if name.is_empty() {
+                return Err(SelectorError::MissingLabel(entry.to_string()));
+            }
+            selector.insert(name.to_string(), value);
+        }
+        Ok(Selector {
+            labels: selector,
+            name: None,
         })
     }
 }
 
+/// An opaque failure of the storage backend (i.e. a database error), which
+/// is not caused by the client's request.
+#[derive(Error, Debug)]
+#[error(transparent)]
+pub struct BackendError(pub Box<dyn std::error::Error + Send + Sync>);
+
+/// Implements `From<$source>` for the given store errors, wrapping the source
+/// error in their `Backend` variant, so that backends can just use `?`.
+#[cfg(any(feature = "sqlite", feature = "postgres", feature = "redis"))]
+macro_rules! backend_errors {
+    ($source:ty => $($target:ident),+ $(,)?) => {
+        $(
+            impl From<$source> for $crate::store::$target {
+                fn from(err: $source) -> Self {
+                    Self::Backend($crate::store::BackendError(Box::new(err)))
+                }
+            }
+        )+
+    };
+}
+#[cfg(any(feature = "sqlite", feature = "postgres", feature = "redis"))]
+pub(crate) use backend_errors;
+
 #[derive(Error, Debug)]
 pub enum MonitorError {
     #[error("Monitoring channel dropped")]
@@ -163,6 +564,8 @@ pub enum MonitorError {
     InvalidTask(TaskKey),
     #[error("Could not cancel the timeout for task: {}", .0)]
     CancelTimeout(TaskKey),
+    #[error("Store backend error: {}", .0)]
+    Backend(BackendError),
 }
 
 #[derive(Error, Debug)]
@@ -171,13 +574,130 @@ pub enum PushError {
     MissingDependency { dependency: TaskKey },
     #[error("Adding a task with the given dependencies would create a dependency cycle")]
     Cycle(#[from] CycleError),
+    #[error("Task {} cannot depend on itself", .0)]
+    SelfDependency(TaskKey),
+    #[error("Task to depend upon {dependency} is listed more than once")]
+    DuplicateDependency { dependency: TaskKey },
+    #[error("Invalid dependency on the task at position {position} of the batch: {reason}")]
+    InvalidBatchDependency {
+        position: usize,
+        reason: &'static str,
+    },
+    #[error("Invalid cron schedule {schedule}: {reason}")]
+    InvalidSchedule { schedule: String, reason: String },
+    #[error("The store does not support recurring tasks")]
+    UnsupportedSchedule,
+    #[error("The store does not support idempotency keys")]
+    UnsupportedIdempotencyKey,
+    #[error("The store does not support callbacks")]
+    UnsupportedCallback,
+    #[error("The store only supports waiting for all the dependencies")]
+    UnsupportedDependencyMode,
+    #[error("The store does not support tenants")]
+    UnsupportedTenant,
+    #[error("The store does not support deduplicating the tasks")]
+    UnsupportedDedupe,
+    #[error("The store only supports tasks costing {}", DEFAULT_COST)]
+    UnsupportedCost,
+    #[error("The store does not support tasks expiring")]
+    UnsupportedExpiry,
+    #[error("The store does not support depending on the tasks of the same batch")]
+    UnsupportedBatchDependency,
+    #[error("All the task keys have been handed out")]
+    KeyExhausted,
+    #[error("The queue is full: at most {limit} tasks can be pending or ready")]
+    QueueFull {
+        limit: usize,
+        /// How long until there is likely room again
+        retry_after: Duration,
+    },
+    #[error("Invalid task duration {duration}: {reason}")]
+    InvalidDuration {
+        duration: Duration,
+        reason: &'static str,
+    },
+    #[error("Store backend error: {}", .0)]
+    Backend(BackendError),
 }
 
 impl PushError {
     pub fn status(&self) -> StatusCode {
         match self {
             PushError::MissingDependency { .. } => StatusCode::BAD_REQUEST,
+            PushError::SelfDependency(_) => StatusCode::BAD_REQUEST,
+            PushError::DuplicateDependency { .. } => StatusCode::BAD_REQUEST,
+            PushError::InvalidBatchDependency { .. } => StatusCode::BAD_REQUEST,
             PushError::Cycle(_) => StatusCode::BAD_REQUEST,
+            PushError::InvalidSchedule { .. } => StatusCode::BAD_REQUEST,
+            PushError::UnsupportedSchedule => StatusCode::NOT_IMPLEMENTED,
+            PushError::UnsupportedIdempotencyKey => StatusCode::NOT_IMPLEMENTED,
+            PushError::UnsupportedCallback => StatusCode::NOT_IMPLEMENTED,
+            PushError::UnsupportedDependencyMode => StatusCode::NOT_IMPLEMENTED,
+            PushError::UnsupportedTenant => StatusCode::NOT_IMPLEMENTED,
+            PushError::UnsupportedDedupe => StatusCode::NOT_IMPLEMENTED,
+            PushError::UnsupportedCost => StatusCode::NOT_IMPLEMENTED,
+            PushError::UnsupportedExpiry => StatusCode::NOT_IMPLEMENTED,
+            PushError::UnsupportedBatchDependency => StatusCode::NOT_IMPLEMENTED,
+            PushError::KeyExhausted => StatusCode::INSUFFICIENT_STORAGE,
+            PushError::QueueFull { .. } => StatusCode::TOO_MANY_REQUESTS,
+            PushError::InvalidDuration { .. } => StatusCode::BAD_REQUEST,
+            PushError::Backend(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            PushError::MissingDependency { .. } => ErrorCode::MissingDependency,
+            PushError::SelfDependency(_) => ErrorCode::SelfDependency,
+            PushError::DuplicateDependency { .. } => ErrorCode::DuplicateDependency,
+            PushError::InvalidBatchDependency { .. } => ErrorCode::InvalidBatchDependency,
+            PushError::Cycle(_) => ErrorCode::CycleDetected,
+            PushError::InvalidSchedule { .. } => ErrorCode::InvalidSchedule,
+            PushError::UnsupportedSchedule
+            | PushError::UnsupportedIdempotencyKey
+            | PushError::UnsupportedCallback
+            | PushError::UnsupportedDependencyMode
+            | PushError::UnsupportedTenant
+            | PushError::UnsupportedDedupe
+            | PushError::UnsupportedCost
+            | PushError::UnsupportedExpiry
+            | PushError::UnsupportedBatchDependency => ErrorCode::Unsupported,
+            PushError::KeyExhausted => ErrorCode::KeyExhausted,
+            PushError::QueueFull { .. } => ErrorCode::QueueFull,
+            PushError::InvalidDuration { .. } => ErrorCode::InvalidDuration,
+            PushError::Backend(_) => ErrorCode::Internal,
+        }
+    }
+}
+
+/// Lists every error found by a dry-run push, along with the position in the
+/// batch of the task it concerns, if any.
+fn describe(errors: &[(Option<usize>, PushError)]) -> String {
+    errors
+        .iter()
+        .map(|(position, err)| match position {
+            Some(position) => format!("task {}: {}", position, err),
+            None => format!("batch: {}", err),
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[derive(Error, Debug)]
+pub enum DryRunError {
+    #[error("{}", describe(.0))]
+    Invalid(Vec<(Option<usize>, PushError)>),
+    #[error("The store does not support dry-run pushes")]
+    Unsupported,
+}
+
+impl DryRunError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            DryRunError::Invalid(errors) => errors
+                .first()
+                .map_or(StatusCode::BAD_REQUEST, |(_, err)| err.status()),
+            DryRunError::Unsupported => StatusCode::NOT_IMPLEMENTED,
         }
     }
 }
@@ -186,8 +706,66 @@ impl PushError {
 pub enum CompleteError {
     #[error("Invalid task id to be completed: {}", .0)]
     InvalidTaskId(TaskKey),
+    /// The task was completed by an earlier call, which the API reports as a
+    /// success so that workers can safely retry their completions
+    #[error("Task {} has already been completed", .0)]
+    AlreadyCompleted(TaskKey),
+    #[error("Neither the id nor the idempotency key of the task to be completed is set")]
+    MissingTask,
+    #[error("No task is remembered with the idempotency key {}", .0)]
+    UnknownIdempotencyKey(String),
+    #[error("Task {id} was not pushed with the idempotency key {idempotency_key}")]
+    AmbiguousTask {
+        id: TaskKey,
+        idempotency_key: String,
+    },
+    #[error("The store cannot look up the tasks by their idempotency key")]
+    UnsupportedIdempotencyKey,
+    #[error("Task {} was not popped with the given lease token", .0)]
+    LeaseMismatch(TaskKey),
     #[error("Communication with the store monitor failed")]
     MonitorCommunication,
+    #[error("Store backend error: {}", .0)]
+    Backend(BackendError),
+}
+
+impl CompleteError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            CompleteError::InvalidTaskId(_) => StatusCode::NOT_FOUND,
+            CompleteError::AlreadyCompleted(_) => StatusCode::CONFLICT,
+            CompleteError::MissingTask => StatusCode::BAD_REQUEST,
+            CompleteError::UnknownIdempotencyKey(_) => StatusCode::NOT_FOUND,
+            CompleteError::AmbiguousTask { .. } => StatusCode::BAD_REQUEST,
+            CompleteError::UnsupportedIdempotencyKey => StatusCode::NOT_IMPLEMENTED,
+            CompleteError::LeaseMismatch(_) => StatusCode::FORBIDDEN,
+            CompleteError::MonitorCommunication => StatusCode::INTERNAL_SERVER_ERROR,
+            CompleteError::Backend(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CompleteAndPushError {
+    #[error("{}", .0)]
+    Complete(#[from] CompleteError),
+    #[error("{}", .0)]
+    Push(#[from] PushError),
+    #[error("The store does not support completing a task and pushing others at once")]
+    Unsupported,
+    #[error("Store backend error: {}", .0)]
+    Backend(BackendError),
+}
+
+impl CompleteAndPushError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            CompleteAndPushError::Complete(err) => err.status(),
+            CompleteAndPushError::Push(err) => err.status(),
+            CompleteAndPushError::Unsupported => StatusCode::NOT_IMPLEMENTED,
+            CompleteAndPushError::Backend(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -196,13 +774,360 @@ pub enum PopError {
     InvalidTaskId(TaskKey),
     #[error("Communication with the store monitor failed")]
     MonitorCommunication,
+    #[error("At least one task has to be popped")]
+    InvalidCount,
+    #[error("Tasks can be popped either by count or by budget, not both")]
+    CountAndBudget,
+    #[error("Only single tasks can be popped without waiting")]
+    NonBlockingBatch,
+    #[error("Task {} was ready with pending dependencies", .0)]
+    PendingDependencies(TaskKey),
+    #[error("Store backend error: {}", .0)]
+    Backend(BackendError),
 }
 
 impl PopError {
     pub fn status(&self) -> StatusCode {
         match self {
             PopError::InvalidTaskId(_) => StatusCode::BAD_REQUEST,
+            PopError::InvalidCount => StatusCode::BAD_REQUEST,
+            PopError::CountAndBudget => StatusCode::BAD_REQUEST,
+            PopError::NonBlockingBatch => StatusCode::BAD_REQUEST,
             PopError::MonitorCommunication => StatusCode::INTERNAL_SERVER_ERROR,
+            PopError::PendingDependencies(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            PopError::Backend(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// The reason recorded for a dead-lettered task whose last execution timed out.
+pub static TIMEOUT_REASON: &str = "Execution timed out";
+
+/// The reason recorded for a dead-lettered task whose last execution was
+/// failed by the worker.
+pub fn fail_reason(reason: Option<String>) -> String {
+    match reason {
+        Some(reason) => format!("Execution failed: {}", reason),
+        None => "Execution failed".to_string(),
+    }
+}
+
+/// Whether the execution of a task leased with `recorded` can be ended or
+/// extended with `lease_token`.
+pub fn leased(recorded: Option<Uuid>, lease_token: Uuid) -> bool {
+    recorded == Some(lease_token)
+}
+
+#[derive(Error, Debug)]
+pub enum PeekError {
+    #[error("The store does not support peeking at the queue")]
+    Unsupported,
+    #[error("Store backend error: {}", .0)]
+    Backend(BackendError),
+}
+
+impl PeekError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            PeekError::Unsupported => StatusCode::NOT_IMPLEMENTED,
+            PeekError::Backend(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum FailError {
+    #[error("Invalid task id to be failed: {}", .0)]
+    InvalidTaskId(TaskKey),
+    #[error("Task {} was not popped with the given lease token", .0)]
+    LeaseMismatch(TaskKey),
+    #[error("Communication with the store monitor failed")]
+    MonitorCommunication,
+    #[error("Store backend error: {}", .0)]
+    Backend(BackendError),
+}
+
+impl FailError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            FailError::InvalidTaskId(_) => StatusCode::NOT_FOUND,
+            FailError::LeaseMismatch(_) => StatusCode::FORBIDDEN,
+            FailError::MonitorCommunication => StatusCode::INTERNAL_SERVER_ERROR,
+            FailError::Backend(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum RequeueError {
+    #[error("Invalid task id to be requeued: {}", .0)]
+    InvalidTaskId(TaskKey),
+    #[error("Communication with the store monitor failed")]
+    MonitorCommunication,
+    #[error("The store does not support requeueing the tasks being processed")]
+    Unsupported,
+    #[error("Store backend error: {}", .0)]
+    Backend(BackendError),
+}
+
+impl RequeueError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            RequeueError::InvalidTaskId(_) => StatusCode::NOT_FOUND,
+            RequeueError::MonitorCommunication => StatusCode::INTERNAL_SERVER_ERROR,
+            RequeueError::Unsupported => StatusCode::NOT_IMPLEMENTED,
+            RequeueError::Backend(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum HeartbeatError {
+    #[error("Invalid task id to extend the deadline of: {}", .0)]
+    InvalidTaskId(TaskKey),
+    #[error("Task {} was not popped with the given lease token", .0)]
+    LeaseMismatch(TaskKey),
+    #[error("Communication with the store monitor failed")]
+    MonitorCommunication,
+    #[error("Store backend error: {}", .0)]
+    Backend(BackendError),
+}
+
+impl HeartbeatError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            HeartbeatError::InvalidTaskId(_) => StatusCode::NOT_FOUND,
+            HeartbeatError::LeaseMismatch(_) => StatusCode::FORBIDDEN,
+            HeartbeatError::MonitorCommunication => StatusCode::INTERNAL_SERVER_ERROR,
+            HeartbeatError::Backend(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum DeadLetterError {
+    #[error("Invalid dead-lettered task id: {}", .0)]
+    InvalidTaskId(TaskKey),
+    #[error("Store backend error: {}", .0)]
+    Backend(BackendError),
+}
+
+impl DeadLetterError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            DeadLetterError::InvalidTaskId(_) => StatusCode::NOT_FOUND,
+            DeadLetterError::Backend(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CancelError {
+    #[error("Invalid task id to be cancelled: {}", .0)]
+    InvalidTaskId(TaskKey),
+    #[error("Task {} is being processed and cannot be cancelled", .0)]
+    Processing(TaskKey),
+    #[error("Task {} cannot be cancelled as other tasks depend on it", .0)]
+    HasDependents(TaskKey),
+    #[error("Store backend error: {}", .0)]
+    Backend(BackendError),
+}
+
+impl CancelError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            CancelError::InvalidTaskId(_) => StatusCode::NOT_FOUND,
+            CancelError::Processing(_) => StatusCode::CONFLICT,
+            CancelError::HasDependents(_) => StatusCode::CONFLICT,
+            CancelError::Backend(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum UpdateError {
+    #[error("Invalid task id to be updated: {}", .0)]
+    InvalidTaskId(TaskKey),
+    #[error("Task {} is being processed and cannot be updated", .0)]
+    Processing(TaskKey),
+    #[error("{}", .0)]
+    Invalid(#[from] PushError),
+    #[error("The store does not support updating the tasks")]
+    Unsupported,
+}
+
+impl UpdateError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            UpdateError::InvalidTaskId(_) => StatusCode::NOT_FOUND,
+            UpdateError::Processing(_) => StatusCode::CONFLICT,
+            UpdateError::Invalid(err) => err.status(),
+            UpdateError::Unsupported => StatusCode::NOT_IMPLEMENTED,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum GetError {
+    #[error("Invalid task id: {}", .0)]
+    InvalidTaskId(TaskKey),
+    #[error("Store backend error: {}", .0)]
+    Backend(BackendError),
+}
+
+impl GetError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            GetError::InvalidTaskId(_) => StatusCode::NOT_FOUND,
+            GetError::Backend(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum StatsError {
+    #[error("Store backend error: {}", .0)]
+    Backend(BackendError),
+}
+
+#[derive(Error, Debug)]
+pub enum ListError {
+    #[error("The store does not support listing the tasks")]
+    Unsupported,
+    #[error("Store backend error: {}", .0)]
+    Backend(BackendError),
+}
+
+impl ListError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ListError::Unsupported => StatusCode::NOT_IMPLEMENTED,
+            ListError::Backend(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum PurgeError {
+    #[error("Store backend error: {}", .0)]
+    Backend(BackendError),
+}
+
+impl PurgeError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            PurgeError::Backend(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl StatsError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            StatsError::Backend(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum RecurringError {
+    #[error("Invalid recurring task id: {}", .0)]
+    InvalidId(TaskKey),
+    #[error("The store does not support recurring tasks")]
+    Unsupported,
+}
+
+impl RecurringError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            RecurringError::InvalidId(_) => StatusCode::NOT_FOUND,
+            RecurringError::Unsupported => StatusCode::NOT_IMPLEMENTED,
+        }
+    }
+}
+
+/// The tasks in the store, along with the dependencies they are waiting for.
+#[derive(Debug, Default)]
+pub struct GraphSnapshot {
+    /// Every task, by key, with its name
+    pub nodes: Vec<(TaskKey, taskie_structures::TaskName)>,
+    /// A `(dependent, dependency)` pair for each pending dependency
+    pub edges: Vec<(TaskKey, TaskKey)>,
+}
+
+#[derive(Error, Debug)]
+pub enum GraphError {
+    #[error("The store does not support exporting the dependency graph")]
+    Unsupported,
+}
+
+impl GraphError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            GraphError::Unsupported => StatusCode::NOT_IMPLEMENTED,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ResultsError {
+    #[error("Invalid task id to look up the results of: {}", .0)]
+    InvalidTaskId(TaskKey),
+    #[error("The task has not been completed or failed yet: {}", .0)]
+    NotFinished(TaskKey),
+    #[error("The store does not retain the results of completed tasks")]
+    Unsupported,
+}
+
+#[derive(Error, Debug)]
+pub enum AwaitError {
+    #[error("Invalid task id to await: {}", .0)]
+    InvalidTaskId(TaskKey),
+    #[error("The store does not support awaiting a task")]
+    Unsupported,
+}
+
+impl AwaitError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AwaitError::InvalidTaskId(_) => StatusCode::NOT_FOUND,
+            AwaitError::Unsupported => StatusCode::NOT_IMPLEMENTED,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ProgressError {
+    #[error("Invalid task id to report the progress of: {}", .0)]
+    InvalidTaskId(TaskKey),
+    #[error("Invalid progress {}%, it has to be at most 100%", .0)]
+    InvalidPercent(u8),
+    #[error("Task {} was not popped with the given lease token", .0)]
+    LeaseMismatch(TaskKey),
+    #[error("Communication with the store monitor failed")]
+    MonitorCommunication,
+    #[error("The store does not support reporting the progress of a task")]
+    Unsupported,
+}
+
+impl ProgressError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ProgressError::InvalidTaskId(_) => StatusCode::NOT_FOUND,
+            ProgressError::InvalidPercent(_) => StatusCode::BAD_REQUEST,
+            ProgressError::LeaseMismatch(_) => StatusCode::FORBIDDEN,
+            ProgressError::MonitorCommunication => StatusCode::INTERNAL_SERVER_ERROR,
+            ProgressError::Unsupported => StatusCode::NOT_IMPLEMENTED,
+        }
+    }
+}
+
+impl ResultsError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ResultsError::InvalidTaskId(_) => StatusCode::NOT_FOUND,
+            ResultsError::NotFinished(_) => StatusCode::CONFLICT,
+            ResultsError::Unsupported => StatusCode::NOT_IMPLEMENTED,
         }
     }
 }
@@ -211,6 +1136,228 @@ impl PopError {
 pub trait Store: Send + Sync {
     async fn monitor(&self) -> Result<(), MonitorError>;
     async fn push(&self, insert_tasks: Vec<InsertTask>) -> Result<Vec<Task>, PushError>;
-    async fn complete(&self, task_id: TaskKey) -> Result<(), CompleteError>;
-    async fn pop(&self) -> Result<Execution, PopError>;
+    /// Completes a task being processed, retaining its `result` for the tasks
+    /// depending on it if the store supports it. The `lease_token` has to be
+    /// the one the task was last popped with, as checked by `leased`.
+    /// Completing it again while its completion is still remembered fails
+    /// with `CompleteError::AlreadyCompleted`, rather than as an invalid task.
+    async fn complete(
+        &self,
+        task_id: TaskKey,
+        lease_token: Uuid,
+        result: Option<Value>,
+    ) -> Result<(), CompleteError>;
+    /// Looks up the task pushed with the idempotency key `key`, for as long as
+    /// the key is remembered, so that it can be completed without its id.
+    async fn idempotent_task(&self, _key: &str) -> Result<TaskKey, CompleteError> {
+        Err(CompleteError::UnsupportedIdempotencyKey)
+    }
+    /// Completes each of the given tasks, with the lease token of its
+    /// execution, independently, so that a task which cannot be completed
+    /// does not prevent the others from being.
+    async fn complete_many(
+        &self,
+        tasks: Vec<(TaskKey, Uuid)>,
+    ) -> Result<Vec<(TaskKey, Result<(), CompleteError>)>, CompleteError> {
+        let mut result = Vec::with_capacity(tasks.len());
+        for (task_id, lease_token) in tasks.into_iter() {
+            result.push((task_id, self.complete(task_id, lease_token, None).await));
+        }
+        Ok(result)
+    }
+    /// Completes a task being processed and pushes `insert_tasks` as a whole:
+    /// either the task is completed and the tasks are pushed, or neither is.
+    /// The pushed tasks can depend on the completed one, in which case they
+    /// are ready right away. The `lease_token` is checked like the one of
+    /// `complete`.
+    async fn complete_and_push(
+        &self,
+        _task_id: TaskKey,
+        _lease_token: Uuid,
+        _result: Option<Value>,
+        _insert_tasks: Vec<InsertTask>,
+    ) -> Result<Vec<Task>, CompleteAndPushError> {
+        Err(CompleteAndPushError::Unsupported)
+    }
+    /// Waits for a task matching `selector` to be ready and pops it. Tasks
+    /// which do not match are skipped, and stay on the queue.
+    async fn pop(&self, selector: &Selector) -> Result<Execution, PopError>;
+    /// Pops a task matching `selector` if one is ready, without waiting.
+    async fn try_pop(&self, selector: &Selector) -> Result<Option<Execution>, PopError>;
+    /// Waits for a task matching `selector` to be ready and pops it, along
+    /// with up to `n - 1` more which are ready as well. Each of them has its
+    /// own deadline.
+    async fn pop_many(&self, selector: &Selector, n: usize) -> Result<Vec<Execution>, PopError> {
+        if n == 0 {
+            return Err(PopError::InvalidCount);
+        }
+        let mut executions = vec![self.pop(selector).await?];
+        while executions.len() < n {
+            match self.try_pop(selector).await {
+                Ok(Some(execution)) => executions.push(execution),
+                Ok(None) => break,
+                // The tasks already popped are handed out anyway, rather
+                // than being left to time out
+                Err(err) => {
+                    tracing::warn!(%err, popped = executions.len(), "Could not pop all the tasks asked for");
+                    break;
+                }
+            }
+        }
+        Ok(executions)
+    }
+    /// Waits for a task matching `selector` to be ready and pops it, whatever
+    /// its cost, along with the ready ones which fit in what is left of
+    /// `budget`.
+    async fn pop_budget(
+        &self,
+        selector: &Selector,
+        budget: u32,
+    ) -> Result<Vec<Execution>, PopError> {
+        // The stores which do not support costs only have tasks costing
+        // `DEFAULT_COST`
+        let n = (budget / DEFAULT_COST).max(1);
+        self.pop_many(selector, n as usize).await
+    }
+    /// Runs every check of a push without storing anything, and returns the
+    /// keys the tasks would be given. All the errors found are reported at
+    /// once, rather than just the first one.
+    async fn dry_run(&self, _insert_tasks: &[InsertTask]) -> Result<Vec<TaskKey>, DryRunError> {
+        Err(DryRunError::Unsupported)
+    }
+    /// Looks up the task which would be popped next, without popping it.
+    async fn peek(&self) -> Result<Option<Task>, PeekError> {
+        Err(PeekError::Unsupported)
+    }
+    /// Ends the execution of a task being processed as if it timed out: the
+    /// task is put back on the queue, unless it exhausted its retries. The
+    /// `lease_token` is checked like the one of `complete`.
+    async fn fail(
+        &self,
+        task_id: TaskKey,
+        lease_token: Uuid,
+        reason: Option<String>,
+    ) -> Result<(), FailError>;
+    /// Times out a task being processed right away, rather than at its
+    /// deadline, i.e. when its worker is known to be dead: the task is put
+    /// back on the queue, unless it exhausted its retries or its timeouts.
+    async fn requeue(&self, _task_id: TaskKey) -> Result<(), RequeueError> {
+        Err(RequeueError::Unsupported)
+    }
+    /// Moves the deadline of a task being processed to `extend` from now, and
+    /// returns the new deadline. The `lease_token` is checked like the one of
+    /// `complete`.
+    async fn heartbeat(
+        &self,
+        task_id: TaskKey,
+        lease_token: Uuid,
+        extend: Duration,
+    ) -> Result<OffsetDateTime, HeartbeatError>;
+    /// Records how far a task being processed got, in percent. When `extend`
+    /// is set, its deadline is moved to leave it the share of its duration
+    /// matching the progress left, just like a heartbeat, and returned. The
+    /// `lease_token` is checked like the one of `complete`.
+    async fn progress(
+        &self,
+        _task_id: TaskKey,
+        _lease_token: Uuid,
+        _percent: u8,
+        _extend: bool,
+    ) -> Result<Option<OffsetDateTime>, ProgressError> {
+        Err(ProgressError::Unsupported)
+    }
+    /// Lists the tasks which exhausted their retries, along with the reason
+    /// their last execution failed.
+    async fn dead_letters(&self) -> Result<Vec<(Task, String)>, DeadLetterError>;
+    /// Puts a dead-lettered task back on the queue, resetting its attempts.
+    async fn requeue_dead_letter(&self, task_id: TaskKey) -> Result<Task, DeadLetterError>;
+    /// Whether the store can serve requests, i.e. its monitor is running or
+    /// its backend can be reached.
+    async fn health(&self) -> bool;
+    /// Called once the server stopped serving requests: the monitor handles
+    /// any pending event and then returns.
+    async fn shutdown(&self);
+    /// Removes a task which is not being processed. Tasks which other tasks
+    /// depend upon are never cancelled, and `CancelError::HasDependents` is
+    /// returned instead: their dependents have to be cancelled first.
+    async fn cancel(&self, task_id: TaskKey) -> Result<(), CancelError>;
+    /// Changes a task which is pending or ready, and returns it. Replacing
+    /// its dependencies puts it back to pending, or on the queue, as they
+    /// are completed or not.
+    async fn update(&self, _task_id: TaskKey, _patch: TaskPatch) -> Result<Task, UpdateError> {
+        Err(UpdateError::Unsupported)
+    }
+    /// Looks up a task, along with its current status. Completed tasks are
+    /// removed from the store, so they cannot be looked up.
+    async fn get(&self, task_id: TaskKey) -> Result<Task, GetError>;
+    /// Lists up to `limit` tasks, skipping the first `offset`, in the order of
+    /// their keys, along with how many there are in all. When `status` is
+    /// set, only the tasks in it are listed.
+    async fn list(
+        &self,
+        _offset: usize,
+        _limit: usize,
+        _status: Option<Status>,
+    ) -> Result<(Vec<Task>, usize), ListError> {
+        Err(ListError::Unsupported)
+    }
+    /// Lists the tasks still waiting for a task to be completed, by key.
+    async fn dependents(&self, task_id: TaskKey) -> Result<Vec<TaskKey>, GetError>;
+    /// Takes a snapshot of the tasks and of the dependencies they are still
+    /// waiting for, to visualize the graph.
+    async fn graph(&self) -> Result<GraphSnapshot, GraphError> {
+        Err(GraphError::Unsupported)
+    }
+    /// How many tasks are ready to be popped, without waiting on the backend:
+    /// the stores which have to query it count them along with the gauges.
+    fn queue_depth(&self) -> usize;
+    /// The name of the backend keeping the tasks, i.e. `memory` or `sqlite`.
+    fn backend_name(&self) -> &'static str;
+    /// Counts the tasks in each state. The waiting workers are counted by the
+    /// API rather than the store, which leaves them at zero.
+    async fn stats(&self) -> Result<Stats, StatsError>;
+    /// Removes every task, whatever its state, along with the dead-lettered
+    /// ones. The keys and the count of the completed tasks are kept.
+    async fn purge(&self) -> Result<(), PurgeError>;
+    /// Lists the recurring tasks, by the key of their first instance, along
+    /// with their current instance.
+    async fn recurring(&self) -> Result<Vec<(TaskKey, Task)>, RecurringError> {
+        Err(RecurringError::Unsupported)
+    }
+    /// Stops a recurring task from being pushed again, and cancels its current
+    /// instance unless it is already being processed.
+    async fn delete_recurring(&self, _id: TaskKey) -> Result<(), RecurringError> {
+        Err(RecurringError::Unsupported)
+    }
+    /// Looks up the results the dependencies of a task were completed with,
+    /// in the order they were declared in.
+    async fn dependency_results(
+        &self,
+        _task_id: TaskKey,
+    ) -> Result<Vec<(TaskKey, Option<Value>)>, ResultsError> {
+        Err(ResultsError::Unsupported)
+    }
+    /// Looks up the final status of a task, along with the result it was
+    /// completed with, for as long as the store retains them.
+    async fn task_result(
+        &self,
+        _task_id: TaskKey,
+    ) -> Result<(Status, Option<Value>), ResultsError> {
+        Err(ResultsError::Unsupported)
+    }
+    /// Waits for a task to be ready, completed or failed, and returns its
+    /// status along with the task as it was then. The task is only missing
+    /// if it was completed, and is no longer stored, before it was awaited.
+    /// Fails once the task is cancelled or dropped.
+    async fn await_task(&self, _task_id: TaskKey) -> Result<(Status, Option<Task>), AwaitError> {
+        Err(AwaitError::Unsupported)
+    }
+    /// Waits for a task to be completed or failed, just like `await_task`
+    /// but going past it being ready.
+    async fn await_finished(
+        &self,
+        _task_id: TaskKey,
+    ) -> Result<(Status, Option<Task>), AwaitError> {
+        Err(AwaitError::Unsupported)
+    }
 }
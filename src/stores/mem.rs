@@ -1,266 +1,4036 @@
 use std::{
-    collections::{HashMap, VecDeque},
-    sync::Arc,
+    cmp::Ordering,
+    collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    ops::ControlFlow,
+    path::Path,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering as AtomicOrdering},
+        Arc, Mutex as StdMutex, MutexGuard, PoisonError,
+    },
     vec,
 };
 
 use axum::async_trait;
-use deadqueue::unlimited::Queue;
+use cron::Schedule;
+use futures::future;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest as _, Sha256};
+use taskie_structures::{DependencyMode, MonitorStats, Status, TaskName, Uuid};
 use thiserror::Error;
-use time::OffsetDateTime;
+use time::{serde::iso8601, Duration, OffsetDateTime};
+use tokio::sync::futures::Notified;
 use tokio::sync::{
-    mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
-    oneshot::{self as oneshot, Sender},
-    Mutex, RwLock,
+    mpsc::{error::SendError, unbounded_channel, UnboundedReceiver, UnboundedSender},
+    watch, Mutex, Notify, OwnedSemaphorePermit, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    Semaphore,
 };
-use tokio::time::timeout;
+use tokio::time::Instant;
 
+use crate::callback;
+use crate::clock::{Clock, SystemClock};
+use crate::events::{Emitter, EventSink};
+use crate::metrics::{PROCESSING, QUEUE_DEPTH, TASKS_EXPIRED, TASKS_TIMED_OUT};
 use crate::store::{
-    CompleteError, Execution, InsertTask, MonitorError, PopError, PushError, Store, Task, TaskKey,
+    fail_reason, leased, AwaitError, CancelError, CompleteAndPushError, CompleteError,
+    DeadLetterError, DryRunError, Execution, FailError, GetError, GraphError, GraphSnapshot,
+    HeartbeatError, InsertTask, Keys, ListError, MonitorError, PeekError, PopError, ProgressError,
+    PurgeError, PushError, RecurringError, RequeueError, ResultsError, Selector, Stats, StatsError,
+    Store, Task, TaskKey, TaskPatch, UpdateError, TIMEOUT_REASON,
 };
 
 #[derive(Clone)]
 enum MonitorMessage {
-    Popped(Task),
     Completed(TaskKey),
     TimedOut(TaskKey),
+    Failed(TaskKey, Option<String>),
+    Extend(TaskKey, Duration),
+    Due(TaskKey),
+    Expired(TaskKey),
+    Shutdown,
 }
 
-pub struct MemoryStore {
-    next_key: RwLock<TaskKey>,
-    tasks: RwLock<HashMap<TaskKey, Task>>,
-    processing: RwLock<HashMap<TaskKey, Sender<()>>>,
-    queue: Queue<TaskKey>,
-    edges: RwLock<HashMap<TaskKey, Vec<TaskKey>>>,
+/// The sending end of the channel of the monitor. Being unbounded, the
+/// channel cannot tell how many messages are in it: they are counted as they
+/// are sent, and discounted by the monitor once handled. Each message is sent
+/// along with when it was, so that the monitor can tell how far behind it is.
+#[derive(Clone)]
+struct MonitorSender {
+    tx: UnboundedSender<(Instant, MonitorMessage)>,
+    backlog: Arc<AtomicUsize>,
+}
+
+impl MonitorSender {
+    fn send(&self, msg: MonitorMessage) -> Result<(), SendError<MonitorMessage>> {
+        // Counted before it is sent, so that it cannot be discounted before
+        self.backlog.fetch_add(1, AtomicOrdering::Relaxed);
+        self.tx
+            .send((Instant::now(), msg))
+            .map_err(|SendError((_, msg))| {
+                self.backlog.fetch_sub(1, AtomicOrdering::Relaxed);
+                SendError(msg)
+            })
+    }
+
+    fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+}
+
+/// A task being executed by a worker.
+struct Processing {
+    /// When the task times out, plus the jitter, as found in the deadlines of
+    /// the store
+    deadline: OffsetDateTime,
+    /// Set once its `TimedOut` message has been sent, while it waits to be
+    /// handled
+    timed_out: bool,
+    /// Handed out with the execution, for the worker to end or extend it
+    lease_token: Uuid,
+    /// The slot taken by the task when `max_processing` is set, freed once
+    /// the task is no longer processing
+    _slot: Option<OwnedSemaphorePermit>,
+}
+
+/// The processing tasks whose key, modulo the number of shards, is the index
+/// of the shard, along with the channel of the monitor loop owning them. All
+/// the messages about a task go to the loop of its shard.
+struct Shard {
+    processing: RwLock<HashMap<TaskKey, Processing>>,
+    /// The deadlines of the processing tasks, earliest first, checked by the
+    /// monitor loop at each tick. They only change while `processing` is
+    /// locked.
+    deadlines: StdMutex<BTreeSet<(OffsetDateTime, TaskKey)>>,
+    /// How many tasks are in `processing`, as last set along with the gauge
+    len: AtomicUsize,
     chan: (
-        UnboundedSender<MonitorMessage>,
-        Mutex<UnboundedReceiver<MonitorMessage>>,
+        MonitorSender,
+        Mutex<UnboundedReceiver<(Instant, MonitorMessage)>>,
     ),
 }
 
-#[derive(Error, Debug)]
-pub struct CycleError;
+impl Shard {
+    /// An empty shard, whose messages are counted in `backlog`.
+    fn new(backlog: Arc<AtomicUsize>) -> Self {
+        let (tx, rx) = unbounded_channel();
+        Shard {
+            processing: RwLock::new(HashMap::new()),
+            deadlines: StdMutex::new(BTreeSet::new()),
+            len: AtomicUsize::new(0),
+            chan: (MonitorSender { tx, backlog }, Mutex::new(rx)),
+        }
+    }
 
-impl std::fmt::Display for CycleError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "A cycle in the DAG has been detected")
+    fn lock_deadlines(&self) -> MutexGuard<'_, BTreeSet<(OffsetDateTime, TaskKey)>> {
+        self.deadlines
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
     }
 }
 
-static EMPTY_VEC: Vec<TaskKey> = vec![];
+/// A task on the ready queue. Tasks are ordered by priority first and then by
+/// insertion order, so that tasks with the same priority are popped in FIFO
+/// order. The name, the labels, the cost and the tenant are copied from the
+/// task, so that the queue can be matched against a selector and a budget,
+/// and shared among the tenants, without looking up the tasks.
+#[derive(Clone)]
+struct Ready {
+    priority: i32,
+    sequence: u64,
+    id: TaskKey,
+    name: String,
+    cost: u32,
+    labels: BTreeMap<String, String>,
+    tenant: Option<String>,
+    /// When the task was created, which it ages from
+    created_at: OffsetDateTime,
+}
 
-impl MemoryStore {
-    pub fn new() -> Self {
-        let (tx, rx) = unbounded_channel();
+impl Ready {
+    /// The priority of the task once it aged by `aging` per second since it
+    /// was created.
+    fn effective_priority(&self, now: OffsetDateTime, aging: f64) -> f64 {
+        self.priority as f64 + aging * (now - self.created_at).as_seconds_f64()
+    }
+}
 
-        MemoryStore {
-            next_key: RwLock::new(TaskKey(1)),
-            tasks: RwLock::new(HashMap::new()),
-            processing: RwLock::new(HashMap::new()),
-            queue: Queue::new(),
-            edges: RwLock::new(HashMap::new()),
-            chan: (tx, Mutex::new(rx)),
+impl Ord for Ready {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+impl PartialOrd for Ready {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Ready {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ready {}
+
+/// The queue of the tasks ready to be executed, highest priority first. It is
+/// kept sorted rather than as a heap, so that a pop can skip the tasks it does
+/// not accept. When the tasks age, their priority changes while they wait, so
+/// the whole queue is scanned for the highest effective priority instead.
+///
+/// Its lock is synchronous, as it is never held across an await nor while
+/// taking another lock: this is what lets a `Dequeued` task be put back on the
+/// queue when it is dropped.
+struct ReadyQueue {
+    ready: StdMutex<BTreeSet<Ready>>,
+    sequence: AtomicU64,
+    notify: Notify,
+    /// Notified whenever tasks leave the queue, for the pushes waiting for it
+    /// to make room
+    room: Notify,
+    /// How much the priority of a task grows for each second it waited
+    aging: f64,
+    /// How much the priority of a task is raised on the queue once the
+    /// completion of its dependencies puts it there
+    boost: i32,
+    mode: PopMode,
+    /// The tenant of the last task popped in fair share, where the turn of
+    /// the next one starts from. Its lock is only taken along with `ready`.
+    last_tenant: StdMutex<Option<Option<String>>>,
+}
+
+impl ReadyQueue {
+    fn new() -> Self {
+        ReadyQueue {
+            ready: StdMutex::new(BTreeSet::new()),
+            sequence: AtomicU64::new(0),
+            notify: Notify::new(),
+            room: Notify::new(),
+            aging: 0.0,
+            boost: 0,
+            mode: PopMode::Priority,
+            last_tenant: StdMutex::new(None),
         }
     }
 
-    async fn get_edges<'a>(
-        edges_map: &'a HashMap<TaskKey, Vec<TaskKey>>,
-        node: &'a TaskKey,
-    ) -> &'a [TaskKey] {
-        match edges_map.get(node) {
-            Some(v) => v,
-            None => &EMPTY_VEC,
+    fn lock(&self) -> MutexGuard<'_, BTreeSet<Ready>> {
+        // The set is never left half updated, so it can be used even if a
+        // thread panicked while holding the lock
+        self.ready.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn push(&self, task: &Task) {
+        self.push_with_priority(task, task.0.priority);
+    }
+
+    /// Pushes a task whose dependencies just completed, with its priority
+    /// raised by the boost. The task itself keeps its priority, so that the
+    /// boost is lost once it is popped.
+    fn push_unblocked(&self, task: &Task) {
+        self.push_with_priority(task, task.0.priority.saturating_add(self.boost));
+    }
+
+    fn push_with_priority(&self, task: &Task, priority: i32) {
+        let sequence = self.sequence.fetch_add(1, AtomicOrdering::Relaxed);
+        self.insert(Ready {
+            priority,
+            sequence,
+            id: task.0.id,
+            name: task.0.name.clone(),
+            cost: task.0.cost,
+            labels: task.0.labels.clone(),
+            tenant: task.0.tenant.clone(),
+            created_at: task.0.created_at,
+        });
+    }
+
+    fn insert(&self, entry: Ready) {
+        let mut ready = self.lock();
+        ready.insert(entry);
+        metrics::gauge!(QUEUE_DEPTH, ready.len() as f64);
+        drop(ready);
+        // Every waiter is woken up, as the new task may only match some of
+        // their selectors
+        self.notify.notify_waiters();
+    }
+
+    /// The first ready task matching `selector`, and costing at most `budget`
+    /// if set: the one with the highest effective priority, among the tasks
+    /// of the tenant whose turn it is when popping in fair share.
+    fn first<'a>(
+        &self,
+        ready: &'a BTreeSet<Ready>,
+        selector: &Selector,
+        budget: Option<u32>,
+        now: OffsetDateTime,
+    ) -> Option<&'a Ready> {
+        let matching = ready.iter().filter(|ready| {
+            selector.matches(&ready.name, &ready.labels)
+                && budget.is_none_or(|budget| ready.cost <= budget)
+        });
+        if self.mode == PopMode::Priority {
+            return self.highest(matching, now);
         }
+        // The tenants take turns in the order of their names, starting from
+        // the one after the last served and wrapping around
+        let last = self
+            .last_tenant
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        let tenants = matching.clone().map(|ready| &ready.tenant);
+        let tenant = match &last {
+            Some(last) => tenants
+                .clone()
+                .filter(|&tenant| tenant > last)
+                .min()
+                .or_else(|| tenants.min()),
+            None => tenants.min(),
+        }?;
+        self.highest(matching.filter(|ready| &ready.tenant == tenant), now)
     }
 
-    async fn add_edge(
+    /// The task with the highest effective priority among `matching`.
+    fn highest<'a>(
         &self,
-        parent: TaskKey,
-        child: TaskKey,
-        tasks: &HashMap<TaskKey, Task>,
-    ) -> Result<(), CycleError> {
-        let mut edges = self.edges.write().await;
-        let parent_edges = edges.entry(parent).or_insert_with(Vec::new);
-        parent_edges.push(child);
+        mut matching: impl DoubleEndedIterator<Item = &'a Ready>,
+        now: OffsetDateTime,
+    ) -> Option<&'a Ready> {
+        if self.aging == 0.0 {
+            return matching.next_back();
+        }
+        // `max_by` keeps the last of the equal ones, so that ties go to the
+        // task first in the queue
+        matching.max_by(|a, b| {
+            a.effective_priority(now, self.aging)
+                .total_cmp(&b.effective_priority(now, self.aging))
+        })
+    }
 
-        // Check for loops in the graph using topological ordering
-        let mut in_degree: HashMap<TaskKey, usize> = tasks.iter().map(|(k, _)| (*k, 0)).collect();
-        for node in edges.keys() {
-            for dest in MemoryStore::get_edges(&edges, node).await.iter() {
-                in_degree.insert(*dest, in_degree.get(dest).unwrap() + 1);
-            }
+    /// Removes the first ready task matching `selector` and `budget` from the
+    /// queue, if any.
+    fn try_pop(
+        &self,
+        selector: &Selector,
+        budget: Option<u32>,
+        now: OffsetDateTime,
+    ) -> Option<Dequeued<'_>> {
+        let mut ready = self.lock();
+        let first = self.first(&ready, selector, budget, now)?.clone();
+        if self.mode == PopMode::FairShare {
+            *self
+                .last_tenant
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner) = Some(first.tenant.clone());
         }
+        ready.remove(&first);
+        metrics::gauge!(QUEUE_DEPTH, ready.len() as f64);
+        Some(Dequeued {
+            queue: self,
+            id: first.id,
+            ready: Some(first),
+        })
+    }
+
+    /// The first ready task, which is popped next by a worker accepting any
+    /// label.
+    fn peek(&self, now: OffsetDateTime) -> Option<TaskKey> {
+        self.first(&self.lock(), &Selector::default(), None, now)
+            .map(|ready| ready.id)
+    }
+
+    /// Resolves on the next push. It has to be called before a `try_pop`
+    /// which finds no task, so that a push in between is not lost.
+    fn notified(&self) -> Notified<'_> {
+        self.notify.notified()
+    }
+
+    /// Resolves once tasks leave the queue. It has to be called before the
+    /// depth is checked, so that the room made in between is not lost.
+    fn room(&self) -> Notified<'_> {
+        self.room.notified()
+    }
+
+    /// Removes a task from the queue, returning its entry if it was there.
+    fn remove(&self, id: TaskKey) -> Option<Ready> {
+        let mut ready = self.lock();
+        let entry = ready.iter().find(|ready| ready.id == id).cloned();
+        if let Some(entry) = &entry {
+            ready.remove(entry);
+        }
+        metrics::gauge!(QUEUE_DEPTH, ready.len() as f64);
+        drop(ready);
+        self.room.notify_waiters();
+        entry
+    }
+
+    fn clear(&self) {
+        self.lock().clear();
+        metrics::gauge!(QUEUE_DEPTH, 0.0);
+        self.room.notify_waiters();
+    }
+}
+
+/// A task taken off the queue by `try_pop`, which is put back in its place
+/// when dropped, unless it is `take`n. This keeps a pop dropped while it
+/// waits for a lock (i.e. on timeout) from losing the task.
+struct Dequeued<'a> {
+    queue: &'a ReadyQueue,
+    id: TaskKey,
+    ready: Option<Ready>,
+}
+
+impl Dequeued<'_> {
+    /// Hands out the task, which is not going to be put back on the queue.
+    fn take(mut self) -> TaskKey {
+        self.ready = None;
+        self.queue.room.notify_waiters();
+        self.id
+    }
+}
+
+impl Drop for Dequeued<'_> {
+    fn drop(&mut self) {
+        if let Some(ready) = self.ready.take() {
+            self.queue.insert(ready);
+        }
+    }
+}
+
+/// The results of the completed tasks, each kept for `retention` after the
+/// task was completed. The tasks completed without a result are recorded as
+/// well, so that their completion can be looked up.
+struct Results {
+    retention: Duration,
+    values: HashMap<TaskKey, (OffsetDateTime, Option<Value>)>,
+    /// The keys of `values` by completion, and so by expiration
+    expiration: VecDeque<TaskKey>,
+}
 
-        let mut queue = VecDeque::with_capacity(tasks.len());
-        for (node, in_deg) in in_degree.iter() {
-            if *in_deg == 0 {
-                queue.push_back(*node);
+impl Results {
+    fn new(retention: Duration) -> Self {
+        Results {
+            retention,
+            values: HashMap::new(),
+            expiration: VecDeque::new(),
+        }
+    }
+
+    fn insert(&mut self, task_id: TaskKey, result: Option<Value>, now: OffsetDateTime) {
+        while let Some(id) = self.expiration.front() {
+            if self
+                .values
+                .get(id)
+                .is_some_and(|(expires, _)| *expires > now)
+            {
+                break;
             }
+            self.values.remove(id);
+            self.expiration.pop_front();
+        }
+        if self.retention.is_positive() {
+            self.values.insert(task_id, (now + self.retention, result));
+            self.expiration.push_back(task_id);
         }
+    }
 
-        let mut count = queue.len();
-        while let Some(node) = queue.pop_front() {
-            for dest in MemoryStore::get_edges(&edges, &node).await.iter() {
-                let updated = in_degree.get(dest).unwrap() - 1;
-                in_degree.insert(*dest, updated);
-                if updated == 0 {
-                    queue.push_back(*dest);
-                    count += 1;
-                }
+    fn get(&self, task_id: &TaskKey, now: OffsetDateTime) -> Option<&Option<Value>> {
+        self.values
+            .get(task_id)
+            .filter(|(expires, _)| *expires > now)
+            .map(|(_, result)| result)
+    }
+}
+
+/// The tasks being awaited, each with the channel its state is sent on once
+/// ready or finished, or `None` once it is gone. The channel is dropped once
+/// the task is finished, but kept for those awaiting it past being ready.
+/// Its lock is a leaf.
+#[derive(Default)]
+struct Awaited(StdMutex<HashMap<TaskKey, watch::Sender<Option<Task>>>>);
+
+impl Awaited {
+    fn lock(&self) -> MutexGuard<'_, HashMap<TaskKey, watch::Sender<Option<Task>>>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn subscribe(&self, task_id: TaskKey) -> Awaiting<'_> {
+        let rx = self
+            .lock()
+            .entry(task_id)
+            .or_insert_with(|| watch::channel(None).0)
+            .subscribe();
+        Awaiting {
+            awaited: self,
+            task_id,
+            rx,
+        }
+    }
+
+    /// Hands `task` over to those awaiting it, once ready or finished.
+    fn notify(&self, task: &Task) {
+        if !matches!(
+            task.0.status,
+            Status::Ready | Status::Completed | Status::Failed
+        ) {
+            return;
+        }
+        let mut awaited = self.lock();
+        if task.0.status == Status::Ready {
+            if let Some(tx) = awaited.get(&task.0.id) {
+                tx.send_replace(Some(task.clone()));
             }
+        } else if let Some(tx) = awaited.remove(&task.0.id) {
+            tx.send_replace(Some(task.clone()));
         }
+    }
 
-        if count != tasks.len() {
-            Err(CycleError)
-        } else {
-            Ok(())
+    /// Tells those awaiting the task that it is gone.
+    fn forget(&self, task_id: TaskKey) {
+        if let Some(tx) = self.lock().remove(&task_id) {
+            tx.send_replace(None);
+        }
+    }
+
+    fn clear(&self) {
+        for (_, tx) in self.lock().drain() {
+            tx.send_replace(None);
         }
     }
 }
 
-#[async_trait]
-impl Store for MemoryStore {
-    async fn monitor(&self) -> Result<(), MonitorError> {
-        let mut rx = self.chan.1.lock().await;
-        let tx = Arc::new(self.chan.0.clone());
-
-        while let Some(msg) = rx.recv().await {
-            match msg {
-                MonitorMessage::Popped(task) => {
-                    let Task(task) = task;
-                    // The task has been popped off of the queue and we have to set a
-                    // timeout to wait for, if the task does not get completed in time.
-                    let (ttx, rx) = oneshot::channel::<()>();
-                    {
-                        let mut processing = self.processing.write().await;
-                        processing.insert(task.id, ttx);
-                    }
-                    let tx = tx.clone();
-                    tokio::spawn(async move {
-                        if timeout(task.duration.unsigned_abs(), rx).await.is_err() {
-                            if let Err(err) = tx.send(MonitorMessage::TimedOut(task.id)) {
-                                tracing::error!(id = %task.id, ?err, "Timeout task cannot communicate with store monitor");
-                            }
-                        }
-                    });
-                }
-                MonitorMessage::Completed(task_id) => {
-                    tracing::info!(id = %task_id, "Task execution complete");
-                    {
-                        let mut processing = self.processing.write().await;
-                        let ttx = processing
-                            .remove(&task_id)
-                            .ok_or(MonitorError::InvalidTask(task_id))?;
-                        ttx.send(())
-                            .map_err(|_| MonitorError::CancelTimeout(task_id))?;
-                        let mut tasks = self.tasks.write().await;
-                        tasks
-                            .remove(&task_id)
-                            .ok_or(MonitorError::InvalidTask(task_id))?;
-                    }
-                }
-                MonitorMessage::TimedOut(task_id) => {
-                    tracing::info!(id = %task_id, "Task execution timed out");
-                    {
-                        let mut processing = self.processing.write().await;
-                        processing
-                            .remove(&task_id)
-                            .ok_or(MonitorError::InvalidTask(task_id))?;
-
-                        self.queue.push(task_id);
-                    }
-                }
-            }
+/// A subscription to the state of an awaited task, whose channel is dropped
+/// along with its last subscriber.
+struct Awaiting<'a> {
+    awaited: &'a Awaited,
+    task_id: TaskKey,
+    rx: watch::Receiver<Option<Task>>,
+}
+
+impl Drop for Awaiting<'_> {
+    fn drop(&mut self) {
+        let mut awaited = self.awaited.lock();
+        // The channel may have been replaced since, once notified
+        if awaited
+            .get(&self.task_id)
+            .is_some_and(|tx| tx.receiver_count() <= 1 && tx.subscribe().same_channel(&self.rx))
+        {
+            awaited.remove(&self.task_id);
         }
-        Err(MonitorError::ChannelDropped)
     }
+}
 
-    async fn push(&self, insert_tasks: Vec<InsertTask>) -> Result<Vec<Task>, PushError> {
-        let mut result = Vec::with_capacity(insert_tasks.len());
-        for insert_task in insert_tasks.into_iter() {
-            let InsertTask(insert_task) = insert_task;
-            let mut next_key = self.next_key.write().await;
-            let TaskKey(id) = *next_key;
-            *next_key = TaskKey(id + 1);
-
-            let task = Task(taskie_structures::Task {
-                id: TaskKey(id),
-                payload: insert_task.payload,
-                name: insert_task.name,
-                duration: insert_task.duration,
-                depends_on: insert_task.depends_on.clone(),
-            });
-            let mut tasks = self.tasks.write().await;
-            tasks.insert(TaskKey(id), task.clone());
-            if insert_task.depends_on.is_empty() {
-                // if the task doesn't have any dependencies, we can just enqueue
-                // it, ready to be consumed by workers
-                self.queue.push(TaskKey(id));
-            } else {
-                for parent in insert_task.depends_on.into_iter() {
-                    if !tasks.contains_key(&parent) {
-                        return Err(PushError::MissingDependency { dependency: parent });
-                    }
-                    self.add_edge(TaskKey(id), parent, &tasks).await?;
-                }
-            }
+/// The digest of the content of a task, that is of its name, payload and
+/// dependencies.
+type Digest = [u8; 32];
 
-            tracing::debug!(nodes = ?tasks.keys(), edges = ?self.edges, "Dependency after task insertion");
-            result.push(task);
+fn digest(name: &str, payload: &Option<Value>, depends_on: &[TaskKey]) -> Digest {
+    let mut depends_on: Vec<u64> = depends_on.iter().map(|id| id.0).collect();
+    depends_on.sort_unstable();
+    let content = serde_json::to_vec(&(name, payload, depends_on))
+        .expect("JSON values can always be encoded");
+    Sha256::digest(content).into()
+}
+
+/// The tasks pushed with `dedupe`, by the digest of their content, and the
+/// other way around. They are only pushed in place of an equivalent task
+/// while they are pending or ready.
+#[derive(Default)]
+struct Contents {
+    tasks: HashMap<Digest, TaskKey>,
+    digests: HashMap<TaskKey, Digest>,
+}
+
+impl Contents {
+    fn get<'a>(&self, digest: &Digest, tasks: &'a HashMap<TaskKey, Task>) -> Option<&'a Task> {
+        let task = tasks.get(self.tasks.get(digest)?)?;
+        matches!(task.0.status, Status::Pending | Status::Ready).then_some(task)
+    }
+
+    fn insert(&mut self, digest: Digest, task_id: TaskKey) {
+        // The task it replaces is already past being ready
+        if let Some(replaced) = self.tasks.insert(digest, task_id) {
+            self.digests.remove(&replaced);
         }
-        Ok(result)
+        self.digests.insert(task_id, digest);
     }
 
-    async fn pop(&self) -> Result<Execution, PopError> {
-        let (tx, _) = &self.chan;
-        let task_id = self.queue.pop().await;
-        let tasks = self.tasks.read().await;
-        let task = tasks
-            .get(&task_id)
-            .ok_or(PopError::InvalidTaskId(task_id))?;
+    fn remove(&mut self, task_id: TaskKey) {
+        if let Some(digest) = self.digests.remove(&task_id) {
+            self.tasks.remove(&digest);
+        }
+    }
+}
 
-        // We should also do
-        // > self.edges.remove(&task_id);
-        // but it is not necesasry, as any node that is on the queue does not
-        // have any pending dependency.
-        // So, instead we do:
-        let edges = self.edges.read().await;
-        assert!(!edges.contains_key(&task_id));
+/// The tasks pushed with an idempotency key, each remembered for `window`
+/// after it was pushed.
+struct Idempotency {
+    window: Duration,
+    tasks: HashMap<String, (OffsetDateTime, Task)>,
+    /// The idempotency keys of `tasks`, with their expiration, by push
+    expiration: VecDeque<(OffsetDateTime, String)>,
+}
 
-        tx.send(MonitorMessage::Popped(task.clone()))
-            .map_err(|_| PopError::MonitorCommunication)?;
-        Ok(Execution(taskie_structures::Execution {
-            deadline: OffsetDateTime::now_utc() + task.0.duration,
-            task: task.clone(),
-        }))
+impl Idempotency {
+    fn new(window: Duration) -> Self {
+        Idempotency {
+            window,
+            tasks: HashMap::new(),
+            expiration: VecDeque::new(),
+        }
     }
 
-    async fn complete(&self, task_id: TaskKey) -> Result<(), CompleteError> {
-        let processing = self.processing.read().await;
-        if !processing.contains_key(&task_id) {
-            return Err(CompleteError::InvalidTaskId(task_id));
+    fn insert(&mut self, key: String, task: Task, now: OffsetDateTime) {
+        while let Some((expires, key)) = self.expiration.front() {
+            if *expires > now {
+                break;
+            }
+            // The key may have been reused since, with a later expiration
+            if self
+                .tasks
+                .get(key)
+                .is_some_and(|(current, _)| current == expires)
+            {
+                self.tasks.remove(key);
+            }
+            self.expiration.pop_front();
+        }
+        if self.window.is_positive() {
+            let expires = now + self.window;
+            self.tasks.insert(key.clone(), (expires, task));
+            self.expiration.push_back((expires, key));
         }
+    }
 
-        let (tx, _) = &self.chan;
-        tx.send(MonitorMessage::Completed(task_id))
-            .map_err(|_| CompleteError::MonitorCommunication)?;
+    /// The task pushed with `key`, as it was when pushed.
+    fn get(&self, key: &str, now: OffsetDateTime) -> Option<&Task> {
+        self.tasks
+            .get(key)
+            .filter(|(expires, _)| *expires > now)
+            .map(|(_, task)| task)
+    }
+}
 
-        let mut edges = self.edges.write().await;
-        // A vector for the tasks which become ready once the current one is popped
-        let mut ready = vec![];
-        for (node, node_edges) in edges.iter_mut() {
-            node_edges.retain(|&dest| dest != task_id);
-            if node_edges.is_empty() {
-                ready.push(*node);
+/// A task pushed with the idempotency key of a task pushed before.
+enum Repeat {
+    /// By another push
+    Pushed(Box<Task>),
+    /// Earlier in the same batch, at this position
+    Batch(usize),
+}
+
+/// The keys the tasks of a batch are given, by position: the ones pushed
+/// again are the task they `repeat`, while the others are handed out in
+/// order from `next_key`, unless the keys run out.
+fn batch_keys(repeats: &[Option<Repeat>], next_key: u64) -> Option<Vec<TaskKey>> {
+    let mut next_key = next_key;
+    let mut keys: Vec<TaskKey> = Vec::with_capacity(repeats.len());
+    for repeat in repeats.iter() {
+        let key = match repeat {
+            Some(Repeat::Pushed(pushed)) => pushed.0.id,
+            Some(Repeat::Batch(i)) => keys[*i],
+            None => {
+                let key = TaskKey(next_key);
+                next_key = next_key.checked_add(1)?;
+                key
             }
+        };
+        keys.push(key);
+    }
+    Some(keys)
+}
+
+/// The keys of the tasks of a batch which are not pushed again.
+fn new_keys(keys: &[TaskKey], repeats: &[Option<Repeat>]) -> HashSet<TaskKey> {
+    keys.iter()
+        .zip(repeats.iter())
+        .filter(|(_, repeat)| repeat.is_none())
+        .map(|(&key, _)| key)
+        .collect()
+}
+
+/// Whether any of the `new` tasks of a batch depends on a later one, which
+/// breaks the order of the keys like an update can.
+fn depends_forward(
+    keys: &[TaskKey],
+    new: &HashSet<TaskKey>,
+    dependencies: &[Vec<TaskKey>],
+) -> bool {
+    keys.iter()
+        .zip(dependencies.iter())
+        .filter(|(key, _)| new.contains(key))
+        .any(|(key, dependencies)| dependencies.iter().any(|dependency| dependency > key))
+}
+
+/// The tasks pushed again every time they are completed.
+#[derive(Default)]
+struct Recurring {
+    /// The schedule and current instance of each recurring task, by the key
+    /// of its first instance
+    definitions: HashMap<TaskKey, (Schedule, TaskKey)>,
+    /// The recurring task each current instance belongs to
+    instances: HashMap<TaskKey, TaskKey>,
+}
+
+fn parse_schedule(schedule: &str) -> Result<Schedule, PushError> {
+    let invalid = |reason: String| PushError::InvalidSchedule {
+        schedule: schedule.to_string(),
+        reason,
+    };
+    let parsed = Schedule::from_str(schedule).map_err(|err| invalid(err.to_string()))?;
+    if next_run(&parsed).is_none() {
+        return Err(invalid("it never fires again".to_string()));
+    }
+    Ok(parsed)
+}
+
+/// How many tasks are pending: only the tasks waiting for some dependency
+/// have edges, the others are waiting for their time to come.
+fn pending(
+    edges: &HashMap<TaskKey, Vec<TaskKey>>,
+    scheduled: &BTreeSet<(OffsetDateTime, TaskKey)>,
+) -> usize {
+    edges.len()
+        + scheduled
+            .iter()
+            .filter(|(_, id)| !edges.contains_key(id))
+            .count()
+}
+
+/// The span of time the completion rate is measured over.
+const DRAIN_WINDOW: Duration = Duration::MINUTE;
+
+/// Counts the completed tasks over the current window of `DRAIN_WINDOW`, and
+/// over the one before it, so that the completion rate follows the recent
+/// load without keeping track of every completion.
+#[derive(Default)]
+struct Drain {
+    /// When the current window started, if any task was ever completed
+    since: Option<OffsetDateTime>,
+    current: u64,
+    previous: u64,
+}
+
+impl Drain {
+    /// Moves to the window `now` falls in.
+    fn roll(&mut self, now: OffsetDateTime) {
+        let Some(since) = self.since else {
+            return;
+        };
+        let windows = ((now - since) / DRAIN_WINDOW).floor();
+        if windows >= 1.0 {
+            self.previous = if windows < 2.0 { self.current } else { 0 };
+            self.current = 0;
+            self.since = Some(since + DRAIN_WINDOW * windows);
         }
+    }
 
-        // Put any ready task on the queue
-        for node in ready.into_iter() {
-            tracing::debug!(id = %node, "Task has become ready");
-            edges.remove(&node);
-            self.queue.push(node);
+    fn record(&mut self, now: OffsetDateTime) {
+        self.roll(now);
+        self.since.get_or_insert(now);
+        self.current += 1;
+    }
+
+    /// How many tasks have been completed per second, over the last two
+    /// windows.
+    fn rate(&mut self, now: OffsetDateTime) -> f64 {
+        self.roll(now);
+        let Some(since) = self.since else {
+            return 0.0;
+        };
+        let elapsed = DRAIN_WINDOW + (now - since);
+        (self.previous + self.current) as f64 / elapsed.as_seconds_f64()
+    }
+}
+
+/// Checks that the new task `id` can depend on `dependency`, which either is
+/// in `tasks` or is one of the `new` tasks of the same batch, and records the
+/// edge in the `added` ones unless it closes a loop.
+fn check_dependency(
+    id: TaskKey,
+    dependency: TaskKey,
+    tasks: &HashMap<TaskKey, Task>,
+    new: &HashSet<TaskKey>,
+    edges: &HashMap<TaskKey, Vec<TaskKey>>,
+    added: &mut HashMap<TaskKey, Vec<TaskKey>>,
+    ordered: bool,
+) -> Result<(), PushError> {
+    if dependency == id {
+        return Err(PushError::SelfDependency(id));
+    }
+    if !tasks.contains_key(&dependency) && !new.contains(&dependency) {
+        return Err(PushError::MissingDependency { dependency });
+    }
+    if closes_loop(edges, added, id, dependency, ordered) {
+        return Err(CycleError.into());
+    }
+    added.entry(id).or_default().push(dependency);
+    Ok(())
+}
+
+/// Whether the edge from `parent` to `child` closes a loop in the graph made
+/// of `edges` along with the `added` ones, which are not stored (yet). The
+/// keys are an `ordered` graph's topological order.
+fn closes_loop(
+    edges: &HashMap<TaskKey, Vec<TaskKey>>,
+    added: &HashMap<TaskKey, Vec<TaskKey>>,
+    parent: TaskKey,
+    child: TaskKey,
+    ordered: bool,
+) -> bool {
+    // Keys are handed out in increasing order and tasks are mostly pushed
+    // depending on tasks which already exist, so unless an update or a batch
+    // made a task depend on a later one, every edge goes from a higher key to a
+    // lower one: the keys are a topological order of the graph, and an edge
+    // which respects it cannot close a loop.
+    if ordered && child < parent {
+        return false;
+    }
+
+    // The graph had no loops before the new edge, so any loop has to go
+    // through it: look for a path back to `parent` from `child`, only
+    // visiting the nodes reachable from the latter
+    let mut visited = HashSet::new();
+    let mut stack = vec![child];
+    while let Some(node) = stack.pop() {
+        if node == parent {
+            return true;
         }
-        Ok(())
+        if !visited.insert(node) {
+            continue;
+        }
+        // Dependencies in the dead-letter queue have no edges of their
+        // own, so they cannot be part of a cycle
+        stack.extend(
+            edges
+                .get(&node)
+                .into_iter()
+                .chain(added.get(&node))
+                .flatten(),
+        );
+    }
+    false
+}
+
+/// How the ready task to pop is chosen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PopMode {
+    /// The task with the highest priority, the oldest first among equals
+    #[default]
+    Priority,
+    /// The tenants with ready tasks take turns, whatever the priority of their
+    /// tasks: each pop goes to the next tenant by name, the tasks without one
+    /// coming first, and picks its task with the highest priority. Priorities
+    /// thus only order the tasks of the same tenant.
+    FairShare,
+}
+
+/// What a push does when the queue is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// The push is refused
+    #[default]
+    Reject,
+    /// The push waits until the tasks on the queue are popped
+    Block,
+}
+
+/// How long the tasks put back after a failure or a timeout wait before they
+/// can be popped again: the first retry waits `base`, and each of the next
+/// ones `factor` times longer than the previous one, up to `max`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Backoff {
+    pub base: Duration,
+    pub factor: f64,
+    pub max: Duration,
+}
+
+pub static DEFAULT_BACKOFF_FACTOR: f64 = 2.0;
+pub static DEFAULT_MAX_BACKOFF: Duration = Duration::HOUR;
+
+impl Backoff {
+    /// The delay of the task retried after its `attempt`-th execution.
+    fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay = self.base.as_seconds_f64() * self.factor.powi(exponent);
+        // Overflows and NaNs end up at the cap as well
+        Duration::seconds_f64(delay.min(self.max.as_seconds_f64())).clamp(Duration::ZERO, self.max)
+    }
+}
+
+/// The next time matching the schedule, if there is any.
+fn next_run(schedule: &Schedule) -> Option<OffsetDateTime> {
+    let next = schedule.upcoming(chrono::Utc).next()?;
+    OffsetDateTime::from_unix_timestamp(next.timestamp()).ok()
+}
+
+/// A store keeping all the tasks in memory.
+///
+/// Whenever more than one of its locks are held at once, they are taken in
+/// the order of the fields below, that is `recurring`, the `processing` tasks
+/// of the `shards` in the order of their index, `tasks`, `idempotency`,
+/// `contents`, `edges`, `scheduled`, `dead_letter`, `timeouts` and then
+/// `results`, so that `push`, `pop`, `complete` and the monitor cannot
+/// deadlock with each other. Only the whole store operations, like `stats`,
+/// hold more than one shard at once. The lock of the `queue` is not part of
+/// the order, as it is never held while awaiting or taking any other lock: a
+/// task is taken off the queue before any other lock is held, and is put back
+/// in its place if the pop does not go through.
+///
+/// The processing tasks are split into shards, each owned by a loop of the
+/// monitor. The messages about a task are handled in the order they were
+/// sent, as they all go to the same loop, but those about tasks in different
+/// shards are not: i.e. a task completed after another one timed out may be
+/// handled first, so that its dependents get on the queue before the other
+/// one is retried. With more shards the monitor keeps up with more
+/// completions and timeouts, at the cost of that ordering.
+pub struct MemoryStore {
+    recurring: RwLock<Recurring>,
+    /// The key of the next task pushed
+    next_key: AtomicU64,
+    /// The tasks with a lower key have been purged, if any
+    purged: AtomicU64,
+    /// The processing tasks, by their key modulo the number of shards
+    shards: Vec<Shard>,
+    /// A slot for each task which can be processing at the same time, if
+    /// they are bounded
+    slots: Option<Arc<Semaphore>>,
+    tasks: RwLock<HashMap<TaskKey, Task>>,
+    /// The tasks pushed with an idempotency key, by that key
+    idempotency: RwLock<Idempotency>,
+    contents: RwLock<Contents>,
+    queue: ReadyQueue,
+    edges: RwLock<HashMap<TaskKey, Vec<TaskKey>>>,
+    /// Set once an update made a task depend on a later one, so that the keys
+    /// are no longer a topological order of `edges`. It only changes while
+    /// `edges` is locked.
+    unordered: AtomicBool,
+    /// Tasks whose `run_at` has not come yet, by `run_at`. They are put on the
+    /// queue once it comes, unless they still have pending dependencies.
+    scheduled: RwLock<BTreeSet<(OffsetDateTime, TaskKey)>>,
+    /// Tasks which timed out or failed more than their `max_retries`, along
+    /// with the reason of their last failure
+    dead_letter: RwLock<HashMap<TaskKey, (Task, String)>>,
+    /// How many times each task timed out, for the tasks still in circulation
+    timeouts: RwLock<HashMap<TaskKey, u32>>,
+    results: RwLock<Results>,
+    /// After how many timeouts a task is moved to the dead-letter queue,
+    /// whatever its `max_retries`
+    max_timeouts: u32,
+    /// Up to which fraction of their duration the timeouts are delayed at
+    /// random
+    timeout_jitter: f64,
+    /// How many tasks can be pending or ready at once, if they are bounded
+    max_queue_depth: Option<usize>,
+    overflow: Overflow,
+    /// How long the pushes refused on a full queue wait at most to retry
+    retry_after: Duration,
+    /// How long the retried tasks wait before they are ready again, if at all
+    backoff: Option<Backoff>,
+    drain: StdMutex<Drain>,
+    /// How many tasks have been completed
+    completed: AtomicU64,
+    clock: Arc<dyn Clock>,
+    /// Where the changes in the state of the tasks are published, if anywhere
+    events: Option<Emitter>,
+    /// How the keys of the tasks are concealed in the events and callbacks
+    keys: Option<Keys>,
+    awaited: Awaited,
+    /// How many messages wait for the monitor, in the channels of all the
+    /// shards
+    backlog: Arc<AtomicUsize>,
+    /// When the monitor last handled a message
+    last_handled: StdMutex<Option<OffsetDateTime>>,
+    /// How long a message can wait for the monitor before a warning is logged
+    lag_warning: Duration,
+    /// How often the monitor times out the tasks past their deadline
+    monitor_tick: Duration,
+}
+
+/// The state of a memory store, as saved to a file. The processing tasks are
+/// saved with their deadline, and the scheduled ones with when they are due,
+/// for their timers to be armed again once loaded.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    next_key: u64,
+    tasks: Vec<taskie_structures::Task<TaskName, TaskKey>>,
+    edges: Vec<(TaskKey, Vec<TaskKey>)>,
+    unordered: bool,
+    processing: Vec<Timer>,
+    scheduled: Vec<Timer>,
+    /// The current instance of each recurring task, by its first one
+    recurring: Vec<(TaskKey, TaskKey)>,
+    dead_letter: Vec<(taskie_structures::Task<TaskName, TaskKey>, String)>,
+    timeouts: Vec<(TaskKey, u32)>,
+}
+
+/// When the timeout or the schedule of a task fires.
+#[derive(Serialize, Deserialize)]
+struct Timer {
+    id: TaskKey,
+    #[serde(with = "iso8601")]
+    at: OffsetDateTime,
+    /// The lease token of a processing task, so that its worker can still
+    /// complete it once loaded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    lease_token: Option<Uuid>,
+}
+
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+    #[error("Could not access the snapshot file: {}", .0)]
+    Io(#[from] std::io::Error),
+    #[error("Invalid snapshot: {}", .0)]
+    Format(#[from] serde_json::Error),
+    #[error("The store has tasks already, and cannot load a snapshot")]
+    NotEmpty,
+    #[error("Invalid schedule for the recurring task {}", .0)]
+    InvalidSchedule(TaskKey),
+}
+
+#[derive(Error, Debug)]
+pub struct CycleError;
+
+impl std::fmt::Display for CycleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "A cycle in the DAG has been detected")
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub static DEFAULT_RESULT_RETENTION: Duration = Duration::HOUR;
+pub static DEFAULT_MAX_TIMEOUTS: u32 = 10;
+pub static DEFAULT_IDEMPOTENCY_WINDOW: Duration = Duration::DAY;
+pub static DEFAULT_LAG_WARNING: Duration = Duration::SECOND;
+pub static DEFAULT_RETRY_AFTER: Duration = Duration::seconds(30);
+pub static DEFAULT_MONITOR_TICK: Duration = Duration::milliseconds(100);
+pub static DEFAULT_MONITOR_SHARDS: usize = 1;
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        let backlog = Arc::new(AtomicUsize::new(0));
+
+        MemoryStore {
+            recurring: RwLock::new(Recurring::default()),
+            next_key: AtomicU64::new(1),
+            purged: AtomicU64::new(0),
+            shards: (0..DEFAULT_MONITOR_SHARDS)
+                .map(|_| Shard::new(backlog.clone()))
+                .collect(),
+            slots: None,
+            tasks: RwLock::new(HashMap::new()),
+            idempotency: RwLock::new(Idempotency::new(DEFAULT_IDEMPOTENCY_WINDOW)),
+            contents: RwLock::new(Contents::default()),
+            queue: ReadyQueue::new(),
+            edges: RwLock::new(HashMap::new()),
+            unordered: AtomicBool::new(false),
+            scheduled: RwLock::new(BTreeSet::new()),
+            dead_letter: RwLock::new(HashMap::new()),
+            timeouts: RwLock::new(HashMap::new()),
+            results: RwLock::new(Results::new(DEFAULT_RESULT_RETENTION)),
+            max_timeouts: DEFAULT_MAX_TIMEOUTS,
+            timeout_jitter: 0.0,
+            max_queue_depth: None,
+            overflow: Overflow::Reject,
+            retry_after: DEFAULT_RETRY_AFTER,
+            backoff: None,
+            drain: StdMutex::new(Drain::default()),
+            completed: AtomicU64::new(0),
+            clock: Arc::new(SystemClock),
+            events: None,
+            keys: None,
+            awaited: Awaited::default(),
+            backlog,
+            last_handled: StdMutex::new(None),
+            lag_warning: DEFAULT_LAG_WARNING,
+            monitor_tick: DEFAULT_MONITOR_TICK,
+        }
+    }
+
+    /// Sets for how long the results of the completed tasks are kept around,
+    /// for the tasks depending on them and the producers awaiting them. A
+    /// zero retention discards them.
+    pub fn result_retention(mut self, retention: Duration) -> Self {
+        self.results.get_mut().retention = retention;
+        self
+    }
+
+    /// Sets for how long a task pushed with an idempotency key is returned
+    /// again when pushed with the same key, instead of a new task. A zero
+    /// window disables the deduplication.
+    pub fn idempotency_window(mut self, window: Duration) -> Self {
+        self.idempotency.get_mut().window = window;
+        self
+    }
+
+    /// Sets after how many timeouts a task is dropped from circulation, even
+    /// if it has retries left, so that a task whose workers always crash
+    /// cannot keep coming back.
+    pub fn max_timeouts(mut self, max_timeouts: u32) -> Self {
+        self.max_timeouts = max_timeouts;
+        self
+    }
+
+    /// Sets how many tasks can be processing at the same time: once they are
+    /// as many, `pop` waits for one of them to be completed, failed or timed
+    /// out. A zero limit leaves them unbounded.
+    pub fn max_processing(mut self, max_processing: usize) -> Self {
+        self.slots = (max_processing > 0).then(|| Arc::new(Semaphore::new(max_processing)));
+        self
+    }
+
+    /// Sets up to which fraction of its duration the timeout of a task is
+    /// delayed, at random, so that the tasks popped in a burst are not all
+    /// re-enqueued at once when their worker crashes. Timeouts are never
+    /// anticipated, so that workers can rely on their deadline. A zero
+    /// fraction, the default, times them out right at their deadline.
+    pub fn timeout_jitter(mut self, fraction: f64) -> Self {
+        self.timeout_jitter = fraction.max(0.0);
+        self
+    }
+
+    /// Sets how many tasks can be pending or ready at once: once they are as
+    /// many, a push either fails with `PushError::QueueFull` or waits for
+    /// enough of them to be popped, depending on `overflow`. The tasks put
+    /// back on the queue after a failure or a timeout are never refused. A
+    /// zero limit leaves them unbounded.
+    pub fn max_queue_depth(mut self, max_queue_depth: usize, overflow: Overflow) -> Self {
+        self.max_queue_depth = (max_queue_depth > 0).then_some(max_queue_depth);
+        self.overflow = overflow;
+        self
+    }
+
+    /// Sets how much the priority of a ready task grows for each second since
+    /// it was created, so that low priority tasks are eventually popped under
+    /// a constant load of higher priority ones. Tasks do not age by default.
+    pub fn priority_aging(mut self, per_second: f64) -> Self {
+        self.queue.aging = per_second.max(0.0);
+        self
+    }
+
+    /// Sets how much the priority of a task is raised when completing its
+    /// dependencies puts it on the queue, so that the tasks unblocked in a DAG,
+    /// often on its critical path, do not wait behind a backlog of
+    /// independent ones. The boost lasts while the task is on the queue, and
+    /// is lost if it is retried. Tasks are not boosted by default.
+    pub fn dependency_boost(mut self, boost: i32) -> Self {
+        self.queue.boost = boost.max(0);
+        self
+    }
+
+    /// Sets how the ready task to pop is chosen: by priority, the default, or
+    /// taking turns among the tenants so that none of them can keep the
+    /// workers busy at the expense of the others. Aging applies in both, only
+    /// among the tasks of a tenant in fair share.
+    pub fn pop_mode(mut self, mode: PopMode) -> Self {
+        self.queue.mode = mode;
+        self
+    }
+
+    /// Sets how long the pushes refused because the queue is full are told
+    /// to wait, at most, before retrying. The wait is estimated from the rate
+    /// the tasks are being completed at, and is exactly `retry_after` when no
+    /// task has been completed lately.
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// Delays the retries of the failed and timed out tasks by `backoff`,
+    /// scheduling them instead of putting them back on the queue right away,
+    /// so that a task failing over and over does not keep the workers busy.
+    /// They are retried right away by default, or when `backoff.base` is
+    /// zero.
+    pub fn retry_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff.base.is_positive().then_some(backoff);
+        self
+    }
+
+    /// Sets how long a completion, a timeout or any other message can wait to
+    /// be handled by the monitor before a warning is logged: the tasks are
+    /// not completed nor timed out until it is.
+    pub fn lag_warning(mut self, lag: Duration) -> Self {
+        self.lag_warning = lag;
+        self
+    }
+
+    /// Sets how often the monitor looks for the tasks past their deadline,
+    /// which time out up to a tick late. No timer is spawned for each task
+    /// popped, so that a short tick costs the same however many tasks are
+    /// processing. It is a millisecond at least.
+    pub fn monitor_tick(mut self, tick: Duration) -> Self {
+        self.monitor_tick = tick.max(Duration::MILLISECOND);
+        self
+    }
+
+    /// Sets into how many shards the processing tasks are split, each owned
+    /// by its own loop of the monitor, so that the completions and the
+    /// timeouts of the tasks in different shards are handled concurrently,
+    /// though in no given order among them. There is one shard at least.
+    pub fn monitor_shards(mut self, shards: usize) -> Self {
+        self.shards = (0..shards.max(1))
+            .map(|_| Shard::new(self.backlog.clone()))
+            .collect();
+        self
+    }
+
+    /// Sets the clock the store runs on, the system one by default.
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Publishes an event to `sink` whenever a task is pushed, popped,
+    /// completed, failed or timed out. No event is published by default, nor
+    /// without the `keys` to conceal the keys of the tasks with.
+    pub fn events(mut self, sink: impl EventSink) -> Self {
+        self.events = Some(Emitter::new(sink));
+        self
+    }
+
+    /// Sets how the keys of the tasks are concealed in the events and in the
+    /// deliveries to their callback, i.e. as by the server using the store.
+    pub fn keys(mut self, keys: Keys) -> Self {
+        self.keys = Some(keys);
+        self
+    }
+
+    /// Saves the tasks, their dependencies, the recurring and dead-lettered
+    /// ones and the deadlines of the processing ones to the JSON file at
+    /// `path`, i.e. once the monitor stopped on shutdown. The file is
+    /// replaced as a whole, so that a crash while saving leaves the previous
+    /// snapshot in place. The idempotency keys, the digests of the
+    /// deduplicated tasks and the results are not saved.
+    pub async fn save(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        let path = path.as_ref();
+        let snapshot = {
+            let recurring = self.recurring.read().await;
+            let processing = self.read_processing().await;
+            let tasks = self.tasks.read().await;
+            let edges = self.edges.read().await;
+            let scheduled = self.scheduled.read().await;
+            let dead_letter = self.dead_letter.read().await;
+            let timeouts = self.timeouts.read().await;
+            Snapshot {
+                next_key: self.next_key.load(AtomicOrdering::Relaxed),
+                tasks: tasks.values().map(|task| task.0.clone()).collect(),
+                edges: edges
+                    .iter()
+                    .map(|(&id, parents)| (id, parents.clone()))
+                    .collect(),
+                unordered: self.unordered.load(AtomicOrdering::Relaxed),
+                processing: processing
+                    .iter()
+                    .flat_map(|processing| processing.iter())
+                    .map(|(&id, entry)| Timer {
+                        id,
+                        at: entry.deadline,
+                        lease_token: Some(entry.lease_token),
+                    })
+                    .collect(),
+                scheduled: scheduled
+                    .iter()
+                    .map(|&(at, id)| Timer {
+                        id,
+                        at,
+                        lease_token: None,
+                    })
+                    .collect(),
+                recurring: recurring
+                    .definitions
+                    .iter()
+                    .map(|(&id, (_, current))| (id, *current))
+                    .collect(),
+                dead_letter: dead_letter
+                    .values()
+                    .map(|(task, reason)| (task.0.clone(), reason.clone()))
+                    .collect(),
+                timeouts: timeouts.iter().map(|(&id, &count)| (id, count)).collect(),
+            }
+        };
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        tokio::fs::write(&partial, serde_json::to_vec(&snapshot)?).await?;
+        tokio::fs::rename(&partial, path).await?;
+        tracing::info!(path = %path.display(), tasks = snapshot.tasks.len(), "Memory store saved");
+        Ok(())
+    }
+
+    /// Loads the snapshot saved by `save` at `path` into the store, which has
+    /// to have no task yet. The processing tasks time out at their saved
+    /// deadline, or at the first tick of the monitor if it passed, unless
+    /// they are completed before, and the scheduled ones are due when they
+    /// were.
+    pub async fn load(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        let path = path.as_ref();
+        let snapshot: Snapshot = serde_json::from_slice(&tokio::fs::read(path).await?)?;
+
+        let mut recurring = self.recurring.write().await;
+        let mut processing = self.write_processing().await;
+        let mut tasks = self.tasks.write().await;
+        let mut edges = self.edges.write().await;
+        let mut scheduled = self.scheduled.write().await;
+        let mut dead_letter = self.dead_letter.write().await;
+        let mut timeouts = self.timeouts.write().await;
+        if !tasks.is_empty() || !dead_letter.is_empty() {
+            return Err(SnapshotError::NotEmpty);
+        }
+        let mut definitions = HashMap::with_capacity(snapshot.recurring.len());
+        for (id, current) in snapshot.recurring {
+            let schedule = snapshot
+                .tasks
+                .iter()
+                .find(|task| task.id == current)
+                .and_then(|task| task.schedule.as_deref())
+                .and_then(|schedule| parse_schedule(schedule).ok())
+                .ok_or(SnapshotError::InvalidSchedule(id))?;
+            definitions.insert(id, (schedule, current));
+        }
+
+        self.next_key
+            .fetch_max(snapshot.next_key, AtomicOrdering::Relaxed);
+        self.unordered
+            .store(snapshot.unordered, AtomicOrdering::Relaxed);
+        recurring.instances = definitions
+            .iter()
+            .map(|(&id, &(_, current))| (current, id))
+            .collect();
+        recurring.definitions = definitions;
+        let mut loaded: Vec<_> = snapshot.tasks.into_iter().map(Task).collect();
+        // Put back on the queue in the order they were pushed
+        loaded.sort_by_key(|task| task.0.id);
+        for task in loaded.into_iter() {
+            if let Some(expires_at) = task.0.expires_at {
+                self.arm_expiry(task.0.id, expires_at);
+            }
+            if task.0.status == Status::Ready {
+                self.queue.push(&task);
+            }
+            tasks.insert(task.0.id, task);
+        }
+        edges.extend(snapshot.edges);
+        for Timer {
+            id,
+            at,
+            lease_token,
+        } in snapshot.processing.into_iter()
+        {
+            let slot = self
+                .slots
+                .as_ref()
+                .and_then(|slots| slots.clone().try_acquire_owned().ok());
+            self.shard(id).lock_deadlines().insert((at, id));
+            processing[self.shard_index(id)].insert(
+                id,
+                Processing {
+                    deadline: at,
+                    timed_out: false,
+                    lease_token: lease_token.unwrap_or_else(Uuid::new_v4),
+                    _slot: slot,
+                },
+            );
+        }
+        for (shard, processing) in self.shards.iter().zip(processing.iter()) {
+            self.processing_gauge(shard, processing.len());
+        }
+        let processing: usize = processing.iter().map(|processing| processing.len()).sum();
+        for Timer { id, at, .. } in snapshot.scheduled.into_iter() {
+            scheduled.insert((at, id));
+            self.arm_schedule(id, at);
+        }
+        dead_letter.extend(
+            snapshot
+                .dead_letter
+                .into_iter()
+                .map(|(task, reason)| (task.id, (Task(task), reason))),
+        );
+        timeouts.extend(snapshot.timeouts);
+        tracing::info!(path = %path.display(), tasks = tasks.len(), processing, "Memory store loaded");
+        Ok(())
+    }
+
+    /// Publishes the current state of `task` to the event sink, if any, and
+    /// hands it over to those awaiting it.
+    fn emit(&self, task: &Task) {
+        match (&self.events, &self.keys) {
+            (Some(events), Some(keys)) => events.emit(task, self.clock.now(), keys),
+            (Some(_), None) => {
+                tracing::error!(id = %task.0.id, "Cannot publish the event of the task without the keys to conceal it");
+            }
+            (None, _) => {}
+        }
+        self.awaited.notify(task);
+    }
+
+    /// Delivers the finished `task` to its callback, if it has one.
+    fn deliver(&self, task: &Task) {
+        match &self.keys {
+            Some(keys) => callback::notify(task, keys),
+            None if task.0.callback_url.is_some() => {
+                tracing::error!(id = %task.0.id, "Cannot deliver the task to its callback without the keys to conceal it");
+            }
+            None => {}
+        }
+    }
+
+    /// Waits for the task to reach a status `reached` holds for, and returns
+    /// it along with the task as it was then.
+    async fn await_status(
+        &self,
+        task_id: TaskKey,
+        reached: fn(Status) -> bool,
+    ) -> Result<(Status, Option<Task>), AwaitError> {
+        // Subscribed before the task is looked up, so that no change can
+        // slip in between
+        let mut awaiting = self.awaited.subscribe(task_id);
+        match self.get(task_id).await {
+            Ok(task) if reached(task.0.status) => return Ok((task.0.status, Some(task))),
+            Ok(_) => {}
+            Err(_) => {
+                let results = self.results.read().await;
+                return match results.get(&task_id, self.clock.now()) {
+                    Some(_) if reached(Status::Completed) => Ok((Status::Completed, None)),
+                    _ => Err(AwaitError::InvalidTaskId(task_id)),
+                };
+            }
+        }
+        // The channel is only dropped after sending the finished task, or
+        // `None`
+        while awaiting.rx.changed().await.is_ok() {
+            match awaiting.rx.borrow_and_update().clone() {
+                Some(task) if reached(task.0.status) => return Ok((task.0.status, Some(task))),
+                Some(_) => {}
+                None => break,
+            }
+        }
+        Err(AwaitError::InvalidTaskId(task_id))
+    }
+
+    /// The index of the shard of the task.
+    fn shard_index(&self, task_id: TaskKey) -> usize {
+        (task_id.0 % self.shards.len() as u64) as usize
+    }
+
+    /// The shard of the task, whose monitor loop handles its messages.
+    fn shard(&self, task_id: TaskKey) -> &Shard {
+        &self.shards[self.shard_index(task_id)]
+    }
+
+    /// Locks the processing tasks of all the shards, in order.
+    async fn read_processing(&self) -> Vec<RwLockReadGuard<'_, HashMap<TaskKey, Processing>>> {
+        let mut processing = Vec::with_capacity(self.shards.len());
+        for shard in self.shards.iter() {
+            processing.push(shard.processing.read().await);
+        }
+        processing
+    }
+
+    /// Locks the processing tasks of all the shards for writing, in order.
+    async fn write_processing(&self) -> Vec<RwLockWriteGuard<'_, HashMap<TaskKey, Processing>>> {
+        let mut processing = Vec::with_capacity(self.shards.len());
+        for shard in self.shards.iter() {
+            processing.push(shard.processing.write().await);
+        }
+        processing
+    }
+
+    /// Sets the gauge of the processing tasks once `shard` holds `len` of
+    /// them, along with those of the other shards.
+    fn processing_gauge(&self, shard: &Shard, len: usize) {
+        shard.len.store(len, AtomicOrdering::Relaxed);
+        let total: usize = self
+            .shards
+            .iter()
+            .map(|shard| shard.len.load(AtomicOrdering::Relaxed))
+            .sum();
+        metrics::gauge!(PROCESSING, total as f64);
+    }
+
+    /// Adds the deadline of the task, once `duration`, plus the jitter, has
+    /// elapsed, for the monitor to time it out unless it is disarmed before.
+    /// `processing` is to be locked.
+    fn arm_timeout(&self, task_id: TaskKey, duration: Duration) -> OffsetDateTime {
+        let duration = if self.timeout_jitter > 0.0 {
+            duration + duration * (self.timeout_jitter * rand::random::<f64>())
+        } else {
+            duration
+        };
+        let deadline = self.clock.now() + duration;
+        self.shard(task_id)
+            .lock_deadlines()
+            .insert((deadline, task_id));
+        deadline
+    }
+
+    /// Removes the deadline of the task, telling whether it was still there.
+    /// `processing` is to be locked.
+    fn disarm_timeout(&self, task_id: TaskKey, entry: &Processing) -> bool {
+        self.shard(task_id)
+            .lock_deadlines()
+            .remove(&(entry.deadline, task_id))
+    }
+
+    /// Sends a `TimedOut` message for every task of `shard` past its
+    /// deadline.
+    async fn fire_timeouts(&self, shard: &Shard) {
+        let mut processing = shard.processing.write().await;
+        let now = self.clock.now();
+        let mut deadlines = shard.lock_deadlines();
+        while let Some(&(deadline, task_id)) = deadlines.first() {
+            if deadline > now {
+                break;
+            }
+            deadlines.pop_first();
+            if let Some(entry) = processing.get_mut(&task_id) {
+                entry.timed_out = true;
+            }
+            if let Err(err) = shard.chan.0.send(MonitorMessage::TimedOut(task_id)) {
+                tracing::error!(id = %task_id, ?err, "Cannot time out a task without the store monitor");
+            }
+        }
+    }
+
+    /// Spawns the timer sending a `Due` message for the task at `run_at`.
+    fn arm_schedule(&self, task_id: TaskKey, run_at: OffsetDateTime) {
+        let tx = self.shard(task_id).chan.0.clone();
+        let sleep = self.clock.sleep(run_at - self.clock.now());
+        tokio::spawn(async move {
+            sleep.await;
+            if let Err(err) = tx.send(MonitorMessage::Due(task_id)) {
+                tracing::error!(id = %task_id, ?err, "Schedule task cannot communicate with store monitor");
+            }
+        });
+    }
+
+    /// Spawns the timer sending an `Expired` message for the task at
+    /// `expires_at`, for the monitor to drop it unless it was popped by then.
+    fn arm_expiry(&self, task_id: TaskKey, expires_at: OffsetDateTime) {
+        let tx = self.shard(task_id).chan.0.clone();
+        let sleep = self.clock.sleep(expires_at - self.clock.now());
+        tokio::spawn(async move {
+            sleep.await;
+            if let Err(err) = tx.send(MonitorMessage::Expired(task_id)) {
+                tracing::error!(id = %task_id, ?err, "Expiry task cannot communicate with store monitor");
+            }
+        });
+    }
+
+    /// Whether `task` has expired, and is to be dropped instead of popped.
+    fn expired(&self, task: &Task) -> bool {
+        task.0
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= self.clock.now())
+    }
+
+    /// Drops an expired task, which is neither processing nor completed, off
+    /// the queue or the schedule. The tasks which cannot run without it are
+    /// moved to the dead-letter queue, rather than waiting for it forever.
+    async fn expire(&self, tasks: &mut HashMap<TaskKey, Task>, task_id: TaskKey) {
+        let Some(task) = tasks.remove(&task_id) else {
+            return;
+        };
+        tracing::info!(id = %task_id, name = %task.0.name, expires_at = ?task.0.expires_at, "Task expired, dropping it");
+        metrics::increment_counter!(TASKS_EXPIRED);
+        self.queue.remove(task_id);
+        self.awaited.forget(task_id);
+        let mut contents = self.contents.write().await;
+        let mut edges = self.edges.write().await;
+        let mut scheduled = self.scheduled.write().await;
+        contents.remove(task_id);
+        edges.remove(&task_id);
+        if let Some(run_at) = task.0.run_at {
+            scheduled.remove(&(run_at, task_id));
+        }
+
+        // Only the tasks waiting for any of their dependencies can still run,
+        // if they have others left
+        let mut stranded = vec![];
+        for (node, node_edges) in edges.iter_mut() {
+            let pending = node_edges.len();
+            node_edges.retain(|&dest| dest != task_id);
+            if node_edges.len() == pending {
+                continue;
+            }
+            let any = tasks
+                .get(node)
+                .is_some_and(|task| task.0.dependency_mode == DependencyMode::Any);
+            if !any || node_edges.is_empty() {
+                stranded.push(*node);
+            }
+        }
+        let mut dead_letter = self.dead_letter.write().await;
+        let mut timeouts = self.timeouts.write().await;
+        for node in stranded {
+            edges.remove(&node);
+            let Some(mut task) = tasks.remove(&node) else {
+                continue;
+            };
+            tracing::warn!(id = %node, dependency = %task_id, "Task dependency expired, moving it to the dead-letter queue");
+            contents.remove(node);
+            timeouts.remove(&node);
+            if let Some(run_at) = task.0.run_at {
+                scheduled.remove(&(run_at, node));
+            }
+            task.0.status = Status::Failed;
+            self.deliver(&task);
+            self.emit(&task);
+            let reason = format!("Its dependency {} expired", task_id);
+            dead_letter.insert(node, (task, reason));
+        }
+    }
+
+    /// Stores a new task, and either puts it on the queue, schedules it or
+    /// records its dependencies, which have to exist in `tasks`.
+    async fn insert(
+        &self,
+        tasks: &mut HashMap<TaskKey, Task>,
+        insert_task: InsertTask,
+    ) -> Result<Task, PushError> {
+        let InsertTask(insert_task) = insert_task;
+        // The key is handed out while holding the lock of the tasks, so that
+        // the keys follow the order the tasks are inserted in
+        let id = self
+            .next_key
+            .fetch_update(AtomicOrdering::Relaxed, AtomicOrdering::Relaxed, |id| {
+                id.checked_add(1)
+            })
+            .map_err(|_| PushError::KeyExhausted)?;
+
+        let now = self.clock.now();
+        let run_at = insert_task.run_at.filter(|run_at| *run_at > now);
+        let task = Task(taskie_structures::Task {
+            id: TaskKey(id),
+            payload: insert_task.payload,
+            name: insert_task.name,
+            duration: insert_task.duration,
+            priority: insert_task.priority,
+            max_retries: insert_task.max_retries,
+            cost: insert_task.cost,
+            labels: insert_task.labels,
+            tenant: insert_task.tenant,
+            attempt: 0,
+            status: if insert_task.depends_on.is_empty() && run_at.is_none() {
+                Status::Ready
+            } else {
+                Status::Pending
+            },
+            created_at: now,
+            started_at: None,
+            run_at: insert_task.run_at,
+            expires_at: insert_task.expires_at,
+            progress: None,
+            schedule: insert_task.schedule,
+            callback_url: insert_task.callback_url,
+            dependency_mode: insert_task.dependency_mode,
+            traceparent: insert_task.traceparent,
+            depends_on: insert_task.depends_on.clone(),
+        });
+        tasks.insert(TaskKey(id), task.clone());
+        if let Some(expires_at) = task.0.expires_at {
+            self.arm_expiry(TaskKey(id), expires_at);
+        }
+        if let Some(run_at) = run_at {
+            // The task is put on the queue when its time comes, if its
+            // dependencies have been completed by then
+            self.scheduled.write().await.insert((run_at, TaskKey(id)));
+            self.arm_schedule(TaskKey(id), run_at);
+        }
+        if insert_task.depends_on.is_empty() {
+            // if the task doesn't have any dependencies, we can just enqueue
+            // it, ready to be consumed by workers
+            if run_at.is_none() {
+                self.queue.push(&task);
+            }
+        } else {
+            for parent in insert_task.depends_on.into_iter() {
+                self.add_edge(TaskKey(id), parent).await?;
+            }
+        }
+
+        tracing::debug!(nodes = ?tasks.keys(), edges = ?self.edges, "Dependency after task insertion");
+        Ok(task)
+    }
+
+    /// The monitor may still be told about the tasks removed by a purge,
+    /// which are ignored, while any other missing task is an error.
+    fn missing(&self, task_id: TaskKey) -> Result<(), MonitorError> {
+        if task_id.0 < self.purged.load(AtomicOrdering::Relaxed) {
+            tracing::debug!(id = %task_id, "Ignoring a message about a purged task");
+            Ok(())
+        } else {
+            Err(MonitorError::InvalidTask(task_id))
+        }
+    }
+
+    /// Completes a task once taken out of `processing`, whose lock is held:
+    /// its dependents become ready and its result is stored.
+    async fn complete_taken(
+        &self,
+        processing: RwLockWriteGuard<'_, HashMap<TaskKey, Processing>>,
+        task_id: TaskKey,
+        entry: Processing,
+        result: Option<Value>,
+    ) -> Result<(), CompleteError> {
+        // Disarm the timeout, and free the slot of the task
+        self.disarm_timeout(task_id, &entry);
+        drop(entry);
+        let shard = self.shard(task_id);
+        self.processing_gauge(shard, processing.len());
+        self.completed.fetch_add(1, AtomicOrdering::Relaxed);
+        self.drain
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .record(self.clock.now());
+
+        let (tx, _) = &shard.chan;
+        tx.send(MonitorMessage::Completed(task_id))
+            .map_err(|_| CompleteError::MonitorCommunication)?;
+
+        let mut tasks = self.tasks.write().await;
+        let mut recurs = None;
+        if let Some(task) = tasks.get_mut(&task_id) {
+            // The monitor removes the task once it handles the message
+            task.0.status = Status::Completed;
+            if task.0.schedule.is_some() {
+                recurs = Some(task.clone());
+            }
+        }
+        let mut edges = self.edges.write().await;
+        // Stored before the dependents are put on the queue, so that they find
+        // it once popped
+        self.results
+            .write()
+            .await
+            .insert(task_id, result, self.clock.now());
+        // A vector for the tasks which become ready once the current one is popped
+        let mut ready = vec![];
+        for (node, node_edges) in edges.iter_mut() {
+            let pending = node_edges.len();
+            node_edges.retain(|&dest| dest != task_id);
+            // The tasks waiting for any of their dependencies drop the edges
+            // to the others along with the node below
+            let any = node_edges.len() < pending
+                && tasks
+                    .get(node)
+                    .is_some_and(|task| task.0.dependency_mode == DependencyMode::Any);
+            if node_edges.is_empty() || any {
+                ready.push(*node);
+            }
+        }
+
+        // Put any ready task on the queue, unless its time has not come yet
+        let scheduled = self.scheduled.read().await;
+        for node in ready.into_iter() {
+            edges.remove(&node);
+            if let Some(task) = tasks.get_mut(&node) {
+                if task
+                    .0
+                    .run_at
+                    .is_some_and(|run_at| scheduled.contains(&(run_at, node)))
+                {
+                    continue;
+                }
+                tracing::debug!(id = %node, "Task has become ready");
+                task.0.status = Status::Ready;
+                self.emit(task);
+                self.queue.push_unblocked(task);
+            }
+        }
+
+        if let Some(task) = recurs {
+            // The recurring lock comes before all the others
+            drop(scheduled);
+            drop(edges);
+            drop(tasks);
+            drop(processing);
+            // The task has been completed regardless, so failing to push its
+            // next instance is not reported to the worker
+            if let Err(err) = self.recur(task).await {
+                tracing::error!(id = %task_id, ?err, "Cannot push the next instance of a recurring task");
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates the tasks to be pushed, and parses their schedules.
+    fn prepare_push(insert_tasks: &[InsertTask]) -> Result<Vec<Option<Schedule>>, PushError> {
+        for insert_task in insert_tasks.iter() {
+            insert_task.validate()?;
+        }
+        insert_tasks
+            .iter()
+            .map(|task| task.0.schedule.as_deref().map(parse_schedule).transpose())
+            .collect()
+    }
+
+    /// Recurring tasks are registered while their first instance is pushed,
+    /// so that it cannot be completed before: the lock of the recurring tasks
+    /// is taken, first of all, when any of the tasks pushed has a schedule.
+    async fn lock_recurring(
+        &self,
+        schedules: &[Option<Schedule>],
+    ) -> Option<RwLockWriteGuard<'_, Recurring>> {
+        match schedules.iter().any(Option::is_some) {
+            true => Some(self.recurring.write().await),
+            false => None,
+        }
+    }
+
+    /// The tasks which are not pushed again, either because they have the
+    /// idempotency key of a task pushed before or because they are pushed
+    /// with `dedupe`, and their `digests`, like a task still pending or ready.
+    /// In both cases the earlier task is either earlier in the batch or has
+    /// been pushed by another push.
+    fn repeats(
+        insert_tasks: &[InsertTask],
+        digests: &[Option<Digest>],
+        tasks: &HashMap<TaskKey, Task>,
+        idempotency: &Idempotency,
+        contents: &Contents,
+        now: OffsetDateTime,
+    ) -> Vec<Option<Repeat>> {
+        let mut batch_keys = HashMap::new();
+        let mut batch_digests = HashMap::new();
+        insert_tasks
+            .iter()
+            .zip(digests)
+            .enumerate()
+            .map(|(i, (task, digest))| {
+                if let Some(key) = task.0.idempotency_key.as_deref() {
+                    if let Some(pushed) = idempotency.get(key, now) {
+                        return Some(Repeat::Pushed(Box::new(pushed.clone())));
+                    }
+                    if idempotency.window.is_positive() {
+                        match batch_keys.entry(key) {
+                            Entry::Occupied(entry) => return Some(Repeat::Batch(*entry.get())),
+                            Entry::Vacant(entry) => {
+                                entry.insert(i);
+                            }
+                        }
+                    }
+                }
+                let digest = digest.as_ref()?;
+                if let Some(pushed) = contents.get(digest, tasks) {
+                    return Some(Repeat::Pushed(Box::new(pushed.clone())));
+                }
+                match batch_digests.entry(digest) {
+                    Entry::Occupied(entry) => Some(Repeat::Batch(*entry.get())),
+                    Entry::Vacant(entry) => {
+                        entry.insert(i);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// How many tasks are completed per second, lately.
+    fn drain_rate(&self) -> f64 {
+        self.drain
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .rate(self.clock.now())
+    }
+
+    /// Fails with `PushError::QueueFull` unless `count` more tasks fit in
+    /// the queue, telling when they are likely to from the rate the tasks
+    /// are being completed at.
+    async fn check_depth(&self, count: usize) -> Result<(), PushError> {
+        let Some(limit) = self.max_queue_depth else {
+            return Ok(());
+        };
+        let edges = self.edges.read().await;
+        let scheduled = self.scheduled.read().await;
+        let depth = self.queue.lock().len() + pending(&edges, &scheduled);
+        if depth + count > limit {
+            let excess = depth + count - limit;
+            let retry_after = match self.drain_rate() {
+                rate if rate > 0.0 => Duration::seconds_f64(excess as f64 / rate)
+                    .max(Duration::SECOND)
+                    .min(self.retry_after),
+                _ => self.retry_after,
+            };
+            return Err(PushError::QueueFull { limit, retry_after });
+        }
+        Ok(())
+    }
+
+    /// Pushes the tasks once prepared, along with the lock of the recurring
+    /// tasks if any of them is. When the queue is full and `overflow` is
+    /// `Block`, it waits for room without holding any other lock.
+    async fn push_prepared(
+        &self,
+        insert_tasks: Vec<InsertTask>,
+        schedules: Vec<Option<Schedule>>,
+        mut recurring: Option<RwLockWriteGuard<'_, Recurring>>,
+        overflow: Overflow,
+    ) -> Result<Vec<Task>, PushError> {
+        let digests: Vec<_> = insert_tasks
+            .iter()
+            .map(|task| {
+                task.0
+                    .dedupe
+                    .then(|| digest(&task.0.name, &task.0.payload, &task.0.depends_on))
+            })
+            .collect();
+        // Everything that could fail is checked before any task is stored,
+        // so that the batch is either pushed as a whole or not at all
+        let (mut tasks, mut idempotency, mut contents, repeats) = loop {
+            let room = self.queue.room();
+            let tasks = self.tasks.write().await;
+            let idempotency = self.idempotency.write().await;
+            let contents = self.contents.write().await;
+            let repeats = MemoryStore::repeats(
+                &insert_tasks,
+                &digests,
+                &tasks,
+                &idempotency,
+                &contents,
+                self.clock.now(),
+            );
+            let count = repeats.iter().filter(|repeat| repeat.is_none()).count();
+            match self.check_depth(count).await {
+                Ok(()) => break (tasks, idempotency, contents, repeats),
+                // A batch larger than the whole queue would wait forever
+                Err(PushError::QueueFull { limit, .. })
+                    if overflow == Overflow::Block && count <= limit =>
+                {
+                    drop(contents);
+                    drop(idempotency);
+                    drop(tasks);
+                    room.await;
+                }
+                Err(err) => return Err(err),
+            }
+        };
+        // The keys are handed out in order while the tasks are locked, to the
+        // whole batch at once so that its tasks can depend on one another
+        let next_key = self.next_key.load(AtomicOrdering::Relaxed);
+        let keys = batch_keys(&repeats, next_key).ok_or(PushError::KeyExhausted)?;
+        let new = new_keys(&keys, &repeats);
+        let mut dependencies = Vec::with_capacity(insert_tasks.len());
+        for (position, insert_task) in insert_tasks.iter().enumerate() {
+            dependencies.push(insert_task.dependencies(position, &keys)?);
+        }
+        let forward = depends_forward(&keys, &new, &dependencies);
+        let ordered = !forward && !self.unordered.load(AtomicOrdering::Relaxed);
+        // The edges are only added as the tasks are inserted
+        {
+            let edges = self.edges.read().await;
+            let mut added = HashMap::new();
+            for (&id, dependencies) in keys.iter().zip(dependencies.iter()) {
+                if !new.contains(&id) {
+                    continue;
+                }
+                for &dependency in dependencies.iter() {
+                    check_dependency(id, dependency, &tasks, &new, &edges, &mut added, ordered)?;
+                }
+            }
+        }
+        if forward {
+            self.unordered.store(true, AtomicOrdering::Relaxed);
+        }
+
+        let mut result: Vec<Task> = Vec::with_capacity(insert_tasks.len());
+        for ((((insert_task, schedule), repeat), digest), dependencies) in insert_tasks
+            .into_iter()
+            .zip(schedules)
+            .zip(repeats)
+            .zip(digests)
+            .zip(dependencies)
+        {
+            match repeat {
+                // The task is returned as it is now, unless it is gone
+                Some(Repeat::Pushed(pushed)) => {
+                    result.push(tasks.get(&pushed.0.id).cloned().unwrap_or(*pushed));
+                    continue;
+                }
+                Some(Repeat::Batch(i)) => {
+                    result.push(result[i].clone());
+                    continue;
+                }
+                None => {}
+            }
+            let InsertTask(mut insert_task) = insert_task;
+            insert_task.depends_on = dependencies;
+            if let Some(schedule) = &schedule {
+                insert_task.run_at = insert_task.run_at.or_else(|| next_run(schedule));
+            }
+            let idempotency_key = insert_task.idempotency_key.take();
+            let task = self.insert(&mut tasks, InsertTask(insert_task)).await?;
+            if let Some(key) = idempotency_key {
+                idempotency.insert(key, task.clone(), self.clock.now());
+            }
+            if let Some(digest) = digest {
+                contents.insert(digest, task.0.id);
+            }
+            if let (Some(schedule), Some(recurring)) = (schedule, recurring.as_mut()) {
+                recurring
+                    .definitions
+                    .insert(task.0.id, (schedule, task.0.id));
+                recurring.instances.insert(task.0.id, task.0.id);
+            }
+            self.emit(&task);
+            result.push(task);
+        }
+        Ok(result)
+    }
+
+    /// Pushes the next instance of a recurring task, once `task`, its current
+    /// instance, has been completed.
+    async fn recur(&self, task: Task) -> Result<(), PushError> {
+        let mut recurring = self.recurring.write().await;
+        let Some(id) = recurring.instances.remove(&task.0.id) else {
+            // The recurring task has been deleted in the meantime
+            return Ok(());
+        };
+        let next = recurring
+            .definitions
+            .get(&id)
+            .and_then(|(schedule, _)| next_run(schedule));
+        let Some(next) = next else {
+            tracing::info!(%id, "Recurring task has no upcoming run left");
+            recurring.definitions.remove(&id);
+            return Ok(());
+        };
+        let mut tasks = self.tasks.write().await;
+        let instance = self
+            .insert(
+                &mut tasks,
+                InsertTask(taskie_structures::InsertTask {
+                    name: task.0.name,
+                    payload: task.0.payload,
+                    depends_on: vec![],
+                    depends_on_batch: vec![],
+                    duration: task.0.duration,
+                    priority: task.0.priority,
+                    max_retries: task.0.max_retries,
+                    cost: task.0.cost,
+                    labels: task.0.labels,
+                    tenant: task.0.tenant,
+                    run_at: Some(next),
+                    expires_at: None,
+                    schedule: task.0.schedule,
+                    idempotency_key: None,
+                    dedupe: false,
+                    callback_url: task.0.callback_url,
+                    dependency_mode: task.0.dependency_mode,
+                    traceparent: task.0.traceparent,
+                }),
+            )
+            .await?;
+        tracing::debug!(%id, instance = %instance.0.id, run_at = %next, "Recurring task pushed again");
+        if let Some((_, current)) = recurring.definitions.get_mut(&id) {
+            *current = instance.0.id;
+        }
+        recurring.instances.insert(instance.0.id, id);
+        Ok(())
+    }
+
+    /// Removes a task which is not being processed, nor depended upon.
+    async fn cancel_task(&self, task_id: TaskKey) -> Result<(), CancelError> {
+        let processing = self.shard(task_id).processing.read().await;
+        if processing.contains_key(&task_id) {
+            return Err(CancelError::Processing(task_id));
+        }
+
+        let mut tasks = self.tasks.write().await;
+        if !tasks.contains_key(&task_id) {
+            return Err(CancelError::InvalidTaskId(task_id));
+        }
+        let mut contents = self.contents.write().await;
+        let mut edges = self.edges.write().await;
+        if edges
+            .values()
+            .any(|node_edges| node_edges.contains(&task_id))
+        {
+            return Err(CancelError::HasDependents(task_id));
+        }
+
+        if let Some(task) = tasks.remove(&task_id) {
+            if let Some(run_at) = task.0.run_at {
+                self.scheduled.write().await.remove(&(run_at, task_id));
+            }
+        }
+        contents.remove(task_id);
+        edges.remove(&task_id);
+        self.queue.remove(task_id);
+        self.timeouts.write().await.remove(&task_id);
+        self.awaited.forget(task_id);
+        Ok(())
+    }
+
+    /// Puts a task whose execution ended without completing back on the queue,
+    /// or schedules it after the backoff if there is one. The task is moved to
+    /// the dead-letter queue instead if it exhausted its retries, or if
+    /// `give_up` is set.
+    async fn retry(
+        &self,
+        task_id: TaskKey,
+        reason: String,
+        give_up: bool,
+    ) -> Result<(), MonitorError> {
+        let mut tasks = self.tasks.write().await;
+        let task = tasks
+            .get_mut(&task_id)
+            .ok_or(MonitorError::InvalidTask(task_id))?;
+        if give_up || task.0.attempt > task.0.max_retries {
+            if !give_up {
+                tracing::warn!(id = %task_id, %reason, "Task exhausted its retries, moving it to the dead-letter queue");
+            }
+            let mut task = tasks
+                .remove(&task_id)
+                .ok_or(MonitorError::InvalidTask(task_id))?;
+            self.contents.write().await.remove(task_id);
+            task.0.status = Status::Failed;
+            self.deliver(&task);
+            self.emit(&task);
+            self.dead_letter
+                .write()
+                .await
+                .insert(task_id, (task, reason));
+            self.timeouts.write().await.remove(&task_id);
+        } else if self.expired(task) {
+            self.expire(&mut tasks, task_id).await;
+        } else if let Some(backoff) = &self.backoff {
+            let run_at = self.clock.now() + backoff.delay(task.0.attempt);
+            tracing::debug!(id = %task_id, %run_at, "Task retried after a backoff");
+            task.0.run_at = Some(run_at);
+            task.0.status = Status::Pending;
+            self.emit(task);
+            self.scheduled.write().await.insert((run_at, task_id));
+            self.arm_schedule(task_id, run_at);
+        } else {
+            task.0.status = Status::Ready;
+            self.emit(task);
+            self.queue.push(task);
+        }
+        Ok(())
+    }
+
+    async fn add_edge(&self, parent: TaskKey, child: TaskKey) -> Result<(), CycleError> {
+        let mut edges = self.edges.write().await;
+        let parent_edges = edges.entry(parent).or_insert_with(Vec::new);
+        parent_edges.push(child);
+        let ordered = !self.unordered.load(AtomicOrdering::Relaxed);
+        match closes_loop(&edges, &HashMap::new(), parent, child, ordered) {
+            true => Err(CycleError),
+            false => Ok(()),
+        }
+    }
+
+    /// Takes the first ready task matching `selector`, and costing at most
+    /// `budget` if set, off the queue and marks it as processing, in the
+    /// given `slot` which is then taken.
+    async fn dequeue(
+        &self,
+        selector: &Selector,
+        budget: Option<u32>,
+        slot: &mut Option<OwnedSemaphorePermit>,
+    ) -> Result<Option<Execution>, PopError> {
+        loop {
+            let Some(dequeued) = self.queue.try_pop(selector, budget, self.clock.now()) else {
+                return Ok(None);
+            };
+
+            // The task is marked as processing while `tasks` is still locked,
+            // so that no one sees it off the queue but not yet processing. If
+            // the pop is dropped while waiting (i.e. on timeout), `dequeued`
+            // puts the task back on the queue.
+            let shard = self.shard(dequeued.id);
+            let mut processing = shard.processing.write().await;
+            let mut tasks = self.tasks.write().await;
+            let Some(task) = tasks.get_mut(&dequeued.id) else {
+                // The task has been cancelled since it was taken off the queue
+                dequeued.take();
+                continue;
+            };
+            if shard.chan.0.is_closed() {
+                return Err(PopError::MonitorCommunication);
+            }
+            if self.expired(task) {
+                let task_id = dequeued.take();
+                self.expire(&mut tasks, task_id).await;
+                continue;
+            }
+            // Any task on the queue has no pending dependency, so its edges
+            // are not removed here. Should one be there anyway, the task goes
+            // back to waiting for its dependencies, which put it on the queue
+            // again once completed.
+            if self.edges.read().await.contains_key(&dequeued.id) {
+                let task_id = dequeued.take();
+                tracing::error!(id = %task_id, "Task was ready with pending dependencies, moving it back to pending");
+                task.0.status = Status::Pending;
+                return Err(PopError::PendingDependencies(task_id));
+            }
+            let task_id = dequeued.take();
+            task.0.attempt += 1;
+            task.0.status = Status::Processing;
+            let now = self.clock.now();
+            task.0.started_at = Some(now);
+            task.0.progress = None;
+
+            let deadline = self.arm_timeout(task_id, task.0.duration);
+            let lease_token = Uuid::new_v4();
+            processing.insert(
+                task_id,
+                Processing {
+                    deadline,
+                    timed_out: false,
+                    lease_token,
+                    _slot: slot.take(),
+                },
+            );
+            self.processing_gauge(shard, processing.len());
+            self.emit(task);
+            return Ok(Some(Execution(taskie_structures::Execution {
+                deadline: now + task.0.duration,
+                remaining: task.0.duration,
+                task: task.clone(),
+                lease_token,
+            })));
+        }
+    }
+
+    /// Like `dequeue`, in a slot taken right away if any is left.
+    async fn try_dequeue(
+        &self,
+        selector: &Selector,
+        budget: Option<u32>,
+    ) -> Result<Option<Execution>, PopError> {
+        let mut slot = match &self.slots {
+            Some(slots) => match slots.clone().try_acquire_owned() {
+                Ok(slot) => Some(slot),
+                // As many tasks as allowed are processing already
+                Err(_) => return Ok(None),
+            },
+            None => None,
+        };
+        self.dequeue(selector, budget, &mut slot).await
+    }
+
+    /// Handles a single message of the monitor, breaking once it has to stop.
+    async fn handle(&self, msg: MonitorMessage) -> Result<ControlFlow<()>, MonitorError> {
+        match msg {
+            MonitorMessage::Completed(task_id) => {
+                tracing::info!(id = %task_id, "Task execution complete");
+                // The task has already been taken out of `processing`
+                let mut tasks = self.tasks.write().await;
+                let Some(mut task) = tasks.remove(&task_id) else {
+                    self.missing(task_id)?;
+                    return Ok(ControlFlow::Continue(()));
+                };
+                self.contents.write().await.remove(task_id);
+                self.timeouts.write().await.remove(&task_id);
+                task.0.status = Status::Completed;
+                self.deliver(&task);
+                self.emit(&task);
+            }
+            MonitorMessage::TimedOut(task_id) => {
+                tracing::info!(id = %task_id, "Task execution timed out");
+                {
+                    let shard = self.shard(task_id);
+                    let mut processing = shard.processing.write().await;
+                    if processing.remove(&task_id).is_none() {
+                        self.missing(task_id)?;
+                        return Ok(ControlFlow::Continue(()));
+                    }
+                    metrics::increment_counter!(TASKS_TIMED_OUT);
+                    self.processing_gauge(shard, processing.len());
+                    let timeouts = {
+                        let mut timeouts = self.timeouts.write().await;
+                        let count = timeouts.entry(task_id).or_default();
+                        *count += 1;
+                        *count
+                    };
+                    let give_up = timeouts >= self.max_timeouts;
+                    if give_up {
+                        tracing::error!(id = %task_id, timeouts, "Task keeps timing out, dropping it from circulation into the dead-letter queue");
+                    } else if timeouts > 1 {
+                        tracing::warn!(id = %task_id, timeouts, max_timeouts = self.max_timeouts, "Task timed out repeatedly");
+                    }
+                    self.retry(task_id, TIMEOUT_REASON.to_string(), give_up)
+                        .await?;
+                }
+            }
+            MonitorMessage::Extend(task_id, extend) => {
+                let mut processing = self.shard(task_id).processing.write().await;
+                let Some(entry) = processing.get_mut(&task_id) else {
+                    self.missing(task_id)?;
+                    return Ok(ControlFlow::Continue(()));
+                };
+                if entry.timed_out {
+                    // The `TimedOut` message is waiting to be handled
+                    tracing::warn!(id = %task_id, "Task timed out before its deadline could be extended");
+                    return Ok(ControlFlow::Continue(()));
+                }
+                if !self.disarm_timeout(task_id, entry) {
+                    return Err(MonitorError::CancelTimeout(task_id));
+                }
+                entry.deadline = self.arm_timeout(task_id, extend);
+            }
+            MonitorMessage::Failed(task_id, reason) => {
+                tracing::info!(id = %task_id, ?reason, "Task execution failed");
+                {
+                    let shard = self.shard(task_id);
+                    let mut processing = shard.processing.write().await;
+                    let Some(entry) = processing.get(&task_id) else {
+                        self.missing(task_id)?;
+                        return Ok(ControlFlow::Continue(()));
+                    };
+                    if entry.timed_out {
+                        // The task is retried once its `TimedOut` message
+                        // is handled
+                        tracing::warn!(id = %task_id, "Task timed out before it failed");
+                        return Ok(ControlFlow::Continue(()));
+                    }
+                    if !self.disarm_timeout(task_id, entry) {
+                        return Err(MonitorError::CancelTimeout(task_id));
+                    }
+                    processing.remove(&task_id);
+                    self.processing_gauge(shard, processing.len());
+                    self.retry(task_id, fail_reason(reason), false).await?;
+                }
+            }
+            MonitorMessage::Due(task_id) => {
+                let mut tasks = self.tasks.write().await;
+                let edges = self.edges.read().await;
+                let mut scheduled = self.scheduled.write().await;
+                let Some(task) = tasks.get_mut(&task_id) else {
+                    // The task has been cancelled
+                    return Ok(ControlFlow::Continue(()));
+                };
+                if !task
+                    .0
+                    .run_at
+                    .is_some_and(|run_at| scheduled.remove(&(run_at, task_id)))
+                {
+                    return Ok(ControlFlow::Continue(()));
+                }
+                if edges.contains_key(&task_id) {
+                    // Completing its last dependency puts it on the queue
+                    return Ok(ControlFlow::Continue(()));
+                }
+                tracing::debug!(id = %task_id, "Scheduled task has become ready");
+                task.0.status = Status::Ready;
+                self.emit(task);
+                self.queue.push(task);
+            }
+            MonitorMessage::Expired(task_id) => {
+                let mut tasks = self.tasks.write().await;
+                // Unless it was popped in time, or is gone already
+                if tasks
+                    .get(&task_id)
+                    .is_some_and(|task| matches!(task.0.status, Status::Pending | Status::Ready))
+                {
+                    self.expire(&mut tasks, task_id).await;
+                }
+            }
+            MonitorMessage::Shutdown => return Ok(ControlFlow::Break(())),
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Handles the messages of `shard`, and times out its tasks, until the
+    /// store shuts down.
+    async fn monitor_shard(&self, shard: &Shard) -> Result<(), MonitorError> {
+        let mut rx = shard.chan.1.lock().await;
+
+        let mut lagging = false;
+        let mut tick = self.clock.sleep(self.monitor_tick);
+        loop {
+            let (sent, msg) = tokio::select! {
+                received = rx.recv() => match received {
+                    Some(received) => received,
+                    None => break,
+                },
+                _ = &mut tick => {
+                    tick = self.clock.sleep(self.monitor_tick);
+                    self.fire_timeouts(shard).await;
+                    continue;
+                }
+            };
+            let lag = sent.elapsed();
+            let backlog = &self.backlog;
+            // Warned about once, until the monitor catches up
+            if lag > self.lag_warning && !lagging {
+                tracing::warn!(
+                    ?lag,
+                    backlog = backlog.load(AtomicOrdering::Relaxed),
+                    "Task monitor is falling behind"
+                );
+            } else if lag <= self.lag_warning && lagging {
+                tracing::info!(?lag, "Task monitor caught up");
+            }
+            lagging = lag > self.lag_warning;
+
+            // A message the store cannot make sense of, like the duplicate
+            // completion of a task, only affects that task, so the monitor
+            // keeps going for all the others
+            let handled = self.handle(msg).await;
+            backlog.fetch_sub(1, AtomicOrdering::Relaxed);
+            *self
+                .last_handled
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner) = Some(self.clock.now());
+            match handled {
+                Ok(ControlFlow::Continue(())) => {}
+                Ok(ControlFlow::Break(())) => return Ok(()),
+                Err(err) => tracing::error!(%err, "Task monitor could not handle a message"),
+            }
+        }
+        Err(MonitorError::ChannelDropped)
+    }
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn monitor(&self) -> Result<(), MonitorError> {
+        future::try_join_all(self.shards.iter().map(|shard| self.monitor_shard(shard))).await?;
+
+        // All the messages sent before have been handled by now
+        let processing = self.read_processing().await;
+        let tasks = self.tasks.read().await;
+        tracing::warn!(
+            processing = ?processing.iter().flat_map(|processing| processing.keys()).collect::<Vec<_>>(),
+            tasks = tasks.len(),
+            "Task monitor stopped, the tasks kept in memory are lost unless saved"
+        );
+        Ok(())
+    }
+
+    async fn push(&self, insert_tasks: Vec<InsertTask>) -> Result<Vec<Task>, PushError> {
+        let schedules = MemoryStore::prepare_push(&insert_tasks)?;
+        let recurring = self.lock_recurring(&schedules).await;
+        self.push_prepared(insert_tasks, schedules, recurring, self.overflow)
+            .await
+    }
+
+    async fn dry_run(&self, insert_tasks: &[InsertTask]) -> Result<Vec<TaskKey>, DryRunError> {
+        let mut errors = Vec::new();
+        for (position, insert_task) in insert_tasks.iter().enumerate() {
+            if let Err(err) = insert_task.validate() {
+                errors.push((Some(position), err));
+            }
+            if let Some(Err(err)) = insert_task.0.schedule.as_deref().map(parse_schedule) {
+                errors.push((Some(position), err));
+            }
+        }
+
+        let digests: Vec<_> = insert_tasks
+            .iter()
+            .map(|task| {
+                task.0
+                    .dedupe
+                    .then(|| digest(&task.0.name, &task.0.payload, &task.0.depends_on))
+            })
+            .collect();
+        let tasks = self.tasks.read().await;
+        let idempotency = self.idempotency.read().await;
+        let contents = self.contents.read().await;
+        let repeats = MemoryStore::repeats(
+            insert_tasks,
+            &digests,
+            &tasks,
+            &idempotency,
+            &contents,
+            self.clock.now(),
+        );
+        let count = repeats.iter().filter(|repeat| repeat.is_none()).count();
+        if let Err(err) = self.check_depth(count).await {
+            errors.push((None, err));
+        }
+        let Some(keys) = batch_keys(&repeats, self.next_key.load(AtomicOrdering::Relaxed)) else {
+            errors.push((None, PushError::KeyExhausted));
+            return Err(DryRunError::Invalid(errors));
+        };
+        let new = new_keys(&keys, &repeats);
+        let mut dependencies = Vec::with_capacity(insert_tasks.len());
+        for (position, insert_task) in insert_tasks.iter().enumerate() {
+            match insert_task.dependencies(position, &keys) {
+                Ok(resolved) => dependencies.push(resolved),
+                Err(err) => {
+                    errors.push((Some(position), err));
+                    dependencies.push(vec![]);
+                }
+            }
+        }
+        let forward = depends_forward(&keys, &new, &dependencies);
+        let edges = self.edges.read().await;
+        let ordered = !forward && !self.unordered.load(AtomicOrdering::Relaxed);
+        // The edges the tasks would add, checked for loops as if they were
+        // stored along with the others
+        let mut added = HashMap::new();
+        for (position, (&id, dependencies)) in keys.iter().zip(dependencies.iter()).enumerate() {
+            if !new.contains(&id) {
+                continue;
+            }
+            for &dependency in dependencies.iter() {
+                if let Err(err) =
+                    check_dependency(id, dependency, &tasks, &new, &edges, &mut added, ordered)
+                {
+                    errors.push((Some(position), err));
+                }
+            }
+        }
+        match errors.is_empty() {
+            true => Ok(keys),
+            false => Err(DryRunError::Invalid(errors)),
+        }
+    }
+
+    async fn pop(&self, selector: &Selector) -> Result<Execution, PopError> {
+        // The slot is taken before waiting for a task, so that the workers
+        // blocked on a full store get one in the order they asked
+        let mut slot = match &self.slots {
+            Some(slots) => Some(
+                slots
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("the slots are never closed"),
+            ),
+            None => None,
+        };
+        loop {
+            let notified = self.queue.notified();
+            if let Some(execution) = self.dequeue(selector, None, &mut slot).await? {
+                return Ok(execution);
+            }
+            notified.await;
+        }
+    }
+
+    async fn try_pop(&self, selector: &Selector) -> Result<Option<Execution>, PopError> {
+        self.try_dequeue(selector, None).await
+    }
+
+    async fn pop_budget(
+        &self,
+        selector: &Selector,
+        budget: u32,
+    ) -> Result<Vec<Execution>, PopError> {
+        let first = self.pop(selector).await?;
+        let mut remaining = budget.saturating_sub(first.0.task.0.cost);
+        let mut executions = vec![first];
+        loop {
+            match self.try_dequeue(selector, Some(remaining)).await {
+                Ok(Some(execution)) => {
+                    remaining -= execution.0.task.0.cost;
+                    executions.push(execution);
+                }
+                Ok(None) => break,
+                // Like in `pop_many`, the tasks already popped are handed out
+                Err(err) => {
+                    tracing::warn!(%err, popped = executions.len(), "Could not pop all the tasks fitting the budget");
+                    break;
+                }
+            }
+        }
+        Ok(executions)
+    }
+
+    async fn peek(&self) -> Result<Option<Task>, PeekError> {
+        // A task is taken off the queue before `tasks` is locked when popped,
+        // so any task on the queue is among the tasks while it is held
+        let tasks = self.tasks.read().await;
+        Ok(self
+            .queue
+            .peek(self.clock.now())
+            .and_then(|id| tasks.get(&id).cloned()))
+    }
+
+    async fn complete(
+        &self,
+        task_id: TaskKey,
+        lease_token: Uuid,
+        result: Option<Value>,
+    ) -> Result<(), CompleteError> {
+        // The task is taken out of `processing` right away, rather than by the
+        // monitor, so that completing it twice is told apart the second time:
+        // by then its completion is among the results, as long as they are
+        // retained.
+        let mut processing = self.shard(task_id).processing.write().await;
+        if let Some(entry) = processing.get(&task_id) {
+            if !leased(Some(entry.lease_token), lease_token) {
+                return Err(CompleteError::LeaseMismatch(task_id));
+            }
+        }
+        let Some(entry) = processing.remove(&task_id) else {
+            let results = self.results.read().await;
+            return match results.get(&task_id, self.clock.now()) {
+                Some(_) => Err(CompleteError::AlreadyCompleted(task_id)),
+                None => Err(CompleteError::InvalidTaskId(task_id)),
+            };
+        };
+        if entry.timed_out {
+            // The `TimedOut` message is waiting to be handled
+            processing.insert(task_id, entry);
+            return Err(CompleteError::InvalidTaskId(task_id));
+        }
+        self.complete_taken(processing, task_id, entry, result)
+            .await
+    }
+
+    async fn idempotent_task(&self, key: &str) -> Result<TaskKey, CompleteError> {
+        let idempotency = self.idempotency.read().await;
+        idempotency
+            .get(key, self.clock.now())
+            .map(|task| task.0.id)
+            .ok_or_else(|| CompleteError::UnknownIdempotencyKey(key.to_string()))
+    }
+
+    async fn complete_and_push(
+        &self,
+        task_id: TaskKey,
+        lease_token: Uuid,
+        result: Option<Value>,
+        insert_tasks: Vec<InsertTask>,
+    ) -> Result<Vec<Task>, CompleteAndPushError> {
+        let schedules = MemoryStore::prepare_push(&insert_tasks)?;
+        let recurring = self.lock_recurring(&schedules).await;
+        // The task is kept in `processing`, with its lock held, while the
+        // tasks are pushed, so that no one else can complete or fail it
+        // before. It may still time out in the meantime, in which case it is
+        // completed all the same, and the monitor reports the timeout of a
+        // task which is not processing.
+        let mut processing = self.shard(task_id).processing.write().await;
+        let Some(entry) = processing.get(&task_id) else {
+            return Err(CompleteError::InvalidTaskId(task_id).into());
+        };
+        if !leased(Some(entry.lease_token), lease_token) {
+            return Err(CompleteError::LeaseMismatch(task_id).into());
+        }
+        if entry.timed_out {
+            return Err(CompleteError::InvalidTaskId(task_id).into());
+        }
+        // Waiting for room while `processing` is locked would stop the pops
+        // making it
+        let pushed = self
+            .push_prepared(insert_tasks, schedules, recurring, Overflow::Reject)
+            .await?;
+        let entry = processing
+            .remove(&task_id)
+            .expect("the task is kept while processing is locked");
+        self.complete_taken(processing, task_id, entry, result)
+            .await?;
+        Ok(pushed)
+    }
+
+    async fn fail(
+        &self,
+        task_id: TaskKey,
+        lease_token: Uuid,
+        reason: Option<String>,
+    ) -> Result<(), FailError> {
+        let shard = self.shard(task_id);
+        let processing = shard.processing.read().await;
+        let Some(entry) = processing.get(&task_id) else {
+            return Err(FailError::InvalidTaskId(task_id));
+        };
+        if !leased(Some(entry.lease_token), lease_token) {
+            return Err(FailError::LeaseMismatch(task_id));
+        }
+
+        let (tx, _) = &shard.chan;
+        tx.send(MonitorMessage::Failed(task_id, reason))
+            .map_err(|_| FailError::MonitorCommunication)
+    }
+
+    async fn requeue(&self, task_id: TaskKey) -> Result<(), RequeueError> {
+        let shard = self.shard(task_id);
+        let mut processing = shard.processing.write().await;
+        let Some(entry) = processing.get_mut(&task_id) else {
+            return Err(RequeueError::InvalidTaskId(task_id));
+        };
+        if entry.timed_out {
+            // The task is about to be requeued anyway
+            return Ok(());
+        }
+        // The task is seen as timed out until the monitor handles the
+        // message, which goes through the same path as a deadline passing
+        self.disarm_timeout(task_id, entry);
+        entry.timed_out = true;
+        let (tx, _) = &shard.chan;
+        tx.send(MonitorMessage::TimedOut(task_id))
+            .map_err(|_| RequeueError::MonitorCommunication)
+    }
+
+    async fn heartbeat(
+        &self,
+        task_id: TaskKey,
+        lease_token: Uuid,
+        extend: Duration,
+    ) -> Result<OffsetDateTime, HeartbeatError> {
+        let shard = self.shard(task_id);
+        let processing = shard.processing.read().await;
+        let Some(entry) = processing.get(&task_id) else {
+            return Err(HeartbeatError::InvalidTaskId(task_id));
+        };
+        if !leased(Some(entry.lease_token), lease_token) {
+            return Err(HeartbeatError::LeaseMismatch(task_id));
+        }
+
+        let (tx, _) = &shard.chan;
+        tx.send(MonitorMessage::Extend(task_id, extend))
+            .map_err(|_| HeartbeatError::MonitorCommunication)?;
+        Ok(self.clock.now() + extend)
+    }
+
+    async fn progress(
+        &self,
+        task_id: TaskKey,
+        lease_token: Uuid,
+        percent: u8,
+        extend: bool,
+    ) -> Result<Option<OffsetDateTime>, ProgressError> {
+        if percent > 100 {
+            return Err(ProgressError::InvalidPercent(percent));
+        }
+        let shard = self.shard(task_id);
+        let processing = shard.processing.read().await;
+        let entry = processing
+            .get(&task_id)
+            .ok_or(ProgressError::InvalidTaskId(task_id))?;
+        if !leased(Some(entry.lease_token), lease_token) {
+            return Err(ProgressError::LeaseMismatch(task_id));
+        }
+        let mut tasks = self.tasks.write().await;
+        let task = tasks
+            .get_mut(&task_id)
+            .ok_or(ProgressError::InvalidTaskId(task_id))?;
+        task.0.progress = Some(percent);
+        // Nothing is left of a finished task, which is only waiting to be
+        // completed
+        if !extend || percent == 100 {
+            return Ok(None);
+        }
+        let extend = task.0.duration * (100 - percent) as f64 / 100.0;
+        drop(tasks);
+        drop(processing);
+
+        let (tx, _) = &shard.chan;
+        tx.send(MonitorMessage::Extend(task_id, extend))
+            .map_err(|_| ProgressError::MonitorCommunication)?;
+        Ok(Some(self.clock.now() + extend))
+    }
+
+    async fn health(&self) -> bool {
+        // The receivers stay locked for as long as their loop of the monitor
+        // is running
+        self.shards.iter().all(|shard| {
+            let (tx, rx) = &shard.chan;
+            !tx.is_closed() && rx.try_lock().is_err()
+        })
+    }
+
+    async fn shutdown(&self) {
+        for shard in self.shards.iter() {
+            let (tx, _) = &shard.chan;
+            if tx.send(MonitorMessage::Shutdown).is_err() {
+                tracing::error!("Cannot stop the store monitor");
+            }
+        }
+    }
+
+    async fn dead_letters(&self) -> Result<Vec<(Task, String)>, DeadLetterError> {
+        let dead_letter = self.dead_letter.read().await;
+        let mut dead_letters: Vec<_> = dead_letter.values().cloned().collect();
+        dead_letters.sort_by_key(|(task, _)| task.0.id);
+        Ok(dead_letters)
+    }
+
+    async fn requeue_dead_letter(&self, task_id: TaskKey) -> Result<Task, DeadLetterError> {
+        let mut tasks = self.tasks.write().await;
+        let (mut task, _) = self
+            .dead_letter
+            .write()
+            .await
+            .remove(&task_id)
+            .ok_or(DeadLetterError::InvalidTaskId(task_id))?;
+        task.0.attempt = 0;
+        task.0.status = Status::Ready;
+        self.emit(&task);
+        tasks.insert(task_id, task.clone());
+        // The task had already been popped, so it has no pending dependency
+        self.queue.push(&task);
+        Ok(task)
+    }
+
+    async fn update(&self, task_id: TaskKey, patch: TaskPatch) -> Result<Task, UpdateError> {
+        patch.validate()?;
+        let TaskPatch(patch) = patch;
+        let processing = self.shard(task_id).processing.read().await;
+        if processing.contains_key(&task_id) {
+            return Err(UpdateError::Processing(task_id));
+        }
+        let mut tasks = self.tasks.write().await;
+        if !tasks.contains_key(&task_id) {
+            return Err(UpdateError::InvalidTaskId(task_id));
+        }
+        let mut contents = self.contents.write().await;
+        let mut edges = self.edges.write().await;
+        if let Some(depends_on) = &patch.depends_on {
+            for &dependency in depends_on {
+                if dependency == task_id {
+                    return Err(PushError::SelfDependency(task_id).into());
+                }
+                if !tasks.contains_key(&dependency) {
+                    return Err(PushError::MissingDependency { dependency }.into());
+                }
+                // Any loop through the new edge ends as soon as it gets back
+                // to the task, so its current edges need not be left out
+                if closes_loop(&edges, &HashMap::new(), task_id, dependency, false) {
+                    return Err(PushError::from(CycleError).into());
+                }
+            }
+        }
+        let task = tasks.get_mut(&task_id).expect("the task was just found");
+        // A ready task is taken off the queue until it is updated, unless a
+        // pop already took it
+        let queued = match task.0.status {
+            Status::Ready => Some(
+                self.queue
+                    .remove(task_id)
+                    .ok_or(UpdateError::Processing(task_id))?,
+            ),
+            _ => None,
+        };
+
+        if let Some(payload) = patch.payload {
+            task.0.payload = Some(payload);
+        }
+        if let Some(duration) = patch.duration {
+            task.0.duration = duration;
+        }
+        if let Some(priority) = patch.priority {
+            task.0.priority = priority;
+        }
+        if let Some(depends_on) = patch.depends_on {
+            if depends_on.iter().any(|&dependency| dependency > task_id) {
+                self.unordered.store(true, AtomicOrdering::Relaxed);
+            }
+            match depends_on.is_empty() {
+                true => edges.remove(&task_id),
+                false => edges.insert(task_id, depends_on.clone()),
+            };
+            task.0.depends_on = depends_on;
+        }
+        if contents.digests.contains_key(&task_id) {
+            contents.remove(task_id);
+            let digest = digest(&task.0.name, &task.0.payload, &task.0.depends_on);
+            contents.insert(digest, task_id);
+        }
+
+        // The task is ready once it has no pending dependency left and its
+        // time has come, as it would be when pushed
+        let scheduled = self.scheduled.read().await;
+        let waiting = task
+            .0
+            .run_at
+            .is_some_and(|run_at| scheduled.contains(&(run_at, task_id)));
+        if edges.contains_key(&task_id) || waiting {
+            task.0.status = Status::Pending;
+        } else {
+            task.0.status = Status::Ready;
+            match queued {
+                // The task keeps its place among the ones of its priority
+                Some(entry) => self.queue.insert(Ready {
+                    priority: task.0.priority,
+                    ..entry
+                }),
+                None => self.queue.push(task),
+            }
+        }
+        self.emit(task);
+        Ok(task.clone())
+    }
+
+    async fn cancel(&self, task_id: TaskKey) -> Result<(), CancelError> {
+        let mut recurring = self.recurring.write().await;
+        self.cancel_task(task_id).await?;
+        // Cancelling the current instance of a recurring task stops it from
+        // recurring
+        if let Some(id) = recurring.instances.remove(&task_id) {
+            recurring.definitions.remove(&id);
+        }
+        Ok(())
+    }
+    async fn get(&self, task_id: TaskKey) -> Result<Task, GetError> {
+        let tasks = self.tasks.read().await;
+        if let Some(task) = tasks.get(&task_id) {
+            return Ok(task.clone());
+        }
+        let dead_letter = self.dead_letter.read().await;
+        dead_letter
+            .get(&task_id)
+            .map(|(task, _)| task.clone())
+            .ok_or(GetError::InvalidTaskId(task_id))
+    }
+
+    async fn list(
+        &self,
+        offset: usize,
+        limit: usize,
+        status: Option<Status>,
+    ) -> Result<(Vec<Task>, usize), ListError> {
+        let tasks = self.tasks.read().await;
+        let dead_letter = self.dead_letter.read().await;
+        let mut matching: Vec<&Task> = tasks
+            .values()
+            .chain(dead_letter.values().map(|(task, _)| task))
+            .filter(|task| status.is_none_or(|status| task.0.status == status))
+            .collect();
+        matching.sort_by_key(|task| task.0.id);
+        let page = matching
+            .iter()
+            .skip(offset)
+            .take(limit)
+            .map(|&task| task.clone())
+            .collect();
+        Ok((page, matching.len()))
+    }
+
+    async fn dependents(&self, task_id: TaskKey) -> Result<Vec<TaskKey>, GetError> {
+        let tasks = self.tasks.read().await;
+        let edges = self.edges.read().await;
+        if !tasks.contains_key(&task_id) && !self.dead_letter.read().await.contains_key(&task_id) {
+            return Err(GetError::InvalidTaskId(task_id));
+        }
+        let mut dependents: Vec<TaskKey> = edges
+            .iter()
+            .filter(|(_, dependencies)| dependencies.contains(&task_id))
+            .map(|(&node, _)| node)
+            .collect();
+        dependents.sort();
+        Ok(dependents)
+    }
+
+    async fn graph(&self) -> Result<GraphSnapshot, GraphError> {
+        let tasks = self.tasks.read().await;
+        let edges = self.edges.read().await;
+        let dead_letter = self.dead_letter.read().await;
+        let mut nodes: Vec<_> = tasks
+            .iter()
+            .map(|(&id, task)| (id, task.0.name.clone()))
+            .collect();
+        let mut graph_edges = Vec::new();
+        for (&node, dependencies) in edges.iter() {
+            for &dependency in dependencies {
+                graph_edges.push((node, dependency));
+                // Tasks can still be waiting for a dead-lettered dependency,
+                // which is not among the tasks anymore
+                if let Some((task, _)) = dead_letter.get(&dependency) {
+                    nodes.push((dependency, task.0.name.clone()));
+                }
+            }
+        }
+        nodes.sort_by_key(|(id, _)| *id);
+        nodes.dedup_by_key(|(id, _)| *id);
+        graph_edges.sort();
+        Ok(GraphSnapshot {
+            nodes,
+            edges: graph_edges,
+        })
+    }
+
+    fn queue_depth(&self) -> usize {
+        self.queue.lock().len()
+    }
+
+    fn backend_name(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn stats(&self) -> Result<Stats, StatsError> {
+        let next_key = TaskKey(self.next_key.load(AtomicOrdering::Relaxed));
+        let processing = self.read_processing().await;
+        let tasks = self.tasks.read().await;
+        let reported: Vec<u8> = processing
+            .iter()
+            .flat_map(|processing| processing.keys())
+            .filter_map(|id| tasks.get(id)?.0.progress)
+            .collect();
+        let progress = (!reported.is_empty()).then(|| {
+            reported.iter().map(|&percent| percent as f64).sum::<f64>() / reported.len() as f64
+        });
+        drop(tasks);
+        let processing = processing
+            .iter()
+            .map(|processing| processing.len() as u64)
+            .sum();
+        let ready = self.queue_depth() as u64;
+        let edges = self.edges.read().await;
+        let scheduled = self.scheduled.read().await;
+        let pending = pending(&edges, &scheduled);
+        drop(scheduled);
+        drop(edges);
+        let dead_lettered = self.dead_letter.read().await.len() as u64;
+        Ok(Stats(taskie_structures::Stats {
+            pending: pending as u64,
+            ready,
+            processing,
+            completed: self.completed.load(AtomicOrdering::Relaxed),
+            dead_lettered,
+            waiting: 0,
+            progress,
+            next_key,
+            monitor: Some(MonitorStats {
+                backlog: self.backlog.load(AtomicOrdering::Relaxed) as u64,
+                last_handled_at: *self
+                    .last_handled
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner),
+            }),
+        }))
+    }
+
+    async fn recurring(&self) -> Result<Vec<(TaskKey, Task)>, RecurringError> {
+        let recurring = self.recurring.read().await;
+        let tasks = self.tasks.read().await;
+        let dead_letter = self.dead_letter.read().await;
+        let mut result: Vec<_> = recurring
+            .definitions
+            .iter()
+            .filter_map(|(id, (_, current))| {
+                let task = tasks
+                    .get(current)
+                    .or_else(|| dead_letter.get(current).map(|(task, _)| task))?;
+                Some((*id, task.clone()))
+            })
+            .collect();
+        result.sort_by_key(|(id, _)| *id);
+        Ok(result)
+    }
+
+    async fn delete_recurring(&self, id: TaskKey) -> Result<(), RecurringError> {
+        let mut recurring = self.recurring.write().await;
+        let (_, current) = recurring
+            .definitions
+            .remove(&id)
+            .ok_or(RecurringError::InvalidId(id))?;
+        recurring.instances.remove(&current);
+        // An instance already being processed is left to finish, it just
+        // won't be pushed again
+        if let Err(err) = self.cancel_task(current).await {
+            tracing::debug!(%id, instance = %current, %err, "Current instance of the recurring task not cancelled");
+        }
+        Ok(())
+    }
+
+    async fn purge(&self) -> Result<(), PurgeError> {
+        let mut recurring = self.recurring.write().await;
+        let mut processing = self.write_processing().await;
+        let mut tasks = self.tasks.write().await;
+        let mut idempotency = self.idempotency.write().await;
+        let mut contents = self.contents.write().await;
+        let mut edges = self.edges.write().await;
+        let mut scheduled = self.scheduled.write().await;
+        let mut dead_letter = self.dead_letter.write().await;
+        let mut timeouts = self.timeouts.write().await;
+        let mut results = self.results.write().await;
+
+        // The keys are handed out while holding the lock of the tasks, so
+        // every task removed below has a lower key
+        self.purged.store(
+            self.next_key.load(AtomicOrdering::Relaxed),
+            AtomicOrdering::Relaxed,
+        );
+        // The tasks which already timed out have sent their message, which
+        // the monitor ignores
+        for (shard, processing) in self.shards.iter().zip(processing.iter_mut()) {
+            processing.clear();
+            shard.lock_deadlines().clear();
+            shard.len.store(0, AtomicOrdering::Relaxed);
+        }
+        *recurring = Recurring::default();
+        tasks.clear();
+        edges.clear();
+        self.unordered.store(false, AtomicOrdering::Relaxed);
+        scheduled.clear();
+        dead_letter.clear();
+        timeouts.clear();
+        results.values.clear();
+        results.expiration.clear();
+        idempotency.tasks.clear();
+        idempotency.expiration.clear();
+        *contents = Contents::default();
+        self.queue.clear();
+        self.awaited.clear();
+        metrics::gauge!(PROCESSING, 0.0);
+        Ok(())
+    }
+
+    async fn dependency_results(
+        &self,
+        task_id: TaskKey,
+    ) -> Result<Vec<(TaskKey, Option<Value>)>, ResultsError> {
+        let depends_on = self
+            .get(task_id)
+            .await
+            .map_err(|_| ResultsError::InvalidTaskId(task_id))?
+            .0
+            .depends_on;
+        let results = self.results.read().await;
+        Ok(depends_on
+            .into_iter()
+            .map(|id| (id, results.get(&id, self.clock.now()).cloned().flatten()))
+            .collect())
+    }
+
+    async fn task_result(&self, task_id: TaskKey) -> Result<(Status, Option<Value>), ResultsError> {
+        let tasks = self.tasks.read().await;
+        let dead_letter = self.dead_letter.read().await;
+        let results = self.results.read().await;
+        if let Some(result) = results.get(&task_id, self.clock.now()) {
+            Ok((Status::Completed, result.clone()))
+        } else if dead_letter.contains_key(&task_id) {
+            Ok((Status::Failed, None))
+        } else if tasks.contains_key(&task_id) {
+            Err(ResultsError::NotFinished(task_id))
+        } else {
+            // Unknown, or its result is no longer retained
+            Err(ResultsError::InvalidTaskId(task_id))
+        }
+    }
+
+    async fn await_task(&self, task_id: TaskKey) -> Result<(Status, Option<Task>), AwaitError> {
+        self.await_status(task_id, |status| {
+            matches!(status, Status::Ready | Status::Completed | Status::Failed)
+        })
+        .await
+    }
+
+    async fn await_finished(&self, task_id: TaskKey) -> Result<(Status, Option<Task>), AwaitError> {
+        self.await_status(task_id, |status| {
+            matches!(status, Status::Completed | Status::Failed)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::clock::TokioClock;
+    use serde_json::json;
+
+    fn insert_task(name: &str) -> InsertTask {
+        InsertTask(taskie_structures::InsertTask {
+            name: name.to_string(),
+            payload: None,
+            depends_on: vec![],
+            depends_on_batch: vec![],
+            duration: Duration::seconds(30),
+            priority: 0,
+            max_retries: 0,
+            cost: 1,
+            labels: BTreeMap::new(),
+            tenant: None,
+            run_at: None,
+            expires_at: None,
+            schedule: None,
+            idempotency_key: None,
+            dedupe: false,
+            callback_url: None,
+            dependency_mode: DependencyMode::All,
+            traceparent: None,
+        })
+    }
+
+    async fn push_with_duration(store: &MemoryStore, duration: Duration) -> Result<(), PushError> {
+        let mut task = insert_task("timed");
+        task.0.duration = duration;
+        store.push(vec![task]).await.map(|_| ())
+    }
+
+    #[tokio::test]
+    async fn push_rejects_non_positive_durations() {
+        let store = MemoryStore::new();
+        for duration in [Duration::ZERO, Duration::seconds(-30)] {
+            assert!(matches!(
+                push_with_duration(&store, duration).await,
+                Err(PushError::InvalidDuration { .. })
+            ));
+        }
+        assert_eq!(store.stats().await.unwrap().0.ready, 0);
+    }
+
+    #[tokio::test]
+    async fn push_rejects_durations_overflowing_the_deadline() {
+        let store = MemoryStore::new();
+        assert!(matches!(
+            push_with_duration(&store, Duration::MAX).await,
+            Err(PushError::InvalidDuration { .. })
+        ));
+        // Long, but representable, durations are fine
+        push_with_duration(&store, Duration::weeks(52 * 100))
+            .await
+            .unwrap();
+        let execution = store.pop(&Selector::default()).await.unwrap();
+        assert_eq!(execution.0.task.0.duration, Duration::weeks(52 * 100));
+    }
+
+    #[tokio::test]
+    async fn push_rejects_the_whole_batch_on_a_missing_dependency() {
+        let store = MemoryStore::new();
+        let mut dependent = insert_task("dependent");
+        dependent.0.depends_on = vec![TaskKey(42)];
+
+        assert!(matches!(
+            store.push(vec![insert_task("first"), dependent]).await,
+            Err(PushError::MissingDependency {
+                dependency: TaskKey(42)
+            })
+        ));
+        let stats = store.stats().await.unwrap().0;
+        assert_eq!((stats.pending, stats.ready), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn push_rejects_self_and_duplicate_dependencies() {
+        let store = MemoryStore::new();
+        let first = store.push(vec![insert_task("first")]).await.unwrap()[0]
+            .0
+            .id;
+
+        let mut itself = insert_task("itself");
+        itself.0.depends_on = vec![first, TaskKey(first.0 + 1)];
+        assert!(matches!(
+            store.push(vec![itself]).await,
+            Err(PushError::SelfDependency(id)) if id == TaskKey(first.0 + 1)
+        ));
+
+        let mut duplicate = insert_task("duplicate");
+        duplicate.0.depends_on = vec![first, first];
+        assert!(matches!(
+            store.push(vec![duplicate]).await,
+            Err(PushError::DuplicateDependency { dependency }) if dependency == first
+        ));
+    }
+
+    #[tokio::test]
+    async fn push_fails_once_the_keys_are_exhausted() {
+        let store = MemoryStore::new();
+        store.next_key.store(u64::MAX - 1, AtomicOrdering::Relaxed);
+
+        let pushed = store.push(vec![insert_task("last")]).await.unwrap();
+        assert_eq!(pushed[0].0.id, TaskKey(u64::MAX - 1));
+        assert!(matches!(
+            store.push(vec![insert_task("one too many")]).await,
+            Err(PushError::KeyExhausted)
+        ));
+        // The failed push does not wrap the counter around
+        assert_eq!(store.stats().await.unwrap().0.next_key, TaskKey(u64::MAX));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn monitor_survives_a_duplicate_completion() {
+        let store = Arc::new(MemoryStore::new().clock(TokioClock::new()));
+        let monitor = tokio::spawn({
+            let store = store.clone();
+            async move { store.monitor().await }
+        });
+
+        store.push(vec![insert_task("done")]).await.unwrap();
+        let execution = store.pop(&Selector::default()).await.unwrap().0;
+        let done = execution.task.0.id;
+        store
+            .complete(done, execution.lease_token, None)
+            .await
+            .unwrap();
+        store
+            .shard(done)
+            .chan
+            .0
+            .send(MonitorMessage::Completed(done))
+            .unwrap();
+
+        // Timeouts are still handled after the bad message
+        let mut timed = insert_task("timed");
+        timed.0.duration = Duration::seconds(10);
+        store.push(vec![timed]).await.unwrap();
+        store.pop(&Selector::default()).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_secs(11)).await;
+        let stats = store.stats().await.unwrap().0;
+        assert_eq!((stats.processing, stats.dead_lettered), (0, 1));
+        assert!(!monitor.is_finished());
+
+        store.shutdown().await;
+        assert!(monitor.await.unwrap().is_ok());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn monitor_backlog_is_reported_in_the_stats() {
+        let store = Arc::new(MemoryStore::new().clock(TokioClock::new()));
+        store.push(vec![insert_task("done")]).await.unwrap();
+        let done = store.pop(&Selector::default()).await.unwrap().0;
+        store
+            .complete(done.task.0.id, done.lease_token, None)
+            .await
+            .unwrap();
+        let monitor = store.stats().await.unwrap().0.monitor.unwrap();
+        assert_eq!(monitor.backlog, 1);
+        assert!(monitor.last_handled_at.is_none());
+
+        let handle = tokio::spawn({
+            let store = store.clone();
+            async move { store.monitor().await }
+        });
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        let stats = store.stats().await.unwrap().0;
+        let monitor = stats.monitor.unwrap();
+        assert_eq!((monitor.backlog, stats.completed), (0, 1));
+        assert!(monitor.last_handled_at.is_some());
+
+        store.shutdown().await;
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn pop_waits_for_a_processing_slot() {
+        let store = MemoryStore::new().max_processing(1);
+        let selector = Selector::default();
+        store
+            .push(vec![insert_task("first"), insert_task("second")])
+            .await
+            .unwrap();
+        let first = store.pop(&selector).await.unwrap().0;
+        assert!(store.try_pop(&selector).await.unwrap().is_none());
+        let blocked =
+            tokio::time::timeout(std::time::Duration::from_millis(100), store.pop(&selector));
+        assert!(blocked.await.is_err());
+
+        store
+            .complete(first.task.0.id, first.lease_token, None)
+            .await
+            .unwrap();
+        let second = store.pop(&selector).await.unwrap();
+        assert_eq!(second.0.task.0.name, "second");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timeouts_are_delayed_by_at_most_the_jitter() {
+        let store = Arc::new(
+            MemoryStore::new()
+                .timeout_jitter(1.0)
+                .clock(TokioClock::new()),
+        );
+        tokio::spawn({
+            let store = store.clone();
+            async move { store.monitor().await }
+        });
+
+        push_with_duration(&store, Duration::seconds(20))
+            .await
+            .unwrap();
+        let execution = store.pop(&Selector::default()).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_secs(19)).await;
+        assert_eq!(store.stats().await.unwrap().0.processing, 1);
+        // Deadlines are on the clock of the store as well, and so is the
+        // timeout once extended
+        let deadline = store
+            .heartbeat(
+                execution.0.task.0.id,
+                execution.0.lease_token,
+                Duration::seconds(2),
+            )
+            .await
+            .unwrap();
+        assert_eq!(deadline, execution.0.deadline + Duration::seconds(1));
+        tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+        assert_eq!(store.stats().await.unwrap().0.processing, 1);
+        tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+        let stats = store.stats().await.unwrap().0;
+        assert_eq!((stats.processing, stats.dead_lettered), (0, 1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timeouts_fire_within_a_tick_of_their_deadline() {
+        let store = Arc::new(
+            MemoryStore::new()
+                .monitor_tick(Duration::seconds(5))
+                .clock(TokioClock::new()),
+        );
+        tokio::spawn({
+            let store = store.clone();
+            async move { store.monitor().await }
+        });
+
+        push_with_duration(&store, Duration::seconds(2))
+            .await
+            .unwrap();
+        let execution = store.pop(&Selector::default()).await.unwrap();
+        let id = execution.0.task.0.id;
+        tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+        // Past its deadline, the task is still processing until the next tick
+        assert_eq!(store.stats().await.unwrap().0.processing, 1);
+        tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+        let stats = store.stats().await.unwrap().0;
+        assert_eq!((stats.processing, stats.dead_lettered), (0, 1));
+        assert!(matches!(
+            store.complete(id, execution.0.lease_token, None).await,
+            Err(CompleteError::InvalidTaskId(_))
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn every_shard_is_monitored() {
+        let store = Arc::new(
+            MemoryStore::new()
+                .monitor_shards(3)
+                .monitor_tick(Duration::SECOND)
+                .clock(TokioClock::new()),
+        );
+        let monitor = tokio::spawn({
+            let store = store.clone();
+            async move { store.monitor().await }
+        });
+
+        for _ in 0..6 {
+            push_with_duration(&store, Duration::seconds(2))
+                .await
+                .unwrap();
+        }
+        let mut executions = vec![];
+        for _ in 0..6 {
+            executions.push(store.pop(&Selector::default()).await.unwrap().0);
+        }
+        // Half of the tasks, one in each shard, are completed and the others
+        // time out
+        for execution in executions.iter().take(3) {
+            store
+                .complete(execution.task.0.id, execution.lease_token, None)
+                .await
+                .unwrap();
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(4)).await;
+        assert!(store.health().await);
+        let stats = store.stats().await.unwrap().0;
+        assert_eq!(
+            (stats.processing, stats.completed, stats.dead_lettered),
+            (0, 3, 3)
+        );
+        assert_eq!(stats.monitor.unwrap().backlog, 0);
+
+        store.shutdown().await;
+        assert!(monitor.await.unwrap().is_ok());
+        assert!(!store.health().await);
+    }
+
+    #[tokio::test]
+    async fn requeued_tasks_are_leased_anew() {
+        let store = Arc::new(MemoryStore::new());
+        tokio::spawn({
+            let store = store.clone();
+            async move { store.monitor().await }
+        });
+
+        let mut task = insert_task("leased");
+        task.0.max_retries = 1;
+        store.push(vec![task]).await.unwrap();
+        let first = store.pop(&Selector::default()).await.unwrap().0;
+        let id = first.task.0.id;
+        store.requeue(id).await.unwrap();
+        let second = store.pop(&Selector::default()).await.unwrap().0;
+        assert_ne!(first.lease_token, second.lease_token);
+
+        // The worker of the first execution can no longer end or extend it
+        let stale = first.lease_token;
+        assert!(matches!(
+            store.heartbeat(id, stale, Duration::seconds(1)).await,
+            Err(HeartbeatError::LeaseMismatch(_))
+        ));
+        assert!(matches!(
+            store.fail(id, stale, None).await,
+            Err(FailError::LeaseMismatch(_))
+        ));
+        assert!(matches!(
+            store.complete(id, stale, None).await,
+            Err(CompleteError::LeaseMismatch(_))
+        ));
+        store.complete(id, second.lease_token, None).await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn snapshots_are_loaded_with_their_deadlines() {
+        let path = std::env::temp_dir().join(format!("taskie-{}.json", std::process::id()));
+        let saved = MemoryStore::new().clock(TokioClock::new());
+        let mut timed = insert_task("timed");
+        timed.0.duration = Duration::seconds(10);
+        timed.0.max_retries = 1;
+        let pushed = saved.push(vec![timed]).await.unwrap();
+        let mut dependent = insert_task("dependent");
+        dependent.0.depends_on = vec![pushed[0].0.id];
+        saved.push(vec![dependent]).await.unwrap();
+        saved.pop(&Selector::default()).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+        saved.save(&path).await.unwrap();
+
+        let store = Arc::new(MemoryStore::new().clock(TokioClock::new()));
+        store.load(&path).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(store.load(&path).await, Err(SnapshotError::Io(_))));
+        tokio::spawn({
+            let store = store.clone();
+            async move { store.monitor().await }
+        });
+        let stats = store.stats().await.unwrap().0;
+        assert_eq!((stats.pending, stats.processing), (1, 1));
+        // The task times out at the deadline it had when saved
+        tokio::time::sleep(std::time::Duration::from_secs(4)).await;
+        assert_eq!(store.stats().await.unwrap().0.processing, 1);
+        tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+        let execution = store.pop(&Selector::default()).await.unwrap();
+        assert_eq!(execution.0.task.0.attempt, 2);
+        store
+            .complete(execution.0.task.0.id, execution.0.lease_token, None)
+            .await
+            .unwrap();
+        let dependent = store.pop(&Selector::default()).await.unwrap();
+        assert_eq!(dependent.0.task.0.name, "dependent");
+        // New tasks are given the keys following the saved ones
+        let next = store.push(vec![insert_task("next")]).await.unwrap();
+        assert_eq!(next[0].0.id, TaskKey(3));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_wait_for_the_backoff() {
+        let store = Arc::new(
+            MemoryStore::new()
+                .retry_backoff(Backoff {
+                    base: Duration::seconds(10),
+                    factor: 2.0,
+                    max: Duration::seconds(15),
+                })
+                .clock(TokioClock::new()),
+        );
+        tokio::spawn({
+            let store = store.clone();
+            async move { store.monitor().await }
+        });
+        let mut task = insert_task("flaky");
+        task.0.duration = Duration::seconds(1);
+        task.0.max_retries = 3;
+        let id = store.push(vec![task]).await.unwrap()[0].0.id;
+
+        // The first retry waits the base delay, the next ones grow up to the
+        // maximum
+        for delay in [10, 15] {
+            let execution = store.pop(&Selector::default()).await.unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+            let task = store.get(id).await.unwrap();
+            assert_eq!(task.0.status, Status::Pending);
+            assert_eq!(
+                task.0.run_at,
+                Some(execution.0.deadline + Duration::seconds(delay))
+            );
+            tokio::time::sleep(std::time::Duration::from_secs(delay as u64 - 1)).await;
+            assert_eq!(store.stats().await.unwrap().0.ready, 0);
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            assert_eq!(store.stats().await.unwrap().0.ready, 1);
+        }
+        assert_eq!(store.get(id).await.unwrap().0.attempt, 2);
+    }
+
+    #[tokio::test]
+    async fn expired_tasks_are_skipped_by_pop() {
+        let store = MemoryStore::new();
+        let mut stale = insert_task("stale");
+        stale.0.expires_at = Some(OffsetDateTime::now_utc() - Duration::SECOND);
+        stale.0.priority = 1;
+        let stale = store.push(vec![stale]).await.unwrap()[0].0.id;
+        store.push(vec![insert_task("fresh")]).await.unwrap();
+
+        let execution = store.pop(&Selector::default()).await.unwrap();
+        assert_eq!(execution.0.task.0.name, "fresh");
+        assert!(matches!(
+            store.get(stale).await,
+            Err(GetError::InvalidTaskId(_))
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn expired_tasks_are_dropped_along_with_their_dependents() {
+        let store = Arc::new(MemoryStore::new().clock(TokioClock::new()));
+        tokio::spawn({
+            let store = store.clone();
+            async move { store.monitor().await }
+        });
+        let now = store.clock.now();
+        let mut expiring = insert_task("expiring");
+        expiring.0.expires_at = Some(now + Duration::seconds(10));
+        let mut other = insert_task("other");
+        other.0.run_at = Some(now + Duration::seconds(60));
+        let pushed = store.push(vec![expiring, other]).await.unwrap();
+        let (expiring, other) = (pushed[0].0.id, pushed[1].0.id);
+        let mut dependent = insert_task("dependent");
+        dependent.0.depends_on = vec![expiring];
+        let mut either = insert_task("either");
+        either.0.depends_on = vec![expiring, other];
+        either.0.dependency_mode = DependencyMode::Any;
+        let pushed = store.push(vec![dependent, either]).await.unwrap();
+        let (dependent, either) = (pushed[0].0.id, pushed[1].0.id);
+
+        tokio::time::sleep(std::time::Duration::from_secs(11)).await;
+        assert!(matches!(
+            store.get(expiring).await,
+            Err(GetError::InvalidTaskId(_))
+        ));
+        // The dependent cannot run anymore, while the other one can still run
+        // once its remaining dependency is completed
+        let stats = store.stats().await.unwrap().0;
+        assert_eq!((stats.pending, stats.dead_lettered), (2, 1));
+        assert_eq!(store.get(dependent).await.unwrap().0.status, Status::Failed);
+        assert_eq!(store.get(either).await.unwrap().0.status, Status::Pending);
+    }
+
+    #[tokio::test]
+    async fn pushes_beyond_the_queue_depth_are_rejected() {
+        let store = MemoryStore::new().max_queue_depth(2, Overflow::Reject);
+        let mut pending = insert_task("pending");
+        pending.0.run_at = Some(OffsetDateTime::now_utc() + Duration::HOUR);
+        store
+            .push(vec![insert_task("ready"), pending])
+            .await
+            .unwrap();
+        assert!(matches!(
+            store.push(vec![insert_task("full")]).await,
+            Err(PushError::QueueFull { limit: 2, .. })
+        ));
+
+        store.pop(&Selector::default()).await.unwrap();
+        store.push(vec![insert_task("room")]).await.unwrap();
+        let stats = store.stats().await.unwrap().0;
+        assert_eq!((stats.pending, stats.ready), (1, 1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_are_estimated_from_the_completion_rate() {
+        let store = MemoryStore::new()
+            .clock(TokioClock::new())
+            .max_queue_depth(1, Overflow::Reject)
+            .retry_after(Duration::seconds(30));
+        store.push(vec![insert_task("first")]).await.unwrap();
+        // With no completions to go by, the configured wait is used
+        let Err(PushError::QueueFull { retry_after, .. }) =
+            store.push(vec![insert_task("full")]).await
+        else {
+            panic!("the queue is full");
+        };
+        assert_eq!(retry_after, Duration::seconds(30));
+
+        for _ in 0..12 {
+            let popped = store.pop(&Selector::default()).await.unwrap().0;
+            store
+                .complete(popped.task.0.id, popped.lease_token, None)
+                .await
+                .unwrap();
+            store.push(vec![insert_task("next")]).await.unwrap();
+        }
+        // 12 tasks a minute leave room for one more in 5 seconds
+        let Err(PushError::QueueFull { retry_after, .. }) =
+            store.push(vec![insert_task("full")]).await
+        else {
+            panic!("the queue is full");
+        };
+        assert!((retry_after - Duration::seconds(5)).abs() < Duration::MILLISECOND);
+    }
+
+    #[tokio::test]
+    async fn pushes_wait_for_room_when_blocking() {
+        let store = Arc::new(MemoryStore::new().max_queue_depth(1, Overflow::Block));
+        store.push(vec![insert_task("first")]).await.unwrap();
+        let blocked = tokio::spawn({
+            let store = store.clone();
+            async move { store.push(vec![insert_task("second")]).await }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert!(!blocked.is_finished());
+        // Batches which could never fit are refused all the same
+        assert!(matches!(
+            store.push(vec![insert_task("a"), insert_task("b")]).await,
+            Err(PushError::QueueFull { limit: 1, .. })
+        ));
+
+        let first = store.pop(&Selector::default()).await.unwrap();
+        assert_eq!(first.0.task.0.name, "first");
+        blocked.await.unwrap().unwrap();
+        let second = store.pop(&Selector::default()).await.unwrap();
+        assert_eq!(second.0.task.0.name, "second");
+    }
+
+    #[tokio::test]
+    async fn tenants_take_turns_in_fair_share() {
+        let store = MemoryStore::new().pop_mode(PopMode::FairShare);
+        let tenant_task = |name: &str, tenant: Option<&str>, priority| {
+            let mut task = insert_task(name);
+            task.0.tenant = tenant.map(str::to_string);
+            task.0.priority = priority;
+            task
+        };
+        store
+            .push(vec![
+                tenant_task("noisy-1", Some("noisy"), 1),
+                tenant_task("noisy-2", Some("noisy"), 1),
+                tenant_task("noisy-3", Some("noisy"), 2),
+                tenant_task("quiet", Some("quiet"), 0),
+                tenant_task("anonymous", None, 0),
+            ])
+            .await
+            .unwrap();
+
+        let selector = Selector::default();
+        let mut popped = vec![];
+        for _ in 0..5 {
+            popped.push(store.pop(&selector).await.unwrap().0.task.0.name);
+        }
+        assert_eq!(
+            popped,
+            ["anonymous", "noisy-3", "quiet", "noisy-1", "noisy-2"]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn aged_tasks_are_popped_before_higher_priorities() {
+        let store = MemoryStore::new()
+            .priority_aging(0.1)
+            .clock(TokioClock::new());
+        store.push(vec![insert_task("old")]).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_secs(30)).await;
+        let mut new = insert_task("new");
+        new.0.priority = 1;
+        store.push(vec![new]).await.unwrap();
+
+        let selector = Selector::default();
+        let first = store.pop(&selector).await.unwrap();
+        assert_eq!(first.0.task.0.name, "old");
+        let second = store.pop(&selector).await.unwrap();
+        assert_eq!(second.0.task.0.name, "new");
+    }
+
+    #[tokio::test]
+    async fn unblocked_tasks_are_boosted_past_the_backlog() {
+        let store = MemoryStore::new().dependency_boost(10);
+        let mut parent = insert_task("parent");
+        parent.0.priority = 5;
+        let parent = store.push(vec![parent]).await.unwrap()[0].0.id;
+        let mut dependent = insert_task("dependent");
+        dependent.0.depends_on = vec![parent];
+        store.push(vec![dependent]).await.unwrap();
+        for _ in 0..3 {
+            let mut backlog = insert_task("backlog");
+            backlog.0.priority = 1;
+            store.push(vec![backlog]).await.unwrap();
+        }
+
+        let selector = Selector::default();
+        let popped = store.pop(&selector).await.unwrap();
+        assert_eq!(popped.0.task.0.id, parent);
+        store
+            .complete(parent, popped.0.lease_token, None)
+            .await
+            .unwrap();
+        let popped = store.pop(&selector).await.unwrap();
+        assert_eq!(popped.0.task.0.name, "dependent");
+        // Only its place on the queue was boosted
+        assert_eq!(popped.0.task.0.priority, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn task_results_are_forgotten_after_the_retention() {
+        let store = Arc::new(
+            MemoryStore::new()
+                .result_retention(Duration::HOUR)
+                .clock(TokioClock::new()),
+        );
+        tokio::spawn({
+            let store = store.clone();
+            async move { store.monitor().await }
+        });
+
+        let id = store.push(vec![insert_task("done")]).await.unwrap()[0].0.id;
+        assert!(matches!(
+            store.task_result(id).await,
+            Err(ResultsError::NotFinished(_))
+        ));
+        let popped = store.pop(&Selector::default()).await.unwrap();
+        store
+            .complete(id, popped.0.lease_token, None)
+            .await
+            .unwrap();
+        assert!(matches!(
+            store.task_result(id).await,
+            Ok((Status::Completed, None))
+        ));
+
+        tokio::time::sleep(std::time::Duration::from_secs(3601)).await;
+        assert!(matches!(
+            store.task_result(id).await,
+            Err(ResultsError::InvalidTaskId(_))
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn repeated_completions_are_told_apart_within_the_retention() {
+        let store = MemoryStore::new()
+            .result_retention(Duration::HOUR)
+            .clock(TokioClock::new());
+        let id = store.push(vec![insert_task("done")]).await.unwrap()[0].0.id;
+        let lease_token = store.pop(&Selector::default()).await.unwrap().0.lease_token;
+        store.complete(id, lease_token, None).await.unwrap();
+        assert!(matches!(
+            store.complete(id, lease_token, None).await,
+            Err(CompleteError::AlreadyCompleted(_))
+        ));
+        assert_eq!(store.stats().await.unwrap().0.completed, 1);
+
+        tokio::time::sleep(std::time::Duration::from_secs(3601)).await;
+        assert!(matches!(
+            store.complete(id, lease_token, None).await,
+            Err(CompleteError::InvalidTaskId(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn push_returns_the_task_pushed_with_the_same_idempotency_key() {
+        let store = MemoryStore::new();
+        let mut keyed = insert_task("keyed");
+        keyed.0.idempotency_key = Some("once".to_string());
+
+        let first = store.push(vec![keyed.clone()]).await.unwrap();
+        let again = store
+            .push(vec![insert_task("other"), keyed.clone(), keyed.clone()])
+            .await
+            .unwrap();
+        assert_eq!(again[1].0.id, first[0].0.id);
+        assert_eq!(again[2].0.id, first[0].0.id);
+        assert_ne!(again[0].0.id, first[0].0.id);
+        assert_eq!(store.stats().await.unwrap().0.ready, 2);
+
+        // Repeats within a batch are pushed once as well
+        let mut batch = insert_task("batch");
+        batch.0.idempotency_key = Some("batch".to_string());
+        let pushed = store.push(vec![batch.clone(), batch]).await.unwrap();
+        assert_eq!(pushed[0].0.id, pushed[1].0.id);
+        assert_eq!(store.stats().await.unwrap().0.ready, 3);
+    }
+
+    #[tokio::test]
+    async fn identical_tasks_are_deduplicated_until_popped() {
+        let store = MemoryStore::new();
+        let dedupe = |payload: Value| {
+            let mut task = insert_task("same");
+            task.0.payload = Some(payload);
+            task.0.dedupe = true;
+            task
+        };
+
+        let pushed = store
+            .push(vec![
+                dedupe(json!({"n": 1})),
+                dedupe(json!({"n": 1})),
+                dedupe(json!({"n": 2})),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(pushed[0].0.id, pushed[1].0.id);
+        assert_ne!(pushed[0].0.id, pushed[2].0.id);
+        let again = store.push(vec![dedupe(json!({"n": 1}))]).await.unwrap();
+        assert_eq!(again[0].0.id, pushed[0].0.id);
+        // Only the tasks pushed with `dedupe` are collapsed
+        let mut plain = dedupe(json!({"n": 1}));
+        plain.0.dedupe = false;
+        let plain = store.push(vec![plain]).await.unwrap();
+        assert_ne!(plain[0].0.id, pushed[0].0.id);
+        assert_eq!(store.stats().await.unwrap().0.ready, 3);
+
+        // Once the task is being processed an identical one is pushed anew
+        let popped = store.pop(&Selector::default()).await.unwrap();
+        assert_eq!(popped.0.task.0.id, pushed[0].0.id);
+        let after = store.push(vec![dedupe(json!({"n": 1}))]).await.unwrap();
+        assert_ne!(after[0].0.id, pushed[0].0.id);
+        store
+            .complete(pushed[0].0.id, popped.0.lease_token, None)
+            .await
+            .unwrap();
+        let last = store.push(vec![dedupe(json!({"n": 1}))]).await.unwrap();
+        assert_eq!(last[0].0.id, after[0].0.id);
+    }
+
+    #[tokio::test]
+    async fn idempotency_keys_are_forgotten_after_the_window() {
+        let store = MemoryStore::new().idempotency_window(Duration::ZERO);
+        let mut keyed = insert_task("keyed");
+        keyed.0.idempotency_key = Some("once".to_string());
+
+        let pushed = store.push(vec![keyed.clone(), keyed]).await.unwrap();
+        assert_ne!(pushed[0].0.id, pushed[1].0.id);
+    }
+
+    #[tokio::test]
+    async fn ready_tasks_with_pending_dependencies_are_not_popped() {
+        let store = MemoryStore::new();
+        let mut inconsistent = insert_task("inconsistent");
+        inconsistent.0.priority = 10;
+        let pushed = store
+            .push(vec![insert_task("dependency"), inconsistent])
+            .await
+            .unwrap();
+        let (dependency, inconsistent) = (pushed[0].0.id, pushed[1].0.id);
+        // The task is on the queue, yet depends on one which is not completed
+        store
+            .edges
+            .write()
+            .await
+            .insert(inconsistent, vec![dependency]);
+
+        assert!(matches!(
+            store.try_pop(&Selector::default()).await,
+            Err(PopError::PendingDependencies(id)) if id == inconsistent
+        ));
+        let task = store.get(inconsistent).await.unwrap().0;
+        assert_eq!((task.status, task.attempt), (Status::Pending, 0));
+
+        // Completing the dependency puts it back on the queue
+        let popped = store.try_pop(&Selector::default()).await.unwrap().unwrap();
+        assert_eq!(popped.0.task.0.id, dependency);
+        store
+            .complete(dependency, popped.0.lease_token, None)
+            .await
+            .unwrap();
+        let popped = store.try_pop(&Selector::default()).await.unwrap();
+        assert_eq!(popped.unwrap().0.task.0.id, inconsistent);
+    }
+
+    #[tokio::test]
+    async fn any_dependency_mode_waits_for_the_first_dependency() {
+        let store = MemoryStore::new();
+        let pushed = store
+            .push(vec![insert_task("first"), insert_task("second")])
+            .await
+            .unwrap();
+        let (first, second) = (pushed[0].0.id, pushed[1].0.id);
+        let mut any = insert_task("any");
+        any.0.depends_on = vec![first, second];
+        any.0.dependency_mode = DependencyMode::Any;
+        let mut all = insert_task("all");
+        all.0.depends_on = vec![first, second];
+        let pushed = store.push(vec![any, all]).await.unwrap();
+        let (any, all) = (pushed[0].0.id, pushed[1].0.id);
+
+        let popped = store.pop(&Selector::default()).await.unwrap().0;
+        assert_eq!(popped.task.0.id, first);
+        store
+            .complete(first, popped.lease_token, None)
+            .await
+            .unwrap();
+        assert_eq!(store.get(any).await.unwrap().0.status, Status::Ready);
+        assert_eq!(store.get(all).await.unwrap().0.status, Status::Pending);
+        // Completing the other one leaves the task on the queue once
+        let popped = store.pop(&Selector::default()).await.unwrap().0;
+        assert_eq!(popped.task.0.id, second);
+        store
+            .complete(second, popped.lease_token, None)
+            .await
+            .unwrap();
+        let stats = store.stats().await.unwrap().0;
+        assert_eq!((stats.pending, stats.ready), (0, 2));
+    }
+
+    #[tokio::test]
+    async fn queue_depth_counts_the_ready_tasks() {
+        let store = MemoryStore::new();
+        let dependency = store.push(vec![insert_task("dependency")]).await.unwrap()[0]
+            .0
+            .id;
+        let mut dependent = insert_task("dependent");
+        dependent.0.depends_on = vec![dependency];
+        store
+            .push(vec![insert_task("independent"), dependent])
+            .await
+            .unwrap();
+        assert_eq!(store.queue_depth(), 2);
+
+        let popped = store.try_pop(&Selector::default()).await.unwrap().unwrap();
+        assert_eq!(store.queue_depth(), 1);
+        store
+            .complete(popped.0.task.0.id, popped.0.lease_token, None)
+            .await
+            .unwrap();
+        let popped = store.try_pop(&Selector::default()).await.unwrap().unwrap();
+        store
+            .complete(popped.0.task.0.id, popped.0.lease_token, None)
+            .await
+            .unwrap();
+        // The dependent is only ready once its dependency is completed
+        assert_eq!(store.queue_depth(), 1);
+    }
+
+    #[tokio::test]
+    async fn tasks_can_depend_on_the_tasks_of_their_batch() {
+        let store = MemoryStore::new();
+        let mut first = insert_task("first");
+        // on a task later in the batch, too
+        first.0.depends_on_batch = vec![1];
+        let mut last = insert_task("last");
+        last.0.depends_on_batch = vec![0, 1];
+        let pushed = store
+            .push(vec![first, insert_task("root"), last])
+            .await
+            .unwrap();
+        let (first, root, last) = (pushed[0].0.id, pushed[1].0.id, pushed[2].0.id);
+        assert_eq!(pushed[0].0.depends_on, vec![root]);
+        assert_eq!(pushed[2].0.depends_on, vec![first, root]);
+        assert_eq!(pushed[0].0.status, Status::Pending);
+
+        for expected in [root, first, last] {
+            let popped = store.try_pop(&Selector::default()).await.unwrap().unwrap();
+            assert_eq!(popped.0.task.0.id, expected);
+            assert!(store.try_pop(&Selector::default()).await.unwrap().is_none());
+            store
+                .complete(expected, popped.0.lease_token, None)
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn push_rejects_invalid_dependencies_within_the_batch() {
+        let store = MemoryStore::new();
+        let mut first = insert_task("first");
+        first.0.depends_on_batch = vec![1];
+        let mut second = insert_task("second");
+        second.0.depends_on_batch = vec![0];
+        assert!(matches!(
+            store.push(vec![first.clone(), second]).await,
+            Err(PushError::Cycle(_))
+        ));
+
+        let mut itself = insert_task("itself");
+        itself.0.depends_on_batch = vec![0];
+        assert!(matches!(
+            store.push(vec![itself]).await,
+            Err(PushError::SelfDependency(_))
+        ));
+        assert!(matches!(
+            store.push(vec![first]).await,
+            Err(PushError::InvalidBatchDependency { position: 1, .. })
+        ));
+        // Nothing is stored, nor are any keys handed out
+        let stats = store.stats().await.unwrap().0;
+        assert_eq!((stats.pending, stats.ready), (0, 0));
+        assert_eq!(stats.next_key, TaskKey(1));
     }
 }
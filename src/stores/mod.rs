@@ -1 +1,12 @@
 pub mod mem;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "redis")]
+pub mod redis;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+crate::store::backend_errors!(sqlx::Error => MonitorError, PushError, CompleteError, CompleteAndPushError, PopError, PeekError, FailError, RequeueError, HeartbeatError, DeadLetterError, CancelError, GetError, ListError, StatsError, PurgeError);
+#[cfg(feature = "redis")]
+crate::store::backend_errors!(::redis::RedisError => MonitorError, PushError, CompleteError, PopError, FailError, HeartbeatError, DeadLetterError, CancelError, GetError, StatsError, PurgeError);
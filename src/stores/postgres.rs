@@ -0,0 +1,802 @@
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration as StdDuration,
+};
+
+use axum::async_trait;
+use serde_json::Value;
+use sqlx::{
+    postgres::{PgListener, PgPool, PgPoolOptions, PgRow},
+    types::Json,
+    Postgres, Row, Transaction,
+};
+use taskie_structures::{DependencyMode, Status, Uuid, DEFAULT_COST};
+use time::{Duration, OffsetDateTime};
+use tokio::{
+    sync::Notify,
+    time::{interval, timeout},
+};
+
+use crate::metrics::{PROCESSING, QUEUE_DEPTH, TASKS_TIMED_OUT};
+use crate::store::{
+    fail_reason, leased, CancelError, CompleteAndPushError, CompleteError, DeadLetterError,
+    Execution, FailError, GetError, HeartbeatError, InsertTask, ListError, MonitorError, PeekError,
+    PopError, PurgeError, PushError, RequeueError, Selector, Stats, StatsError, Store, Task,
+    TaskKey, TIMEOUT_REASON,
+};
+
+/// How often the monitor looks for expired deadlines, and the longest a
+/// blocked `pop` waits before checking the queue again.
+static POLL_INTERVAL: StdDuration = StdDuration::from_secs(1);
+
+/// Parses the lease token recorded in `processing`, missing for the tasks
+/// popped before the tokens were handed out, which nothing matches.
+fn lease(recorded: Option<String>) -> Option<Uuid> {
+    recorded.and_then(|recorded| recorded.parse().ok())
+}
+
+/// The channel used to tell every server sharing the database that new tasks
+/// have been put on the queue.
+static READY_CHANNEL: &str = "taskie_ready";
+
+/// A store shared between any number of taskie instances. Tasks are handed to
+/// a single worker thanks to `SELECT ... FOR UPDATE SKIP LOCKED`, and the
+/// timeouts are tracked as deadlines in the `processing` table.
+pub struct PostgresStore {
+    pool: PgPool,
+    ready: Notify,
+    /// Notified by `shutdown` to stop the monitor
+    stop: Notify,
+    /// The tasks on the queue when the gauges were last recorded
+    queued: AtomicUsize,
+}
+
+/// The status of a row of `tasks`, which is not stored but derived from the
+/// table holding the task. Queued tasks whose `run_at` has not come yet are
+/// still pending.
+static STATUS: &str = "CASE
+    WHEN failed THEN 'failed'
+    WHEN EXISTS (SELECT 1 FROM processing WHERE task = tasks.id) THEN 'processing'
+    WHEN EXISTS (SELECT 1 FROM queue WHERE task = tasks.id)
+        AND (tasks.run_at IS NULL OR tasks.run_at <= now()) THEN 'ready'
+    ELSE 'pending'
+END";
+
+fn task_from_row(row: &PgRow, status: Status) -> Result<Task, sqlx::Error> {
+    let Json(depends_on): Json<Vec<u64>> = row.try_get("depends_on")?;
+    let payload: Option<Json<Value>> = row.try_get("payload")?;
+    Ok(Task(taskie_structures::Task {
+        id: TaskKey(row.try_get::<i64, _>("id")? as u64),
+        name: row.try_get("name")?,
+        payload: payload.map(|Json(payload)| payload),
+        depends_on: depends_on.into_iter().map(TaskKey).collect(),
+        duration: Duration::seconds(row.try_get("duration")?),
+        priority: row.try_get("priority")?,
+        max_retries: row.try_get::<i64, _>("max_retries")? as u32,
+        cost: DEFAULT_COST,
+        labels: row.try_get::<Json<_>, _>("labels")?.0,
+        tenant: None,
+        attempt: row.try_get::<i64, _>("attempt")? as u32,
+        status,
+        created_at: row.try_get("created_at")?,
+        started_at: row.try_get("started_at")?,
+        run_at: row.try_get("run_at")?,
+        expires_at: None,
+        progress: None,
+        schedule: None,
+        callback_url: None,
+        dependency_mode: DependencyMode::All,
+        traceparent: row.try_get("traceparent")?,
+    }))
+}
+
+async fn enqueue(tx: &mut Transaction<'_, Postgres>, id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO queue (task, priority) SELECT id, priority FROM tasks WHERE id = $1
+        ON CONFLICT DO NOTHING",
+    )
+    .bind(id)
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query("SELECT pg_notify($1, '')")
+        .bind(READY_CHANNEL)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+/// Puts a task whose execution ended without completing back on the queue,
+/// or in the dead-letter queue once it exhausted its retries.
+async fn retry(
+    tx: &mut Transaction<'_, Postgres>,
+    id: i64,
+    reason: &str,
+) -> Result<(), sqlx::Error> {
+    let failed = sqlx::query(
+        "UPDATE tasks SET failed = true, failure_reason = $1 WHERE id = $2 AND attempt > max_retries",
+    )
+    .bind(reason)
+    .bind(id)
+    .execute(&mut **tx)
+    .await?
+    .rows_affected()
+        > 0;
+    if failed {
+        tracing::warn!(id = %TaskKey(id as u64), %reason, "Task exhausted its retries, moving it to the dead-letter queue");
+        Ok(())
+    } else {
+        enqueue(tx, id).await
+    }
+}
+
+/// Rejects the tasks using the features the store does not support.
+fn check_supported(insert_tasks: &[InsertTask]) -> Result<(), PushError> {
+    for insert_task in insert_tasks.iter() {
+        insert_task.validate()?;
+    }
+    if insert_tasks.iter().any(|task| task.0.schedule.is_some()) {
+        return Err(PushError::UnsupportedSchedule);
+    }
+    if insert_tasks
+        .iter()
+        .any(|task| task.0.idempotency_key.is_some())
+    {
+        return Err(PushError::UnsupportedIdempotencyKey);
+    }
+    if insert_tasks
+        .iter()
+        .any(|task| task.0.callback_url.is_some())
+    {
+        return Err(PushError::UnsupportedCallback);
+    }
+    if insert_tasks
+        .iter()
+        .any(|task| task.0.dependency_mode != DependencyMode::All)
+    {
+        return Err(PushError::UnsupportedDependencyMode);
+    }
+    if insert_tasks.iter().any(|task| task.0.tenant.is_some()) {
+        return Err(PushError::UnsupportedTenant);
+    }
+    if insert_tasks.iter().any(|task| task.0.dedupe) {
+        return Err(PushError::UnsupportedDedupe);
+    }
+    if insert_tasks.iter().any(|task| task.0.cost != DEFAULT_COST) {
+        return Err(PushError::UnsupportedCost);
+    }
+    if insert_tasks.iter().any(|task| task.0.expires_at.is_some()) {
+        return Err(PushError::UnsupportedExpiry);
+    }
+    if insert_tasks
+        .iter()
+        .any(|task| !task.0.depends_on_batch.is_empty())
+    {
+        return Err(PushError::UnsupportedBatchDependency);
+    }
+    Ok(())
+}
+
+/// Stores the tasks, putting those without dependencies on the queue.
+async fn insert(
+    tx: &mut Transaction<'_, Postgres>,
+    insert_tasks: Vec<InsertTask>,
+) -> Result<Vec<Task>, PushError> {
+    let mut result = Vec::with_capacity(insert_tasks.len());
+    for insert_task in insert_tasks.into_iter() {
+        let InsertTask(insert_task) = insert_task;
+        let id: i64 = sqlx::query_scalar("SELECT nextval('task_keys')")
+            .fetch_one(&mut **tx)
+            .await?;
+        if insert_task.depends_on.contains(&TaskKey(id as u64)) {
+            return Err(PushError::SelfDependency(TaskKey(id as u64)));
+        }
+
+        // Lock the dependencies so they cannot be completed (and deleted)
+        // before the new edges are committed.
+        for dependency in insert_task.depends_on.iter() {
+            let exists = sqlx::query("SELECT 1 FROM tasks WHERE id = $1 AND NOT failed FOR SHARE")
+                .bind(dependency.0 as i64)
+                .fetch_optional(&mut **tx)
+                .await?
+                .is_some();
+            if !exists {
+                return Err(PushError::MissingDependency {
+                    dependency: *dependency,
+                });
+            }
+        }
+
+        // Scheduled tasks are queued all the same, but not popped before
+        // their time comes
+        let now = OffsetDateTime::now_utc();
+        let scheduled = insert_task.run_at.is_some_and(|run_at| run_at > now);
+        let task = Task(taskie_structures::Task {
+            id: TaskKey(id as u64),
+            payload: insert_task.payload,
+            name: insert_task.name,
+            duration: insert_task.duration,
+            priority: insert_task.priority,
+            max_retries: insert_task.max_retries,
+            cost: DEFAULT_COST,
+            labels: insert_task.labels,
+            tenant: None,
+            attempt: 0,
+            status: if insert_task.depends_on.is_empty() && !scheduled {
+                Status::Ready
+            } else {
+                Status::Pending
+            },
+            created_at: now,
+            started_at: None,
+            run_at: insert_task.run_at,
+            expires_at: None,
+            progress: None,
+            schedule: None,
+            callback_url: None,
+            dependency_mode: DependencyMode::All,
+            traceparent: insert_task.traceparent,
+            depends_on: insert_task.depends_on,
+        });
+        sqlx::query(
+            "INSERT INTO tasks (id, name, payload, depends_on, duration, priority, max_retries, created_at, labels, run_at, traceparent) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+        )
+        .bind(id)
+        .bind(&task.0.name)
+        .bind(task.0.payload.as_ref().map(Json))
+        .bind(Json(task.0.depends_on.iter().map(|k| k.0).collect::<Vec<_>>()))
+        .bind(task.0.duration.whole_seconds())
+        .bind(task.0.priority)
+        .bind(task.0.max_retries as i64)
+        .bind(task.0.created_at)
+        .bind(Json(&task.0.labels))
+        .bind(task.0.run_at)
+        .bind(&task.0.traceparent)
+        .execute(&mut **tx)
+        .await?;
+
+        if task.0.depends_on.is_empty() {
+            // if the task doesn't have any dependencies, we can just enqueue
+            // it, ready to be consumed by workers
+            enqueue(tx, id).await?;
+        } else {
+            for dependency in task.0.depends_on.iter() {
+                sqlx::query(
+                    "INSERT INTO edges (task, dependency) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+                )
+                .bind(id)
+                .bind(dependency.0 as i64)
+                .execute(&mut **tx)
+                .await?;
+            }
+        }
+        result.push(task);
+    }
+    Ok(result)
+}
+
+/// Completes a task being processed, putting its dependents without other
+/// pending dependencies on the queue.
+async fn complete(
+    tx: &mut Transaction<'_, Postgres>,
+    task_id: TaskKey,
+    lease_token: Uuid,
+) -> Result<(), CompleteError> {
+    let id = task_id.0 as i64;
+    let removed: Option<Option<String>> =
+        sqlx::query_scalar("DELETE FROM processing WHERE task = $1 RETURNING lease_token")
+            .bind(id)
+            .fetch_optional(&mut **tx)
+            .await?;
+    let Some(recorded) = removed else {
+        return Err(CompleteError::InvalidTaskId(task_id));
+    };
+    // The transaction is rolled back when dropped, putting the task back
+    if !leased(lease(recorded), lease_token) {
+        return Err(CompleteError::LeaseMismatch(task_id));
+    }
+
+    // Deleting the task waits for any concurrent push holding a lock on
+    // it, so that all the edges towards it are visible below.
+    sqlx::query("DELETE FROM tasks WHERE id = $1")
+        .bind(id)
+        .execute(&mut **tx)
+        .await?;
+    let dependents: Vec<i64> =
+        sqlx::query_scalar("DELETE FROM edges WHERE dependency = $1 RETURNING task")
+            .bind(id)
+            .fetch_all(&mut **tx)
+            .await?;
+
+    // Lock the dependents, so that when two of their dependencies are
+    // completed concurrently the last one sees the other's deleted edge.
+    let dependents: Vec<i64> =
+        sqlx::query_scalar("SELECT id FROM tasks WHERE id = ANY($1) ORDER BY id FOR UPDATE")
+            .bind(&dependents)
+            .fetch_all(&mut **tx)
+            .await?;
+
+    sqlx::query("SELECT nextval('completed_tasks')")
+        .execute(&mut **tx)
+        .await?;
+
+    // Put any task without pending dependencies on the queue
+    for node in dependents.into_iter() {
+        let pending = sqlx::query("SELECT 1 FROM edges WHERE task = $1 LIMIT 1")
+            .bind(node)
+            .fetch_optional(&mut **tx)
+            .await?
+            .is_some();
+        if !pending {
+            tracing::debug!(id = %TaskKey(node as u64), "Task has become ready");
+            enqueue(tx, node).await?;
+        }
+    }
+    Ok(())
+}
+
+impl PostgresStore {
+    /// Connects to the database at `url` and runs any pending migration.
+    pub async fn connect(url: &str) -> Result<Self, sqlx::Error> {
+        let pool = PgPoolOptions::new().connect(url).await?;
+        sqlx::migrate!("migrations/postgres").run(&pool).await?;
+
+        let store = PostgresStore {
+            pool,
+            ready: Notify::new(),
+            stop: Notify::new(),
+            queued: AtomicUsize::new(0),
+        };
+        store.requeue_expired().await?;
+        Ok(store)
+    }
+
+    /// Moves all the tasks in `processing` whose deadline has passed back on
+    /// the queue, just like a `TimedOut` message does for the `MemoryStore`.
+    async fn requeue_expired(&self) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let expired: Vec<i64> =
+            sqlx::query_scalar("DELETE FROM processing WHERE deadline <= now() RETURNING task")
+                .fetch_all(&mut *tx)
+                .await?;
+        for id in expired.into_iter() {
+            tracing::info!(id = %TaskKey(id as u64), "Task execution timed out");
+            metrics::increment_counter!(TASKS_TIMED_OUT);
+            retry(&mut tx, id, TIMEOUT_REASON).await?;
+        }
+        tx.commit().await
+    }
+
+    /// Updates the gauges with the size of the queue and of `processing`.
+    async fn record_gauges(&self) -> Result<(), sqlx::Error> {
+        let (queued, processing): (i64, i64) = sqlx::query_as(
+            "SELECT (SELECT COUNT(*) FROM queue), (SELECT COUNT(*) FROM processing)",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        metrics::gauge!(QUEUE_DEPTH, queued as f64);
+        self.queued.store(queued as usize, Ordering::Relaxed);
+        metrics::gauge!(PROCESSING, processing as f64);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Store for PostgresStore {
+    async fn monitor(&self) -> Result<(), MonitorError> {
+        let mut listener = PgListener::connect_with(&self.pool).await?;
+        listener.listen(READY_CHANNEL).await?;
+
+        let mut ticker = interval(POLL_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    self.requeue_expired().await?;
+                    self.record_gauges().await?;
+                }
+                notification = listener.recv() => {
+                    // The listener reconnects by itself on the next `recv`, and
+                    // in the meantime the pops are still polling the queue.
+                    if let Err(err) = notification {
+                        tracing::warn!(%err, "Lost the connection listening for ready tasks");
+                    }
+                    self.ready.notify_waiters();
+                }
+                _ = self.stop.notified() => break,
+            }
+        }
+        self.pool.close().await;
+        Ok(())
+    }
+
+    async fn push(&self, insert_tasks: Vec<InsertTask>) -> Result<Vec<Task>, PushError> {
+        check_supported(&insert_tasks)?;
+        let mut tx = self.pool.begin().await?;
+        let result = insert(&mut tx, insert_tasks).await?;
+        tx.commit().await?;
+        Ok(result)
+    }
+
+    async fn pop(&self, selector: &Selector) -> Result<Execution, PopError> {
+        loop {
+            // Subscribe before checking the queue, so that no push can slip
+            // in between the check and the wait.
+            let notified = self.ready.notified();
+            if let Some(execution) = self.try_pop(selector).await? {
+                return Ok(execution);
+            }
+            let _ = timeout(POLL_INTERVAL, notified).await;
+        }
+    }
+
+    async fn try_pop(&self, selector: &Selector) -> Result<Option<Execution>, PopError> {
+        let (values, names) = selector.split();
+        let mut tx = self.pool.begin().await?;
+        // Rows locked by a concurrent pop are skipped, so that no two workers
+        // (possibly connected to different servers) get the same task.
+        let id: Option<i64> = sqlx::query_scalar(
+            "DELETE FROM queue WHERE position = (
+                SELECT queue.position FROM queue JOIN tasks ON tasks.id = queue.task
+                WHERE tasks.labels @> $1 AND tasks.labels ?& $2
+                    AND ($3::text IS NULL OR tasks.name = $3)
+                    AND (tasks.run_at IS NULL OR tasks.run_at <= now())
+                ORDER BY queue.priority DESC, queue.position
+                FOR UPDATE OF queue SKIP LOCKED LIMIT 1
+            ) RETURNING task",
+        )
+        .bind(Json(values))
+        .bind(names)
+        .bind(selector.name.as_deref())
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(id) = id else {
+            return Ok(None);
+        };
+
+        let now = OffsetDateTime::now_utc();
+        let row = sqlx::query(
+            "UPDATE tasks SET attempt = attempt + 1, started_at = $1 WHERE id = $2 RETURNING *",
+        )
+        .bind(now)
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(PopError::InvalidTaskId(TaskKey(id as u64)))?;
+        let task = task_from_row(&row, Status::Processing)?;
+        let deadline = now + task.0.duration;
+        let lease_token = Uuid::new_v4();
+        sqlx::query("INSERT INTO processing (task, deadline, lease_token) VALUES ($1, $2, $3)")
+            .bind(id)
+            .bind(deadline)
+            .bind(lease_token.to_string())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(Some(Execution(taskie_structures::Execution {
+            remaining: task.0.duration,
+            task,
+            deadline,
+            lease_token,
+        })))
+    }
+
+    // The results are not retained, so `dependency_results` is unsupported
+    async fn complete(
+        &self,
+        task_id: TaskKey,
+        lease_token: Uuid,
+        _result: Option<Value>,
+    ) -> Result<(), CompleteError> {
+        let mut tx = self.pool.begin().await?;
+        complete(&mut tx, task_id, lease_token).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn complete_and_push(
+        &self,
+        task_id: TaskKey,
+        lease_token: Uuid,
+        _result: Option<Value>,
+        insert_tasks: Vec<InsertTask>,
+    ) -> Result<Vec<Task>, CompleteAndPushError> {
+        check_supported(&insert_tasks)?;
+        let mut tx = self.pool.begin().await?;
+        // Pushed first, so that the tasks can depend on the completed one
+        let result = insert(&mut tx, insert_tasks).await?;
+        complete(&mut tx, task_id, lease_token).await?;
+        tx.commit().await?;
+        Ok(result)
+    }
+
+    async fn peek(&self) -> Result<Option<Task>, PeekError> {
+        let row = sqlx::query(
+            "SELECT tasks.* FROM queue JOIN tasks ON tasks.id = queue.task
+            WHERE tasks.run_at IS NULL OR tasks.run_at <= now()
+            ORDER BY queue.priority DESC, queue.position LIMIT 1",
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row
+            .map(|row| task_from_row(&row, Status::Ready))
+            .transpose()?)
+    }
+
+    async fn fail(
+        &self,
+        task_id: TaskKey,
+        lease_token: Uuid,
+        reason: Option<String>,
+    ) -> Result<(), FailError> {
+        let id = task_id.0 as i64;
+        let mut tx = self.pool.begin().await?;
+        let removed: Option<Option<String>> =
+            sqlx::query_scalar("DELETE FROM processing WHERE task = $1 RETURNING lease_token")
+                .bind(id)
+                .fetch_optional(&mut *tx)
+                .await?;
+        let Some(recorded) = removed else {
+            return Err(FailError::InvalidTaskId(task_id));
+        };
+        if !leased(lease(recorded), lease_token) {
+            return Err(FailError::LeaseMismatch(task_id));
+        }
+        retry(&mut tx, id, &fail_reason(reason)).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn requeue(&self, task_id: TaskKey) -> Result<(), RequeueError> {
+        let id = task_id.0 as i64;
+        let mut tx = self.pool.begin().await?;
+        let removed = sqlx::query("DELETE FROM processing WHERE task = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        if removed == 0 {
+            return Err(RequeueError::InvalidTaskId(task_id));
+        }
+        metrics::increment_counter!(TASKS_TIMED_OUT);
+        retry(&mut tx, id, TIMEOUT_REASON).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn heartbeat(
+        &self,
+        task_id: TaskKey,
+        lease_token: Uuid,
+        extend: Duration,
+    ) -> Result<OffsetDateTime, HeartbeatError> {
+        let deadline = OffsetDateTime::now_utc() + extend;
+        let mut tx = self.pool.begin().await?;
+        let updated: Option<Option<String>> = sqlx::query_scalar(
+            "UPDATE processing SET deadline = $1 WHERE task = $2 RETURNING lease_token",
+        )
+        .bind(deadline)
+        .bind(task_id.0 as i64)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(recorded) = updated else {
+            return Err(HeartbeatError::InvalidTaskId(task_id));
+        };
+        if !leased(lease(recorded), lease_token) {
+            return Err(HeartbeatError::LeaseMismatch(task_id));
+        }
+        tx.commit().await?;
+        Ok(deadline)
+    }
+
+    async fn health(&self) -> bool {
+        sqlx::query("SELECT 1").execute(&self.pool).await.is_ok()
+    }
+
+    async fn shutdown(&self) {
+        self.stop.notify_one();
+    }
+
+    async fn dead_letters(&self) -> Result<Vec<(Task, String)>, DeadLetterError> {
+        let rows = sqlx::query("SELECT * FROM tasks WHERE failed ORDER BY id")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| {
+                let reason: Option<String> = row.try_get("failure_reason")?;
+                Ok((
+                    task_from_row(row, Status::Failed)?,
+                    reason.unwrap_or_default(),
+                ))
+            })
+            .collect::<Result<Vec<_>, sqlx::Error>>()?)
+    }
+
+    async fn requeue_dead_letter(&self, task_id: TaskKey) -> Result<Task, DeadLetterError> {
+        let id = task_id.0 as i64;
+        let mut tx = self.pool.begin().await?;
+        let row = sqlx::query(
+            "UPDATE tasks SET failed = false, failure_reason = NULL, attempt = 0
+            WHERE id = $1 AND failed RETURNING *",
+        )
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(DeadLetterError::InvalidTaskId(task_id))?;
+        let task = task_from_row(&row, Status::Ready)?;
+        // The task had already been popped, so it has no pending dependency
+        enqueue(&mut tx, id).await?;
+        tx.commit().await?;
+        Ok(task)
+    }
+
+    async fn cancel(&self, task_id: TaskKey) -> Result<(), CancelError> {
+        let id = task_id.0 as i64;
+        let mut tx = self.pool.begin().await?;
+        // Lock the task, so that no new dependent can be pushed concurrently
+        let exists = sqlx::query("SELECT 1 FROM tasks WHERE id = $1 AND NOT failed FOR UPDATE")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .is_some();
+        if !exists {
+            return Err(CancelError::InvalidTaskId(task_id));
+        }
+        sqlx::query("DELETE FROM queue WHERE task = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        let processing = sqlx::query("SELECT 1 FROM processing WHERE task = $1")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .is_some();
+        if processing {
+            return Err(CancelError::Processing(task_id));
+        }
+        let has_dependents = sqlx::query("SELECT 1 FROM edges WHERE dependency = $1 LIMIT 1")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .is_some();
+        if has_dependents {
+            return Err(CancelError::HasDependents(task_id));
+        }
+
+        sqlx::query("DELETE FROM edges WHERE task = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM tasks WHERE id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+    async fn get(&self, task_id: TaskKey) -> Result<Task, GetError> {
+        let row = sqlx::query(&format!(
+            "SELECT *, {} AS status FROM tasks WHERE id = $1",
+            STATUS
+        ))
+        .bind(task_id.0 as i64)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(GetError::InvalidTaskId(task_id))?;
+        let status = row
+            .try_get::<String, _>("status")?
+            .parse()
+            .map_err(|err| sqlx::Error::Decode(Box::new(err)))?;
+        Ok(task_from_row(&row, status)?)
+    }
+
+    async fn list(
+        &self,
+        offset: usize,
+        limit: usize,
+        status: Option<Status>,
+    ) -> Result<(Vec<Task>, usize), ListError> {
+        let status = status.map(|status| status.as_str());
+        let mut tx = self.pool.begin().await?;
+        let rows = sqlx::query(&format!(
+            "SELECT * FROM (SELECT *, {} AS status FROM tasks) AS tasks
+            WHERE $1::text IS NULL OR status = $1 ORDER BY id LIMIT $2 OFFSET $3",
+            STATUS
+        ))
+        .bind(status)
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .fetch_all(&mut *tx)
+        .await?;
+        let total: i64 = sqlx::query_scalar(&format!(
+            "SELECT count(*) FROM (SELECT {} AS status FROM tasks) AS tasks
+            WHERE $1::text IS NULL OR status = $1",
+            STATUS
+        ))
+        .bind(status)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        let tasks = rows
+            .iter()
+            .map(|row| {
+                let status = row
+                    .try_get::<String, _>("status")?
+                    .parse()
+                    .map_err(|err| sqlx::Error::Decode(Box::new(err)))?;
+                task_from_row(row, status)
+            })
+            .collect::<Result<_, sqlx::Error>>()?;
+        Ok((tasks, total as usize))
+    }
+
+    async fn dependents(&self, task_id: TaskKey) -> Result<Vec<TaskKey>, GetError> {
+        let id = task_id.0 as i64;
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("SELECT 1 FROM tasks WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(GetError::InvalidTaskId(task_id))?;
+        let dependents: Vec<i64> =
+            sqlx::query_scalar("SELECT task FROM edges WHERE dependency = $1 ORDER BY task")
+                .bind(id)
+                .fetch_all(&mut *tx)
+                .await?;
+        tx.commit().await?;
+        Ok(dependents
+            .into_iter()
+            .map(|node| TaskKey(node as u64))
+            .collect())
+    }
+
+    fn queue_depth(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    fn backend_name(&self) -> &'static str {
+        "postgres"
+    }
+
+    async fn stats(&self) -> Result<Stats, StatsError> {
+        let row = sqlx::query(
+            "SELECT
+                (SELECT count(*) FROM tasks WHERE NOT failed) AS active,
+                (SELECT count(*) FROM queue JOIN tasks ON tasks.id = queue.task
+                    WHERE tasks.run_at IS NULL OR tasks.run_at <= now()) AS ready,
+                (SELECT count(*) FROM processing) AS processing,
+                (SELECT count(*) FROM tasks WHERE failed) AS dead_lettered,
+                (SELECT CASE WHEN is_called THEN last_value ELSE 0 END FROM completed_tasks) AS completed,
+                (SELECT CASE WHEN is_called THEN last_value + 1 ELSE last_value END FROM task_keys) AS next_key",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        let count = |column| row.try_get::<i64, _>(column).map(|count| count as u64);
+        let (ready, processing) = (count("ready")?, count("processing")?);
+        Ok(Stats(taskie_structures::Stats {
+            // The tasks neither ready nor processing are waiting for some of
+            // their dependencies, or for their time to come
+            pending: count("active")?.saturating_sub(ready + processing),
+            ready,
+            processing,
+            completed: count("completed")?,
+            dead_lettered: count("dead_lettered")?,
+            waiting: 0,
+            progress: None,
+            next_key: TaskKey(count("next_key")?),
+            monitor: None,
+        }))
+    }
+
+    async fn purge(&self) -> Result<(), PurgeError> {
+        // The counters are kept, so that the keys are not given out again
+        let mut tx = self.pool.begin().await?;
+        for table in ["edges", "processing", "queue", "tasks"] {
+            sqlx::query(&format!("DELETE FROM {}", table))
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+}
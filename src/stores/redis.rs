@@ -0,0 +1,942 @@
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration as StdDuration,
+};
+
+use axum::async_trait;
+use futures::StreamExt;
+use redis::{aio::ConnectionManager, AsyncCommands, Client, Script, ScriptInvocation};
+use taskie_structures::{DependencyMode, Status, Uuid, DEFAULT_COST};
+use time::{Duration, OffsetDateTime};
+use tokio::{
+    sync::Notify,
+    time::{interval, timeout},
+};
+
+use crate::metrics::{PROCESSING, QUEUE_DEPTH, TASKS_TIMED_OUT};
+use crate::store::{
+    fail_reason, CancelError, CompleteError, DeadLetterError, Execution, FailError, GetError,
+    HeartbeatError, InsertTask, MonitorError, PopError, PurgeError, PushError, Selector, Stats,
+    StatsError, Store, Task, TaskKey, TIMEOUT_REASON,
+};
+
+/// How often the monitor looks for expired deadlines, and the longest a
+/// blocked `pop` waits before checking the queue again.
+static POLL_INTERVAL: StdDuration = StdDuration::from_secs(1);
+
+/// The counter used to allocate task keys.
+static NEXT_KEY: &str = "taskie:next_key";
+
+/// The channel used to tell every server sharing the database that new tasks
+/// have been put on the queue.
+static READY_CHANNEL: &str = "taskie:ready";
+
+/// The keys passed to every script, in the order they are bound by `PRELUDE`:
+/// - `taskie:tasks`: a hash from task key to its JSON encoding;
+/// - `taskie:queue`: a sorted set of the ready tasks, see `enqueue`;
+/// - `taskie:queued`: a hash from task key to its member in the queue;
+/// - `taskie:sequence`: the counter giving the insertion order in the queue;
+/// - `taskie:processing`: a sorted set of the executing tasks, by deadline;
+/// - `taskie:edges:<key>`: the set of pending dependencies of a task;
+/// - `taskie:dependents:<key>`: the set of tasks depending on a task;
+/// - `taskie:ready`: the `READY_CHANNEL`;
+/// - `taskie:attempts`: a hash from task key to the number of times it was popped;
+/// - `taskie:dead_letter`: a hash from task key to the encoding of a task
+///   which exhausted its retries;
+/// - `taskie:failure_reasons`: a hash from dead-lettered task key to why its
+///   last execution failed;
+/// - `taskie:started_at`: a hash from task key to when its latest execution
+///   started, in milliseconds;
+/// - `taskie:completed`: the counter of the completed tasks;
+/// - `taskie:scheduled`: a sorted set of the tasks whose `run_at` has not come
+///   yet, by `run_at` in milliseconds;
+/// - `taskie:leases`: a hash from processing task key to the lease token
+///   handed out with its execution.
+static KEYS: [&str; 15] = [
+    "taskie:tasks",
+    "taskie:queue",
+    "taskie:queued",
+    "taskie:sequence",
+    "taskie:processing",
+    "taskie:edges:",
+    "taskie:dependents:",
+    "taskie:ready",
+    "taskie:attempts",
+    "taskie:dead_letter",
+    "taskie:failure_reasons",
+    "taskie:started_at",
+    "taskie:completed",
+    "taskie:scheduled",
+    "taskie:leases",
+];
+
+static PRELUDE: &str = r#"
+local tasks, queue, queued, sequence, processing, edges, dependents, ready, attempts,
+    dead_letter, failure_reasons, started_at, completed,
+    scheduled, leases = unpack(KEYS)
+
+-- All the members of the queue have the same score, so they are sorted
+-- lexicographically: first by inverted priority, then by insertion order.
+local function enqueue(id)
+    local task = cjson.decode(redis.call('HGET', tasks, id))
+    local member = string.format('%010d:%020d:%s',
+        2147483647 - (task.priority or 0), redis.call('INCR', sequence), id)
+    redis.call('ZADD', queue, 0, member)
+    redis.call('HSET', queued, id, member)
+    redis.call('PUBLISH', ready, '')
+end
+
+-- Puts a task whose execution ended without completing back on the queue, or
+-- in the dead-letter queue once it exhausted its retries, returning true if so.
+local function retry(id, reason)
+    redis.call('HDEL', leases, id)
+    local task = redis.call('HGET', tasks, id)
+    local attempt = tonumber(redis.call('HGET', attempts, id) or 0)
+    if attempt > (cjson.decode(task).max_retries or 3) then
+        redis.call('HSET', dead_letter, id, task)
+        redis.call('HSET', failure_reasons, id, reason)
+        redis.call('HDEL', tasks, id)
+        return true
+    end
+    enqueue(id)
+    return false
+end
+
+-- Whether the execution of a task can be ended or extended with the lease
+-- token given.
+local function leased(id, token)
+    return redis.call('HGET', leases, id) == token
+end
+"#;
+
+/// Stores a batch of tasks, provided all of their dependencies exist, and
+/// either enqueues each of them or records its pending edges. Every task is
+/// given as its key, its encoding, its `run_at` (in milliseconds, or empty),
+/// the number of its dependencies and then the dependencies themselves. A
+/// task with a `run_at` in the future is scheduled instead of being enqueued.
+/// Returns the first missing dependency, if any, in which case no task is
+/// stored.
+static PUSH_SCRIPT: &str = r#"
+local batch, i = {}, 1
+while i <= #ARGV do
+    local count = tonumber(ARGV[i + 3])
+    local task = {id = ARGV[i], encoded = ARGV[i + 1], run_at = ARGV[i + 2], deps = {}}
+    for j = i + 4, i + 3 + count do
+        if redis.call('HEXISTS', tasks, ARGV[j]) == 0 then
+            return ARGV[j]
+        end
+        table.insert(task.deps, ARGV[j])
+    end
+    table.insert(batch, task)
+    i = i + 4 + count
+end
+for _, task in ipairs(batch) do
+    redis.call('HSET', tasks, task.id, task.encoded)
+    if task.run_at ~= '' then
+        redis.call('ZADD', scheduled, task.run_at, task.id)
+    elseif #task.deps == 0 then
+        enqueue(task.id)
+    end
+    for _, dependency in ipairs(task.deps) do
+        redis.call('SADD', edges .. task.id, dependency)
+        redis.call('SADD', dependents .. dependency, task.id)
+    end
+end
+return false
+"#;
+
+/// Takes the first ready task matching the selector and marks it as
+/// processing until the deadline computed from the current time (ARGV[1], in
+/// milliseconds) and its duration. The selector is given as the JSON object
+/// of the labels which must have a value (ARGV[2]), the JSON array of the
+/// ones which only have to be set (ARGV[3]) and the JSON name of the task, or
+/// null for any (ARGV[4]). The execution is leased with the token ARGV[5].
+/// Returns the task key, its encoding, the deadline and the attempt.
+static POP_SCRIPT: &str = r#"
+local values, names = cjson.decode(ARGV[2]), cjson.decode(ARGV[3])
+local name = cjson.decode(ARGV[4])
+local function matches(task)
+    if name ~= cjson.null and task.name ~= name then
+        return false
+    end
+    local labels = task.labels or {}
+    for name, value in pairs(values) do
+        if labels[name] ~= value then
+            return false
+        end
+    end
+    for _, name in ipairs(names) do
+        if labels[name] == nil then
+            return false
+        end
+    end
+    return true
+end
+
+-- Scan the queue in order, a batch at a time, for the first matching task
+local member, id, task
+local offset = 0
+while not member do
+    local batch = redis.call('ZRANGE', queue, offset, offset + 99)
+    if #batch == 0 then
+        return false
+    end
+    for _, candidate in ipairs(batch) do
+        local candidate_id = string.match(candidate, ':(%d+)$')
+        local candidate_task = redis.call('HGET', tasks, candidate_id)
+        if not candidate_task or matches(cjson.decode(candidate_task)) then
+            member, id, task = candidate, candidate_id, candidate_task
+            break
+        end
+    end
+    offset = offset + #batch
+end
+redis.call('ZREM', queue, member)
+redis.call('HDEL', queued, id)
+if not task then
+    return {id, false, false}
+end
+local deadline = tonumber(ARGV[1]) + cjson.decode(task).duration * 1000
+redis.call('ZADD', processing, deadline, id)
+redis.call('HSET', leases, id, ARGV[5])
+local attempt = redis.call('HINCRBY', attempts, id, 1)
+redis.call('HSET', started_at, id, ARGV[1])
+return {id, task, deadline, attempt}
+"#;
+
+/// Removes a task from the processing set, provided it was leased with the
+/// token ARGV[2], and promotes any dependent without other pending
+/// dependencies to the queue, unless it is still scheduled. Returns one of
+/// the `COMPLETE_*` outcomes along with the list of promoted task keys.
+static COMPLETE_SCRIPT: &str = r#"
+local id = ARGV[1]
+if not redis.call('ZSCORE', processing, id) then
+    return {1, {}}
+end
+if not leased(id, ARGV[2]) then
+    return {2, {}}
+end
+redis.call('ZREM', processing, id)
+redis.call('HDEL', leases, id)
+redis.call('HDEL', tasks, id)
+redis.call('HDEL', attempts, id)
+redis.call('HDEL', started_at, id)
+redis.call('INCR', completed)
+local promoted = {}
+for _, dependent in ipairs(redis.call('SMEMBERS', dependents .. id)) do
+    redis.call('SREM', edges .. dependent, id)
+    if redis.call('SCARD', edges .. dependent) == 0
+        and not redis.call('ZSCORE', scheduled, dependent) then
+        enqueue(dependent)
+        table.insert(promoted, dependent)
+    end
+end
+redis.call('DEL', dependents .. id)
+return {0, promoted}
+"#;
+const COMPLETE_MISSING: i64 = 1;
+const COMPLETE_LEASE_MISMATCH: i64 = 2;
+
+/// Moves all the tasks whose deadline (in milliseconds) is before ARGV[1]
+/// back on the queue, or in the dead-letter queue once they exhausted their
+/// retries. Also enqueues the scheduled tasks whose time has come, if they
+/// have no pending dependencies. Returns the keys of the expired tasks and of
+/// the failed ones.
+static REQUEUE_SCRIPT: &str = r#"
+for _, id in ipairs(redis.call('ZRANGEBYSCORE', scheduled, '-inf', ARGV[1])) do
+    redis.call('ZREM', scheduled, id)
+    if redis.call('SCARD', edges .. id) == 0 then
+        enqueue(id)
+    end
+end
+local expired = redis.call('ZRANGEBYSCORE', processing, '-inf', ARGV[1])
+local exhausted = {}
+for _, id in ipairs(expired) do
+    redis.call('ZREM', processing, id)
+    if retry(id, ARGV[2]) then
+        table.insert(exhausted, id)
+    end
+end
+return {expired, exhausted}
+"#;
+
+/// Ends the execution of a task being processed as if it timed out, provided
+/// it was leased with the token ARGV[3]. Returns one of the `FAIL_*`
+/// outcomes.
+static FAIL_SCRIPT: &str = r#"
+local id = ARGV[1]
+if not redis.call('ZSCORE', processing, id) then
+    return 1
+end
+if not leased(id, ARGV[3]) then
+    return 3
+end
+redis.call('ZREM', processing, id)
+if retry(id, ARGV[2]) then
+    return 2
+end
+return 0
+"#;
+const FAIL_MISSING: i64 = 1;
+const FAIL_EXHAUSTED: i64 = 2;
+const FAIL_LEASE_MISMATCH: i64 = 3;
+
+/// Moves the deadline of a task being processed to ARGV[2] (in milliseconds),
+/// provided it was leased with the token ARGV[3]. Returns one of the
+/// `HEARTBEAT_*` outcomes.
+static HEARTBEAT_SCRIPT: &str = r#"
+local id = ARGV[1]
+if not redis.call('ZSCORE', processing, id) then
+    return 1
+end
+if not leased(id, ARGV[3]) then
+    return 2
+end
+redis.call('ZADD', processing, ARGV[2], id)
+return 0
+"#;
+const HEARTBEAT_MISSING: i64 = 1;
+const HEARTBEAT_LEASE_MISMATCH: i64 = 2;
+
+/// Lists the dead-lettered tasks, as their encoding, failure reason, attempt
+/// and start of their last execution.
+static DEAD_LETTERS_SCRIPT: &str = r#"
+local result = {}
+for _, id in ipairs(redis.call('HKEYS', dead_letter)) do
+    table.insert(result, {
+        redis.call('HGET', dead_letter, id),
+        redis.call('HGET', failure_reasons, id) or '',
+        tonumber(redis.call('HGET', attempts, id) or 0),
+        tonumber(redis.call('HGET', started_at, id) or false),
+    })
+end
+return result
+"#;
+
+/// Moves a dead-lettered task back on the queue, resetting its attempts.
+/// Returns its encoding, or nil if it was not dead-lettered.
+static REQUEUE_DEAD_LETTER_SCRIPT: &str = r#"
+local id = ARGV[1]
+local task = redis.call('HGET', dead_letter, id)
+if not task then
+    return false
+end
+redis.call('HDEL', dead_letter, id)
+redis.call('HDEL', failure_reasons, id)
+redis.call('HDEL', attempts, id)
+redis.call('HSET', tasks, id, task)
+enqueue(id)
+return task
+"#;
+
+/// Removes a task which is neither processing nor depended upon, along with
+/// its pending edges. Returns one of the `CANCEL_*` outcomes.
+static CANCEL_SCRIPT: &str = r#"
+local id = ARGV[1]
+if redis.call('HEXISTS', tasks, id) == 0 then
+    return 1
+end
+if redis.call('ZSCORE', processing, id) then
+    return 2
+end
+if redis.call('SCARD', dependents .. id) > 0 then
+    return 3
+end
+redis.call('HDEL', tasks, id)
+redis.call('HDEL', attempts, id)
+redis.call('HDEL', started_at, id)
+redis.call('ZREM', scheduled, id)
+local member = redis.call('HGET', queued, id)
+if member then
+    redis.call('ZREM', queue, member)
+    redis.call('HDEL', queued, id)
+end
+for _, dependency in ipairs(redis.call('SMEMBERS', edges .. id)) do
+    redis.call('SREM', dependents .. dependency, id)
+end
+redis.call('DEL', edges .. id)
+return 0
+"#;
+/// Looks up a task, either stored or dead-lettered. Returns its encoding,
+/// attempt, status and start of its latest execution, or nil if it does not
+/// exist.
+static GET_SCRIPT: &str = r#"
+local id = ARGV[1]
+local task = redis.call('HGET', tasks, id)
+local status = 'pending'
+if not task then
+    task = redis.call('HGET', dead_letter, id)
+    if not task then
+        return false
+    end
+    status = 'failed'
+elseif redis.call('ZSCORE', processing, id) then
+    status = 'processing'
+elseif redis.call('HEXISTS', queued, id) == 1 then
+    status = 'ready'
+end
+return {
+    task,
+    tonumber(redis.call('HGET', attempts, id) or 0),
+    status,
+    tonumber(redis.call('HGET', started_at, id) or false),
+}
+"#;
+
+/// Removes every task, along with its edges, whatever its state. The
+/// counters are kept, so that the keys are not given out again.
+static PURGE_SCRIPT: &str = r#"
+for _, hash in ipairs({tasks, dead_letter}) do
+    for _, id in ipairs(redis.call('HKEYS', hash)) do
+        redis.call('DEL', edges .. id, dependents .. id)
+    end
+end
+redis.call('DEL', tasks, queue, queued, processing, attempts, dead_letter, failure_reasons,
+    started_at, scheduled, leases)
+return true
+"#;
+
+const CANCEL_MISSING: i64 = 1;
+const CANCEL_PROCESSING: i64 = 2;
+const CANCEL_HAS_DEPENDENTS: i64 = 3;
+
+/// The outcome of `POP_SCRIPT` for a popped task: its key, encoding, deadline
+/// and attempt, where all but the key are missing if the task does not exist.
+type Popped = (u64, Option<String>, Option<i64>, Option<u32>);
+
+/// A store shared between any number of taskie instances. Every step which
+/// touches more than one key runs as a Lua script, so that it is atomic.
+pub struct RedisStore {
+    client: Client,
+    connection: ConnectionManager,
+    ready: Notify,
+    /// Notified by `shutdown` to stop the monitor
+    stop: Notify,
+    /// The tasks on the queue when the gauges were last recorded
+    queued: AtomicUsize,
+    push_script: Script,
+    pop_script: Script,
+    complete_script: Script,
+    requeue_script: Script,
+    fail_script: Script,
+    heartbeat_script: Script,
+    dead_letters_script: Script,
+    requeue_dead_letter_script: Script,
+    cancel_script: Script,
+    get_script: Script,
+    purge_script: Script,
+}
+
+fn script(source: &str) -> Script {
+    Script::new(&format!("{}{}", PRELUDE, source))
+}
+
+fn prepare(script: &Script) -> ScriptInvocation<'_> {
+    let mut invocation = script.prepare_invoke();
+    for key in KEYS.iter() {
+        invocation.key(*key);
+    }
+    invocation
+}
+
+fn timestamp(time: OffsetDateTime) -> i64 {
+    (time.unix_timestamp_nanos() / 1_000_000) as i64
+}
+
+fn encoding_error(err: serde_json::Error) -> redis::RedisError {
+    redis::RedisError::from((
+        redis::ErrorKind::TypeError,
+        "Invalid task encoding",
+        err.to_string(),
+    ))
+}
+
+fn encode_task(task: &Task) -> Result<String, redis::RedisError> {
+    let Task(task) = task;
+    serde_json::to_string(&taskie_structures::Task {
+        id: task.id.0,
+        name: task.name.to_owned(),
+        payload: task.payload.to_owned(),
+        depends_on: task.depends_on.iter().map(|k| k.0).collect(),
+        duration: task.duration,
+        priority: task.priority,
+        max_retries: task.max_retries,
+        cost: DEFAULT_COST,
+        labels: task.labels.to_owned(),
+        tenant: None,
+        attempt: 0,
+        status: Status::Pending,
+        created_at: task.created_at,
+        started_at: None,
+        run_at: task.run_at,
+        expires_at: None,
+        progress: None,
+        schedule: None,
+        callback_url: None,
+        dependency_mode: DependencyMode::All,
+        traceparent: task.traceparent.to_owned(),
+    })
+    .map_err(encoding_error)
+}
+
+fn from_timestamp(timestamp: i64) -> Option<OffsetDateTime> {
+    OffsetDateTime::from_unix_timestamp_nanos(timestamp as i128 * 1_000_000).ok()
+}
+
+/// Decodes a task stored by `encode_task`; the attempt and the start of the
+/// latest execution are kept in their own hashes and the status is given by
+/// the keys holding the task, so that the scripts never need to re-encode a
+/// task.
+fn decode_task(
+    task: &str,
+    attempt: u32,
+    status: Status,
+    started_at: Option<i64>,
+) -> Result<Task, redis::RedisError> {
+    let task: taskie_structures::Task<taskie_structures::TaskName, u64> =
+        serde_json::from_str(task).map_err(encoding_error)?;
+    Ok(Task(taskie_structures::Task {
+        id: TaskKey(task.id),
+        name: task.name,
+        payload: task.payload,
+        depends_on: task.depends_on.into_iter().map(TaskKey).collect(),
+        duration: task.duration,
+        priority: task.priority,
+        max_retries: task.max_retries,
+        cost: DEFAULT_COST,
+        labels: task.labels,
+        tenant: None,
+        attempt,
+        status,
+        created_at: task.created_at,
+        started_at: started_at.and_then(from_timestamp),
+        run_at: task.run_at,
+        expires_at: None,
+        progress: None,
+        schedule: None,
+        callback_url: None,
+        dependency_mode: DependencyMode::All,
+        traceparent: task.traceparent,
+    }))
+}
+
+impl RedisStore {
+    /// Connects to the Redis server at `url`, and puts back on the queue any
+    /// task whose deadline expired while no server was running.
+    pub async fn connect(url: &str) -> Result<Self, redis::RedisError> {
+        let client = Client::open(url)?;
+        let connection = ConnectionManager::new(client.clone()).await?;
+        let store = RedisStore {
+            client,
+            connection,
+            ready: Notify::new(),
+            stop: Notify::new(),
+            queued: AtomicUsize::new(0),
+            push_script: script(PUSH_SCRIPT),
+            pop_script: script(POP_SCRIPT),
+            complete_script: script(COMPLETE_SCRIPT),
+            requeue_script: script(REQUEUE_SCRIPT),
+            fail_script: script(FAIL_SCRIPT),
+            heartbeat_script: script(HEARTBEAT_SCRIPT),
+            dead_letters_script: script(DEAD_LETTERS_SCRIPT),
+            requeue_dead_letter_script: script(REQUEUE_DEAD_LETTER_SCRIPT),
+            cancel_script: script(CANCEL_SCRIPT),
+            get_script: script(GET_SCRIPT),
+            purge_script: script(PURGE_SCRIPT),
+        };
+        store.requeue_expired().await?;
+        Ok(store)
+    }
+
+    /// Moves all the tasks in `processing` whose deadline has passed back on
+    /// the queue, just like a `TimedOut` message does for the `MemoryStore`.
+    async fn requeue_expired(&self) -> Result<(), redis::RedisError> {
+        let (expired, exhausted): (Vec<u64>, Vec<u64>) = prepare(&self.requeue_script)
+            .arg(timestamp(OffsetDateTime::now_utc()))
+            .arg(TIMEOUT_REASON)
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        for id in expired.into_iter() {
+            tracing::info!(id = %TaskKey(id), "Task execution timed out");
+            metrics::increment_counter!(TASKS_TIMED_OUT);
+        }
+        for id in exhausted.into_iter() {
+            tracing::warn!(id = %TaskKey(id), reason = %TIMEOUT_REASON, "Task exhausted its retries, moving it to the dead-letter queue");
+        }
+        Ok(())
+    }
+
+    /// Updates the gauges with the size of the queue and of the processing set.
+    async fn record_gauges(&self) -> Result<(), redis::RedisError> {
+        let (queued, processing): (u64, u64) = redis::pipe()
+            .zcard(KEYS[1])
+            .zcard(KEYS[4])
+            .query_async(&mut self.connection.clone())
+            .await?;
+        metrics::gauge!(QUEUE_DEPTH, queued as f64);
+        self.queued.store(queued as usize, Ordering::Relaxed);
+        metrics::gauge!(PROCESSING, processing as f64);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Store for RedisStore {
+    async fn monitor(&self) -> Result<(), MonitorError> {
+        let mut ticker = interval(POLL_INTERVAL);
+        loop {
+            let mut pubsub = self.client.get_async_connection().await?.into_pubsub();
+            pubsub.subscribe(READY_CHANNEL).await?;
+            let mut messages = pubsub.on_message();
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {
+                        self.requeue_expired().await?;
+                        self.record_gauges().await?;
+                    }
+                    message = messages.next() => match message {
+                        Some(_) => self.ready.notify_waiters(),
+                        None => break,
+                    },
+                    _ = self.stop.notified() => return Ok(()),
+                }
+            }
+            tracing::warn!("Lost the connection listening for ready tasks, reconnecting");
+        }
+    }
+
+    async fn push(&self, insert_tasks: Vec<InsertTask>) -> Result<Vec<Task>, PushError> {
+        for insert_task in insert_tasks.iter() {
+            insert_task.validate()?;
+        }
+        if insert_tasks.iter().any(|task| task.0.schedule.is_some()) {
+            return Err(PushError::UnsupportedSchedule);
+        }
+        if insert_tasks
+            .iter()
+            .any(|task| task.0.idempotency_key.is_some())
+        {
+            return Err(PushError::UnsupportedIdempotencyKey);
+        }
+        if insert_tasks
+            .iter()
+            .any(|task| task.0.callback_url.is_some())
+        {
+            return Err(PushError::UnsupportedCallback);
+        }
+        if insert_tasks
+            .iter()
+            .any(|task| task.0.dependency_mode != DependencyMode::All)
+        {
+            return Err(PushError::UnsupportedDependencyMode);
+        }
+        if insert_tasks.iter().any(|task| task.0.tenant.is_some()) {
+            return Err(PushError::UnsupportedTenant);
+        }
+        if insert_tasks.iter().any(|task| task.0.dedupe) {
+            return Err(PushError::UnsupportedDedupe);
+        }
+        if insert_tasks.iter().any(|task| task.0.cost != DEFAULT_COST) {
+            return Err(PushError::UnsupportedCost);
+        }
+        if insert_tasks.iter().any(|task| task.0.expires_at.is_some()) {
+            return Err(PushError::UnsupportedExpiry);
+        }
+        if insert_tasks
+            .iter()
+            .any(|task| !task.0.depends_on_batch.is_empty())
+        {
+            return Err(PushError::UnsupportedBatchDependency);
+        }
+        let mut connection = self.connection.clone();
+        // The keys of the whole batch are reserved at once
+        let last: u64 = connection.incr(NEXT_KEY, insert_tasks.len()).await?;
+        let first = last + 1 - insert_tasks.len() as u64;
+        let mut invocation = prepare(&self.push_script);
+        let mut result = Vec::with_capacity(insert_tasks.len());
+        for (id, insert_task) in (first..).zip(insert_tasks) {
+            let InsertTask(insert_task) = insert_task;
+            if insert_task.depends_on.contains(&TaskKey(id)) {
+                return Err(PushError::SelfDependency(TaskKey(id)));
+            }
+
+            let now = OffsetDateTime::now_utc();
+            let run_at = insert_task.run_at.filter(|run_at| *run_at > now);
+            let task = Task(taskie_structures::Task {
+                id: TaskKey(id),
+                payload: insert_task.payload,
+                name: insert_task.name,
+                duration: insert_task.duration,
+                priority: insert_task.priority,
+                max_retries: insert_task.max_retries,
+                cost: DEFAULT_COST,
+                labels: insert_task.labels,
+                tenant: None,
+                attempt: 0,
+                status: if insert_task.depends_on.is_empty() && run_at.is_none() {
+                    Status::Ready
+                } else {
+                    Status::Pending
+                },
+                created_at: now,
+                started_at: None,
+                run_at: insert_task.run_at,
+                expires_at: None,
+                progress: None,
+                schedule: None,
+                callback_url: None,
+                dependency_mode: DependencyMode::All,
+                traceparent: insert_task.traceparent,
+                depends_on: insert_task.depends_on,
+            });
+            invocation
+                .arg(id)
+                .arg(encode_task(&task)?)
+                .arg(run_at.map_or(String::new(), |run_at| timestamp(run_at).to_string()))
+                .arg(task.0.depends_on.len());
+            for dependency in task.0.depends_on.iter() {
+                invocation.arg(dependency.0);
+            }
+            result.push(task);
+        }
+        let missing: Option<u64> = invocation.invoke_async(&mut connection).await?;
+        if let Some(dependency) = missing {
+            return Err(PushError::MissingDependency {
+                dependency: TaskKey(dependency),
+            });
+        }
+        Ok(result)
+    }
+
+    async fn pop(&self, selector: &Selector) -> Result<Execution, PopError> {
+        loop {
+            // Subscribe before checking the queue, so that no push can slip
+            // in between the check and the wait.
+            let notified = self.ready.notified();
+            if let Some(execution) = self.try_pop(selector).await? {
+                return Ok(execution);
+            }
+            let _ = timeout(POLL_INTERVAL, notified).await;
+        }
+    }
+
+    async fn try_pop(&self, selector: &Selector) -> Result<Option<Execution>, PopError> {
+        let (values, names) = selector.split();
+        let now = timestamp(OffsetDateTime::now_utc());
+        let lease_token = Uuid::new_v4();
+        let popped: Option<Popped> = prepare(&self.pop_script)
+            .arg(now)
+            .arg(serde_json::to_string(&values).map_err(encoding_error)?)
+            .arg(serde_json::to_string(&names).map_err(encoding_error)?)
+            .arg(serde_json::to_string(&selector.name).map_err(encoding_error)?)
+            .arg(lease_token.to_string())
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        let Some((id, task, deadline, attempt)) = popped else {
+            return Ok(None);
+        };
+        let (Some(task), Some(deadline), Some(attempt)) = (task, deadline, attempt) else {
+            return Err(PopError::InvalidTaskId(TaskKey(id)));
+        };
+
+        let deadline = from_timestamp(deadline).ok_or(PopError::InvalidTaskId(TaskKey(id)))?;
+        let task = decode_task(&task, attempt, Status::Processing, Some(now))?;
+        Ok(Some(Execution(taskie_structures::Execution {
+            remaining: task.0.duration,
+            task,
+            deadline,
+            lease_token,
+        })))
+    }
+
+    // The results are not retained, so `dependency_results` is unsupported
+    async fn complete(
+        &self,
+        task_id: TaskKey,
+        lease_token: Uuid,
+        _result: Option<serde_json::Value>,
+    ) -> Result<(), CompleteError> {
+        let (outcome, ready): (i64, Vec<u64>) = prepare(&self.complete_script)
+            .arg(task_id.0)
+            .arg(lease_token.to_string())
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        match outcome {
+            COMPLETE_MISSING => return Err(CompleteError::InvalidTaskId(task_id)),
+            COMPLETE_LEASE_MISMATCH => return Err(CompleteError::LeaseMismatch(task_id)),
+            _ => {}
+        }
+        for node in ready.into_iter() {
+            tracing::debug!(id = %TaskKey(node), "Task has become ready");
+        }
+        Ok(())
+    }
+
+    async fn fail(
+        &self,
+        task_id: TaskKey,
+        lease_token: Uuid,
+        reason: Option<String>,
+    ) -> Result<(), FailError> {
+        let reason = fail_reason(reason);
+        let outcome: i64 = prepare(&self.fail_script)
+            .arg(task_id.0)
+            .arg(&reason)
+            .arg(lease_token.to_string())
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        match outcome {
+            FAIL_MISSING => Err(FailError::InvalidTaskId(task_id)),
+            FAIL_LEASE_MISMATCH => Err(FailError::LeaseMismatch(task_id)),
+            FAIL_EXHAUSTED => {
+                tracing::warn!(id = %task_id, %reason, "Task exhausted its retries, moving it to the dead-letter queue");
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn heartbeat(
+        &self,
+        task_id: TaskKey,
+        lease_token: Uuid,
+        extend: Duration,
+    ) -> Result<OffsetDateTime, HeartbeatError> {
+        let deadline = OffsetDateTime::now_utc() + extend;
+        let outcome: i64 = prepare(&self.heartbeat_script)
+            .arg(task_id.0)
+            .arg(timestamp(deadline))
+            .arg(lease_token.to_string())
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        match outcome {
+            HEARTBEAT_MISSING => Err(HeartbeatError::InvalidTaskId(task_id)),
+            HEARTBEAT_LEASE_MISMATCH => Err(HeartbeatError::LeaseMismatch(task_id)),
+            _ => Ok(deadline),
+        }
+    }
+
+    async fn health(&self) -> bool {
+        redis::cmd("PING")
+            .query_async::<_, ()>(&mut self.connection.clone())
+            .await
+            .is_ok()
+    }
+
+    async fn shutdown(&self) {
+        self.stop.notify_one();
+    }
+
+    async fn dead_letters(&self) -> Result<Vec<(Task, String)>, DeadLetterError> {
+        let dead_letters: Vec<(String, String, u32, Option<i64>)> =
+            prepare(&self.dead_letters_script)
+                .invoke_async(&mut self.connection.clone())
+                .await?;
+        Ok(dead_letters
+            .into_iter()
+            .map(|(task, reason, attempt, started_at)| {
+                Ok((
+                    decode_task(&task, attempt, Status::Failed, started_at)?,
+                    reason,
+                ))
+            })
+            .collect::<Result<Vec<_>, redis::RedisError>>()?)
+    }
+
+    async fn requeue_dead_letter(&self, task_id: TaskKey) -> Result<Task, DeadLetterError> {
+        let task: Option<String> = prepare(&self.requeue_dead_letter_script)
+            .arg(task_id.0)
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        let task = task.ok_or(DeadLetterError::InvalidTaskId(task_id))?;
+        Ok(decode_task(&task, 0, Status::Ready, None)?)
+    }
+
+    async fn cancel(&self, task_id: TaskKey) -> Result<(), CancelError> {
+        let outcome: i64 = prepare(&self.cancel_script)
+            .arg(task_id.0)
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        match outcome {
+            CANCEL_MISSING => Err(CancelError::InvalidTaskId(task_id)),
+            CANCEL_PROCESSING => Err(CancelError::Processing(task_id)),
+            CANCEL_HAS_DEPENDENTS => Err(CancelError::HasDependents(task_id)),
+            _ => Ok(()),
+        }
+    }
+    async fn get(&self, task_id: TaskKey) -> Result<Task, GetError> {
+        let found: Option<(String, u32, String, Option<i64>)> = prepare(&self.get_script)
+            .arg(task_id.0)
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        let (task, attempt, status, started_at) = found.ok_or(GetError::InvalidTaskId(task_id))?;
+        let status = status.parse().map_err(|_| {
+            redis::RedisError::from((redis::ErrorKind::TypeError, "Invalid task status", status))
+        })?;
+        Ok(decode_task(&task, attempt, status, started_at)?)
+    }
+
+    async fn dependents(&self, task_id: TaskKey) -> Result<Vec<TaskKey>, GetError> {
+        let (active, dead_lettered, mut dependents): (bool, bool, Vec<u64>) = redis::pipe()
+            .atomic()
+            .hexists(KEYS[0], task_id.0)
+            .hexists(KEYS[9], task_id.0)
+            .smembers(format!("{}{}", KEYS[6], task_id.0))
+            .query_async(&mut self.connection.clone())
+            .await?;
+        if !active && !dead_lettered {
+            return Err(GetError::InvalidTaskId(task_id));
+        }
+        dependents.sort_unstable();
+        Ok(dependents.into_iter().map(TaskKey).collect())
+    }
+
+    fn queue_depth(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    fn backend_name(&self) -> &'static str {
+        "redis"
+    }
+
+    async fn stats(&self) -> Result<Stats, StatsError> {
+        let (active, ready, processing, dead_lettered, completed, next_key): (
+            u64,
+            u64,
+            u64,
+            u64,
+            Option<u64>,
+            Option<u64>,
+        ) = redis::pipe()
+            .atomic()
+            .hlen(KEYS[0])
+            .zcard(KEYS[1])
+            .zcard(KEYS[4])
+            .hlen(KEYS[9])
+            .get(KEYS[12])
+            .get(NEXT_KEY)
+            .query_async(&mut self.connection.clone())
+            .await?;
+        Ok(Stats(taskie_structures::Stats {
+            // The tasks neither ready nor processing are waiting for some of
+            // their dependencies, or for their time to come
+            pending: active.saturating_sub(ready + processing),
+            ready,
+            processing,
+            completed: completed.unwrap_or(0),
+            dead_lettered,
+            waiting: 0,
+            progress: None,
+            // `NEXT_KEY` holds the last key given out
+            next_key: TaskKey(next_key.unwrap_or(0) + 1),
+            monitor: None,
+        }))
+    }
+
+    async fn purge(&self) -> Result<(), PurgeError> {
+        let _: bool = prepare(&self.purge_script)
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,813 @@
+use std::{
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration as StdDuration,
+};
+
+use axum::async_trait;
+use serde_json::Value;
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions, SqliteRow},
+    types::Json,
+    Row, Sqlite, Transaction,
+};
+use taskie_structures::{DependencyMode, Status, Uuid, DEFAULT_COST};
+use time::{Duration, OffsetDateTime};
+use tokio::{
+    sync::Notify,
+    time::{interval, timeout},
+};
+
+use crate::metrics::{PROCESSING, QUEUE_DEPTH, TASKS_TIMED_OUT};
+use crate::store::{
+    fail_reason, leased, CancelError, CompleteAndPushError, CompleteError, DeadLetterError,
+    Execution, FailError, GetError, HeartbeatError, InsertTask, ListError, MonitorError, PeekError,
+    PopError, PurgeError, PushError, RequeueError, Selector, Stats, StatsError, Store, Task,
+    TaskKey, TIMEOUT_REASON,
+};
+
+/// How often the monitor looks for expired deadlines, and the longest a
+/// blocked `pop` waits before checking the queue again.
+static POLL_INTERVAL: StdDuration = StdDuration::from_secs(1);
+
+/// Parses the lease token recorded in `processing`, missing for the tasks
+/// popped before the tokens were handed out, which nothing matches.
+fn lease(recorded: Option<String>) -> Option<Uuid> {
+    recorded.and_then(|recorded| recorded.parse().ok())
+}
+
+pub struct SqliteStore {
+    pool: SqlitePool,
+    ready: Notify,
+    /// Notified by `shutdown` to stop the monitor
+    stop: Notify,
+    /// The tasks on the queue when the gauges were last recorded
+    queued: AtomicUsize,
+}
+
+fn timestamp(time: OffsetDateTime) -> i64 {
+    (time.unix_timestamp_nanos() / 1_000_000) as i64
+}
+
+fn from_timestamp(timestamp: i64) -> Result<OffsetDateTime, sqlx::Error> {
+    OffsetDateTime::from_unix_timestamp_nanos(timestamp as i128 * 1_000_000)
+        .map_err(|err| sqlx::Error::Decode(Box::new(err)))
+}
+
+/// The status of a row of `tasks`, which is not stored but derived from the
+/// table holding the task. Queued tasks whose `run_at` has not come yet (in
+/// unix milliseconds, computed from the julian day) are still pending.
+static STATUS: &str = "CASE
+    WHEN failed THEN 'failed'
+    WHEN EXISTS (SELECT 1 FROM processing WHERE task = tasks.id) THEN 'processing'
+    WHEN EXISTS (SELECT 1 FROM queue WHERE task = tasks.id)
+        AND coalesce(tasks.run_at, 0) <= CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)
+        THEN 'ready'
+    ELSE 'pending'
+END";
+
+fn task_from_row(row: &SqliteRow, status: Status) -> Result<Task, sqlx::Error> {
+    let Json(depends_on): Json<Vec<u64>> = row.try_get("depends_on")?;
+    let payload: Option<Json<Value>> = row.try_get("payload")?;
+    Ok(Task(taskie_structures::Task {
+        id: TaskKey(row.try_get::<i64, _>("id")? as u64),
+        name: row.try_get("name")?,
+        payload: payload.map(|Json(payload)| payload),
+        depends_on: depends_on.into_iter().map(TaskKey).collect(),
+        duration: Duration::seconds(row.try_get("duration")?),
+        priority: row.try_get("priority")?,
+        max_retries: row.try_get::<i64, _>("max_retries")? as u32,
+        cost: DEFAULT_COST,
+        labels: row.try_get::<Json<_>, _>("labels")?.0,
+        tenant: None,
+        attempt: row.try_get::<i64, _>("attempt")? as u32,
+        status,
+        created_at: from_timestamp(row.try_get("created_at")?)?,
+        started_at: row
+            .try_get::<Option<i64>, _>("started_at")?
+            .map(from_timestamp)
+            .transpose()?,
+        run_at: row
+            .try_get::<Option<i64>, _>("run_at")?
+            .map(from_timestamp)
+            .transpose()?,
+        expires_at: None,
+        progress: None,
+        schedule: None,
+        callback_url: None,
+        dependency_mode: DependencyMode::All,
+        traceparent: row.try_get("traceparent")?,
+    }))
+}
+
+async fn enqueue(tx: &mut Transaction<'_, Sqlite>, id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO queue (task, priority) SELECT id, priority FROM tasks WHERE id = ?")
+        .bind(id)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+/// Puts a task whose execution ended without completing back on the queue,
+/// or in the dead-letter queue once it exhausted its retries.
+async fn retry(tx: &mut Transaction<'_, Sqlite>, id: i64, reason: &str) -> Result<(), sqlx::Error> {
+    let failed = sqlx::query(
+        "UPDATE tasks SET failed = 1, failure_reason = ? WHERE id = ? AND attempt > max_retries",
+    )
+    .bind(reason)
+    .bind(id)
+    .execute(&mut **tx)
+    .await?
+    .rows_affected()
+        > 0;
+    if failed {
+        tracing::warn!(id = %TaskKey(id as u64), %reason, "Task exhausted its retries, moving it to the dead-letter queue");
+        Ok(())
+    } else {
+        enqueue(tx, id).await
+    }
+}
+
+/// Rejects the tasks using the features the store does not support.
+fn check_supported(insert_tasks: &[InsertTask]) -> Result<(), PushError> {
+    for insert_task in insert_tasks.iter() {
+        insert_task.validate()?;
+    }
+    if insert_tasks.iter().any(|task| task.0.schedule.is_some()) {
+        return Err(PushError::UnsupportedSchedule);
+    }
+    if insert_tasks
+        .iter()
+        .any(|task| task.0.idempotency_key.is_some())
+    {
+        return Err(PushError::UnsupportedIdempotencyKey);
+    }
+    if insert_tasks
+        .iter()
+        .any(|task| task.0.callback_url.is_some())
+    {
+        return Err(PushError::UnsupportedCallback);
+    }
+    if insert_tasks
+        .iter()
+        .any(|task| task.0.dependency_mode != DependencyMode::All)
+    {
+        return Err(PushError::UnsupportedDependencyMode);
+    }
+    if insert_tasks.iter().any(|task| task.0.tenant.is_some()) {
+        return Err(PushError::UnsupportedTenant);
+    }
+    if insert_tasks.iter().any(|task| task.0.dedupe) {
+        return Err(PushError::UnsupportedDedupe);
+    }
+    if insert_tasks.iter().any(|task| task.0.cost != DEFAULT_COST) {
+        return Err(PushError::UnsupportedCost);
+    }
+    if insert_tasks.iter().any(|task| task.0.expires_at.is_some()) {
+        return Err(PushError::UnsupportedExpiry);
+    }
+    if insert_tasks
+        .iter()
+        .any(|task| !task.0.depends_on_batch.is_empty())
+    {
+        return Err(PushError::UnsupportedBatchDependency);
+    }
+    Ok(())
+}
+
+/// Stores the tasks, putting those without dependencies on the queue.
+async fn insert(
+    tx: &mut Transaction<'_, Sqlite>,
+    insert_tasks: Vec<InsertTask>,
+) -> Result<Vec<Task>, PushError> {
+    let mut result = Vec::with_capacity(insert_tasks.len());
+    for insert_task in insert_tasks.into_iter() {
+        let InsertTask(insert_task) = insert_task;
+        let id: i64 = sqlx::query_scalar(
+            "UPDATE counters SET value = value + 1 WHERE name = 'next_key' RETURNING value - 1",
+        )
+        .fetch_one(&mut **tx)
+        .await?;
+        if insert_task.depends_on.contains(&TaskKey(id as u64)) {
+            return Err(PushError::SelfDependency(TaskKey(id as u64)));
+        }
+
+        for dependency in insert_task.depends_on.iter() {
+            let exists = sqlx::query("SELECT 1 FROM tasks WHERE id = ? AND NOT failed")
+                .bind(dependency.0 as i64)
+                .fetch_optional(&mut **tx)
+                .await?
+                .is_some();
+            if !exists {
+                return Err(PushError::MissingDependency {
+                    dependency: *dependency,
+                });
+            }
+        }
+
+        // Scheduled tasks are queued all the same, but not popped before
+        // their time comes
+        let now = OffsetDateTime::now_utc();
+        let scheduled = insert_task.run_at.is_some_and(|run_at| run_at > now);
+        let task = Task(taskie_structures::Task {
+            id: TaskKey(id as u64),
+            payload: insert_task.payload,
+            name: insert_task.name,
+            duration: insert_task.duration,
+            priority: insert_task.priority,
+            max_retries: insert_task.max_retries,
+            cost: DEFAULT_COST,
+            labels: insert_task.labels,
+            tenant: None,
+            attempt: 0,
+            status: if insert_task.depends_on.is_empty() && !scheduled {
+                Status::Ready
+            } else {
+                Status::Pending
+            },
+            created_at: now,
+            started_at: None,
+            run_at: insert_task.run_at,
+            expires_at: None,
+            progress: None,
+            schedule: None,
+            callback_url: None,
+            dependency_mode: DependencyMode::All,
+            traceparent: insert_task.traceparent,
+            depends_on: insert_task.depends_on,
+        });
+        sqlx::query(
+            "INSERT INTO tasks (id, name, payload, depends_on, duration, priority, max_retries, created_at, labels, run_at, traceparent) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(id)
+        .bind(&task.0.name)
+        .bind(task.0.payload.as_ref().map(Json))
+        .bind(Json(task.0.depends_on.iter().map(|k| k.0).collect::<Vec<_>>()))
+        .bind(task.0.duration.whole_seconds())
+        .bind(task.0.priority)
+        .bind(task.0.max_retries as i64)
+        .bind(timestamp(task.0.created_at))
+        .bind(Json(&task.0.labels))
+        .bind(task.0.run_at.map(timestamp))
+        .bind(&task.0.traceparent)
+        .execute(&mut **tx)
+        .await?;
+
+        if task.0.depends_on.is_empty() {
+            // if the task doesn't have any dependencies, we can just enqueue
+            // it, ready to be consumed by workers
+            enqueue(tx, id).await?;
+        } else {
+            for dependency in task.0.depends_on.iter() {
+                sqlx::query("INSERT OR IGNORE INTO edges (task, dependency) VALUES (?, ?)")
+                    .bind(id)
+                    .bind(dependency.0 as i64)
+                    .execute(&mut **tx)
+                    .await?;
+            }
+        }
+        result.push(task);
+    }
+    Ok(result)
+}
+
+/// Completes a task being processed, putting its dependents without other
+/// pending dependencies on the queue.
+async fn complete(
+    tx: &mut Transaction<'_, Sqlite>,
+    task_id: TaskKey,
+    lease_token: Uuid,
+) -> Result<(), CompleteError> {
+    let id = task_id.0 as i64;
+    let removed: Option<Option<String>> =
+        sqlx::query_scalar("DELETE FROM processing WHERE task = ? RETURNING lease_token")
+            .bind(id)
+            .fetch_optional(&mut **tx)
+            .await?;
+    let Some(recorded) = removed else {
+        return Err(CompleteError::InvalidTaskId(task_id));
+    };
+    // The transaction is rolled back when dropped, putting the task back
+    if !leased(lease(recorded), lease_token) {
+        return Err(CompleteError::LeaseMismatch(task_id));
+    }
+
+    let dependents: Vec<i64> =
+        sqlx::query_scalar("DELETE FROM edges WHERE dependency = ? RETURNING task")
+            .bind(id)
+            .fetch_all(&mut **tx)
+            .await?;
+    sqlx::query("DELETE FROM tasks WHERE id = ?")
+        .bind(id)
+        .execute(&mut **tx)
+        .await?;
+
+    sqlx::query("UPDATE counters SET value = value + 1 WHERE name = 'completed'")
+        .execute(&mut **tx)
+        .await?;
+
+    // Put any task without pending dependencies on the queue
+    for node in dependents.into_iter() {
+        let pending = sqlx::query("SELECT 1 FROM edges WHERE task = ? LIMIT 1")
+            .bind(node)
+            .fetch_optional(&mut **tx)
+            .await?
+            .is_some();
+        if !pending {
+            tracing::debug!(id = %TaskKey(node as u64), "Task has become ready");
+            enqueue(tx, node).await?;
+        }
+    }
+    Ok(())
+}
+
+impl SqliteStore {
+    /// Opens (or creates) the database at `url`, brings its schema up to date
+    /// and puts back on the queue any task whose deadline expired while the
+    /// server was down.
+    pub async fn connect(url: &str) -> Result<Self, sqlx::Error> {
+        let options = SqliteConnectOptions::from_str(url)?
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal);
+        let pool = SqlitePoolOptions::new().connect_with(options).await?;
+        sqlx::migrate!("migrations/sqlite").run(&pool).await?;
+
+        let store = SqliteStore {
+            pool,
+            ready: Notify::new(),
+            stop: Notify::new(),
+            queued: AtomicUsize::new(0),
+        };
+        store.requeue_expired().await?;
+        Ok(store)
+    }
+
+    /// Moves all the tasks in `processing` whose deadline has passed back on
+    /// the queue, just like a `TimedOut` message does for the `MemoryStore`.
+    async fn requeue_expired(&self) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let expired: Vec<i64> =
+            sqlx::query_scalar("DELETE FROM processing WHERE deadline <= ? RETURNING task")
+                .bind(timestamp(OffsetDateTime::now_utc()))
+                .fetch_all(&mut *tx)
+                .await?;
+        for id in expired.iter() {
+            tracing::info!(id = %TaskKey(*id as u64), "Task execution timed out");
+            metrics::increment_counter!(TASKS_TIMED_OUT);
+            retry(&mut tx, *id, TIMEOUT_REASON).await?;
+        }
+        tx.commit().await?;
+
+        if !expired.is_empty() {
+            self.ready.notify_waiters();
+        }
+        Ok(())
+    }
+
+    /// Updates the gauges with the size of the queue and of `processing`.
+    async fn record_gauges(&self) -> Result<(), sqlx::Error> {
+        let (queued, processing): (i64, i64) = sqlx::query_as(
+            "SELECT (SELECT COUNT(*) FROM queue), (SELECT COUNT(*) FROM processing)",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        metrics::gauge!(QUEUE_DEPTH, queued as f64);
+        self.queued.store(queued as usize, Ordering::Relaxed);
+        metrics::gauge!(PROCESSING, processing as f64);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Store for SqliteStore {
+    async fn monitor(&self) -> Result<(), MonitorError> {
+        let mut ticker = interval(POLL_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    self.requeue_expired().await?;
+                    self.record_gauges().await?;
+                }
+                _ = self.stop.notified() => break,
+            }
+        }
+        self.pool.close().await;
+        Ok(())
+    }
+
+    async fn push(&self, insert_tasks: Vec<InsertTask>) -> Result<Vec<Task>, PushError> {
+        check_supported(&insert_tasks)?;
+        let mut tx = self.pool.begin().await?;
+        let result = insert(&mut tx, insert_tasks).await?;
+        tx.commit().await?;
+
+        self.ready.notify_waiters();
+        Ok(result)
+    }
+
+    async fn pop(&self, selector: &Selector) -> Result<Execution, PopError> {
+        loop {
+            // Subscribe before checking the queue, so that no push can slip
+            // in between the check and the wait.
+            let notified = self.ready.notified();
+            if let Some(execution) = self.try_pop(selector).await? {
+                return Ok(execution);
+            }
+            let _ = timeout(POLL_INTERVAL, notified).await;
+        }
+    }
+
+    async fn try_pop(&self, selector: &Selector) -> Result<Option<Execution>, PopError> {
+        let mut filter: String = selector
+            .labels
+            .values()
+            .map(|value| match value {
+                Some(_) => " AND EXISTS (SELECT 1 FROM json_each(tasks.labels) WHERE key = ? AND value = ?)",
+                None => " AND EXISTS (SELECT 1 FROM json_each(tasks.labels) WHERE key = ?)",
+            })
+            .collect();
+        if selector.name.is_some() {
+            filter.push_str(" AND tasks.name = ?");
+        }
+        let sql = format!(
+            "DELETE FROM queue WHERE position = (
+                SELECT queue.position FROM queue JOIN tasks ON tasks.id = queue.task
+                WHERE coalesce(tasks.run_at, 0) <= ?{}
+                ORDER BY queue.priority DESC, queue.position LIMIT 1
+            ) RETURNING task",
+            filter
+        );
+        let now = OffsetDateTime::now_utc();
+        let mut query = sqlx::query_scalar(&sql).bind(timestamp(now));
+        for (name, value) in selector.labels.iter() {
+            query = query.bind(name);
+            if let Some(value) = value {
+                query = query.bind(value);
+            }
+        }
+        if let Some(name) = &selector.name {
+            query = query.bind(name);
+        }
+
+        // Start with a write, so that the transaction holds the database lock
+        // from the beginning and concurrent pops cannot take the same task.
+        let mut tx = self.pool.begin().await?;
+        let id: Option<i64> = query.fetch_optional(&mut *tx).await?;
+        let Some(id) = id else {
+            return Ok(None);
+        };
+
+        let row = sqlx::query(
+            "UPDATE tasks SET attempt = attempt + 1, started_at = ? WHERE id = ? RETURNING *",
+        )
+        .bind(timestamp(now))
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(PopError::InvalidTaskId(TaskKey(id as u64)))?;
+        let task = task_from_row(&row, Status::Processing)?;
+        let deadline = now + task.0.duration;
+        let lease_token = Uuid::new_v4();
+        sqlx::query("INSERT INTO processing (task, deadline, lease_token) VALUES (?, ?, ?)")
+            .bind(id)
+            .bind(timestamp(deadline))
+            .bind(lease_token.to_string())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(Some(Execution(taskie_structures::Execution {
+            remaining: task.0.duration,
+            task,
+            deadline,
+            lease_token,
+        })))
+    }
+
+    // The results are not retained, so `dependency_results` is unsupported
+    async fn complete(
+        &self,
+        task_id: TaskKey,
+        lease_token: Uuid,
+        _result: Option<Value>,
+    ) -> Result<(), CompleteError> {
+        let mut tx = self.pool.begin().await?;
+        complete(&mut tx, task_id, lease_token).await?;
+        tx.commit().await?;
+
+        self.ready.notify_waiters();
+        Ok(())
+    }
+
+    async fn complete_and_push(
+        &self,
+        task_id: TaskKey,
+        lease_token: Uuid,
+        _result: Option<Value>,
+        insert_tasks: Vec<InsertTask>,
+    ) -> Result<Vec<Task>, CompleteAndPushError> {
+        check_supported(&insert_tasks)?;
+        let mut tx = self.pool.begin().await?;
+        // Pushed first, so that the tasks can depend on the completed one
+        let result = insert(&mut tx, insert_tasks).await?;
+        complete(&mut tx, task_id, lease_token).await?;
+        tx.commit().await?;
+
+        self.ready.notify_waiters();
+        Ok(result)
+    }
+
+    async fn peek(&self) -> Result<Option<Task>, PeekError> {
+        let row = sqlx::query(
+            "SELECT tasks.* FROM queue JOIN tasks ON tasks.id = queue.task
+            WHERE coalesce(tasks.run_at, 0) <= ?
+            ORDER BY queue.priority DESC, queue.position LIMIT 1",
+        )
+        .bind(timestamp(OffsetDateTime::now_utc()))
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row
+            .map(|row| task_from_row(&row, Status::Ready))
+            .transpose()?)
+    }
+
+    async fn fail(
+        &self,
+        task_id: TaskKey,
+        lease_token: Uuid,
+        reason: Option<String>,
+    ) -> Result<(), FailError> {
+        let id = task_id.0 as i64;
+        let mut tx = self.pool.begin().await?;
+        let removed: Option<Option<String>> =
+            sqlx::query_scalar("DELETE FROM processing WHERE task = ? RETURNING lease_token")
+                .bind(id)
+                .fetch_optional(&mut *tx)
+                .await?;
+        let Some(recorded) = removed else {
+            return Err(FailError::InvalidTaskId(task_id));
+        };
+        if !leased(lease(recorded), lease_token) {
+            return Err(FailError::LeaseMismatch(task_id));
+        }
+        retry(&mut tx, id, &fail_reason(reason)).await?;
+        tx.commit().await?;
+
+        self.ready.notify_waiters();
+        Ok(())
+    }
+
+    async fn requeue(&self, task_id: TaskKey) -> Result<(), RequeueError> {
+        let id = task_id.0 as i64;
+        let mut tx = self.pool.begin().await?;
+        let removed = sqlx::query("DELETE FROM processing WHERE task = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        if removed == 0 {
+            return Err(RequeueError::InvalidTaskId(task_id));
+        }
+        metrics::increment_counter!(TASKS_TIMED_OUT);
+        retry(&mut tx, id, TIMEOUT_REASON).await?;
+        tx.commit().await?;
+
+        self.ready.notify_waiters();
+        Ok(())
+    }
+
+    async fn heartbeat(
+        &self,
+        task_id: TaskKey,
+        lease_token: Uuid,
+        extend: Duration,
+    ) -> Result<OffsetDateTime, HeartbeatError> {
+        let deadline = OffsetDateTime::now_utc() + extend;
+        let mut tx = self.pool.begin().await?;
+        let updated: Option<Option<String>> = sqlx::query_scalar(
+            "UPDATE processing SET deadline = ? WHERE task = ? RETURNING lease_token",
+        )
+        .bind(timestamp(deadline))
+        .bind(task_id.0 as i64)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(recorded) = updated else {
+            return Err(HeartbeatError::InvalidTaskId(task_id));
+        };
+        if !leased(lease(recorded), lease_token) {
+            return Err(HeartbeatError::LeaseMismatch(task_id));
+        }
+        tx.commit().await?;
+        Ok(deadline)
+    }
+
+    async fn health(&self) -> bool {
+        sqlx::query("SELECT 1").execute(&self.pool).await.is_ok()
+    }
+
+    async fn shutdown(&self) {
+        self.stop.notify_one();
+    }
+
+    async fn dead_letters(&self) -> Result<Vec<(Task, String)>, DeadLetterError> {
+        let rows = sqlx::query("SELECT * FROM tasks WHERE failed ORDER BY id")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| {
+                let reason: Option<String> = row.try_get("failure_reason")?;
+                Ok((
+                    task_from_row(row, Status::Failed)?,
+                    reason.unwrap_or_default(),
+                ))
+            })
+            .collect::<Result<Vec<_>, sqlx::Error>>()?)
+    }
+
+    async fn requeue_dead_letter(&self, task_id: TaskKey) -> Result<Task, DeadLetterError> {
+        let id = task_id.0 as i64;
+        let mut tx = self.pool.begin().await?;
+        let row = sqlx::query(
+            "UPDATE tasks SET failed = 0, failure_reason = NULL, attempt = 0
+            WHERE id = ? AND failed RETURNING *",
+        )
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(DeadLetterError::InvalidTaskId(task_id))?;
+        let task = task_from_row(&row, Status::Ready)?;
+        // The task had already been popped, so it has no pending dependency
+        enqueue(&mut tx, id).await?;
+        tx.commit().await?;
+
+        self.ready.notify_waiters();
+        Ok(task)
+    }
+
+    async fn cancel(&self, task_id: TaskKey) -> Result<(), CancelError> {
+        let id = task_id.0 as i64;
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM queue WHERE task = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        let processing = sqlx::query("SELECT 1 FROM processing WHERE task = ?")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .is_some();
+        if processing {
+            return Err(CancelError::Processing(task_id));
+        }
+        let has_dependents = sqlx::query("SELECT 1 FROM edges WHERE dependency = ? LIMIT 1")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .is_some();
+        if has_dependents {
+            return Err(CancelError::HasDependents(task_id));
+        }
+
+        sqlx::query("DELETE FROM edges WHERE task = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        let removed = sqlx::query("DELETE FROM tasks WHERE id = ? AND NOT failed")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        if removed == 0 {
+            return Err(CancelError::InvalidTaskId(task_id));
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+    async fn get(&self, task_id: TaskKey) -> Result<Task, GetError> {
+        let row = sqlx::query(&format!(
+            "SELECT *, {} AS status FROM tasks WHERE id = ?",
+            STATUS
+        ))
+        .bind(task_id.0 as i64)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(GetError::InvalidTaskId(task_id))?;
+        let status = row
+            .try_get::<String, _>("status")?
+            .parse()
+            .map_err(|err| sqlx::Error::Decode(Box::new(err)))?;
+        Ok(task_from_row(&row, status)?)
+    }
+
+    async fn list(
+        &self,
+        offset: usize,
+        limit: usize,
+        status: Option<Status>,
+    ) -> Result<(Vec<Task>, usize), ListError> {
+        let status = status.map(|status| status.as_str());
+        let mut tx = self.pool.begin().await?;
+        let rows = sqlx::query(&format!(
+            "SELECT * FROM (SELECT *, {} AS status FROM tasks)
+            WHERE ?1 IS NULL OR status = ?1 ORDER BY id LIMIT ?2 OFFSET ?3",
+            STATUS
+        ))
+        .bind(status)
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .fetch_all(&mut *tx)
+        .await?;
+        let total: i64 = sqlx::query_scalar(&format!(
+            "SELECT count(*) FROM (SELECT {} AS status FROM tasks)
+            WHERE ?1 IS NULL OR status = ?1",
+            STATUS
+        ))
+        .bind(status)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        let tasks = rows
+            .iter()
+            .map(|row| {
+                let status = row
+                    .try_get::<String, _>("status")?
+                    .parse()
+                    .map_err(|err| sqlx::Error::Decode(Box::new(err)))?;
+                task_from_row(row, status)
+            })
+            .collect::<Result<_, sqlx::Error>>()?;
+        Ok((tasks, total as usize))
+    }
+
+    async fn dependents(&self, task_id: TaskKey) -> Result<Vec<TaskKey>, GetError> {
+        let id = task_id.0 as i64;
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("SELECT 1 FROM tasks WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(GetError::InvalidTaskId(task_id))?;
+        let dependents: Vec<i64> =
+            sqlx::query_scalar("SELECT task FROM edges WHERE dependency = ? ORDER BY task")
+                .bind(id)
+                .fetch_all(&mut *tx)
+                .await?;
+        tx.commit().await?;
+        Ok(dependents
+            .into_iter()
+            .map(|node| TaskKey(node as u64))
+            .collect())
+    }
+
+    fn queue_depth(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    fn backend_name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    async fn stats(&self) -> Result<Stats, StatsError> {
+        let row = sqlx::query(
+            "SELECT
+                (SELECT count(*) FROM tasks WHERE NOT failed) AS active,
+                (SELECT count(*) FROM queue JOIN tasks ON tasks.id = queue.task
+                    WHERE coalesce(tasks.run_at, 0) <= ?) AS ready,
+                (SELECT count(*) FROM processing) AS processing,
+                (SELECT count(*) FROM tasks WHERE failed) AS dead_lettered,
+                (SELECT value FROM counters WHERE name = 'completed') AS completed,
+                (SELECT value FROM counters WHERE name = 'next_key') AS next_key",
+        )
+        .bind(timestamp(OffsetDateTime::now_utc()))
+        .fetch_one(&self.pool)
+        .await?;
+        let count = |column| row.try_get::<i64, _>(column).map(|count| count as u64);
+        let (ready, processing) = (count("ready")?, count("processing")?);
+        Ok(Stats(taskie_structures::Stats {
+            // The tasks neither ready nor processing are waiting for some of
+            // their dependencies, or for their time to come
+            pending: count("active")?.saturating_sub(ready + processing),
+            ready,
+            processing,
+            completed: count("completed")?,
+            dead_lettered: count("dead_lettered")?,
+            waiting: 0,
+            progress: None,
+            next_key: TaskKey(count("next_key")?),
+            monitor: None,
+        }))
+    }
+
+    async fn purge(&self) -> Result<(), PurgeError> {
+        // The counters are kept, so that the keys are not given out again
+        let mut tx = self.pool.begin().await?;
+        for table in ["edges", "processing", "queue", "tasks"] {
+            sqlx::query(&format!("DELETE FROM {}", table))
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+//! W3C trace context propagation, so that the lifecycle of a task appears as
+//! a single trace spanning the producer pushing it, the server and the worker
+//! completing it. Spans are exported over OTLP when
+//! `OTEL_EXPORTER_OTLP_ENDPOINT` is set.
+
+use axum::{
+    http::{HeaderMap, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use opentelemetry::{
+    propagation::TextMapPropagator,
+    trace::{TraceContextExt, TraceError},
+    Context, KeyValue,
+};
+use opentelemetry_http::{HeaderExtractor, HeaderInjector};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    runtime::Tokio,
+    trace::{config, Tracer},
+    Resource,
+};
+use tracing::{Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+pub static TRACEPARENT: &str = "traceparent";
+
+/// Builds the tracer exporting the spans over OTLP/HTTP, to be installed as a
+/// `tracing_opentelemetry` layer, or `None` when no endpoint is configured.
+pub fn tracer() -> Result<Option<Tracer>, TraceError> {
+    let Ok(endpoint) = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT") else {
+        return Ok(None);
+    };
+    // The endpoint is the base URL of the collector, as in the OTLP spec
+    let endpoint = format!("{}/v1/traces", endpoint.trim_end_matches('/'));
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .http()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            config().with_resource(Resource::new([KeyValue::new("service.name", "taskie")])),
+        )
+        .install_batch(Tokio)
+        .map(Some)
+}
+
+/// Exports the spans which have not been yet.
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+fn extract(headers: &HeaderMap) -> Context {
+    TraceContextPropagator::new().extract(&HeaderExtractor(headers))
+}
+
+/// Runs each request in a span continuing the trace of its `traceparent`
+/// header, if it has one.
+pub async fn propagate<B>(request: Request<B>, next: Next<B>) -> Response {
+    let span = tracing::info_span!(
+        "request",
+        method = %request.method(),
+        path = %request.uri().path()
+    );
+    span.set_parent(extract(request.headers()));
+    next.run(request).instrument(span).await
+}
+
+/// The trace context pushed tasks are stored with: the one of the current
+/// span when it is exported, so that the worker continues the trace from the
+/// server, or else the `traceparent` header of the push as it is.
+pub fn traceparent(headers: &HeaderMap) -> Option<String> {
+    let context = Span::current().context();
+    let header = if context.span().span_context().is_valid() {
+        let mut injected = HeaderMap::new();
+        TraceContextPropagator::new().inject_context(&context, &mut HeaderInjector(&mut injected));
+        injected.remove(TRACEPARENT)
+    } else {
+        headers.get(TRACEPARENT).cloned()
+    };
+    header.and_then(|header| header.to_str().ok().map(str::to_owned))
+}
+
+/// Links the current span to the trace a task was pushed in, so that popping
+/// it shows up in the lifecycle of the task.
+pub fn link(traceparent: &str) {
+    let Ok(header) = HeaderValue::from_str(traceparent) else {
+        return;
+    };
+    let mut headers = HeaderMap::new();
+    headers.insert(TRACEPARENT, header);
+    let context = extract(&headers);
+    let span_context = context.span().span_context().clone();
+    if span_context.is_valid() {
+        Span::current().add_link(span_context);
+    }
+}
@@ -1,22 +1,196 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use serde_with::{serde_as, DurationSeconds};
 use time::{serde::iso8601, Duration, OffsetDateTime};
+use url::Url;
+pub use uuid::Uuid;
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Error {
     pub status: u16,
+    /// What went wrong, one of the `ErrorCode`s, for clients to tell the
+    /// errors apart without matching the message. Empty when sent by a server
+    /// not reporting it.
+    #[serde(default)]
+    pub code: String,
     pub message: String,
 }
 
-pub type TaskKey = String;
+impl Error {
+    pub fn code(&self) -> ErrorCode {
+        self.code.parse().unwrap_or(ErrorCode::Unknown)
+    }
+}
+
+/// The kinds of errors the API reports, as found in the `code` of an `Error`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// The request is malformed or asks for something invalid
+    InvalidRequest,
+    /// A task key is not one handed out by the server
+    InvalidKey,
+    /// The API token is missing or invalid
+    Unauthorized,
+    /// There is no such task, or no longer
+    NotFound,
+    /// The task is not in a state the request applies to
+    Conflict,
+    /// The payload of a task, or the body, is too large
+    PayloadTooLarge,
+    /// Too many requests, to be retried later
+    RateLimited,
+    /// The store of the server does not support the request
+    Unsupported,
+    /// The server is shutting down
+    Unavailable,
+    /// Something went wrong on the server
+    Internal,
+    /// A task to depend upon does not exist, or is finished
+    MissingDependency,
+    /// The dependencies of the tasks would make a cycle
+    CycleDetected,
+    /// A task depends on itself
+    SelfDependency,
+    /// A task to depend upon is listed more than once
+    DuplicateDependency,
+    /// A task depends on an invalid position of its batch
+    InvalidBatchDependency,
+    /// The cron schedule of a recurring task is invalid
+    InvalidSchedule,
+    /// The duration of a task is not positive
+    InvalidDuration,
+    /// The queue is full, to be retried later
+    QueueFull,
+    /// All the task keys have been handed out
+    KeyExhausted,
+    /// The lease token is missing, or the task was popped by someone else
+    LeaseMismatch,
+    /// A code this version does not know about
+    Unknown,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidRequest => "invalid_request",
+            ErrorCode::InvalidKey => "invalid_key",
+            ErrorCode::Unauthorized => "unauthorized",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::Conflict => "conflict",
+            ErrorCode::PayloadTooLarge => "payload_too_large",
+            ErrorCode::RateLimited => "rate_limited",
+            ErrorCode::Unsupported => "unsupported",
+            ErrorCode::Unavailable => "unavailable",
+            ErrorCode::Internal => "internal",
+            ErrorCode::MissingDependency => "missing_dependency",
+            ErrorCode::CycleDetected => "cycle_detected",
+            ErrorCode::SelfDependency => "self_dependency",
+            ErrorCode::DuplicateDependency => "duplicate_dependency",
+            ErrorCode::InvalidBatchDependency => "invalid_batch_dependency",
+            ErrorCode::InvalidSchedule => "invalid_schedule",
+            ErrorCode::InvalidDuration => "invalid_duration",
+            ErrorCode::QueueFull => "queue_full",
+            ErrorCode::KeyExhausted => "key_exhausted",
+            ErrorCode::LeaseMismatch => "lease_mismatch",
+            ErrorCode::Unknown => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ErrorCode {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "invalid_request" => ErrorCode::InvalidRequest,
+            "invalid_key" => ErrorCode::InvalidKey,
+            "unauthorized" => ErrorCode::Unauthorized,
+            "not_found" => ErrorCode::NotFound,
+            "conflict" => ErrorCode::Conflict,
+            "payload_too_large" => ErrorCode::PayloadTooLarge,
+            "rate_limited" => ErrorCode::RateLimited,
+            "unsupported" => ErrorCode::Unsupported,
+            "unavailable" => ErrorCode::Unavailable,
+            "internal" => ErrorCode::Internal,
+            "missing_dependency" => ErrorCode::MissingDependency,
+            "cycle_detected" => ErrorCode::CycleDetected,
+            "self_dependency" => ErrorCode::SelfDependency,
+            "duplicate_dependency" => ErrorCode::DuplicateDependency,
+            "invalid_batch_dependency" => ErrorCode::InvalidBatchDependency,
+            "invalid_schedule" => ErrorCode::InvalidSchedule,
+            "invalid_duration" => ErrorCode::InvalidDuration,
+            "queue_full" => ErrorCode::QueueFull,
+            "key_exhausted" => ErrorCode::KeyExhausted,
+            "lease_mismatch" => ErrorCode::LeaseMismatch,
+            _ => ErrorCode::Unknown,
+        })
+    }
+}
+
+/// The key of a task as handed out by the server, which conceals the number
+/// it is stored by. It can only be sent back as it is.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TaskId(pub String);
+
+impl TaskId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for TaskId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for TaskId {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(TaskId(s.to_string()))
+    }
+}
+
+impl From<String> for TaskId {
+    fn from(key: String) -> Self {
+        TaskId(key)
+    }
+}
+
+impl From<TaskId> for String {
+    fn from(id: TaskId) -> Self {
+        id.0
+    }
+}
+
+pub type TaskKey = TaskId;
 pub type TaskName = String;
 pub static DEFAULT_DURATION: Duration = Duration::new(30, 0);
+pub static DEFAULT_MAX_RETRIES: u32 = 3;
+pub static DEFAULT_COST: u32 = 1;
 
 fn default_duration() -> Duration {
     DEFAULT_DURATION
 }
 
+fn default_max_retries() -> u32 {
+    DEFAULT_MAX_RETRIES
+}
+
+fn default_cost() -> u32 {
+    DEFAULT_COST
+}
+
 #[serde_as]
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct InsertTask<N = TaskName, K = TaskKey> {
@@ -24,9 +198,142 @@ pub struct InsertTask<N = TaskName, K = TaskKey> {
     pub payload: Option<Value>,
     #[serde(default = "Vec::new")]
     pub depends_on: Vec<K>,
+    /// The positions of the tasks of the same push this one depends on as
+    /// well, which have no key yet, so that a whole graph can be pushed at
+    /// once
+    #[serde(default = "Vec::new")]
+    pub depends_on_batch: Vec<usize>,
     #[serde_as(as = "DurationSeconds<i64>")]
     #[serde(default = "default_duration")]
     pub duration: Duration,
+    /// Ready tasks with a higher priority are popped first
+    #[serde(default)]
+    pub priority: i32,
+    /// How many times the task is put back on the queue after timing out,
+    /// before being marked as failed
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// How much of the budget of a worker the task takes up, when it leases
+    /// tasks by their cost
+    #[serde(default = "default_cost")]
+    pub cost: u32,
+    /// Workers can ask to only pop the tasks with some labels
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// The customer the task is run for. Stores popping in fair share take
+    /// turns among the tenants with ready tasks, so that none of them can
+    /// keep the workers to itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// The task is not popped before this time, even when its dependencies
+    /// have been completed
+    #[serde(default, with = "iso8601::option")]
+    pub run_at: Option<OffsetDateTime>,
+    /// The task is dropped, instead of being popped, if still pending or
+    /// ready at this time, and so are the tasks depending on it
+    #[serde(default, with = "iso8601::option")]
+    pub expires_at: Option<OffsetDateTime>,
+    /// A cron expression, including the seconds, on which the task recurs: a
+    /// new instance of it is pushed, to run at the next matching time, every
+    /// time one is completed. Unless `run_at` is set the first instance also
+    /// runs at the next matching time.
+    #[serde(default)]
+    pub schedule: Option<String>,
+    /// Pushing again a task with the same key, while the store remembers it,
+    /// returns the task pushed the first time instead of a new one, so that
+    /// pushes can be retried safely
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+    /// Pushing a task with the same name, payload and dependencies as one
+    /// still pending or ready, and pushed with `dedupe` as well, returns the
+    /// latter instead of a new task
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dedupe: bool,
+    /// Once the task is completed, or moved to the dead-letter queue, it is
+    /// POSTed to this URL, along with its final status
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback_url: Option<Url>,
+    /// Whether the task waits for all of its dependencies or just one
+    #[serde(default)]
+    pub dependency_mode: DependencyMode,
+    /// The W3C trace context the task was pushed in, handed to the worker
+    /// popping it so that it can continue the trace. It defaults to the
+    /// `traceparent` header of the push
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub traceparent: Option<String>,
+}
+
+/// Which of its dependencies a task waits for before being ready
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyMode {
+    /// All of them have to be completed
+    #[default]
+    All,
+    /// The first one completed makes the task ready, and the task no longer
+    /// waits for the others
+    Any,
+}
+
+/// Where a task is in its lifecycle
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    /// Waiting for some of its dependencies to be completed
+    #[default]
+    Pending,
+    /// On the queue, waiting to be popped by a worker
+    Ready,
+    /// Popped by a worker, which has not completed it yet
+    Processing,
+    /// Completed by a worker
+    Completed,
+    /// Exhausted its retries, and moved to the dead-letter queue
+    Failed,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Pending => "pending",
+            Status::Ready => "ready",
+            Status::Processing => "processing",
+            Status::Completed => "completed",
+            Status::Failed => "failed",
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct InvalidStatus(pub String);
+
+impl fmt::Display for InvalidStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid task status: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidStatus {}
+
+impl FromStr for Status {
+    type Err = InvalidStatus;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(Status::Pending),
+            "ready" => Ok(Status::Ready),
+            "processing" => Ok(Status::Processing),
+            "completed" => Ok(Status::Completed),
+            "failed" => Ok(Status::Failed),
+            _ => Err(InvalidStatus(s.to_string())),
+        }
+    }
 }
 
 #[serde_as]
@@ -38,16 +345,310 @@ pub struct Task<N = TaskName, K = TaskKey> {
     pub depends_on: Vec<K>,
     #[serde_as(as = "DurationSeconds<i64>")]
     pub duration: Duration,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    #[serde(default = "default_cost")]
+    pub cost: u32,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// The customer the task is run for, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// How many times the task has been popped, including the current one
+    #[serde(default)]
+    pub attempt: u32,
+    #[serde(default)]
+    pub status: Status,
+    /// When the task was pushed
+    #[serde(with = "iso8601")]
+    pub created_at: OffsetDateTime,
+    /// When the latest execution of the task started, if it was ever popped
+    #[serde(default, with = "iso8601::option")]
+    pub started_at: Option<OffsetDateTime>,
+    /// The task is not popped before this time, which is also when it is
+    /// retried after a backoff
+    #[serde(default, with = "iso8601::option")]
+    pub run_at: Option<OffsetDateTime>,
+    /// The task is dropped if not popped by this time
+    #[serde(default, with = "iso8601::option")]
+    pub expires_at: Option<OffsetDateTime>,
+    /// How far the current execution got, in percent, as last reported by
+    /// its worker
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<u8>,
+    /// The cron expression the task recurs on, if any
+    #[serde(default)]
+    pub schedule: Option<String>,
+    /// Where the task is POSTed once completed or dead-lettered, if anywhere
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback_url: Option<Url>,
+    #[serde(default)]
+    pub dependency_mode: DependencyMode,
+    /// The W3C trace context the task was pushed in, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub traceparent: Option<String>,
 }
 
+#[serde_as]
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Execution<T = Task<TaskName, TaskKey>> {
     pub task: T,
     #[serde(with = "iso8601")]
     pub deadline: OffsetDateTime,
+    /// How long is left until the deadline when the task is handed out. It
+    /// does not depend on the worker's clock agreeing with the server's, so
+    /// it is the budget workers should keep to
+    #[serde_as(as = "DurationSeconds<i64>")]
+    pub remaining: Duration,
+    /// Handed out with this execution only, to be sent back to complete,
+    /// fail or extend it. Once the task is popped again, i.e. after timing
+    /// out, the token of the previous execution is no longer accepted.
+    pub lease_token: Uuid,
+}
+
+/// The tasks leased within a budget, along with their total cost
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Lease<T = Execution> {
+    pub executions: Vec<T>,
+    pub cost: u64,
+}
+
+/// A change in the state of a task, published to the event sink
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TaskEvent<K = TaskKey> {
+    pub id: K,
+    pub name: TaskName,
+    /// The state the task is in after the change
+    pub status: Status,
+    #[serde(with = "iso8601")]
+    pub at: OffsetDateTime,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CompleteTask<K = TaskKey> {
+    /// The task to complete, unless it is looked up by `idempotency_key`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<K>,
+    /// The idempotency key the task to complete was pushed with, for the
+    /// workers which do not know its id. When `id` is set as well, it has to
+    /// be the same task.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+    /// The `lease_token` of the execution being completed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lease_token: Option<Uuid>,
+    /// The output of the task, which the tasks depending on it can look up
+    /// for a while after its completion
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+}
+
+/// Completes a task and pushes the tasks following it, as a whole
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CompleteAndPush<N = TaskName, K = TaskKey> {
     pub id: K,
+    /// The `lease_token` of the execution being completed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lease_token: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    /// The tasks to push, which can depend on the completed one
+    pub tasks: Vec<InsertTask<N, K>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CompleteBatch<K = TaskKey> {
+    pub ids: Vec<K>,
+    /// The `lease_token` of the execution of each of the `ids`, in the same
+    /// order
+    #[serde(default)]
+    pub lease_tokens: Vec<Uuid>,
+}
+
+/// The outcome of completing one of the tasks of a `CompleteBatch`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Completion<K = TaskKey> {
+    pub id: K,
+    /// Why the task could not be completed, if it failed to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<Error>,
+}
+
+/// A task pushed again every time it is completed, following its schedule
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Recurring<T = Task<TaskName, TaskKey>, K = TaskKey> {
+    /// The key of the first instance of the task, which identifies it
+    pub id: K,
+    /// The current instance of the task
+    pub task: T,
+}
+
+/// The result a dependency of a task was completed with
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DependencyResult<K = TaskKey> {
+    pub id: K,
+    /// Missing if the dependency was completed without a result, if it was
+    /// not completed yet, or if its result is no longer retained
+    #[serde(default)]
+    pub result: Option<Value>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FailTask<K = TaskKey> {
+    pub id: K,
+    /// The `lease_token` of the execution being failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lease_token: Option<Uuid>,
+    /// Why the execution failed, as reported by the worker
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// The outcome of a task which reached a terminal state
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TaskResult<K = TaskKey> {
+    pub id: K,
+    /// Either completed or failed, once moved to the dead-letter queue
+    pub status: Status,
+    /// What the task was completed with, if anything
+    #[serde(default)]
+    pub result: Option<Value>,
+}
+
+/// The state an awaited task reached, either ready or finished
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AwaitedTask<N = TaskName, K = TaskKey> {
+    pub id: K,
+    pub status: Status,
+    /// The task as it was when it reached `status`. Only missing for the
+    /// tasks completed before they were awaited, which are no longer stored
+    pub task: Option<Task<N, K>>,
+}
+
+/// The tasks to wait for until they are all finished
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WaitTasks<K = TaskKey> {
+    pub ids: Vec<K>,
+}
+
+/// A page of the tasks in the store, in the order of their keys
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TaskPage<T = Task<TaskName, TaskKey>> {
+    pub tasks: Vec<T>,
+    /// How many tasks there are in all the pages
+    pub total: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeadLetter<T = Task<TaskName, TaskKey>> {
+    pub task: T,
+    /// Why the last execution of the task failed
+    pub reason: String,
+}
+
+/// The changes to a task which has not been popped yet. The fields left out,
+/// or null, are kept as they are.
+#[serde_as]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TaskPatch<K = TaskKey> {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<Value>,
+    #[serde_as(as = "Option<DurationSeconds<i64>>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<Duration>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+    /// Replaces all the dependencies of the task
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depends_on: Option<Vec<K>>,
+}
+
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Heartbeat<K = TaskKey> {
+    pub id: K,
+    /// The `lease_token` of the execution being extended
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lease_token: Option<Uuid>,
+    /// How long from now the deadline of the task is moved to
+    #[serde_as(as = "DurationSeconds<i64>")]
+    #[serde(default = "default_duration")]
+    pub extend: Duration,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReportProgress<K = TaskKey> {
+    pub id: K,
+    /// The `lease_token` of the execution making progress
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lease_token: Option<Uuid>,
+    /// How far the task got, from 0 to 100
+    pub percent: u8,
+    /// Whether to move the deadline of the task to leave it the share of its
+    /// duration matching the progress left
+    #[serde(default)]
+    pub extend: bool,
+}
+
+/// The progress recorded for a task, and its deadline if it was moved
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Progress {
+    pub percent: u8,
+    #[serde(default, with = "iso8601::option")]
+    pub deadline: Option<OffsetDateTime>,
+}
+
+/// How many tasks are in each state
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Stats<K = TaskKey> {
+    pub pending: u64,
+    /// How many tasks are on the queue, ready to be popped
+    pub ready: u64,
+    pub processing: u64,
+    /// How many tasks have ever been completed
+    pub completed: u64,
+    pub dead_lettered: u64,
+    /// How many workers are waiting for a task to be ready. Many waiting
+    /// workers with no ready task mean that there are more than needed
+    #[serde(default)]
+    pub waiting: u64,
+    /// The average progress of the tasks being processed which reported it,
+    /// in percent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<f64>,
+    /// The key the next pushed task is going to get
+    pub next_key: K,
+    /// How far behind its monitor is, for the stores which run one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monitor: Option<MonitorStats>,
+}
+
+/// The messages the monitor of the store, which handles the completions and
+/// the timeouts of the tasks, has yet to handle.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MonitorStats {
+    /// How many messages are waiting to be handled
+    pub backlog: u64,
+    /// When the monitor last handled a message, if it ever did
+    #[serde(default, with = "iso8601::option")]
+    pub last_handled_at: Option<OffsetDateTime>,
+}
+
+/// The build of the server, and the backend keeping its tasks
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Version {
+    /// The version of the crate
+    pub version: String,
+    /// The commit the server was built from, or `unknown`
+    pub git_sha: String,
+    /// The name of the store backend, i.e. `memory` or `postgres`
+    pub backend: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Deadline {
+    #[serde(with = "iso8601")]
+    pub deadline: OffsetDateTime,
 }
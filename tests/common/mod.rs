@@ -0,0 +1,112 @@
+//! Boots the API in-process, backed by a `MemoryStore`, for the integration
+//! tests to exercise through the real `Client`.
+
+// Every test binary only uses a part of the helpers
+#![allow(dead_code)]
+
+use std::{
+    net::{SocketAddr, TcpListener},
+    sync::Arc,
+};
+
+use block_id::{Alphabet, BlockId};
+use taskie::{router, store::Keys, stores::mem::MemoryStore, AppState, Context};
+use taskie_client::{Client, DependencyMode, InsertTask};
+use tokio_util::sync::CancellationToken;
+
+/// The task type returned by the server, with concealed keys.
+pub type Task = taskie_client::Task;
+
+/// A server listening on an ephemeral port, stopped when dropped.
+pub struct TestServer {
+    pub client: Client,
+    pub store: Context,
+    /// The state of the API, to serve it over other transports as well.
+    pub state: AppState,
+    address: SocketAddr,
+    shutdown: CancellationToken,
+}
+
+impl TestServer {
+    pub async fn start() -> TestServer {
+        TestServer::with_store(MemoryStore::new()).await
+    }
+
+    pub async fn with_store(store: MemoryStore) -> TestServer {
+        TestServer::with_keys(store, keys()).await
+    }
+
+    /// A server concealing the keys of the tasks with `keys`, rather than
+    /// with the ones of the other servers.
+    pub async fn with_keys(store: MemoryStore, keys: Keys) -> TestServer {
+        let store: Context = Arc::new(store.keys(keys.clone()));
+        let state = AppState::new(store.clone(), keys.clone());
+        let shutdown = state.shutdown.clone();
+
+        let monitor_store = store.clone();
+        tokio::spawn(async move { keys.scope(monitor_store.monitor()).await });
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(router(state.clone(), None).into_make_service())
+            .with_graceful_shutdown(shutdown.clone().cancelled_owned());
+        tokio::spawn(server);
+
+        TestServer {
+            client: Client::new(format!("http://{}", address).parse().unwrap()),
+            store,
+            state,
+            address,
+            shutdown,
+        }
+    }
+
+    /// The URL of an endpoint of the server, to send raw requests to it.
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.address, path)
+    }
+
+    /// Another client of the server, besides `client`.
+    pub fn connect(&self) -> Client {
+        Client::new(format!("http://{}", self.address).parse().unwrap())
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+        let store = self.store.clone();
+        tokio::spawn(async move { store.shutdown().await });
+    }
+}
+
+/// The keys the servers conceal the keys of the tasks with by default.
+pub fn keys() -> Keys {
+    Keys::new(BlockId::new(Alphabet::alphanumeric(), 42, 4))
+}
+
+/// A task with the given name and the default settings.
+pub fn task(name: &str) -> InsertTask {
+    InsertTask {
+        name: name.to_string(),
+        payload: None,
+        depends_on: vec![],
+        depends_on_batch: vec![],
+        duration: time::Duration::seconds(30),
+        priority: 0,
+        max_retries: 3,
+        cost: 1,
+        labels: Default::default(),
+        tenant: None,
+        run_at: None,
+        expires_at: None,
+        schedule: None,
+        idempotency_key: None,
+        dedupe: false,
+        callback_url: None,
+        dependency_mode: DependencyMode::All,
+        traceparent: None,
+    }
+}
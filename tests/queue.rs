@@ -0,0 +1,1848 @@
+mod common;
+
+use std::{
+    net::{SocketAddr, TcpListener},
+    sync::Arc,
+    time::Duration,
+};
+
+use axum::{http::StatusCode, routing::post, Json, Router};
+use block_id::{Alphabet, BlockId};
+use common::{task, Task, TestServer};
+use serde::{Deserialize, Serialize};
+use taskie::{
+    cors,
+    events::EventSink,
+    namespaces::Namespaces,
+    ratelimit::RateLimit,
+    store::Keys,
+    stores::mem::{MemoryStore, Overflow},
+    DEFAULT_MAX_PAYLOAD_BYTES,
+};
+use taskie_client::{
+    Client, ClientError, ErrorCode, Execution, Stats, Status, TaskEvent, TaskId, TaskPatch,
+    TypedClient, Uuid,
+};
+
+#[tokio::test]
+async fn push_pop_complete() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let pushed: Task = client.push(&task("single")).await.unwrap();
+    let execution = client
+        .pop::<String>(Some(Duration::from_secs(1)))
+        .await
+        .unwrap()
+        .expect("the pushed task is ready");
+    assert_eq!(execution.task.id, pushed.id);
+    assert_eq!(execution.task.name, "single");
+    assert_eq!(execution.task.attempt, 1);
+    assert_eq!(execution.remaining, time::Duration::seconds(30));
+
+    client
+        .complete(&pushed.id, execution.lease_token)
+        .await
+        .unwrap();
+    let stats: Stats = client.stats().await.unwrap();
+    assert_eq!((stats.ready, stats.processing, stats.completed), (0, 0, 1));
+    // Completing it twice succeeds, as a retry, without completing it again
+    client
+        .complete(&pushed.id, execution.lease_token)
+        .await
+        .unwrap();
+    let stats: Stats = client.stats().await.unwrap();
+    assert_eq!(stats.completed, 1);
+    // while the tasks which were never popped cannot be completed
+    let pending: Task = client.push(&task("pending")).await.unwrap();
+    assert!(matches!(
+        client.complete(&pending.id, execution.lease_token).await,
+        Err(ClientError::Api {
+            status: StatusCode::NOT_FOUND,
+            code: ErrorCode::NotFound,
+            ..
+        })
+    ));
+}
+
+#[tokio::test]
+async fn only_the_lease_token_of_the_execution_is_accepted() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let pushed: Task = client.push(&task("leased")).await.unwrap();
+    let first = client
+        .pop::<String>(Some(Duration::from_secs(1)))
+        .await
+        .unwrap()
+        .expect("the pushed task is ready");
+    let response = reqwest::Client::new()
+        .post(server.url("/v1/complete"))
+        .json(&serde_json::json!({ "id": pushed.id }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // Once requeued, the task is leased to whoever pops it next
+    client.requeue(&pushed.id).await.unwrap();
+    let second = client
+        .pop::<String>(Some(Duration::from_secs(1)))
+        .await
+        .unwrap()
+        .expect("the requeued task is ready");
+    assert_ne!(first.lease_token, second.lease_token);
+    assert!(matches!(
+        client.complete(&pushed.id, first.lease_token).await,
+        Err(ClientError::Api {
+            status: StatusCode::FORBIDDEN,
+            code: ErrorCode::LeaseMismatch,
+            ..
+        })
+    ));
+    assert!(matches!(
+        client
+            .heartbeat(&pushed.id, first.lease_token, time::Duration::seconds(10))
+            .await,
+        Err(ClientError::Api {
+            status: StatusCode::FORBIDDEN,
+            code: ErrorCode::LeaseMismatch,
+            ..
+        })
+    ));
+    client
+        .heartbeat(&pushed.id, second.lease_token, time::Duration::seconds(10))
+        .await
+        .unwrap();
+    client
+        .complete(&pushed.id, second.lease_token)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn every_completion_and_extension_checks_the_lease_token() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let first: Task = client.push(&task("first")).await.unwrap();
+    let second: Task = client.push(&task("second")).await.unwrap();
+    let mut executions = vec![];
+    for _ in 0..2 {
+        executions.push(
+            client
+                .pop::<String>(Some(Duration::from_secs(1)))
+                .await
+                .unwrap()
+                .expect("the pushed tasks are ready"),
+        );
+    }
+    let (first_lease, second_lease) = (executions[0].lease_token, executions[1].lease_token);
+    let http = reqwest::Client::new();
+
+    // Neither without the token, nor with another one
+    for body in [
+        serde_json::json!({ "id": first.id, "percent": 10, "extend": true }),
+        serde_json::json!({
+            "id": first.id,
+            "lease_token": second_lease,
+            "percent": 10,
+            "extend": true,
+        }),
+    ] {
+        let response = http
+            .post(server.url("/v1/progress"))
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+    for body in [
+        serde_json::json!({ "id": first.id, "tasks": [] }),
+        serde_json::json!({ "id": first.id, "lease_token": second_lease, "tasks": [] }),
+    ] {
+        let response = http
+            .post(server.url("/v1/complete-and-push"))
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+    let completions: Vec<serde_json::Value> = http
+        .post(server.url("/v1/complete-batch"))
+        .json(&serde_json::json!({ "ids": [first.id, second.id], "lease_tokens": [second_lease] }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    for completion in completions.iter() {
+        assert_eq!(completion["error"]["status"], 403);
+        assert_eq!(completion["error"]["code"], "lease_mismatch");
+    }
+    assert!(matches!(
+        client
+            .complete_and_push::<String>(&first.id, second_lease, None, &[])
+            .await,
+        Err(ClientError::Api {
+            status: StatusCode::FORBIDDEN,
+            code: ErrorCode::LeaseMismatch,
+            ..
+        })
+    ));
+    let stats: Stats = client.stats().await.unwrap();
+    assert_eq!((stats.processing, stats.completed), (2, 0));
+
+    client
+        .progress(&first.id, first_lease, 10, true)
+        .await
+        .unwrap();
+    let completions = client
+        .complete_many(vec![
+            (first.id.clone(), first_lease),
+            (second.id.clone(), second_lease),
+        ])
+        .await
+        .unwrap();
+    assert!(completions
+        .iter()
+        .all(|completion| completion.error.is_none()));
+    let stats: Stats = client.stats().await.unwrap();
+    assert_eq!((stats.processing, stats.completed), (0, 2));
+}
+
+#[tokio::test]
+async fn tasks_are_completed_by_their_idempotency_key() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let mut keyed = task("keyed");
+    keyed.idempotency_key = Some("once".to_string());
+    let pushed: Task = client.push(&keyed).await.unwrap();
+    let mut other = task("other");
+    other.idempotency_key = Some("other".to_string());
+    let other: Task = client.push(&other).await.unwrap();
+    let execution = client
+        .pop::<String>(Some(Duration::from_secs(1)))
+        .await
+        .unwrap()
+        .expect("the pushed task is ready");
+
+    // The id and the key have to be of the same task, when both are set
+    let http = reqwest::Client::new();
+    for body in [
+        serde_json::json!({ "id": other.id, "idempotency_key": "once" }),
+        serde_json::json!({}),
+    ] {
+        let response = http
+            .post(server.url("/v1/complete"))
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+    assert!(matches!(
+        client
+            .complete_by_idempotency_key("unknown", execution.lease_token, None)
+            .await,
+        Err(ClientError::Api {
+            status: StatusCode::NOT_FOUND,
+            code: ErrorCode::NotFound,
+            ..
+        })
+    ));
+
+    client
+        .complete_by_idempotency_key("once", execution.lease_token, None)
+        .await
+        .unwrap();
+    let stats: Stats = client.stats().await.unwrap();
+    assert_eq!((stats.processing, stats.completed), (0, 1));
+    let response = http
+        .post(server.url("/v1/complete"))
+        .json(&serde_json::json!({
+            "id": pushed.id,
+            "idempotency_key": "once",
+            "lease_token": execution.lease_token,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn push_errors_are_told_apart_by_their_code() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let finished: Task = client.push(&task("finished")).await.unwrap();
+    let execution = client
+        .pop::<String>(Some(Duration::from_secs(1)))
+        .await
+        .unwrap()
+        .expect("the pushed task is ready");
+    client
+        .complete(&finished.id, execution.lease_token)
+        .await
+        .unwrap();
+    let mut late = task("late");
+    late.depends_on = vec![finished.id.clone()];
+    let err = client.push::<String>(&late).await.unwrap_err();
+    assert_eq!(err.status(), Some(StatusCode::BAD_REQUEST));
+    assert_eq!(err.code(), Some(ErrorCode::MissingDependency));
+
+    let mut looping = task("looping");
+    looping.depends_on_batch = vec![1];
+    let mut back = task("back");
+    back.depends_on_batch = vec![0];
+    let err = client
+        .push_many::<String>(&[looping, back])
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::CycleDetected));
+}
+
+#[tokio::test]
+async fn try_pop_does_not_wait() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let empty = tokio::time::timeout(Duration::from_secs(1), client.try_pop::<String>());
+    assert!(empty
+        .await
+        .expect("the pop does not wait")
+        .unwrap()
+        .is_none());
+    let pushed: Task = client.push(&task("ready")).await.unwrap();
+    let execution = client.try_pop::<String>().await.unwrap().unwrap();
+    assert_eq!(execution.task.id, pushed.id);
+
+    let response = reqwest::get(server.url("/v1/pop?block=false&count=2"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn dependencies_are_popped_first() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let parent: Task = client.push(&task("parent")).await.unwrap();
+    let mut dependent = task("dependent");
+    dependent.depends_on = vec![parent.id.clone()];
+    let dependent: Task = client.push(&dependent).await.unwrap();
+    let dependents = client.dependents(&parent.id).await.unwrap();
+    assert_eq!(dependents, vec![dependent.id.clone()]);
+
+    let execution = client
+        .pop::<String>(Some(Duration::from_secs(1)))
+        .await
+        .unwrap()
+        .expect("the parent is ready");
+    assert_eq!(execution.task.id, parent.id);
+    // The dependent waits for the parent to be completed
+    let none = client
+        .pop::<String>(Some(Duration::from_secs(1)))
+        .await
+        .unwrap();
+    assert!(none.is_none());
+
+    client
+        .complete(&parent.id, execution.lease_token)
+        .await
+        .unwrap();
+    let execution = client
+        .pop::<String>(Some(Duration::from_secs(1)))
+        .await
+        .unwrap()
+        .expect("the dependent became ready");
+    assert_eq!(execution.task.id, dependent.id);
+}
+
+#[tokio::test]
+async fn tasks_are_listed_in_pages() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let mut pushed: Vec<Task> = Vec::new();
+    for name in ["a", "b", "c", "d", "e"] {
+        pushed.push(client.push(&task(name)).await.unwrap());
+    }
+    client
+        .pop::<String>(Some(Duration::from_secs(1)))
+        .await
+        .unwrap()
+        .expect("a task is ready");
+
+    let page = client.list::<String>(1, 2, None).await.unwrap();
+    assert_eq!(page.total, 5);
+    let names: Vec<_> = page.tasks.iter().map(|task| task.name.as_str()).collect();
+    assert_eq!(names, ["b", "c"]);
+    let last = client.list::<String>(4, 2, None).await.unwrap();
+    assert_eq!(last.tasks.len(), 1);
+    assert_eq!(last.tasks[0].id, pushed[4].id);
+
+    let processing = client
+        .list::<String>(0, 10, Some(Status::Processing))
+        .await
+        .unwrap();
+    assert_eq!(processing.total, 1);
+    assert_eq!(processing.tasks[0].id, pushed[0].id);
+    let ready = client
+        .list::<String>(0, 10, Some(Status::Ready))
+        .await
+        .unwrap();
+    assert_eq!(ready.total, 4);
+}
+
+#[tokio::test]
+async fn peek_does_not_pop() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    assert!(client.peek::<String>().await.unwrap().is_none());
+    client.push(&task("low")).await.unwrap();
+    let mut high = task("high");
+    high.priority = 1;
+    let high: Task = client.push(&high).await.unwrap();
+
+    let peeked: Task = client.peek().await.unwrap().expect("a task is ready");
+    assert_eq!(peeked.id, high.id);
+    assert_eq!(peeked.status, Status::Ready);
+    assert_eq!(peeked.attempt, 0);
+    // Peeking again returns the same task, which is then popped
+    let again: Task = client.peek().await.unwrap().unwrap();
+    assert_eq!(again.id, high.id);
+    let execution = client
+        .pop::<String>(Some(Duration::from_secs(1)))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(execution.task.id, high.id);
+    let next: Task = client.peek().await.unwrap().unwrap();
+    assert_eq!(next.name, "low");
+}
+
+/// Reads the next event of a `GET /v1/stream` response, skipping the keep
+/// alive comments, as its name and its data.
+async fn next_event(response: &mut reqwest::Response, buffer: &mut String) -> (String, String) {
+    loop {
+        if let Some(end) = buffer.find("\n\n") {
+            let event: String = buffer.drain(..end + 2).collect();
+            let mut name = None;
+            let mut data = String::new();
+            for line in event.lines() {
+                if let Some(value) = line.strip_prefix("event:") {
+                    name = Some(value.trim().to_string());
+                } else if let Some(value) = line.strip_prefix("data:") {
+                    data.push_str(value.trim());
+                }
+            }
+            match name {
+                Some(name) => return (name, data),
+                None => continue,
+            }
+        }
+        let chunk = tokio::time::timeout(Duration::from_secs(5), response.chunk())
+            .await
+            .expect("an event is streamed")
+            .unwrap()
+            .expect("the stream is still open");
+        buffer.push_str(std::str::from_utf8(&chunk).unwrap());
+    }
+}
+
+#[tokio::test]
+async fn streamed_tasks_are_processing_and_not_popped_again() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let first: Task = client.push(&task("first")).await.unwrap();
+    let second: Task = client.push(&task("second")).await.unwrap();
+    let mut stream = reqwest::get(server.url("/v1/stream")).await.unwrap();
+    assert_eq!(stream.status(), StatusCode::OK);
+    let mut buffer = String::new();
+    let (name, data) = next_event(&mut stream, &mut buffer).await;
+    assert_eq!(name, "execution");
+    let streamed: Execution = serde_json::from_str(&data).unwrap();
+    assert_eq!(streamed.task.id, first.id);
+    let fetched: Task = client.get(&first.id).await.unwrap();
+    assert_eq!(fetched.status, Status::Processing);
+
+    // Only one task is streamed at once by default, so the other one is left
+    // to a concurrent pop, and the streamed one is not handed out again
+    let popped = client
+        .pop::<String>(Some(Duration::from_secs(1)))
+        .await
+        .unwrap()
+        .expect("the second task is ready");
+    assert_eq!(popped.task.id, second.id);
+    let third: Task = client.push(&task("third")).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let stats: Stats = client.stats().await.unwrap();
+    assert_eq!((stats.ready, stats.processing), (1, 2));
+
+    // Completing the streamed task frees the connection for the next one
+    client
+        .complete(&first.id, streamed.lease_token)
+        .await
+        .unwrap();
+    let (name, data) = next_event(&mut stream, &mut buffer).await;
+    assert_eq!(name, "execution");
+    let streamed: Execution = serde_json::from_str(&data).unwrap();
+    assert_eq!(streamed.task.id, third.id);
+
+    // The task still processing when the stream is closed is retried
+    drop(stream);
+    let retried = client
+        .pop::<String>(Some(Duration::from_secs(5)))
+        .await
+        .unwrap()
+        .expect("the third task is back on the queue");
+    assert_eq!(retried.task.id, third.id);
+    assert_eq!(retried.task.attempt, 2);
+
+    let none = reqwest::get(server.url("/v1/stream?max_in_flight=0"))
+        .await
+        .unwrap();
+    assert_eq!(none.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn graph_is_exported_as_dot() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let parent: Task = client.push(&task("parent")).await.unwrap();
+    let mut dependent = task("say \"hi\"");
+    dependent.depends_on = vec![parent.id.clone()];
+    let dependent: Task = client.push(&dependent).await.unwrap();
+
+    let dot = client.graph().await.unwrap();
+    assert!(dot.starts_with("digraph taskie {\n"));
+    assert!(dot.ends_with("}\n"));
+    assert!(dot.contains(&format!("\"{}\" [label=\"parent\"];", parent.id)));
+    assert!(dot.contains(&format!("\"{}\" [label=\"say \\\"hi\\\"\"];", dependent.id)));
+    assert!(dot.contains(&format!("\"{}\" -> \"{}\";", parent.id, dependent.id)));
+
+    let unknown = reqwest::get(server.url("/v1/graph?format=svg"))
+        .await
+        .unwrap();
+    assert_eq!(unknown.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn timed_out_tasks_are_popped_again() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let mut short = task("short");
+    short.duration = time::Duration::seconds(1);
+    let pushed: Task = client.push(&short).await.unwrap();
+
+    let first = client
+        .pop::<String>(Some(Duration::from_secs(1)))
+        .await
+        .unwrap()
+        .expect("the task is ready");
+    assert_eq!(first.task.id, pushed.id);
+
+    // Not completed, so it is back on the queue after its duration
+    let second = client
+        .pop::<String>(Some(Duration::from_secs(5)))
+        .await
+        .unwrap()
+        .expect("the task is requeued once timed out");
+    assert_eq!(second.task.id, pushed.id);
+    assert_eq!(second.task.attempt, 2);
+    assert!(second.deadline > first.deadline);
+}
+
+#[tokio::test]
+async fn purge_leaves_the_monitor_running() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let mut short = task("short");
+    short.duration = time::Duration::seconds(1);
+    let _: Task = client.push(&short).await.unwrap();
+    let parent: Task = client.push(&task("parent")).await.unwrap();
+    let mut dependent = task("dependent");
+    dependent.depends_on = vec![parent.id.clone()];
+    let _: Task = client.push(&dependent).await.unwrap();
+    client
+        .pop::<String>(Some(Duration::from_secs(1)))
+        .await
+        .unwrap()
+        .expect("the short task is ready");
+
+    client.purge().await.unwrap();
+    let stats: Stats = client.stats().await.unwrap();
+    assert_eq!((stats.pending, stats.ready, stats.processing), (0, 0, 0));
+
+    // Past the deadline of the purged task, the store keeps working
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert!(server.store.health().await);
+    let pushed: Task = client.push(&task("after")).await.unwrap();
+    let execution = client
+        .pop::<String>(Some(Duration::from_secs(1)))
+        .await
+        .unwrap()
+        .expect("the new task is ready");
+    assert_eq!(execution.task.id, pushed.id);
+    client
+        .complete(&pushed.id, execution.lease_token)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn waiting_workers_are_counted() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let push = async {
+        // Leaves the time for the pop to start waiting
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let stats: Stats = client.stats().await.unwrap();
+        assert_eq!((stats.ready, stats.waiting), (0, 1));
+        let _: Task = client.push(&task("awaited")).await.unwrap();
+    };
+    let (execution, ()) = tokio::join!(client.pop::<String>(Some(Duration::from_secs(5))), push);
+    let execution = execution.unwrap().expect("the pushed task is ready");
+    assert_eq!(execution.task.name, "awaited");
+    let stats: Stats = client.stats().await.unwrap();
+    assert_eq!(stats.waiting, 0);
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Resize {
+    image: String,
+    width: u32,
+}
+
+#[tokio::test]
+async fn typed_client_converts_the_payload() {
+    let server = TestServer::start().await;
+    let client = TypedClient::<Resize>::new(server.connect(), "resize");
+
+    let pushed = client
+        .push(Resize {
+            image: "cat.png".to_string(),
+            width: 640,
+        })
+        .await
+        .unwrap();
+    assert_eq!(pushed.name, "resize");
+    let execution = client
+        .pop(Some(Duration::from_secs(1)))
+        .await
+        .unwrap()
+        .expect("the pushed task is ready");
+    assert_eq!(execution.task.id, pushed.id);
+    assert_eq!(
+        execution.payload,
+        Resize {
+            image: "cat.png".to_string(),
+            width: 640,
+        }
+    );
+    client
+        .client()
+        .complete(&pushed.id, execution.lease_token)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn pop_many_returns_the_ready_tasks() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let tasks = [task("first"), task("second"), task("third")];
+    let pushed: Vec<Task> = client.push_many(&tasks).await.unwrap();
+    // Only the ready tasks are returned, without waiting for the full count
+    let executions = client
+        .pop_many::<String>(Some(Duration::from_secs(1)), 5)
+        .await
+        .unwrap();
+    let popped: Vec<_> = executions.iter().map(|e| e.task.id.clone()).collect();
+    let pushed: Vec<_> = pushed.into_iter().map(|task| task.id).collect();
+    assert_eq!(popped, pushed);
+    let stats: Stats = client.stats().await.unwrap();
+    assert_eq!((stats.ready, stats.processing), (0, 3));
+
+    let none = client
+        .pop_many::<String>(Some(Duration::from_secs(1)), 5)
+        .await
+        .unwrap();
+    assert!(none.is_empty());
+    assert!(client
+        .pop_many::<String>(Some(Duration::from_secs(1)), 0)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn pop_budget_leases_the_tasks_fitting_the_budget() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let tasks = [("medium", 3), ("large", 5), ("small", 2)].map(|(name, cost)| {
+        let mut task = task(name);
+        task.cost = cost;
+        task
+    });
+    let _: Vec<Task> = client.push_many(&tasks).await.unwrap();
+    // The large task does not fit in what is left, but does not hold back the
+    // small one behind it
+    let lease = client
+        .pop_budget::<String>(Some(Duration::ZERO), 6)
+        .await
+        .unwrap()
+        .expect("the tasks are ready");
+    let popped: Vec<_> = lease
+        .executions
+        .iter()
+        .map(|e| e.task.name.as_str())
+        .collect();
+    assert_eq!((popped, lease.cost), (vec!["medium", "small"], 5));
+
+    // The first task is leased even beyond the budget
+    let lease = client
+        .pop_budget::<String>(Some(Duration::ZERO), 1)
+        .await
+        .unwrap()
+        .expect("the large task is ready");
+    assert_eq!((lease.executions.len(), lease.cost), (1, 5));
+    assert!(client
+        .pop_budget::<String>(Some(Duration::ZERO), 1)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn pop_filters_the_tasks_by_name() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+    let mut sms = task("send_sms");
+    sms.priority = 10;
+    let _: Task = client.push(&sms).await.unwrap();
+    let email: Task = client.push(&task("send_email")).await.unwrap();
+
+    // The task at the front of the queue does not hold back the others
+    let execution = client
+        .pop_named::<String>(Some(Duration::ZERO), "send_email")
+        .await
+        .unwrap()
+        .expect("the email task is ready");
+    assert_eq!(execution.task.id, email.id);
+    let none = client
+        .pop_named::<String>(Some(Duration::ZERO), "send_email")
+        .await
+        .unwrap();
+    assert!(none.is_none());
+    let execution: Execution = client.pop(Some(Duration::ZERO)).await.unwrap().unwrap();
+    assert_eq!(execution.task.name, "send_sms");
+}
+
+#[tokio::test]
+async fn oversized_payloads_are_rejected() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    // A string is encoded with its quotes
+    let mut under = task("under");
+    under.payload = Some("x".repeat(DEFAULT_MAX_PAYLOAD_BYTES - 2).into());
+    let _: Task = client.push(&under).await.unwrap();
+
+    let mut over = task("over");
+    over.payload = Some("x".repeat(DEFAULT_MAX_PAYLOAD_BYTES - 1).into());
+    let rejected = client.push::<String>(&over).await;
+    assert!(matches!(
+        rejected,
+        Err(ClientError::Api {
+            status: StatusCode::PAYLOAD_TOO_LARGE,
+            code: ErrorCode::PayloadTooLarge,
+            ..
+        })
+    ));
+    let stats: Stats = client.stats().await.unwrap();
+    assert_eq!(stats.ready, 1);
+}
+
+#[tokio::test]
+async fn completed_tasks_are_posted_to_their_callback() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<Task>();
+    let receiver = Router::new().route(
+        "/done",
+        post(move |Json(task): Json<Task>| async move { tx.send(task).unwrap() }),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(receiver.into_make_service()),
+    );
+
+    let mut notified = task("notified");
+    notified.callback_url = Some(format!("http://{}/done", address).parse().unwrap());
+    let pushed: Task = client.push(&notified).await.unwrap();
+    let execution = client
+        .pop::<String>(Some(Duration::from_secs(1)))
+        .await
+        .unwrap()
+        .expect("the pushed task is ready");
+    client
+        .complete(&pushed.id, execution.lease_token)
+        .await
+        .unwrap();
+
+    let delivered = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .expect("the task is delivered in time")
+        .unwrap();
+    assert_eq!(delivered.id, pushed.id);
+    assert_eq!(delivered.status, Status::Completed);
+}
+
+#[tokio::test]
+async fn traceparent_is_handed_to_the_worker() {
+    let server = TestServer::start().await;
+    let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    let http = reqwest::Client::new();
+    let pushed: Vec<Task> = http
+        .put(server.url("/v1/push"))
+        .header("traceparent", traceparent)
+        .json(&[task("traced")])
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(pushed[0].traceparent.as_deref(), Some(traceparent));
+
+    let response = http
+        .get(server.url("/v1/pop?timeout=1"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["traceparent"], traceparent);
+    let execution: taskie_client::Execution = response.json().await.unwrap();
+    assert_eq!(execution.task.id, pushed[0].id);
+    assert_eq!(execution.task.traceparent.as_deref(), Some(traceparent));
+}
+
+#[tokio::test]
+async fn pending_tasks_are_updated_in_place() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let first: Task = client.push(&task("first")).await.unwrap();
+    let mut second = task("second");
+    second.depends_on = vec![first.id.clone()];
+    let second: Task = client.push(&second).await.unwrap();
+
+    let patch = TaskPatch {
+        payload: Some(serde_json::json!({ "retries": 2 })),
+        duration: Some(time::Duration::seconds(60)),
+        ..Default::default()
+    };
+    let updated: Task = client.update(&second.id, &patch).await.unwrap();
+    assert_eq!(updated.payload, patch.payload);
+    assert_eq!(updated.duration, time::Duration::seconds(60));
+    assert_eq!(updated.status, Status::Pending);
+
+    // Swapping the dependencies around, which is only checked for loops
+    let patch = TaskPatch {
+        depends_on: Some(vec![]),
+        ..Default::default()
+    };
+    let updated: Task = client.update(&second.id, &patch).await.unwrap();
+    assert_eq!(updated.status, Status::Ready);
+    let patch = TaskPatch {
+        depends_on: Some(vec![second.id.clone()]),
+        ..Default::default()
+    };
+    let updated: Task = client.update(&first.id, &patch).await.unwrap();
+    assert_eq!(updated.status, Status::Pending);
+    let patch = TaskPatch {
+        depends_on: Some(vec![first.id.clone()]),
+        ..Default::default()
+    };
+    assert!(matches!(
+        client.update::<String>(&second.id, &patch).await,
+        Err(ClientError::Api {
+            status: StatusCode::BAD_REQUEST,
+            code: ErrorCode::CycleDetected,
+            ..
+        })
+    ));
+
+    let execution = client
+        .pop::<String>(Some(Duration::ZERO))
+        .await
+        .unwrap()
+        .expect("the second task is ready");
+    assert_eq!(execution.task.id, second.id);
+    assert_eq!(execution.remaining, time::Duration::seconds(60));
+    assert!(matches!(
+        client
+            .update::<String>(&second.id, &TaskPatch::default())
+            .await,
+        Err(ClientError::Api {
+            status: StatusCode::CONFLICT,
+            code: ErrorCode::Conflict,
+            ..
+        })
+    ));
+    client
+        .complete(&second.id, execution.lease_token)
+        .await
+        .unwrap();
+    let execution = client
+        .pop::<String>(Some(Duration::ZERO))
+        .await
+        .unwrap()
+        .expect("the first task is ready once the second is completed");
+    assert_eq!(execution.task.id, first.id);
+}
+
+#[tokio::test]
+async fn dry_runs_report_every_error_without_pushing() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+    let parent: Task = client.push(&task("parent")).await.unwrap();
+    let mut child = task("child");
+    child.depends_on = vec![parent.id.clone()];
+
+    let keys: Vec<TaskId> = client
+        .dry_run(&[child.clone(), task("other")])
+        .await
+        .unwrap();
+    let pushed: Vec<Task> = client
+        .push_many(&[child.clone(), task("other")])
+        .await
+        .unwrap();
+    assert_eq!(keys, vec![pushed[0].id.clone(), pushed[1].id.clone()]);
+
+    let cancelled: Task = client.push(&task("cancelled")).await.unwrap();
+    client.cancel(&cancelled.id).await.unwrap();
+    let mut missing = task("missing");
+    missing.depends_on = vec![cancelled.id.clone()];
+    let mut instant = task("instant");
+    instant.duration = time::Duration::ZERO;
+    let response = reqwest::Client::new()
+        .put(server.url("/v1/push?dry_run=true"))
+        .json(&[child, missing, instant])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let err: serde_json::Value = response.json().await.unwrap();
+    let message = err["message"].as_str().unwrap();
+    assert!(message.contains("task 1: Missing task to depend upon"));
+    assert!(message.contains("task 2: Invalid task duration"));
+    // Only the tasks pushed for real are there
+    let stats: Stats = client.stats().await.unwrap();
+    assert_eq!((stats.ready, stats.pending), (2, 1));
+}
+
+#[tokio::test]
+async fn pushes_are_retried_when_the_queue_has_room() {
+    let store = MemoryStore::new()
+        .max_queue_depth(1, Overflow::Reject)
+        .retry_after(time::Duration::SECOND);
+    let server = TestServer::with_store(store).await;
+    let _: Task = server.client.push(&task("first")).await.unwrap();
+
+    let response = reqwest::Client::new()
+        .put(server.url("/v1/push"))
+        .json(&[task("refused")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()["retry-after"], "1");
+    let err: taskie_client::Error = response.json().await.unwrap();
+    assert_eq!(err.code(), ErrorCode::QueueFull);
+
+    // The client waits as told, while the queue is drained
+    let client = Client::builder(server.url("/").parse().unwrap())
+        .max_retries(1)
+        .build()
+        .unwrap();
+    let worker = server.connect();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        worker.pop::<String>(Some(Duration::from_secs(1))).await
+    });
+    let pushed: Task = client.push(&task("retried")).await.unwrap();
+    assert_eq!(pushed.name, "retried");
+}
+
+#[tokio::test]
+async fn bodies_are_negotiated_as_cbor() {
+    let server = TestServer::start().await;
+    let mut encoded = Vec::new();
+    let mut cbor = task("binary");
+    cbor.payload = Some(serde_json::json!({ "n": 1, "tags": ["a", "b"] }));
+    ciborium::into_writer(&[cbor], &mut encoded).unwrap();
+
+    let http = reqwest::Client::new();
+    let response = http
+        .put(server.url("/v1/push"))
+        .header("content-type", "application/cbor")
+        .header("accept", "application/cbor")
+        .body(encoded)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["content-type"], "application/cbor");
+    let body = response.bytes().await.unwrap();
+    let pushed: Vec<Task> = ciborium::from_reader(body.as_ref()).unwrap();
+    assert_eq!(pushed[0].name, "binary");
+
+    // The media types which are not supported are skipped
+    let response = http
+        .get(server.url("/v1/pop?timeout=1&count=1"))
+        .header("accept", "text/html, application/cbor;q=0.9")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["content-type"], "application/cbor");
+    let body = response.bytes().await.unwrap();
+    let executions: Vec<taskie_client::Execution> = ciborium::from_reader(body.as_ref()).unwrap();
+    assert_eq!(executions[0].task.id, pushed[0].id);
+    assert_eq!(
+        executions[0].task.payload,
+        Some(serde_json::json!({ "n": 1, "tags": ["a", "b"] }))
+    );
+
+    let invalid = http
+        .put(server.url("/v1/push"))
+        .header("content-type", "application/cbor")
+        .body(r#"[{"name":"json"}]"#)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(invalid.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn blocking_client_pushes_pops_and_completes() {
+    let server = TestServer::start().await;
+    let url = server.url("/");
+
+    let (pushed, popped) = tokio::task::spawn_blocking(move || {
+        let client = taskie_client::blocking::Client::new(url.parse().unwrap()).unwrap();
+        let pushed: Task = client.push(&task("sync")).unwrap();
+        let execution = client
+            .pop::<String>(Some(Duration::from_secs(1)))
+            .unwrap()
+            .expect("the pushed task is ready");
+        client
+            .complete(&execution.task.id, execution.lease_token)
+            .unwrap();
+        (pushed, execution.task)
+    })
+    .await
+    .unwrap();
+    assert_eq!(popped.id, pushed.id);
+    let stats: Stats = server.client.stats().await.unwrap();
+    assert_eq!((stats.processing, stats.completed), (0, 1));
+}
+
+#[tokio::test]
+async fn push_bodies_and_responses_are_gzipped() {
+    let server = TestServer::start().await;
+    let client = taskie_client::Client::builder(server.url("/").parse().unwrap())
+        .compress_push(0)
+        .build()
+        .unwrap();
+
+    let mut compressed = task("compressed");
+    compressed.payload = Some(serde_json::json!({"data": "x".repeat(4096)}));
+    let pushed: Task = client.push(&compressed).await.unwrap();
+    let fetched: Task = client.get(&pushed.id).await.unwrap();
+    assert_eq!(fetched.payload, compressed.payload);
+
+    let response = reqwest::Client::builder()
+        .no_gzip()
+        .build()
+        .unwrap()
+        .get(server.url(&format!("/v1/task/{}", pushed.id)))
+        .header("accept-encoding", "gzip")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-encoding"], "gzip");
+}
+
+#[tokio::test]
+async fn completed_tasks_can_be_awaited() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let pushed: Task = client.push(&task("awaited")).await.unwrap();
+    // It has not even been popped yet
+    let pending = client
+        .await_result(&pushed.id, Duration::from_millis(300))
+        .await
+        .unwrap();
+    assert!(pending.is_none());
+
+    let execution = client
+        .pop::<String>(Some(Duration::from_secs(1)))
+        .await
+        .unwrap()
+        .expect("the pushed task is ready");
+    let worker = server.connect();
+    let id = pushed.id.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        worker
+            .complete_with_result(
+                &id,
+                execution.lease_token,
+                Some(serde_json::json!({"answer": 42})),
+            )
+            .await
+            .unwrap();
+    });
+    let outcome = client
+        .await_result(&pushed.id, Duration::from_secs(5))
+        .await
+        .unwrap()
+        .expect("the task is completed in time");
+    assert_eq!(outcome.id, pushed.id);
+    assert_eq!(outcome.status, Status::Completed);
+    assert_eq!(outcome.result, Some(serde_json::json!({"answer": 42})));
+}
+
+#[tokio::test]
+async fn specific_tasks_can_be_awaited_until_ready() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let first: Task = client.push(&task("first")).await.unwrap();
+    let mut second = task("second");
+    second.depends_on = vec![first.id.clone()];
+    let second: Task = client.push(&second).await.unwrap();
+    let mut cancelled = task("cancelled");
+    cancelled.depends_on = vec![first.id.clone()];
+    let cancelled: Task = client.push(&cancelled).await.unwrap();
+
+    // It waits for its dependency
+    let pending = client
+        .await_task::<String>(&second.id, Some(Duration::from_secs(1)))
+        .await
+        .unwrap();
+    assert!(pending.is_none());
+
+    let awaiter = server.connect();
+    let id = second.id.clone();
+    let awaited = tokio::spawn(async move {
+        awaiter
+            .await_task::<String>(&id, Some(Duration::from_secs(5)))
+            .await
+    });
+    let cancelling = server.connect();
+    let id = cancelled.id.clone();
+    let gone = tokio::spawn(async move { cancelling.await_task::<String>(&id, None).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    client.cancel(&cancelled.id).await.unwrap();
+    assert!(matches!(
+        gone.await.unwrap(),
+        Err(ClientError::Api {
+            status: StatusCode::NOT_FOUND,
+            code: ErrorCode::NotFound,
+            ..
+        })
+    ));
+
+    let execution = client
+        .pop::<String>(Some(Duration::from_secs(1)))
+        .await
+        .unwrap()
+        .expect("the first task is ready");
+    client
+        .complete(&first.id, execution.lease_token)
+        .await
+        .unwrap();
+    let awaited = awaited
+        .await
+        .unwrap()
+        .unwrap()
+        .expect("the second task is ready in time");
+    assert_eq!(awaited.id, second.id);
+    assert_eq!(awaited.status, Status::Ready);
+    assert_eq!(awaited.task.unwrap().name, "second");
+
+    // A task completed beforehand is returned right away, if only by status
+    let completed = client
+        .await_task::<String>(&first.id, None)
+        .await
+        .unwrap()
+        .expect("the first task was completed");
+    assert_eq!(completed.status, Status::Completed);
+}
+
+#[tokio::test]
+async fn progress_is_recorded_and_extends_the_deadline() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let mut long = task("long");
+    long.duration = time::Duration::seconds(2);
+    let long: Task = client.push(&long).await.unwrap();
+    let execution = client
+        .pop::<String>(Some(Duration::from_secs(1)))
+        .await
+        .unwrap()
+        .expect("the task is ready");
+
+    assert!(client
+        .progress(&long.id, execution.lease_token, 40, false)
+        .await
+        .unwrap()
+        .is_none());
+    let task: Task = client.get(&long.id).await.unwrap();
+    assert_eq!(task.progress, Some(40));
+    let page = client
+        .list::<String>(0, 10, Some(Status::Processing))
+        .await
+        .unwrap();
+    assert_eq!(page.tasks[0].progress, Some(40));
+    let stats: Stats = server.client.stats().await.unwrap();
+    assert_eq!(stats.progress, Some(40.0));
+    assert!(matches!(
+        client
+            .progress(&long.id, execution.lease_token, 101, false)
+            .await,
+        Err(ClientError::Api {
+            status: StatusCode::BAD_REQUEST,
+            code: ErrorCode::InvalidRequest,
+            ..
+        })
+    ));
+
+    // 90% of the duration is left from now, past the original deadline
+    tokio::time::sleep(Duration::from_millis(1200)).await;
+    let before = time::OffsetDateTime::now_utc();
+    let deadline = client
+        .progress(&long.id, execution.lease_token, 10, true)
+        .await
+        .unwrap()
+        .expect("the deadline is moved");
+    let extended = deadline - before;
+    assert!(extended > time::Duration::milliseconds(1700) && extended < time::Duration::seconds(2));
+    tokio::time::sleep(Duration::from_millis(1200)).await;
+    let task: Task = client.get(&long.id).await.unwrap();
+    assert_eq!((task.status, task.progress), (Status::Processing, Some(10)));
+}
+
+#[tokio::test]
+async fn whole_graphs_can_be_waited_for() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let root: Task = client.push(&task("root")).await.unwrap();
+    let mut leaf = task("leaf");
+    leaf.depends_on = vec![root.id.clone()];
+    let leaf: Task = client.push(&leaf).await.unwrap();
+    let mut failing = task("failing");
+    failing.max_retries = 0;
+    let failing: Task = client.push(&failing).await.unwrap();
+    let ids = vec![root.id.clone(), leaf.id.clone(), failing.id.clone()];
+
+    let unfinished = client
+        .wait::<String>(ids.clone(), Some(Duration::from_secs(1)))
+        .await
+        .unwrap();
+    assert!(unfinished.is_none());
+
+    let waiter = server.connect();
+    let waited = tokio::spawn({
+        let ids = ids.clone();
+        async move {
+            waiter
+                .wait::<String>(ids, Some(Duration::from_secs(5)))
+                .await
+        }
+    });
+    // Being ready is not enough for the tasks to be waited for
+    for _ in 0..3 {
+        let execution = client
+            .pop::<String>(Some(Duration::from_secs(1)))
+            .await
+            .unwrap()
+            .expect("a task is ready");
+        if execution.task.id == failing.id {
+            client
+                .fail(&failing.id, execution.lease_token, None)
+                .await
+                .unwrap();
+        } else {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert!(!waited.is_finished());
+            client
+                .complete(&execution.task.id, execution.lease_token)
+                .await
+                .unwrap();
+        }
+    }
+
+    let waited = waited
+        .await
+        .unwrap()
+        .unwrap()
+        .expect("the tasks finished in time");
+    let statuses: Vec<_> = waited.iter().map(|task| (&task.id, task.status)).collect();
+    assert_eq!(
+        statuses,
+        vec![
+            (&root.id, Status::Completed),
+            (&leaf.id, Status::Completed),
+            (&failing.id, Status::Failed)
+        ]
+    );
+}
+
+#[tokio::test]
+async fn followups_are_pushed_on_completion() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let pushed: Task = client.push(&task("first")).await.unwrap();
+    let mut followup = task("second");
+    followup.depends_on = vec![pushed.id.clone()];
+    // The task is not being processed, so nothing is pushed
+    assert!(matches!(
+        client
+            .complete_and_push(
+                &pushed.id,
+                Uuid::nil(),
+                None,
+                std::slice::from_ref(&followup)
+            )
+            .await,
+        Err(ClientError::Api {
+            status: StatusCode::NOT_FOUND,
+            code: ErrorCode::NotFound,
+            ..
+        })
+    ));
+    let stats: Stats = client.stats().await.unwrap();
+    assert_eq!(stats.ready, 1);
+
+    let popped = client
+        .pop::<String>(Some(Duration::from_secs(1)))
+        .await
+        .unwrap()
+        .expect("the pushed task is ready");
+    let followups: Vec<Task> = client
+        .complete_and_push(&pushed.id, popped.lease_token, None, &[followup])
+        .await
+        .unwrap();
+    assert_eq!(followups.len(), 1);
+    let execution = client
+        .pop::<String>(Some(Duration::from_secs(1)))
+        .await
+        .unwrap()
+        .expect("the followup is ready once its dependency is completed");
+    assert_eq!(execution.task.id, followups[0].id);
+    assert_eq!(execution.task.name, "second");
+    let stats: Stats = client.stats().await.unwrap();
+    assert_eq!((stats.processing, stats.completed), (1, 1));
+}
+
+#[tokio::test]
+async fn clones_of_an_injected_client_push_concurrently() {
+    let server = TestServer::start().await;
+    let http = reqwest::Client::builder()
+        .user_agent("taskie-tests")
+        .build()
+        .unwrap();
+    let client = Client::with_client(server.url("/").parse().unwrap(), http);
+
+    let pushes: Vec<_> = (0..4)
+        .map(|i| {
+            let client = client.clone();
+            tokio::spawn(async move { client.push(&task(&format!("task-{}", i))).await })
+        })
+        .collect();
+    for push in pushes {
+        push.await.unwrap().unwrap();
+    }
+    let stats: Stats = client.stats().await.unwrap();
+    assert_eq!(stats.ready, 4);
+}
+
+#[tokio::test]
+async fn payloads_are_validated_against_their_schema() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let schema = serde_json::json!({
+        "type": "object",
+        "properties": {"count": {"type": "integer"}},
+        "required": ["count"],
+    });
+    client.register_schema("counted", &schema).await.unwrap();
+    let invalid = serde_json::json!({"type": "nope"});
+    assert!(matches!(
+        client.register_schema("broken", &invalid).await,
+        Err(ClientError::Api {
+            status: StatusCode::BAD_REQUEST,
+            code: ErrorCode::InvalidRequest,
+            ..
+        })
+    ));
+
+    let mut valid = task("counted");
+    valid.payload = Some(serde_json::json!({"count": 1}));
+    client.push(&valid).await.unwrap();
+    for payload in [None, Some(serde_json::json!({"count": "one"}))] {
+        let mut invalid = task("counted");
+        invalid.payload = payload;
+        // The whole batch is rejected
+        assert!(matches!(
+            client.push_many(&[task("other"), invalid]).await,
+            Err(ClientError::Api {
+                status: StatusCode::BAD_REQUEST,
+                code: ErrorCode::InvalidRequest,
+                ..
+            })
+        ));
+    }
+    // The tasks without a schema are pushed as they are
+    client.push(&task("other")).await.unwrap();
+    let stats: Stats = client.stats().await.unwrap();
+    assert_eq!(stats.ready, 2);
+}
+
+#[tokio::test]
+async fn processing_tasks_are_requeued_on_demand() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let pushed: Task = client.push(&task("reclaimed")).await.unwrap();
+    // Only the tasks being processed can be requeued
+    assert!(matches!(
+        client.requeue(&pushed.id).await,
+        Err(ClientError::Api {
+            status: StatusCode::NOT_FOUND,
+            code: ErrorCode::NotFound,
+            ..
+        })
+    ));
+    client
+        .pop::<String>(Some(Duration::from_secs(1)))
+        .await
+        .unwrap()
+        .expect("the pushed task is ready");
+    client.requeue(&pushed.id).await.unwrap();
+
+    // It is handed out again well before its deadline, as a new attempt
+    let execution = client
+        .pop::<String>(Some(Duration::from_secs(1)))
+        .await
+        .unwrap()
+        .expect("the requeued task is ready");
+    assert_eq!(execution.task.id, pushed.id);
+    assert_eq!(execution.task.attempt, 2);
+    assert_eq!(execution.remaining, time::Duration::seconds(30));
+}
+
+#[cfg(feature = "graphql")]
+#[tokio::test]
+async fn graphql_queries_the_store() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+    let parent: Task = client.push(&task("parent")).await.unwrap();
+    let mut child = task("child");
+    child.depends_on = vec![parent.id.clone()];
+    let child: Task = client.push(&child).await.unwrap();
+
+    let query = format!(
+        r#"{{
+            task(id: "{}") {{ name status dependsOn }}
+            missing: task(id: "{}") {{ name }}
+            tasks(status: PENDING) {{ total tasks {{ id }} }}
+            stats {{ pending ready nextKey }}
+            graph {{ edges {{ dependent dependency }} }}
+        }}"#,
+        child.id,
+        client.stats().await.unwrap().next_key,
+    );
+    let response: serde_json::Value = reqwest::Client::new()
+        .post(server.url("/graphql"))
+        .json(&serde_json::json!({ "query": query }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        response,
+        serde_json::json!({
+            "data": {
+                "task": { "name": "child", "status": "PENDING", "dependsOn": [parent.id] },
+                "missing": null,
+                "tasks": { "total": 1, "tasks": [{ "id": child.id }] },
+                "stats": {
+                    "pending": 1,
+                    "ready": 1,
+                    "nextKey": client.stats().await.unwrap().next_key,
+                },
+                "graph": { "edges": [{ "dependent": child.id, "dependency": parent.id }] },
+            }
+        })
+    );
+}
+
+#[cfg(feature = "grpc")]
+#[tokio::test]
+async fn grpc_push_pop_complete() {
+    use taskie::grpc::{self, proto};
+
+    let server = TestServer::start().await;
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(grpc::server(server.state.clone(), None))
+            .serve_with_incoming_shutdown(
+                tokio_stream::wrappers::TcpListenerStream::new(listener),
+                server.state.shutdown.clone().cancelled_owned(),
+            ),
+    );
+    let mut client = proto::taskie_client::TaskieClient::connect(format!("http://{}", address))
+        .await
+        .unwrap();
+
+    let pushed = client
+        .push(proto::PushRequest {
+            tasks: vec![proto::InsertTask {
+                name: "grpc".to_string(),
+                payload: Some(r#"{"n":1}"#.to_string()),
+                labels: [("region".to_string(), "eu".to_string())].into(),
+                ..Default::default()
+            }],
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .tasks;
+    assert_eq!(pushed.len(), 1);
+    assert_eq!(pushed[0].duration, 30);
+
+    let execution = client
+        .pop(proto::PopRequest {
+            timeout: Some(1),
+            label: "region=eu".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .execution
+        .expect("the pushed task is ready");
+    let task = execution.task.unwrap();
+    assert_eq!(task.id, pushed[0].id);
+    assert_eq!(task.payload.as_deref(), Some(r#"{"n":1}"#));
+    assert_eq!(task.attempt, 1);
+
+    let status = client
+        .complete(proto::CompleteRequest {
+            id: task.id.clone(),
+            result: None,
+            lease_token: String::new(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::PermissionDenied);
+    client
+        .complete(proto::CompleteRequest {
+            id: task.id.clone(),
+            result: None,
+            lease_token: execution.lease_token.clone(),
+        })
+        .await
+        .unwrap();
+    // The task is shared with the HTTP API
+    let stats: Stats = server.client.stats().await.unwrap();
+    assert_eq!((stats.ready, stats.processing, stats.completed), (0, 0, 1));
+    let status = client
+        .complete(proto::CompleteRequest {
+            id: task.id,
+            result: None,
+            lease_token: execution.lease_token,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::NotFound);
+}
+
+#[tokio::test]
+async fn cors_preflights_are_answered_for_the_allowed_origins() {
+    let server = TestServer::start().await;
+    // The preflights are answered even though they carry no token
+    let app = taskie::router(server.state.clone(), Some("secret".into()))
+        .layer(cors::layer("https://app.example, https://admin.example").unwrap());
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service()),
+    );
+
+    let http = reqwest::Client::new();
+    let preflight = |origin: &'static str| {
+        http.request(
+            reqwest::Method::OPTIONS,
+            format!("http://{}/v1/push", address),
+        )
+        .header("origin", origin)
+        .header("access-control-request-method", "PUT")
+        .header(
+            "access-control-request-headers",
+            "authorization, content-type",
+        )
+        .send()
+    };
+    let response = preflight("https://admin.example").await.unwrap();
+    assert!(response.status().is_success());
+    let headers = response.headers();
+    assert_eq!(
+        headers["access-control-allow-origin"],
+        "https://admin.example"
+    );
+    assert!(headers["access-control-allow-methods"]
+        .to_str()
+        .unwrap()
+        .contains("PUT"));
+
+    let response = preflight("https://evil.example").await.unwrap();
+    assert!(!response
+        .headers()
+        .contains_key("access-control-allow-origin"));
+
+    // The actual requests still have to be authenticated
+    let response = http
+        .get(format!("http://{}/v1/stats", address))
+        .header("origin", "https://app.example")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        response.headers()["access-control-allow-origin"],
+        "https://app.example"
+    );
+}
+
+#[tokio::test]
+async fn requests_over_the_rate_limit_are_refused() {
+    let server = TestServer::start().await;
+    let state = taskie::AppState {
+        rate_limit: RateLimit::new(0.5, 2),
+        ..server.state.clone()
+    };
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(taskie::router(state, None).into_make_service_with_connect_info::<SocketAddr>()),
+    );
+
+    let http = reqwest::Client::new();
+    let stats = || http.get(format!("http://{}/v1/stats", address)).send();
+    for _ in 0..2 {
+        assert!(stats().await.unwrap().status().is_success());
+    }
+    let response = stats().await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()["retry-after"], "2");
+
+    // The probes are never limited
+    let response = http
+        .get(format!("http://{}/health", address))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+}
+
+#[tokio::test]
+async fn namespaces_have_their_own_store() {
+    let server = TestServer::start().await;
+    let state = taskie::AppState {
+        namespaces: Namespaces::new(2, || Arc::new(MemoryStore::new())),
+        ..server.state.clone()
+    };
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(taskie::router(state, None).into_make_service()),
+    );
+
+    let http = reqwest::Client::new();
+    let url = |path: &str| format!("http://{}{}", address, path);
+    let pushed: Vec<Task> = http
+        .put(url("/v1/first/push"))
+        .json(&[task("namespaced")])
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let id = &pushed[0].id;
+    let found: Task = http
+        .get(url(&format!("/v1/first/task/{}", id)))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(found.name, "namespaced");
+
+    let ready = |path: &'static str| {
+        let request = http.get(url(path)).send();
+        async move { request.await.unwrap().json::<Stats>().await.unwrap().ready }
+    };
+    assert_eq!(ready("/v1/first/stats").await, 1);
+    assert_eq!(ready("/v1/second/stats").await, 0);
+    assert_eq!(ready("/v1/stats").await, 0);
+
+    // The tasks of a namespace cannot depend on the ones of another
+    let mut dependent = task("dependent");
+    dependent.depends_on = vec![id.clone()];
+    let response = http
+        .put(url("/v1/second/push"))
+        .json(&[dependent])
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_client_error());
+
+    let response = http.get(url("/v1/third/stats")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+    let response = http.get(url("/v1/in.valid/stats")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+/// Hands the published events over to the test.
+struct Recorder(tokio::sync::mpsc::UnboundedSender<TaskEvent>);
+
+#[axum::async_trait]
+impl EventSink for Recorder {
+    async fn emit(&self, event: TaskEvent) {
+        let _ = self.0.send(event);
+    }
+}
+
+#[tokio::test]
+async fn task_events_are_published_in_order() {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let server = TestServer::with_store(MemoryStore::new().events(Recorder(tx))).await;
+    let client = &server.client;
+
+    let mut short = task("evented");
+    short.duration = time::Duration::seconds(1);
+    let pushed: Task = client.push(&short).await.unwrap();
+    client
+        .pop::<String>(Some(Duration::from_secs(1)))
+        .await
+        .unwrap()
+        .expect("the task is ready");
+    // Left to time out, and completed once popped again
+    let execution = client
+        .pop::<String>(Some(Duration::from_secs(5)))
+        .await
+        .unwrap()
+        .expect("the task is requeued once timed out");
+    client
+        .complete(&pushed.id, execution.lease_token)
+        .await
+        .unwrap();
+
+    let mut statuses = Vec::new();
+    let mut last = None;
+    for _ in 0..5 {
+        let event = rx.recv().await.unwrap();
+        assert_eq!(event.id, pushed.id);
+        assert_eq!(event.name, "evented");
+        assert!(last.is_none_or(|last| last <= event.at));
+        last = Some(event.at);
+        statuses.push(event.status);
+    }
+    assert_eq!(
+        statuses,
+        [
+            Status::Ready,
+            Status::Processing,
+            Status::Ready,
+            Status::Processing,
+            Status::Completed
+        ]
+    );
+}
+
+#[tokio::test]
+async fn servers_conceal_the_keys_with_their_own_keys() {
+    let first = TestServer::start().await;
+    let generator = BlockId::new(Alphabet::alphanumeric(), 7, 4);
+    let second = TestServer::with_keys(MemoryStore::new(), Keys::new(generator)).await;
+
+    let pushed: Task = first.client.push(&task("first")).await.unwrap();
+    let other: Task = second.client.push(&task("second")).await.unwrap();
+    // Both are the first task of their store, yet their ids differ
+    assert_ne!(pushed.id, other.id);
+    assert_eq!(
+        second.client.get::<String>(&other.id).await.unwrap().name,
+        "second"
+    );
+    assert!(first.client.get::<String>(&other.id).await.is_err());
+
+    // and so do the ones in the errors
+    let response = reqwest::Client::new()
+        .post(second.url("/v1/complete"))
+        .json(&serde_json::json!({ "id": other.id, "lease_token": Uuid::nil() }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert!(response.text().await.unwrap().contains(other.id.as_str()));
+}
+
+#[tokio::test]
+async fn version_reports_the_build_and_the_backend() {
+    let server = TestServer::start().await;
+
+    let version = server.client.server_version().await.unwrap();
+    assert_eq!(version.version, env!("CARGO_PKG_VERSION"));
+    assert!(!version.git_sha.is_empty());
+    assert_eq!(version.backend, "memory");
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::{keys, task, Task, TestServer};
+use taskie::{store::KeySigner, stores::mem::MemoryStore};
+use taskie_client::{ClientError, ErrorCode, TaskId, Uuid};
+
+#[tokio::test]
+async fn forged_keys_are_refused() {
+    let keys = keys().signed(KeySigner::new(b"secret"));
+    let server = TestServer::with_keys(MemoryStore::new(), keys).await;
+    let client = &server.client;
+
+    let first: Task = client.push(&task("first")).await.unwrap();
+    let second: Task = client.push(&task("second")).await.unwrap();
+    assert_eq!(client.get::<String>(&first.id).await.unwrap().id, first.id);
+
+    // The key of another task, with a tag that is not its own
+    let (first_id, second_id) = (first.id.as_str(), second.id.as_str());
+    let split = second_id.len() - 16;
+    let forged = TaskId(format!(
+        "{}{}",
+        &second_id[..split],
+        &first_id[first_id.len() - 16..]
+    ));
+    assert!(matches!(
+        client.get::<String>(&forged).await,
+        Err(ClientError::Api {
+            status: StatusCode::BAD_REQUEST,
+            code: ErrorCode::InvalidKey,
+            ..
+        })
+    ));
+    // nor are the keys stripped of their tag accepted
+    assert!(matches!(
+        client
+            .complete(&TaskId(second_id[..split].to_string()), Uuid::nil())
+            .await,
+        Err(ClientError::Api {
+            status: StatusCode::BAD_REQUEST,
+            code: ErrorCode::InvalidKey,
+            ..
+        })
+    ));
+    assert!(matches!(
+        client.cancel(&forged).await,
+        Err(ClientError::Api {
+            status: StatusCode::BAD_REQUEST,
+            code: ErrorCode::InvalidKey,
+            ..
+        })
+    ));
+    assert_eq!(
+        client.get::<String>(&second.id).await.unwrap().name,
+        "second"
+    );
+}